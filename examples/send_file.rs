@@ -1,16 +1,19 @@
 #![feature(async_await)]
 #![deny(warnings)]
 
-use tokio::io::AsyncReadExt;
-use tokio_fs::File;
+use std::io;
 
-use hyper::{Body, Method, Result, Request, Response, Server, StatusCode};
+use hyper::{Body, Chunk, Method, Result, Request, Response, Server, StatusCode};
+use hyper::body::{Either, FileBody, Full};
 use hyper::service::{make_service_fn, service_fn};
 
 static INDEX: &str = "examples/send_file_index.html";
 static INTERNAL_SERVER_ERROR: &[u8] = b"Internal Server Error";
 static NOTFOUND: &[u8] = b"Not Found";
 
+/// A file, or one of the error messages above.
+type ResBody = Either<FileBody, Full<Chunk>>;
+
 #[tokio::main]
 async fn main() {
     pretty_env_logger::init();
@@ -21,8 +24,10 @@ async fn main() {
         Ok::<_, hyper::Error>(service_fn(response_examples))
     });
 
-    let server = Server::bind(&addr)
-        .serve(make_service);
+    let builder = Server::bind(&addr);
+    #[cfg(target_os = "linux")]
+    let builder = builder.http1_sendfile(true);
+    let server = builder.serve(make_service);
 
     println!("Listening on http://{}", addr);
 
@@ -31,7 +36,7 @@ async fn main() {
     }
 }
 
-async fn response_examples(req: Request<Body>) -> Result<Response<Body>> {
+async fn response_examples(req: Request<Body>) -> Result<Response<ResBody>> {
     match (req.method(), req.uri().path()) {
         (&Method::GET, "/") |
         (&Method::GET, "/index.html") |
@@ -47,36 +52,28 @@ async fn response_examples(req: Request<Body>) -> Result<Response<Body>> {
 }
 
 /// HTTP status code 404
-fn not_found() -> Response<Body> {
+fn not_found() -> Response<ResBody> {
     Response::builder()
         .status(StatusCode::NOT_FOUND)
-        .body(NOTFOUND.into())
+        .body(Either::Right(NOTFOUND.into()))
         .unwrap()
 }
 
 /// HTTP status code 500
-fn internal_server_error() -> Response<Body> {
+fn internal_server_error() -> Response<ResBody> {
     Response::builder()
         .status(StatusCode::INTERNAL_SERVER_ERROR)
-        .body(INTERNAL_SERVER_ERROR.into())
+        .body(Either::Right(INTERNAL_SERVER_ERROR.into()))
         .unwrap()
 }
 
-async fn simple_file_send(f: &str) -> Result<Response<Body>> {
-    // Serve a file by asynchronously reading it entirely into memory.
-    // Uses tokio_fs to open file asynchronously, then tokio::io::AsyncReadExt
-    // to read into memory asynchronously.
-
-    let filename = f.to_string(); // we need to copy for lifetime issues
-
-    if let Ok(mut file) = File::open(filename).await {
-        let mut buf = Vec::new();
-        if let Ok(_) = file.read_to_end(&mut buf).await {
-            return Ok(Response::new(buf.into()));
-        }
-
-        return Ok(internal_server_error());
+async fn simple_file_send(filename: &str) -> Result<Response<ResBody>> {
+    // Serve a file without reading it into memory first. The body knows
+    // its length, and is read as it is sent, or sent straight from the
+    // file to the socket with `sendfile` where possible.
+    match FileBody::open(filename) {
+        Ok(body) => Ok(Response::new(Either::Left(body))),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(not_found()),
+        Err(_) => Ok(internal_server_error()),
     }
-
-    return Ok(not_found());
 }
@@ -0,0 +1,149 @@
+use std::error::Error as StdError;
+
+use bytes::{Buf, Bytes};
+use futures_util::future::poll_fn;
+
+use crate::common::BufList;
+use super::Payload;
+
+/// Aggregates the data buffers of a body into a single `Buf`, without
+/// copying them.
+///
+/// Fails with an error where `Error::is_body_too_large` is true if the
+/// body is longer than `limit` bytes. If the body's `content_length`
+/// already exceeds the limit, nothing is read.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(async_await)]
+/// # fn main() {}
+/// # async fn doc(body: hyper::Body) -> hyper::Result<()> {
+/// use bytes::Buf;
+///
+/// let buf = hyper::body::aggregate(body, 64 * 1024).await?;
+/// println!("body is {} bytes", buf.remaining());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn aggregate<T>(body: T, limit: usize) -> crate::Result<impl Buf>
+where
+    T: Payload,
+{
+    if let Some(len) = body.content_length() {
+        if len > limit as u64 {
+            debug!("body content-length {} is larger than limit {}", len, limit);
+            return Err(crate::Error::new_body_too_large());
+        }
+    }
+
+    pin_utils::pin_mut!(body);
+
+    let mut bufs = BufList::new();
+    let mut remaining = limit;
+    while let Some(buf) = poll_fn(|cx| body.as_mut().poll_data(cx)).await {
+        let buf = buf.map_err(into_error)?;
+        let len = buf.remaining();
+        if len > remaining {
+            debug!("body is larger than limit {}", limit);
+            return Err(crate::Error::new_body_too_large());
+        }
+        remaining -= len;
+        if len > 0 {
+            bufs.push(buf);
+        }
+    }
+
+    Ok(bufs)
+}
+
+/// Concatenates the data buffers of a body into a single `Bytes`.
+///
+/// Fails with an error where `Error::is_body_too_large` is true if the
+/// body is longer than `limit` bytes. If the body's `content_length`
+/// already exceeds the limit, nothing is read.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(async_await)]
+/// # fn main() {}
+/// # async fn doc(body: hyper::Body) -> hyper::Result<()> {
+/// let bytes = hyper::body::to_bytes(body, 64 * 1024).await?;
+/// println!("body: {:?}", bytes);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn to_bytes<T>(body: T, limit: usize) -> crate::Result<Bytes>
+where
+    T: Payload,
+{
+    let buf = aggregate(body, limit).await?;
+    Ok(buf.collect())
+}
+
+// Errors from a `Body` are already `hyper::Error`s, so don't wrap them again.
+fn into_error<E>(err: E) -> crate::Error
+where
+    E: Into<Box<dyn StdError + Send + Sync>>,
+{
+    match err.into().downcast::<crate::Error>() {
+        Ok(err) => *err,
+        Err(err) => crate::Error::new_user_body(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Buf;
+
+    use crate::Body;
+    use super::{aggregate, to_bytes};
+
+    #[tokio::test]
+    async fn to_bytes_concatenates_chunks() {
+        let chunks: Vec<Result<_, ::std::io::Error>> = vec![Ok("hello"), Ok(" "), Ok("world")];
+        let body = Body::wrap_stream(futures_util::stream::iter(chunks));
+
+        let bytes = to_bytes(body, 11).await.expect("to_bytes");
+        assert_eq!(bytes, "hello world");
+    }
+
+    #[tokio::test]
+    async fn aggregate_keeps_chunks() {
+        let chunks: Vec<Result<_, ::std::io::Error>> = vec![Ok("hello"), Ok(" "), Ok("world")];
+        let body = Body::wrap_stream(futures_util::stream::iter(chunks));
+
+        let buf = aggregate(body, 1024).await.expect("aggregate");
+        assert_eq!(buf.remaining(), 11);
+        assert_eq!(buf.bytes(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn streamed_length_over_limit() {
+        let chunks: Vec<Result<_, ::std::io::Error>> = vec![Ok("hello"), Ok(" "), Ok("world")];
+        let body = Body::wrap_stream(futures_util::stream::iter(chunks));
+
+        let err = to_bytes(body, 10).await.expect_err("over limit");
+        assert!(err.is_body_too_large());
+    }
+
+    #[tokio::test]
+    async fn content_length_over_limit() {
+        let (mut tx, body) = Body::new_channel(Some(11), false);
+        tx.send_data("hello world".into()).expect("send_data");
+
+        let err = aggregate(body, 10).await.err().expect("over limit");
+        assert!(err.is_body_too_large());
+    }
+
+    #[tokio::test]
+    async fn body_errors_not_wrapped() {
+        let (mut tx, body) = Body::channel();
+        tx.send_error(crate::Error::new_incomplete());
+        drop(tx);
+
+        let err = to_bytes(body, 10).await.expect_err("body error");
+        assert!(err.is_incomplete_message());
+    }
+}
@@ -1,6 +1,7 @@
 use std::borrow::Cow;
 use std::error::Error as StdError;
 use std::fmt;
+use std::sync::{Arc, Mutex};
 
 use bytes::Bytes;
 use futures_core::{Stream, TryStream};
@@ -11,7 +12,9 @@ use h2;
 use http::HeaderMap;
 
 use crate::common::{Future, Never, Pin, Poll, task};
+use crate::ext::OnProgress;
 use super::internal::{FullDataArg, FullDataRet};
+use super::progress::Observer;
 use super::{Chunk, Payload};
 use crate::upgrade::OnUpgrade;
 
@@ -36,9 +39,17 @@ enum Kind {
         content_length: Option<u64>,
         abort_rx: oneshot::Receiver<()>,
         rx: mpsc::Receiver<Result<Chunk, crate::Error>>,
+        trailers_rx: oneshot::Receiver<HeaderMap>,
+        /// Tells the `Sender` that the body has been polled, if it waits
+        /// for that before sending.
+        want_tx: Option<want::Taker>,
     },
     H2 {
         content_length: Option<u64>,
+        /// How much more data is allowed, if the stream is limited.
+        limit: Option<u64>,
+        /// Asks the stream's owner to reset it, once the limit is exceeded.
+        reset_tx: Option<oneshot::Sender<h2::Reason>>,
         recv: h2::RecvStream,
     },
     // NOTE: This requires `Sync` because of how easy it is to use `await`
@@ -46,6 +57,27 @@ enum Kind {
     //
     // See https://github.com/rust-lang/rust/issues/57017
     Wrapped(Pin<Box<dyn Stream<Item = Result<Chunk, Box<dyn StdError + Send + Sync>>> + Send + Sync>>),
+    Replay {
+        shared: Arc<Mutex<Replay>>,
+        /// The index of the next chunk this body yields.
+        pos: usize,
+        content_length: Option<u64>,
+    },
+}
+
+/// The state shared by all replays of a `Body::replayable`.
+struct Replay {
+    body: Body,
+    /// The chunks read from `body` so far, while under the limit.
+    chunks: Vec<Bytes>,
+    /// How many chunks at the start are no longer buffered.
+    dropped: usize,
+    buffered: usize,
+    limit: usize,
+    replayable: bool,
+    /// The trailers of `body`, once polled.
+    trailers: Option<Option<HeaderMap>>,
+    content_length: Option<u64>,
 }
 
 struct Extra {
@@ -59,6 +91,8 @@ struct Extra {
     /// connection yet.
     delayed_eof: Option<DelayEof>,
     on_upgrade: OnUpgrade,
+    /// Allow the client to report the progress of a response body.
+    progress: Option<Observer>,
 }
 
 type DelayEofUntil = oneshot::Receiver<Never>;
@@ -81,6 +115,8 @@ enum DelayEof {
 pub struct Sender {
     abort_tx: oneshot::Sender<()>,
     tx: BodySender,
+    trailers_tx: Option<oneshot::Sender<HeaderMap>>,
+    want_rx: Option<want::Giver>,
 }
 
 impl Body {
@@ -104,21 +140,34 @@ impl Body {
     /// Useful when wanting to stream chunks from another thread.
     #[inline]
     pub fn channel() -> (Sender, Body) {
-        Self::new_channel(None)
+        Self::new_channel(None, false)
     }
 
-    pub(crate) fn new_channel(content_length: Option<u64>) -> (Sender, Body) {
+    /// If `wanter` is true, the `Sender` isn't ready until the `Body` is
+    /// polled for the first time.
+    pub(crate) fn new_channel(content_length: Option<u64>, wanter: bool) -> (Sender, Body) {
         let (tx, rx) = mpsc::channel(0);
         let (abort_tx, abort_rx) = oneshot::channel();
+        let (trailers_tx, trailers_rx) = oneshot::channel();
+        let (want_rx, want_tx) = if wanter {
+            let (giver, taker) = want::new();
+            (Some(giver), Some(taker))
+        } else {
+            (None, None)
+        };
 
         let tx = Sender {
             abort_tx: abort_tx,
             tx: tx,
+            trailers_tx: Some(trailers_tx),
+            want_rx,
         };
         let rx = Body::new(Kind::Chan {
             content_length,
             abort_rx,
             rx,
+            trailers_rx,
+            want_tx,
         });
 
         (tx, rx)
@@ -153,9 +202,83 @@ impl Body {
         Body::new(Kind::Wrapped(Box::pin(mapped)))
     }
 
+    /// Wrap a `Body` so that it can be replayed, such as to retry or
+    /// redirect a request.
+    ///
+    /// Chunks are buffered while they are streamed, up to `limit` bytes.
+    /// Once the body is larger than that, it can no longer be replayed.
+    ///
+    /// The `Client` uses this to retry requests on reused connections that
+    /// were closed while the request was being sent. Use
+    /// [`try_replay`](Body::try_replay) to send it again yourself, such as
+    /// when following a 307 or 308 redirect.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use hyper::Body;
+    /// let (_tx, body) = Body::channel();
+    /// let body = Body::replayable(body, 64 * 1024);
+    ///
+    /// // keep a copy to replay if needed
+    /// let replay = body.try_replay();
+    /// assert!(replay.is_some());
+    /// ```
+    pub fn replayable(body: Body, limit: usize) -> Body {
+        let content_length = body.content_length();
+        let replay = Replay {
+            body,
+            chunks: Vec::new(),
+            dropped: 0,
+            buffered: 0,
+            limit,
+            replayable: true,
+            trailers: None,
+            content_length,
+        };
+        Body::new(Kind::Replay {
+            shared: Arc::new(Mutex::new(replay)),
+            pos: 0,
+            content_length,
+        })
+    }
+
+    /// Returns a `Body` that replays this one from the start.
+    ///
+    /// Returns `None` if this body wasn't made with
+    /// [`Body::replayable`](Body::replayable), or if it was larger than
+    /// its limit.
+    pub fn try_replay(&self) -> Option<Body> {
+        match self.kind {
+            Kind::Replay { ref shared, .. } => {
+                let replay = shared.lock().unwrap();
+                if replay.replayable {
+                    Some(Body::new(Kind::Replay {
+                        shared: shared.clone(),
+                        pos: 0,
+                        content_length: replay.content_length,
+                    }))
+                } else {
+                    None
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns whether this body can still be replayed.
+    ///
+    /// See [`Body::replayable`](Body::replayable).
+    pub fn is_replayable(&self) -> bool {
+        match self.kind {
+            Kind::Replay { ref shared, .. } => shared.lock().unwrap().replayable,
+            _ => false,
+        }
+    }
+
     /// dox
     pub async fn next(&mut self) -> Option<crate::Result<Chunk>> {
-        futures_util::future::poll_fn(|cx| self.poll_eof(cx)).await
+        futures_util::future::poll_fn(|cx| self.poll_observed(cx)).await
     }
 
     /// Converts this `Body` into a `Future` of a pending HTTP upgrade.
@@ -175,9 +298,19 @@ impl Body {
         }
     }
 
-    pub(crate) fn h2(recv: h2::RecvStream, content_length: Option<u64>) -> Self {
+    pub(crate) fn h2(
+        recv: h2::RecvStream,
+        content_length: Option<u64>,
+        limit: Option<(u64, oneshot::Sender<h2::Reason>)>,
+    ) -> Self {
+        let (limit, reset_tx) = match limit {
+            Some((limit, reset_tx)) => (Some(limit), Some(reset_tx)),
+            None => (None, None),
+        };
         Body::new(Kind::H2 {
             content_length,
+            limit,
+            reset_tx,
             recv,
         })
     }
@@ -193,6 +326,11 @@ impl Body {
         self.extra_mut().delayed_eof = Some(DelayEof::NotEof(fut));
     }
 
+    pub(crate) fn set_on_progress(&mut self, on_progress: OnProgress) {
+        let total = self.content_length();
+        self.extra_mut().progress = Some(Observer::new(on_progress, total));
+    }
+
     fn take_delayed_eof(&mut self) -> Option<DelayEof> {
         self
             .extra
@@ -206,9 +344,25 @@ impl Body {
             .get_or_insert_with(|| Box::new(Extra {
                 delayed_eof: None,
                 on_upgrade: OnUpgrade::none(),
+                progress: None,
             }))
     }
 
+    fn poll_observed(&mut self, cx: &mut task::Context<'_>) -> Poll<Option<crate::Result<Chunk>>> {
+        match self.extra.as_ref().and_then(|extra| extra.progress.as_ref()) {
+            None => return self.poll_eof(cx),
+            Some(observer) if observer.is_stalled() => return Poll::Ready(None),
+            Some(_) => (),
+        }
+        let polled = self.poll_eof(cx);
+        let is_end_stream = self.is_end_stream();
+        self.extra_mut()
+            .progress
+            .as_mut()
+            .expect("progress observer")
+            .observe(cx, polled, is_end_stream)
+    }
+
     fn poll_eof(&mut self, cx: &mut task::Context<'_>) -> Poll<Option<crate::Result<Chunk>>> {
         match self.take_delayed_eof() {
             Some(DelayEof::NotEof(mut delay)) => {
@@ -254,11 +408,17 @@ impl Body {
                 content_length: ref mut len,
                 ref mut rx,
                 ref mut abort_rx,
+                ref mut want_tx,
+                ..
             } => {
                 if let Poll::Ready(Ok(())) = Pin::new(abort_rx).poll(cx) {
                     return Poll::Ready(Some(Err(crate::Error::new_body_write("body write aborted"))));
                 }
 
+                if let Some(ref mut want_tx) = *want_tx {
+                    want_tx.want();
+                }
+
                 match ready!(Pin::new(rx).poll_next(cx)?) {
                     Some(chunk) => {
                         if let Some(ref mut len) = *len {
@@ -271,9 +431,26 @@ impl Body {
                 }
             },
             Kind::H2 {
-                recv: ref mut h2, ..
+                recv: ref mut h2,
+                ref mut limit,
+                ref mut reset_tx,
+                ..
             } => match ready!(h2.poll_data(cx)) {
                 Some(Ok(bytes)) => {
+                    if let Some(ref mut remaining) = *limit {
+                        if bytes.len() as u64 > *remaining {
+                            debug!("incoming body is larger than the limit");
+                            // The capacity is never released, and the stream
+                            // is answered or reset so the peer stops sending
+                            // the rest.
+                            if let Some(tx) = reset_tx.take() {
+                                let _ = tx.send(h2::Reason::ENHANCE_YOUR_CALM);
+                            }
+                            self.kind = Kind::Once(None);
+                            return Poll::Ready(Some(Err(crate::Error::new_body_too_large())));
+                        }
+                        *remaining -= bytes.len() as u64;
+                    }
                     let _ = h2.release_capacity().release_capacity(bytes.len());
                     Poll::Ready(Some(Ok(Chunk::from(bytes))))
                 },
@@ -285,9 +462,88 @@ impl Body {
                     Some(res) => Poll::Ready(Some(res.map_err(crate::Error::new_body))),
                     None => Poll::Ready(None),
                 }
-            }
+            },
+            Kind::Replay {
+                ref shared,
+                ref mut pos,
+                content_length: ref mut len,
+            } => {
+                let mut replay = shared.lock().unwrap();
+                let chunk = match replay.poll_chunk(*pos, cx) {
+                    Poll::Ready(Some(Ok(chunk))) => chunk,
+                    Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                    Poll::Ready(None) => return Poll::Ready(None),
+                    Poll::Pending => return Poll::Pending,
+                };
+                *pos += 1;
+                if let Some(ref mut len) = *len {
+                    *len = len.saturating_sub(chunk.len() as u64);
+                }
+                Poll::Ready(Some(Ok(Chunk::from(chunk))))
+            },
+        }
+    }
+}
+
+impl Replay {
+    fn poll_chunk(&mut self, pos: usize, cx: &mut task::Context<'_>) -> Poll<Option<crate::Result<Bytes>>> {
+        if pos < self.dropped {
+            return Poll::Ready(Some(Err(crate::Error::new_body("replayed body is larger than its limit"))));
+        }
+        if let Some(chunk) = self.chunks.get(pos - self.dropped) {
+            return Poll::Ready(Some(Ok(chunk.clone())));
+        }
+
+        match ready!(Pin::new(&mut self.body).poll_data(cx)) {
+            Some(Ok(chunk)) => {
+                let chunk = chunk.into_bytes();
+                if self.replayable && self.buffered + chunk.len() <= self.limit {
+                    self.buffered += chunk.len();
+                    self.chunks.push(chunk.clone());
+                } else {
+                    if self.replayable {
+                        trace!("replayable body is larger than its limit ({})", self.limit);
+                        self.replayable = false;
+                    }
+                    self.dropped += self.chunks.len() + 1;
+                    self.chunks = Vec::new();
+                    self.buffered = 0;
+                }
+                Poll::Ready(Some(Ok(chunk)))
+            },
+            Some(Err(e)) => {
+                // A broken body can't be sent again.
+                self.replayable = false;
+                Poll::Ready(Some(Err(e)))
+            },
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn poll_trailers(&mut self, cx: &mut task::Context<'_>) -> Poll<Option<crate::Result<HeaderMap>>> {
+        if let Some(ref trailers) = self.trailers {
+            return Poll::Ready(trailers.clone().map(Ok));
+        }
+
+        match ready!(Pin::new(&mut self.body).poll_trailers(cx)) {
+            Some(Ok(trailers)) => {
+                self.trailers = Some(Some(trailers.clone()));
+                Poll::Ready(Some(Ok(trailers)))
+            },
+            Some(Err(e)) => {
+                self.replayable = false;
+                Poll::Ready(Some(Err(e)))
+            },
+            None => {
+                self.trailers = Some(None);
+                Poll::Ready(None)
+            },
         }
     }
+
+    fn is_end_stream(&self, pos: usize) -> bool {
+        pos >= self.dropped + self.chunks.len() && self.body.is_end_stream()
+    }
 }
 
 impl Default for Body {
@@ -303,7 +559,7 @@ impl Payload for Body {
     type Error = crate::Error;
 
     fn poll_data(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
-        self.poll_eof(cx)
+        self.poll_observed(cx)
     }
 
     fn poll_trailers(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<HeaderMap, Self::Error>>> {
@@ -313,6 +569,12 @@ impl Payload for Body {
                 Err(e) => Poll::Ready(Some(Err(crate::Error::new_h2(e)))),
                 Ok(None) => Poll::Ready(None),
             },
+            Kind::Chan { ref mut trailers_rx, .. } => match ready!(Pin::new(trailers_rx).poll(cx)) {
+                Ok(t) => Poll::Ready(Some(Ok(t))),
+                // the `Sender` was dropped without sending trailers
+                Err(_canceled) => Poll::Ready(None),
+            },
+            Kind::Replay { ref shared, .. } => shared.lock().unwrap().poll_trailers(cx),
             _ => Poll::Ready(None),
         }
     }
@@ -323,6 +585,7 @@ impl Payload for Body {
             Kind::Chan { content_length, .. } => content_length == Some(0),
             Kind::H2 { recv: ref h2, .. } => h2.is_end_stream(),
             Kind::Wrapped(..) => false,
+            Kind::Replay { ref shared, pos, .. } => shared.lock().unwrap().is_end_stream(pos),
         }
     }
 
@@ -331,7 +594,9 @@ impl Payload for Body {
             Kind::Once(Some(ref val)) => Some(val.len() as u64),
             Kind::Once(None) => Some(0),
             Kind::Wrapped(..) => None,
-            Kind::Chan { content_length, .. } | Kind::H2 { content_length, .. } => content_length,
+            Kind::Chan { content_length, .. } |
+            Kind::H2 { content_length, .. } |
+            Kind::Replay { content_length, .. } => content_length,
         }
     }
 
@@ -343,6 +608,15 @@ impl Payload for Body {
             _ => FullDataRet(None),
         }
     }
+
+    #[doc(hidden)]
+    fn __hyper_replay(&self) -> Option<Body> {
+        match self.kind {
+            // a full body can always be sent again
+            Kind::Once(ref val) => Some(Body::new(Kind::Once(val.as_ref().map(Chunk::share)))),
+            _ => self.try_replay(),
+        }
+    }
 }
 
 impl fmt::Debug for Body {
@@ -494,6 +768,10 @@ impl Sender {
             Poll::Pending => (), // fallthrough
         }
 
+        if let Some(ref mut want_rx) = self.want_rx {
+            ready!(want_rx.poll_want(cx)).map_err(|_| crate::Error::new_closed())?;
+        }
+
         self.tx.poll_ready(cx).map_err(|_| crate::Error::new_closed())
     }
 
@@ -510,6 +788,23 @@ impl Sender {
             .map_err(|err| err.into_inner().expect("just sent Ok"))
     }
 
+    /// Sends trailers on this channel.
+    ///
+    /// The trailers are yielded by `Payload::poll_trailers` once all the
+    /// data has been received, which is when this `Sender` is dropped. They
+    /// are sent as trailers over HTTP/2, and after the last chunk of a
+    /// chunked HTTP/1 body. Other HTTP/1 bodies can't carry trailers, so
+    /// they are dropped.
+    ///
+    /// Returns `Err(HeaderMap)` if trailers were already sent, or if the
+    /// `Body` was dropped.
+    pub fn send_trailers(&mut self, trailers: HeaderMap) -> Result<(), HeaderMap> {
+        match self.trailers_tx.take() {
+            Some(tx) => tx.send(trailers),
+            None => Err(trailers),
+        }
+    }
+
     /// Aborts the body in an abnormal fashion.
     pub fn abort(self) {
         let _ = self.abort_tx.send(());
@@ -540,3 +835,26 @@ impl Sink for Sender {
     }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::Body;
+
+    #[tokio::test]
+    async fn replayable_body_replays_until_limit() {
+        let chunks: Vec<Result<_, ::std::io::Error>> = vec![Ok("hello"), Ok(" world")];
+        let mut body = Body::replayable(Body::wrap_stream(futures_util::stream::iter(chunks)), 5);
+
+        let mut replay = body.try_replay().expect("replay before polling");
+        assert_eq!(&body.next().await.expect("chunk 1").expect("ok")[..], &b"hello"[..]);
+        assert!(body.is_replayable());
+
+        // a replay sees buffered chunks, and pulls the rest itself
+        assert_eq!(&replay.next().await.expect("chunk 1").expect("ok")[..], &b"hello"[..]);
+        assert_eq!(&replay.next().await.expect("chunk 2").expect("ok")[..], &b" world"[..]);
+        assert!(replay.next().await.is_none());
+
+        assert!(!body.is_replayable());
+        assert!(body.try_replay().is_none());
+    }
+}
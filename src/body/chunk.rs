@@ -29,6 +29,13 @@ impl Chunk {
     pub fn into_bytes(self) -> Bytes {
         self.into()
     }
+
+    // Another `Chunk` of the same buffer, without copying it.
+    pub(crate) fn share(&self) -> Chunk {
+        Chunk {
+            bytes: self.bytes.clone(),
+        }
+    }
 }
 
 impl Buf for Chunk {
@@ -0,0 +1,336 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+use bytes::Buf;
+use http::HeaderMap;
+use pin_utils::{unsafe_pinned, unsafe_unpinned};
+
+use crate::common::{Pin, Poll, Unpin, task};
+use super::internal::{FileRegionArg, FileRegionRet, FullDataArg, FullDataRet};
+use super::Payload;
+
+/// A `Payload` that maps the data buffers of another.
+///
+/// Created with [`Payload::map_data`](Payload::map_data).
+#[must_use = "streams do nothing unless polled"]
+pub struct MapData<B, F> {
+    inner: B,
+    f: F,
+}
+
+impl<B, F> MapData<B, F> {
+    unsafe_pinned!(inner: B);
+    unsafe_unpinned!(f: F);
+
+    pub(super) fn new(inner: B, f: F) -> MapData<B, F> {
+        MapData { inner, f }
+    }
+
+    /// Consumes this `MapData`, returning the underlying body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B: Unpin, F> Unpin for MapData<B, F> {}
+
+impl<B, F, D> Payload for MapData<B, F>
+where
+    B: Payload,
+    F: FnMut(B::Data) -> D + Send + 'static,
+    D: Buf + Send,
+{
+    type Data = D;
+    type Error = B::Error;
+
+    fn poll_data(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match ready!(self.as_mut().inner().poll_data(cx)) {
+            Some(Ok(data)) => Poll::Ready(Some(Ok((self.f())(data)))),
+            Some(Err(err)) => Poll::Ready(Some(Err(err))),
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn poll_trailers(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<HeaderMap, Self::Error>>> {
+        self.inner().poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.inner.content_length()
+    }
+
+    #[doc(hidden)]
+    fn __hyper_full_data(&mut self, arg: FullDataArg) -> FullDataRet<Self::Data> {
+        FullDataRet(self.inner.__hyper_full_data(arg).0.map(&mut self.f))
+    }
+}
+
+impl<B: fmt::Debug, F> fmt::Debug for MapData<B, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MapData")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// A `Payload` that maps the errors of another.
+///
+/// Created with [`Payload::map_err`](Payload::map_err).
+#[must_use = "streams do nothing unless polled"]
+pub struct MapErr<B, F> {
+    inner: B,
+    f: F,
+}
+
+impl<B, F> MapErr<B, F> {
+    unsafe_pinned!(inner: B);
+    unsafe_unpinned!(f: F);
+
+    pub(super) fn new(inner: B, f: F) -> MapErr<B, F> {
+        MapErr { inner, f }
+    }
+
+    /// Consumes this `MapErr`, returning the underlying body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B: Unpin, F> Unpin for MapErr<B, F> {}
+
+impl<B, F, E> Payload for MapErr<B, F>
+where
+    B: Payload,
+    F: FnMut(B::Error) -> E + Send + 'static,
+    E: Into<Box<dyn StdError + Send + Sync>>,
+{
+    type Data = B::Data;
+    type Error = E;
+
+    fn poll_data(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match ready!(self.as_mut().inner().poll_data(cx)) {
+            Some(Ok(data)) => Poll::Ready(Some(Ok(data))),
+            Some(Err(err)) => Poll::Ready(Some(Err((self.f())(err)))),
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn poll_trailers(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<HeaderMap, Self::Error>>> {
+        match ready!(self.as_mut().inner().poll_trailers(cx)) {
+            Some(Ok(trailers)) => Poll::Ready(Some(Ok(trailers))),
+            Some(Err(err)) => Poll::Ready(Some(Err((self.f())(err)))),
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.inner.content_length()
+    }
+
+    #[doc(hidden)]
+    fn __hyper_full_data(&mut self, arg: FullDataArg) -> FullDataRet<Self::Data> {
+        self.inner.__hyper_full_data(arg)
+    }
+
+    #[doc(hidden)]
+    fn __hyper_file_region(&mut self, arg: FileRegionArg) -> FileRegionRet {
+        self.inner.__hyper_file_region(arg)
+    }
+}
+
+impl<B: fmt::Debug, F> fmt::Debug for MapErr<B, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MapErr")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// A boxed `Payload`, with its type erased.
+///
+/// Unlike `Body::wrap_stream`, the body doesn't need to be `Sync`, and its
+/// trailers, length, and end of stream hints are kept.
+///
+/// Created with [`Payload::boxed`](Payload::boxed).
+#[must_use = "streams do nothing unless polled"]
+pub struct BoxBody<D, E> {
+    inner: Pin<Box<dyn Payload<Data = D, Error = E>>>,
+}
+
+impl<D, E> BoxBody<D, E> {
+    /// Boxes a `Payload`.
+    pub fn new<B>(body: B) -> BoxBody<D, E>
+    where
+        B: Payload<Data = D, Error = E>,
+    {
+        BoxBody {
+            inner: Box::pin(body),
+        }
+    }
+}
+
+impl<D, E> Payload for BoxBody<D, E>
+where
+    D: Buf + Send + 'static,
+    E: Into<Box<dyn StdError + Send + Sync>> + 'static,
+{
+    type Data = D;
+    type Error = E;
+
+    fn poll_data(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        self.inner.as_mut().poll_data(cx)
+    }
+
+    fn poll_trailers(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<HeaderMap, Self::Error>>> {
+        self.inner.as_mut().poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.inner.content_length()
+    }
+}
+
+impl<D, E> fmt::Debug for BoxBody<D, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BoxBody").finish()
+    }
+}
+
+/// A `Payload` that is one of two bodies with the same data type.
+///
+/// Errors of either body are boxed.
+#[must_use = "streams do nothing unless polled"]
+#[derive(Debug)]
+pub enum Either<L, R> {
+    /// The first body.
+    Left(L),
+    /// The second body.
+    Right(R),
+}
+
+impl<L, R> Either<L, R> {
+    fn project(self: Pin<&mut Self>) -> Either<Pin<&mut L>, Pin<&mut R>> {
+        // Safety: the bodies are never moved out of the pinned `Either`.
+        unsafe {
+            match self.get_unchecked_mut() {
+                Either::Left(left) => Either::Left(Pin::new_unchecked(left)),
+                Either::Right(right) => Either::Right(Pin::new_unchecked(right)),
+            }
+        }
+    }
+}
+
+impl<L, R> Payload for Either<L, R>
+where
+    L: Payload,
+    R: Payload<Data = L::Data>,
+{
+    type Data = L::Data;
+    type Error = Box<dyn StdError + Send + Sync>;
+
+    fn poll_data(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let data = match self.project() {
+            Either::Left(left) => ready!(left.poll_data(cx)).map(|res| res.map_err(Into::into)),
+            Either::Right(right) => ready!(right.poll_data(cx)).map(|res| res.map_err(Into::into)),
+        };
+        Poll::Ready(data)
+    }
+
+    fn poll_trailers(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<HeaderMap, Self::Error>>> {
+        let trailers = match self.project() {
+            Either::Left(left) => ready!(left.poll_trailers(cx)).map(|res| res.map_err(Into::into)),
+            Either::Right(right) => ready!(right.poll_trailers(cx)).map(|res| res.map_err(Into::into)),
+        };
+        Poll::Ready(trailers)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match *self {
+            Either::Left(ref left) => left.is_end_stream(),
+            Either::Right(ref right) => right.is_end_stream(),
+        }
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        match *self {
+            Either::Left(ref left) => left.content_length(),
+            Either::Right(ref right) => right.content_length(),
+        }
+    }
+
+    #[doc(hidden)]
+    fn __hyper_full_data(&mut self, arg: FullDataArg) -> FullDataRet<Self::Data> {
+        match *self {
+            Either::Left(ref mut left) => left.__hyper_full_data(arg),
+            Either::Right(ref mut right) => right.__hyper_full_data(arg),
+        }
+    }
+
+    #[doc(hidden)]
+    fn __hyper_file_region(&mut self, arg: FileRegionArg) -> FileRegionRet {
+        match *self {
+            Either::Left(ref mut left) => left.__hyper_file_region(arg),
+            Either::Right(ref mut right) => right.__hyper_file_region(arg),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Buf;
+    use futures_util::future::poll_fn;
+    use http::HeaderMap;
+
+    use crate::{Body, Chunk};
+    use crate::common::Pin;
+    use super::super::{Either, Full, Payload};
+
+    #[tokio::test]
+    async fn boxed_map_keeps_trailers_and_length() {
+        let (mut tx, body) = Body::new_channel(Some(5), false);
+        tx.send_data("hello".into()).expect("send_data");
+        let mut trailers = HeaderMap::new();
+        trailers.insert("checksum", "abc".parse().unwrap());
+        tx.send_trailers(trailers).expect("send_trailers");
+        drop(tx);
+
+        let mut body = body
+            .map_data(|chunk| Chunk::from(chunk.bytes().to_ascii_uppercase()))
+            .map_err(|_err| "mapped error")
+            .boxed();
+        assert_eq!(body.content_length(), Some(5));
+
+        let data = poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await;
+        assert_eq!(data.expect("data").expect("data ok").bytes(), b"HELLO");
+        assert!(poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await.is_none());
+
+        let trailers = poll_fn(|cx| Pin::new(&mut body).poll_trailers(cx)).await;
+        assert_eq!(trailers.expect("trailers").expect("trailers ok")["checksum"], "abc");
+    }
+
+    #[tokio::test]
+    async fn either_boxes_errors() {
+        let mut body: Either<Body, Full<Chunk>> = Either::Left(Body::wrap_stream(
+            futures_util::stream::iter(vec![Err::<Chunk, _>("oh no")])
+        ));
+        let err = poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await
+            .expect("some")
+            .expect_err("body error");
+        assert_eq!(err.to_string(), "error reading a body from connection: oh no");
+
+        let body: Either<Body, Full<Chunk>> = Either::Right(Full::new(Chunk::from("hello")));
+        assert_eq!(body.content_length(), Some(5));
+    }
+}
@@ -0,0 +1,250 @@
+use std::cmp;
+use std::fmt;
+use std::fs;
+use std::io::{self, Seek, SeekFrom};
+use std::iter;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+
+use bytes::BytesMut;
+use tokio_io::AsyncRead;
+
+use crate::common::{Pin, Poll, task};
+use super::internal::{FileRegion, FileRegionArg, FileRegionRet};
+use super::{Chunk, Payload};
+
+/// The most bytes read from the file at once.
+const READ_BUF_SIZE: usize = 64 * 1024;
+
+/// A `Payload` of a file, or a range of one.
+///
+/// It reports the exact `content_length` of the bytes it sends. The file
+/// is read with `tokio-fs`, unless the server sends it straight to the
+/// socket: on Linux, a `Server` built with `http1_sendfile(true)` uses
+/// `sendfile(2)` on the plain TCP streams it accepts, so the bytes are
+/// never copied into `Chunk`s.
+///
+/// # Example
+///
+/// ```no_run
+/// use hyper::{Response, StatusCode};
+/// use hyper::body::{FileBody, Payload};
+/// use hyper::header::CONTENT_RANGE;
+///
+/// # fn run() -> std::io::Result<()> {
+/// // send the first 100 bytes of a file.
+/// let body = FileBody::open("static/video.mp4")?;
+/// let len = body.content_length().unwrap_or(0);
+/// let res = Response::builder()
+///     .status(StatusCode::PARTIAL_CONTENT)
+///     .header(CONTENT_RANGE, format!("bytes 0-99/{}", len))
+///     .body(body.range(0..100)?)
+///     .unwrap();
+/// # drop(res);
+/// # Ok(())
+/// # }
+/// ```
+#[must_use = "streams do nothing unless polled"]
+pub struct FileBody {
+    state: State,
+    /// Where the rest of the body starts in the file.
+    offset: u64,
+    remaining: u64,
+}
+
+enum State {
+    /// Nothing has been read, so the rest can still be sent as a region.
+    Idle(fs::File),
+    /// Reading, into a buffer kept between polls, whose bytes are all
+    /// initialized.
+    Reading(tokio_fs::File, BytesMut),
+    Done,
+}
+
+impl FileBody {
+    /// Creates a body of the whole `file`.
+    ///
+    /// The length is taken from the file's metadata now, and the body is
+    /// sent from the start of the file, wherever its cursor is.
+    pub fn new(file: fs::File) -> io::Result<FileBody> {
+        let len = file.metadata()?.len();
+        Ok(FileBody {
+            state: State::Idle(file),
+            offset: 0,
+            remaining: len,
+        })
+    }
+
+    /// Opens the file at `path`, and creates a body of all of it.
+    ///
+    /// Opening the file is done in place; only reading it is asynchronous.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<FileBody> {
+        FileBody::new(fs::File::open(path)?)
+    }
+
+    /// Narrows this body to a `range` of the bytes it would send.
+    ///
+    /// The bounds are relative to the start of this body, so ranges can be
+    /// narrowed again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if `range` doesn't fit in this
+    /// body, or if it has already started reading.
+    pub fn range<R: RangeBounds<u64>>(mut self, range: R) -> io::Result<FileBody> {
+        if let State::Reading(..) = self.state {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "file body has already started reading"));
+        }
+        let start = match range.start_bound() {
+            Bound::Included(&start) => Some(start),
+            Bound::Excluded(&start) => start.checked_add(1),
+            Bound::Unbounded => Some(0),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.checked_add(1),
+            Bound::Excluded(&end) => Some(end),
+            Bound::Unbounded => Some(self.remaining),
+        };
+        match (start, end) {
+            (Some(start), Some(end)) if start <= end && end <= self.remaining => {
+                self.offset += start;
+                self.remaining = end - start;
+                Ok(self)
+            },
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "range is out of bounds of the file body")),
+        }
+    }
+
+    fn poll_start(&mut self) -> io::Result<()> {
+        if let State::Idle(_) = self.state {
+            let mut file = match mem::replace(&mut self.state, State::Done) {
+                State::Idle(file) => file,
+                _ => unreachable!("file body was just idle"),
+            };
+            file.seek(SeekFrom::Start(self.offset))?;
+            self.state = State::Reading(tokio_fs::File::from_std(file), BytesMut::new());
+        }
+        Ok(())
+    }
+}
+
+impl Payload for FileBody {
+    type Data = Chunk;
+    type Error = io::Error;
+
+    fn poll_data(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let me = &mut *self;
+        if me.remaining == 0 {
+            me.state = State::Done;
+            return Poll::Ready(None);
+        }
+        if let Err(err) = me.poll_start() {
+            return Poll::Ready(Some(Err(err)));
+        }
+        let (file, buf) = match me.state {
+            State::Reading(ref mut file, ref mut buf) => (file, buf),
+            _ => return Poll::Ready(None),
+        };
+
+        let want = cmp::min(me.remaining, READ_BUF_SIZE as u64) as usize;
+        if buf.len() < want {
+            // only zero what wasn't used by a previous read
+            let more = want - buf.len();
+            buf.reserve(more);
+            buf.extend(iter::repeat(0).take(more));
+        }
+        let n = match ready!(Pin::new(file).poll_read(cx, &mut buf[..want])) {
+            Ok(0) => {
+                me.state = State::Done;
+                return Poll::Ready(Some(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "file ended before the end of the body",
+                ))));
+            },
+            Ok(n) => n,
+            Err(err) => return Poll::Ready(Some(Err(err))),
+        };
+        let chunk = buf.split_to(n).freeze();
+        me.offset += n as u64;
+        me.remaining -= n as u64;
+        if me.remaining == 0 {
+            me.state = State::Done;
+        }
+        Poll::Ready(Some(Ok(Chunk::from(chunk))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining == 0
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        Some(self.remaining)
+    }
+
+    #[doc(hidden)]
+    fn __hyper_file_region(&mut self, _: FileRegionArg) -> FileRegionRet {
+        if self.remaining == 0 {
+            return FileRegionRet(None);
+        }
+        match mem::replace(&mut self.state, State::Done) {
+            State::Idle(file) => {
+                let region = FileRegion {
+                    file,
+                    offset: self.offset,
+                    len: self.remaining,
+                };
+                self.offset += self.remaining;
+                self.remaining = 0;
+                FileRegionRet(Some(region))
+            },
+            state => {
+                self.state = state;
+                FileRegionRet(None)
+            },
+        }
+    }
+}
+
+impl fmt::Debug for FileBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FileBody")
+            .field("offset", &self.offset)
+            .field("remaining", &self.remaining)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+
+    use super::super::internal::FileRegionArg;
+    use super::super::Payload;
+    use super::FileBody;
+
+    #[test]
+    fn range_narrows_length_and_region() {
+        let path = ::std::env::temp_dir().join(format!("hyper-file-body-{}", ::std::process::id()));
+        fs::File::create(&path)
+            .and_then(|mut file| file.write_all(b"hello world"))
+            .expect("write temp file");
+
+        let body = FileBody::open(&path).expect("open");
+        assert_eq!(body.content_length(), Some(11));
+
+        let mut body = body.range(6..).expect("6..").range(..=3).expect("..=3");
+        assert_eq!(body.content_length(), Some(4));
+        assert!(FileBody::open(&path).expect("open").range(2..12).is_err());
+        assert!(FileBody::open(&path).expect("open").range(5..4).is_err());
+
+        let region = body.__hyper_file_region(FileRegionArg(())).0.expect("region");
+        assert_eq!((region.offset, region.len), (6, 4));
+        assert!(body.is_end_stream());
+        assert!(body.__hyper_file_region(FileRegionArg(())).0.is_none());
+
+        drop(region);
+        fs::remove_file(&path).expect("remove temp file");
+    }
+}
@@ -0,0 +1,203 @@
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::fmt;
+use std::marker::PhantomData;
+
+use bytes::{Buf, Bytes};
+
+use crate::common::{Pin, Poll, Unpin, task};
+use super::internal::{FullDataArg, FullDataRet};
+use super::{Chunk, Payload};
+
+/// A `Payload` of a single buffer, known up front.
+///
+/// It reports its exact `content_length`, and is at its end once the
+/// buffer has been polled.
+#[must_use = "streams do nothing unless polled"]
+#[derive(Clone, Debug)]
+pub struct Full<D> {
+    data: Option<D>,
+}
+
+impl<D: Buf> Full<D> {
+    /// Creates a `Full` body of `data`.
+    pub fn new(data: D) -> Full<D> {
+        let data = if data.has_remaining() {
+            Some(data)
+        } else {
+            None
+        };
+        Full { data }
+    }
+}
+
+// The buffer is never pinned.
+impl<D> Unpin for Full<D> {}
+
+impl<D> Payload for Full<D>
+where
+    D: Buf + Send + 'static,
+{
+    type Data = D;
+    type Error = Infallible;
+
+    fn poll_data(mut self: Pin<&mut Self>, _cx: &mut task::Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Poll::Ready(self.data.take().map(Ok))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_none()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        Some(self.data.as_ref().map_or(0, |data| data.remaining() as u64))
+    }
+
+    #[doc(hidden)]
+    fn __hyper_full_data(&mut self, _: FullDataArg) -> FullDataRet<Self::Data> {
+        FullDataRet(self.data.take())
+    }
+}
+
+impl From<Chunk> for Full<Chunk> {
+    #[inline]
+    fn from(chunk: Chunk) -> Full<Chunk> {
+        Full::new(chunk)
+    }
+}
+
+impl From<Bytes> for Full<Chunk> {
+    #[inline]
+    fn from(bytes: Bytes) -> Full<Chunk> {
+        Full::new(Chunk::from(bytes))
+    }
+}
+
+impl From<Vec<u8>> for Full<Chunk> {
+    #[inline]
+    fn from(vec: Vec<u8>) -> Full<Chunk> {
+        Full::new(Chunk::from(vec))
+    }
+}
+
+impl From<&'static [u8]> for Full<Chunk> {
+    #[inline]
+    fn from(slice: &'static [u8]) -> Full<Chunk> {
+        Full::new(Chunk::from(slice))
+    }
+}
+
+impl From<Cow<'static, [u8]>> for Full<Chunk> {
+    #[inline]
+    fn from(cow: Cow<'static, [u8]>) -> Full<Chunk> {
+        match cow {
+            Cow::Borrowed(b) => Full::from(b),
+            Cow::Owned(o) => Full::from(o),
+        }
+    }
+}
+
+impl From<String> for Full<Chunk> {
+    #[inline]
+    fn from(s: String) -> Full<Chunk> {
+        Full::new(Chunk::from(s))
+    }
+}
+
+impl From<&'static str> for Full<Chunk> {
+    #[inline]
+    fn from(slice: &'static str) -> Full<Chunk> {
+        Full::new(Chunk::from(slice))
+    }
+}
+
+/// A `Payload` that is always empty.
+///
+/// It reports a `content_length` of 0, and is always at its end.
+#[must_use = "streams do nothing unless polled"]
+pub struct Empty<D> {
+    _marker: PhantomData<fn() -> D>,
+}
+
+impl<D> Empty<D> {
+    /// Creates an `Empty` body.
+    pub fn new() -> Empty<D> {
+        Empty {
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<D> Default for Empty<D> {
+    fn default() -> Empty<D> {
+        Empty::new()
+    }
+}
+
+impl<D> Clone for Empty<D> {
+    fn clone(&self) -> Empty<D> {
+        Empty::new()
+    }
+}
+
+impl<D> Copy for Empty<D> {}
+
+impl<D> fmt::Debug for Empty<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Empty").finish()
+    }
+}
+
+impl<D> Payload for Empty<D>
+where
+    D: Buf + Send + 'static,
+{
+    type Data = D;
+    type Error = Infallible;
+
+    fn poll_data(self: Pin<&mut Self>, _cx: &mut task::Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Poll::Ready(None)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        true
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        Some(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::future::poll_fn;
+
+    use crate::Chunk;
+    use crate::common::Pin;
+    use super::super::Payload;
+    use super::{Empty, Full};
+
+    #[tokio::test]
+    async fn full_reports_length_and_end() {
+        let mut body: Full<Chunk> = Full::from("hello");
+        assert_eq!(body.content_length(), Some(5));
+        assert!(!body.is_end_stream());
+
+        let data = poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await;
+        assert!(data.is_some());
+        assert!(body.is_end_stream());
+        assert_eq!(body.content_length(), Some(0));
+
+        let body: Full<Chunk> = Full::from("");
+        assert!(body.is_end_stream());
+        assert_eq!(body.content_length(), Some(0));
+    }
+
+    #[tokio::test]
+    async fn empty_is_always_done() {
+        let mut body = Empty::<Chunk>::new();
+        assert!(body.is_end_stream());
+        assert_eq!(body.content_length(), Some(0));
+        assert!(poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await.is_none());
+    }
+}
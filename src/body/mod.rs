@@ -14,19 +14,44 @@
 //!  and returned by hyper as a "receive stream" (so, for server requests and
 //!  client responses). It is also a decent default implementation if you don't
 //!  have very custom needs of your send streams.
+//!
+//! There are also [`to_bytes`](body::to_bytes) and
+//! [`aggregate`](body::aggregate), to buffer a whole body up to a limit.
+//!
+//! Other `Payload`s can be built from [`Full`](body::Full) and
+//! [`Empty`](body::Empty), combined with [`Either`](body::Either), and
+//! transformed with the adapters of the `Payload` trait, like
+//! [`boxed`](body::Payload::boxed). With the `runtime` feature,
+//! [`Throttle`](body::Throttle) limits how fast a body is sent, and
+//! [`FileBody`](body::FileBody) sends a file, or a range of one.
+//! [`Progress`](body::Progress) reports how much of a body has been sent or
+//! received.
+pub use self::aggregate::{aggregate, to_bytes};
 pub use self::body::{Body, Sender};
 pub use self::chunk::Chunk;
+pub use self::combinators::{BoxBody, Either, MapData, MapErr};
+pub use self::full::{Empty, Full};
 pub use self::payload::Payload;
+pub use self::progress::{Progress, Transfer};
+#[cfg(feature = "runtime")] pub use self::file::FileBody;
+#[cfg(feature = "runtime")] pub use self::throttle::Throttle;
 
+mod aggregate;
 mod body;
 mod chunk;
+mod combinators;
+#[cfg(feature = "runtime")] mod file;
+mod full;
 mod payload;
+mod progress;
+#[cfg(feature = "runtime")] mod throttle;
 
 // The full_data API is not stable, so these types are to try to prevent
 // users from being able to:
 //
-// - Implment `__hyper_full_data` on their own Payloads.
-// - Call `__hyper_full_data` on any Payload.
+// - Implment `__hyper_full_data` or `__hyper_file_region` on their own
+//   Payloads.
+// - Call `__hyper_full_data` or `__hyper_file_region` on any Payload.
 //
 // That's because to implement it, they need to name these types, and
 // they can't because they aren't exported. And to call it, they would
@@ -36,6 +61,19 @@ pub(crate) mod internal {
     pub struct FullDataArg(pub(crate) ());
     #[allow(missing_debug_implementations)]
     pub struct FullDataRet<B>(pub(crate) Option<B>);
+
+    #[allow(missing_debug_implementations)]
+    pub struct FileRegionArg(pub(crate) ());
+    #[allow(missing_debug_implementations)]
+    pub struct FileRegionRet(pub(crate) Option<FileRegion>);
+
+    /// The `len` bytes of `file` starting at `offset`.
+    #[derive(Debug)]
+    pub struct FileRegion {
+        pub(crate) file: ::std::fs::File,
+        pub(crate) offset: u64,
+        pub(crate) len: u64,
+    }
 }
 
 fn _assert_send_sync() {
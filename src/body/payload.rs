@@ -4,7 +4,8 @@ use bytes::Buf;
 use http::HeaderMap;
 
 use crate::common::{Pin, Poll, task};
-use super::internal::{FullDataArg, FullDataRet};
+use super::internal::{FileRegionArg, FileRegionRet, FullDataArg, FullDataRet};
+use super::{BoxBody, MapData, MapErr};
 
 /// This trait represents a streaming body of a `Request` or `Response`.
 ///
@@ -27,7 +28,8 @@ pub trait Payload: Send + 'static {
     ///
     /// This should **only** be called after `poll_data` has ended.
     ///
-    /// Note: Trailers aren't currently used for HTTP/1, only for HTTP/2.
+    /// Note: HTTP/1 can only send trailers after a chunked body, so they're
+    /// dropped when the body has a known length.
     fn poll_trailers(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<HeaderMap, Self::Error>>> {
         Poll::Ready(None)
     }
@@ -59,6 +61,34 @@ pub trait Payload: Send + 'static {
         None
     }
 
+    /// Maps this body's data buffers to a different type.
+    fn map_data<F, B>(self, f: F) -> MapData<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Data) -> B + Send + 'static,
+        B: Buf + Send,
+    {
+        MapData::new(self, f)
+    }
+
+    /// Maps this body's errors to a different type.
+    fn map_err<F, E>(self, f: F) -> MapErr<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Error) -> E + Send + 'static,
+        E: Into<Box<dyn StdError + Send + Sync>>,
+    {
+        MapErr::new(self, f)
+    }
+
+    /// Turns this body into a `BoxBody`, erasing its type.
+    fn boxed(self) -> BoxBody<Self::Data, Self::Error>
+    where
+        Self: Sized,
+    {
+        BoxBody::new(self)
+    }
+
     // This API is unstable, and is impossible to use outside of hyper. Some
     // form of it may become stable in a later version.
     //
@@ -68,6 +98,23 @@ pub trait Payload: Send + 'static {
     fn __hyper_full_data(&mut self, _: FullDataArg) -> FullDataRet<Self::Data> {
         FullDataRet(None)
     }
+
+    // Returns a body that sends the same data as this one from the start,
+    // if possible. The `Client` uses this to retry requests.
+    #[doc(hidden)]
+    fn __hyper_replay(&self) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    // Takes the rest of this body as a region of a file, if it is one, so
+    // that it can be sent without copying it through `Self::Data`.
+    #[doc(hidden)]
+    fn __hyper_file_region(&mut self, _: FileRegionArg) -> FileRegionRet {
+        FileRegionRet(None)
+    }
 }
 
 /*
@@ -0,0 +1,341 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::time::Instant;
+
+use bytes::Buf;
+use http::HeaderMap;
+use pin_utils::{unsafe_pinned, unsafe_unpinned};
+#[cfg(feature = "runtime")]
+use tokio_timer::Delay;
+
+#[cfg(feature = "runtime")]
+use crate::common::Future;
+use crate::common::{Pin, Poll, Unpin, task};
+use crate::ext::OnProgress;
+use super::Payload;
+
+/// How much of a body has been transferred, given to an
+/// [`OnProgress`](::ext::OnProgress) callback.
+#[derive(Clone, Copy, Debug)]
+pub struct Transfer {
+    transferred: u64,
+    total: Option<u64>,
+    started_at: Instant,
+    last_data_at: Option<Instant>,
+    is_end_stream: bool,
+}
+
+impl Transfer {
+    /// Returns how many bytes of data have been transferred.
+    pub fn transferred(&self) -> u64 {
+        self.transferred
+    }
+
+    /// Returns how many bytes the body was expected to have, from its
+    /// `content_length` hint when observing it started.
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+
+    /// Returns when observing the body started.
+    pub fn started_at(&self) -> Instant {
+        self.started_at
+    }
+
+    /// Returns when the last data was transferred, if any was.
+    pub fn last_data_at(&self) -> Option<Instant> {
+        self.last_data_at
+    }
+
+    /// Returns true once the body has ended.
+    pub fn is_end_stream(&self) -> bool {
+        self.is_end_stream
+    }
+}
+
+/// Reports the progress of a body to an `OnProgress`, and checks its stall
+/// timeout.
+///
+/// Once the body has stalled, its stall error is returned once, and the
+/// body is then ended: check `is_stalled` before polling it again.
+pub(crate) struct Observer {
+    on_progress: OnProgress,
+    transfer: Transfer,
+    stalled: bool,
+    #[cfg(feature = "runtime")]
+    stall: Option<Delay>,
+}
+
+impl Observer {
+    pub(crate) fn new(on_progress: OnProgress, total: Option<u64>) -> Observer {
+        Observer {
+            on_progress,
+            transfer: Transfer {
+                transferred: 0,
+                total,
+                started_at: Instant::now(),
+                last_data_at: None,
+                is_end_stream: false,
+            },
+            stalled: false,
+            #[cfg(feature = "runtime")]
+            stall: None,
+        }
+    }
+
+    /// Returns true once the body has failed with its stall error, so it
+    /// shouldn't be polled anymore.
+    pub(crate) fn is_stalled(&self) -> bool {
+        self.stalled
+    }
+
+    /// Records the result of polling the body for data, or fails it if it
+    /// has stalled.
+    pub(crate) fn observe<D, E>(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        polled: Poll<Option<Result<D, E>>>,
+        is_end_stream: bool,
+    ) -> Poll<Option<Result<D, E>>>
+    where
+        D: Buf,
+        E: From<crate::Error>,
+    {
+        match polled {
+            Poll::Ready(Some(Ok(data))) => {
+                self.transfer.transferred += data.remaining() as u64;
+                self.transfer.last_data_at = Some(Instant::now());
+                self.transfer.is_end_stream = is_end_stream;
+                self.reset_stall();
+                self.on_progress.call(&self.transfer);
+                Poll::Ready(Some(Ok(data)))
+            },
+            Poll::Ready(None) => {
+                if !self.transfer.is_end_stream {
+                    self.transfer.is_end_stream = true;
+                    self.on_progress.call(&self.transfer);
+                }
+                Poll::Ready(None)
+            },
+            Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(err))),
+            Poll::Pending => {
+                let err = ready!(self.poll_stall(cx));
+                self.stalled = true;
+                self.reset_stall();
+                Poll::Ready(Some(Err(err.into())))
+            },
+        }
+    }
+
+    #[cfg(feature = "runtime")]
+    fn poll_stall(&mut self, cx: &mut task::Context<'_>) -> Poll<crate::Error> {
+        let timeout = match self.on_progress.get_stall_timeout() {
+            Some(timeout) if !self.transfer.is_end_stream => timeout,
+            _ => return Poll::Pending,
+        };
+        // counted from when the body started waiting, so that a consumer
+        // slow to poll again isn't blamed on the body
+        let stall = self.stall
+            .get_or_insert_with(|| Delay::new(Instant::now() + timeout));
+        ready!(Pin::new(stall).poll(cx));
+        debug!("body stalled for {:?}", timeout);
+        Poll::Ready(crate::Error::new_body_stalled())
+    }
+
+    // A stall timeout can't be set without the runtime.
+    #[cfg(not(feature = "runtime"))]
+    fn poll_stall(&mut self, _cx: &mut task::Context<'_>) -> Poll<crate::Error> {
+        Poll::Pending
+    }
+
+    fn reset_stall(&mut self) {
+        #[cfg(feature = "runtime")]
+        {
+            self.stall = None;
+        }
+    }
+}
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Observer")
+            .field("on_progress", &self.on_progress)
+            .field("transfer", &self.transfer)
+            .finish()
+    }
+}
+
+/// A `Payload` that reports its progress to an
+/// [`OnProgress`](::ext::OnProgress).
+///
+/// The expected total is the `content_length` hint of the wrapped body when
+/// it is wrapped. Errors of the wrapped body are boxed, and a stalled body
+/// fails with a `hyper::Error`.
+///
+/// # Example
+///
+/// ```
+/// use hyper::Body;
+/// use hyper::body::Progress;
+/// use hyper::ext::OnProgress;
+///
+/// let upload = Progress::new(Body::from("hello"), OnProgress::new(|transfer| {
+///     println!("sent {} bytes", transfer.transferred());
+/// }));
+/// # drop(upload);
+/// ```
+#[must_use = "streams do nothing unless polled"]
+pub struct Progress<B> {
+    inner: B,
+    observer: Observer,
+}
+
+impl<B: Payload> Progress<B> {
+    unsafe_pinned!(inner: B);
+    unsafe_unpinned!(observer: Observer);
+
+    /// Wraps a body, to report its progress to `on_progress`.
+    pub fn new(body: B, on_progress: OnProgress) -> Progress<B> {
+        let total = body.content_length();
+        Progress {
+            inner: body,
+            observer: Observer::new(on_progress, total),
+        }
+    }
+
+    /// Consumes this `Progress`, returning the underlying body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B: Unpin> Unpin for Progress<B> {}
+
+impl<B: Payload> Payload for Progress<B> {
+    type Data = B::Data;
+    type Error = Box<dyn StdError + Send + Sync>;
+
+    fn poll_data(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        if self.observer.is_stalled() {
+            return Poll::Ready(None);
+        }
+        let polled = self.as_mut()
+            .inner()
+            .poll_data(cx)
+            .map(|opt| opt.map(|res| res.map_err(Into::into)));
+        let is_end_stream = self.inner.is_end_stream();
+        self.observer().observe(cx, polled, is_end_stream)
+    }
+
+    fn poll_trailers(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<HeaderMap, Self::Error>>> {
+        self.inner()
+            .poll_trailers(cx)
+            .map(|opt| opt.map(|res| res.map_err(Into::into)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.inner.content_length()
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for Progress<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Progress")
+            .field("inner", &self.inner)
+            .field("observer", &self.observer)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use futures_util::future::poll_fn;
+    use tokio_timer::Delay;
+
+    use crate::Body;
+    use crate::body::Payload;
+    use crate::common::Pin;
+    use crate::ext::OnProgress;
+    use super::super::to_bytes;
+    use super::Progress;
+
+    #[tokio::test]
+    async fn progress_reports_each_chunk_and_end() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen2 = seen.clone();
+        let on_progress = OnProgress::new(move |transfer| {
+            seen2.lock().unwrap().push((transfer.transferred(), transfer.total(), transfer.is_end_stream()));
+        });
+
+        let (mut tx, body) = Body::new_channel(Some(10), false);
+        tokio::spawn(async move {
+            for chunk in &["hello", "world"] {
+                poll_fn(|cx| tx.poll_ready(cx)).await.expect("poll_ready");
+                tx.send_data((*chunk).into()).expect("send_data");
+            }
+        });
+
+        let bytes = to_bytes(Progress::new(body, on_progress), 1024).await.expect("to_bytes");
+        assert_eq!(bytes, "helloworld");
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.first(), Some(&(5, Some(10), false)));
+        assert_eq!(seen.last().map(|last| (last.0, last.2)), Some((10, true)));
+    }
+
+    #[tokio::test]
+    async fn stall_timeout_fails_the_body() {
+        let on_progress = OnProgress::new(|_| ())
+            .stall_timeout(Duration::from_millis(20));
+        let (_tx, body) = Body::channel();
+
+        let err = to_bytes(Progress::new(body, on_progress), 1024).await.expect_err("stalled");
+        assert!(err.is_body_stalled(), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn stall_timeout_errors_once_then_ends() {
+        let on_progress = OnProgress::new(|_| ())
+            .stall_timeout(Duration::from_millis(20));
+        let (mut tx, body) = Body::channel();
+        let mut body = Progress::new(body, on_progress);
+
+        let polled = poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await;
+        let err = polled.expect("stall error").expect_err("stalled");
+        assert!(err.downcast_ref::<crate::Error>().expect("hyper::Error").is_body_stalled());
+
+        // data arriving late isn't delivered, and no error repeats
+        let _ = tx.send_data("late".into());
+        assert!(poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await.is_none());
+        assert!(poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn stall_timeout_ignores_slow_consumer() {
+        let on_progress = OnProgress::new(|_| ())
+            .stall_timeout(Duration::from_millis(50));
+        let (mut tx, body) = Body::channel();
+        let mut body = Progress::new(body, on_progress);
+
+        tx.send_data("hello".into()).expect("send_data");
+        let chunk = poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await;
+        assert_eq!(chunk.expect("chunk").expect("ok").as_ref(), b"hello");
+
+        // the consumer takes longer than the timeout to poll again, and the
+        // body then delivers within the timeout
+        Delay::new(Instant::now() + Duration::from_millis(100)).await;
+        tokio::spawn(async move {
+            Delay::new(Instant::now() + Duration::from_millis(10)).await;
+            poll_fn(|cx| tx.poll_ready(cx)).await.expect("poll_ready");
+            tx.send_data("world".into()).expect("send_data");
+        });
+        let chunk = poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await;
+        assert_eq!(chunk.expect("chunk").expect("not stalled").as_ref(), b"world");
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+
+use bytes::Buf;
+use http::HeaderMap;
+use pin_utils::{unsafe_pinned, unsafe_unpinned};
+
+use crate::common::{Pin, Poll, Unpin, task};
+use crate::common::rate::Pacer;
+use crate::rt::RateLimit;
+use super::Payload;
+
+/// A `Payload` that is sent no faster than a `RateLimit` allows.
+///
+/// Each buffer of the wrapped body is taken from the limit as a whole, so
+/// large buffers make the rate more bursty.
+///
+/// # Example
+///
+/// ```
+/// use hyper::Body;
+/// use hyper::body::Throttle;
+/// use hyper::rt::RateLimit;
+///
+/// // every body given a clone of `limit` shares the same 1MB/s.
+/// let limit = RateLimit::new(1024 * 1024);
+/// let body = Throttle::new(Body::from("hello"), limit.clone());
+/// # drop(body);
+/// ```
+#[must_use = "streams do nothing unless polled"]
+pub struct Throttle<B> {
+    inner: B,
+    pacer: Pacer,
+}
+
+impl<B> Throttle<B> {
+    unsafe_pinned!(inner: B);
+    unsafe_unpinned!(pacer: Pacer);
+
+    /// Wraps a body, to be sent no faster than `limit` allows.
+    pub fn new(body: B, limit: RateLimit) -> Throttle<B> {
+        Throttle {
+            inner: body,
+            pacer: Pacer::new(limit),
+        }
+    }
+
+    /// Consumes this `Throttle`, returning the underlying body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B: Unpin> Unpin for Throttle<B> {}
+
+impl<B: Payload> Payload for Throttle<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        ready!(self.as_mut().pacer().poll_acquire(cx, 1));
+        let data = ready!(self.as_mut().inner().poll_data(cx));
+        if let Some(Ok(ref buf)) = data {
+            self.pacer().consume(buf.remaining());
+        }
+        Poll::Ready(data)
+    }
+
+    fn poll_trailers(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<HeaderMap, Self::Error>>> {
+        self.inner().poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.inner.content_length()
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for Throttle<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Throttle")
+            .field("inner", &self.inner)
+            .field("pacer", &self.pacer)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::Body;
+    use crate::rt::RateLimit;
+    use super::super::to_bytes;
+    use super::Throttle;
+
+    #[tokio::test]
+    async fn throttle_waits_for_the_limit() {
+        let chunks: Vec<Result<_, ::std::io::Error>> = vec![Ok("hello"), Ok("hello"), Ok("hello")];
+        let body = Body::wrap_stream(futures_util::stream::iter(chunks));
+
+        // 5 bytes are available at once, then 1 more every 10ms, so the
+        // last 2 chunks wait for 10ms and 50ms.
+        let start = Instant::now();
+        let body = Throttle::new(body, RateLimit::with_burst(100, 5));
+        let bytes = to_bytes(body, 1024).await.expect("to_bytes");
+        assert_eq!(bytes, "hellohellohello");
+        assert!(start.elapsed() >= Duration::from_millis(50), "{:?}", start.elapsed());
+    }
+}
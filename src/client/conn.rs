@@ -11,6 +11,7 @@ use std::fmt;
 use std::marker::PhantomData;
 use std::mem;
 use std::sync::Arc;
+use std::time::Duration;
 
 use bytes::Bytes;
 use futures_util::future::{self, Either, FutureExt as _};
@@ -19,6 +20,9 @@ use tokio_io::{AsyncRead, AsyncWrite};
 
 use crate::body::Payload;
 use crate::common::{Exec, Future, Pin, Poll, task};
+use crate::common::io::Throttled;
+use crate::common::rate::RateLimits;
+#[cfg(feature = "runtime")] use crate::rt::RateLimit;
 use crate::upgrade::Upgraded;
 use crate::proto;
 use super::dispatch;
@@ -77,8 +81,14 @@ pub struct Builder {
     h1_title_case_headers: bool,
     h1_read_buf_exact_size: Option<usize>,
     h1_max_buf_size: Option<usize>,
+    h1_head_limits: proto::h1::HeadLimits,
+    h1_preserve_header_case: bool,
+    h1_leniency: proto::h1::Leniency,
+    h1_expect_continue_timeout: Option<Duration>,
+    h1_pipeline_depth: usize,
     http2: bool,
     h2_builder: h2::client::Builder,
+    rate_limits: RateLimits,
 }
 
 /// A future returned by `SendRequest::send_request`.
@@ -432,8 +442,14 @@ impl Builder {
             h1_read_buf_exact_size: None,
             h1_title_case_headers: false,
             h1_max_buf_size: None,
+            h1_head_limits: proto::h1::HeadLimits::default(),
+            h1_preserve_header_case: false,
+            h1_leniency: proto::h1::Leniency::default(),
+            h1_expect_continue_timeout: Some(Duration::from_secs(1)),
+            h1_pipeline_depth: 1,
             http2: false,
             h2_builder,
+            rate_limits: RateLimits::default(),
         }
     }
 
@@ -474,6 +490,112 @@ impl Builder {
         self
     }
 
+    pub(super) fn h1_preserve_header_case(&mut self, enabled: bool) -> &mut Builder {
+        self.h1_preserve_header_case = enabled;
+        self
+    }
+
+    pub(super) fn h1_allow_obsolete_multiline_headers(&mut self, enabled: bool) -> &mut Builder {
+        self.h1_leniency.obs_fold = enabled;
+        self
+    }
+
+    pub(super) fn h1_allow_spaces_before_header_colon(&mut self, enabled: bool) -> &mut Builder {
+        self.h1_leniency.space_before_colon = enabled;
+        self
+    }
+
+    pub(super) fn h1_allow_bare_lf(&mut self, enabled: bool) -> &mut Builder {
+        self.h1_leniency.bare_lf = enabled;
+        self
+    }
+
+    pub(super) fn h1_allow_invalid_reason_phrase(&mut self, enabled: bool) -> &mut Builder {
+        self.h1_leniency.invalid_reason = enabled;
+        self
+    }
+
+    pub(super) fn h1_expect_continue_timeout(&mut self, timeout: Option<Duration>) -> &mut Builder {
+        self.h1_expect_continue_timeout = timeout;
+        self
+    }
+
+    /// Set the maximum number of headers in an HTTP/1 response.
+    ///
+    /// Responses with more headers fail with an error for which
+    /// `is_too_many_headers()` is true.
+    ///
+    /// Default is 100.
+    pub fn http1_max_headers(&mut self, max: usize) -> &mut Builder {
+        self.h1_head_limits.max_headers = max;
+        self
+    }
+
+    /// Set the maximum size in bytes of an HTTP/1 response head, including
+    /// the status line and all headers.
+    ///
+    /// Default is no limit, other than the read buffer size.
+    pub fn http1_max_head_size(&mut self, max: usize) -> &mut Builder {
+        self.h1_head_limits.max_head_size = Some(max);
+        self
+    }
+
+    /// Set the maximum size in bytes of a single HTTP/1 response header value.
+    ///
+    /// Responses with a longer value fail with an error for which
+    /// `is_header_value_too_long()` is true.
+    ///
+    /// Default is no limit, other than the read buffer size.
+    pub fn http1_max_header_value_size(&mut self, max: usize) -> &mut Builder {
+        self.h1_head_limits.max_header_value_size = Some(max);
+        self
+    }
+
+    /// Sets how many HTTP/1 requests can be sent before their responses
+    /// are received.
+    ///
+    /// Above 1, idempotent requests are pipelined: they're written one
+    /// after another on the connection, and the responses are matched to
+    /// them in order. A request that isn't idempotent, such as a `POST`,
+    /// waits for the earlier responses, and no request is pipelined after
+    /// it until its response is received.
+    ///
+    /// If the connection fails or closes, the requests still waiting for
+    /// their responses fail with an error for which `is_canceled()` is
+    /// true, and they can be sent again.
+    ///
+    /// Default is 1, meaning requests aren't pipelined.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `depth` is 0.
+    pub fn http1_pipeline_depth(&mut self, depth: usize) -> &mut Builder {
+        assert!(depth > 0, "the pipeline depth must be at least 1");
+        self.h1_pipeline_depth = depth;
+        self
+    }
+
+    /// Set a [`RateLimit`](crate::rt::RateLimit) of how fast bytes are read
+    /// from the connection.
+    ///
+    /// Default is no limit (`None`).
+    #[cfg(feature = "runtime")]
+    pub fn read_rate_limit(&mut self, limit: impl Into<Option<RateLimit>>) -> &mut Builder {
+        self.rate_limits.read = limit.into();
+        self
+    }
+
+    /// Set a [`RateLimit`](crate::rt::RateLimit) of how fast bytes are
+    /// written to the connection, such as to keep uploads from saturating a
+    /// link.
+    ///
+    /// Default is no limit (`None`).
+    #[cfg(feature = "runtime")]
+    pub fn write_rate_limit(&mut self, limit: impl Into<Option<RateLimit>>) -> &mut Builder {
+        self.rate_limits.write = limit.into();
+        self
+    }
+
     /// Sets whether HTTP2 is required.
     ///
     /// Default is false.
@@ -532,10 +654,22 @@ impl Builder {
                 if let Some(max) = opts.h1_max_buf_size {
                     conn.set_max_buf_size(max);
                 }
-                let cd = proto::h1::dispatch::Client::new(rx);
+                conn.set_head_limits(opts.h1_head_limits);
+                if opts.h1_preserve_header_case {
+                    conn.set_preserve_header_case();
+                }
+                conn.set_leniency(opts.h1_leniency);
+                conn.set_expect_continue_timeout(opts.h1_expect_continue_timeout);
+                conn.set_rate_limits(opts.rate_limits.clone());
+                let mut cd = proto::h1::dispatch::Client::new(rx);
+                if opts.h1_pipeline_depth > 1 {
+                    conn.set_pipeline();
+                    cd.set_pipeline_depth(opts.h1_pipeline_depth);
+                }
                 let dispatch = proto::h1::Dispatcher::new(cd, conn);
                 Either::Left(dispatch)
             } else {
+                let io = Throttled::new(io, opts.rate_limits.clone());
                 let h2 = proto::h2::client::handshake(io, rx, &opts.h2_builder, opts.exec.clone())
                     .await?;
                 Either::Right(h2)
@@ -67,12 +67,14 @@ use std::time::Duration;
 use futures_channel::oneshot;
 use futures_util::future::{self, FutureExt as _, Either};
 use futures_util::try_future::TryFutureExt as _;
-use http::{Method, Request, Response, Uri, Version};
+use http::{Extensions, Method, Request, Response, Uri, Version};
 use http::header::{HeaderValue, HOST};
 use http::uri::Scheme;
 
 use crate::body::{Body, Payload};
 use crate::common::{lazy as hyper_lazy, Lazy, Future, Pin, Poll, task};
+use crate::ext::{HeaderCaseMap, KeepAlive, OnInformational, OnProgress};
+#[cfg(feature = "runtime")] use crate::rt::RateLimit;
 use self::connect::{Alpn, Connect, Connected, Destination};
 use self::pool::{Key as PoolKey, Pool, Poolable, Pooled, Reservation};
 
@@ -85,6 +87,9 @@ mod pool;
 #[cfg(test)]
 mod tests;
 
+// The most times a started request is sent again on another connection.
+const MAX_REPLAYS: usize = 3;
+
 /// A Client to make outgoing HTTP requests.
 pub struct Client<C, B = Body> {
     config: Config,
@@ -253,11 +258,43 @@ where C: Connect + Sync + 'static,
         let client = self.clone();
         let uri = req.uri().clone();
 
+        // If the body can be replayed, keep what is needed to send the
+        // request again after it was started.
+        let replay = if client.config.retry_canceled_requests && req.method().is_idempotent() {
+            req.body().__hyper_replay().map(|body| (replay_head(&req), body))
+        } else {
+            None
+        };
+        let mut replays = 0;
+
         let mut send_fut = client.send_request(req, pool_key.clone());
         future::poll_fn(move |cx| loop {
             match ready!(Pin::new(&mut send_fut).poll(cx)) {
                 Ok(resp) => return Poll::Ready(Ok(resp)),
                 Err(ClientError::Normal(err)) => return Poll::Ready(Err(err)),
+                Err(ClientError::Started(reason)) => {
+                    // An incomplete message may have been processed by the
+                    // server already, but the method is idempotent.
+                    let retryable = (reason.is_canceled() || reason.is_incomplete_message())
+                        && replays < MAX_REPLAYS;
+                    let replayed = match replay {
+                        Some((ref head, ref body)) if retryable => {
+                            body.__hyper_replay().map(|body| replay_head(head).map(|()| body))
+                        },
+                        _ => None,
+                    };
+                    let mut req = match replayed {
+                        Some(req) => req,
+                        // the body can no longer be sent again, or was sent
+                        // too many times already
+                        None => return Poll::Ready(Err(reason)),
+                    };
+                    replays += 1;
+
+                    trace!("started request canceled, replaying body (reason={:?})", reason);
+                    *req.uri_mut() = uri.clone();
+                    send_fut = client.send_request(req, pool_key.clone());
+                },
                 Err(ClientError::Canceled {
                     connection_reused,
                     mut req,
@@ -282,6 +319,7 @@ where C: Connect + Sync + 'static,
 
         let set_host = self.config.set_host;
         let executor = self.conn_builder.exec.clone();
+        let on_progress = req.extensions().get::<OnProgress>().cloned();
         conn.and_then(move |mut pooled| {
             if pooled.is_http1() {
                 if set_host {
@@ -323,6 +361,9 @@ where C: Connect + Sync + 'static,
                 if let Some(extra) = extra_info {
                     extra.set(&mut res);
                 }
+                if let Some(on_progress) = on_progress {
+                    res.body_mut().set_on_progress(on_progress);
+                }
                 res
             });
 
@@ -339,6 +380,15 @@ where C: Connect + Sync + 'static,
 
             Either::Right(Either::Right(fut
                 .map_ok(move |mut res| {
+                    if pooled.is_http1() {
+                        let keep_alive = res.extensions().get::<KeepAlive>().cloned();
+                        // a fresh `max` replaces what was counted so far
+                        if let Some(max) = keep_alive.and_then(|ka| ka.max()) {
+                            pooled.requests_left = Some(max);
+                        }
+                        pooled.keep_alive = keep_alive;
+                    }
+
                     // If pooled is HTTP/2, we can toss this reference immediately.
                     //
                     // when pooled is dropped, it will try to insert back into the
@@ -540,6 +590,8 @@ where C: Connect + Sync + 'static,
                                 } else {
                                     PoolTx::Http1(tx)
                                 },
+                                keep_alive: None,
+                                requests_left: None,
                             })
                         })))
                 }))
@@ -601,6 +653,11 @@ impl Future for ResponseFuture {
 struct PoolClient<B> {
     conn_info: Connected,
     tx: PoolTx<B>,
+    /// What the server last advertised in a `Keep-Alive` header.
+    keep_alive: Option<KeepAlive>,
+    /// How many more requests the server accepts, counting down from the
+    /// last `Keep-Alive: max` as requests are sent.
+    requests_left: Option<u32>,
 }
 
 enum PoolTx<B> {
@@ -647,6 +704,9 @@ impl<B: Payload + 'static> PoolClient<B> {
     where
         B: Send,
     {
+        if let Some(ref mut left) = self.requests_left {
+            *left = left.saturating_sub(1);
+        }
         match self.tx {
             PoolTx::Http1(ref mut tx) => Either::Left(tx.send_request_retryable(req)),
             PoolTx::Http2(ref mut tx) => Either::Right(tx.send_request_retryable(req)),
@@ -659,6 +719,10 @@ where
     B: Send + 'static,
 {
     fn is_open(&self) -> bool {
+        // the server won't accept another request
+        if let Some(0) = self.requests_left {
+            return false;
+        }
         match self.tx {
             PoolTx::Http1(ref tx) => tx.is_ready(),
             PoolTx::Http2(ref tx) => tx.is_ready(),
@@ -671,16 +735,22 @@ where
                 Reservation::Unique(PoolClient {
                     conn_info: self.conn_info,
                     tx: PoolTx::Http1(tx),
+                    keep_alive: self.keep_alive,
+                    requests_left: self.requests_left,
                 })
             },
             PoolTx::Http2(tx) => {
                 let b = PoolClient {
                     conn_info: self.conn_info.clone(),
                     tx: PoolTx::Http2(tx.clone()),
+                    keep_alive: None,
+                    requests_left: None,
                 };
                 let a = PoolClient {
                     conn_info: self.conn_info,
                     tx: PoolTx::Http2(tx),
+                    keep_alive: None,
+                    requests_left: None,
                 };
                 Reservation::Shared(a, b)
             }
@@ -690,6 +760,10 @@ where
     fn can_share(&self) -> bool {
         self.is_http2()
     }
+
+    fn idle_timeout(&self) -> Option<Duration> {
+        self.keep_alive.and_then(|ka| ka.timeout())
+    }
 }
 
 // ===== impl ClientError =====
@@ -702,7 +776,10 @@ enum ClientError<B> {
         connection_reused: bool,
         req: Request<B>,
         reason: crate::Error,
-    }
+    },
+    /// The request was started on a reused connection before it failed,
+    /// so it can only be retried if its body can be replayed.
+    Started(crate::Error),
 }
 
 impl<B> ClientError<B> {
@@ -716,6 +793,8 @@ impl<B> ClientError<B> {
                     reason: err,
                     req,
                 }
+            } else if conn_reused {
+                ClientError::Started(err)
             } else {
                 ClientError::Normal(err)
             }
@@ -730,6 +809,27 @@ enum Ver {
     Http2,
 }
 
+// Copies the head of `req`, to send it again. `Extensions` can't be
+// cloned, so only the ones hyper reads when sending are kept, as documented
+// on `Builder::retry_canceled_requests`.
+pub(crate) fn replay_head<T>(req: &Request<T>) -> Request<()> {
+    let mut head = Request::new(());
+    *head.method_mut() = req.method().clone();
+    *head.uri_mut() = req.uri().clone();
+    *head.version_mut() = req.version();
+    *head.headers_mut() = req.headers().clone();
+    copy_extension::<HeaderCaseMap>(req.extensions(), head.extensions_mut());
+    copy_extension::<OnInformational>(req.extensions(), head.extensions_mut());
+    copy_extension::<OnProgress>(req.extensions(), head.extensions_mut());
+    head
+}
+
+fn copy_extension<T: Clone + Send + Sync + 'static>(from: &Extensions, to: &mut Extensions) {
+    if let Some(ext) = from.get::<T>() {
+        to.insert(ext.clone());
+    }
+}
+
 fn origin_form(uri: &mut Uri) {
     let path = match uri.path_and_query() {
         Some(path) if path.as_str() != "/" => {
@@ -925,6 +1025,142 @@ impl Builder {
         self
     }
 
+    /// Set a [`RateLimit`](crate::rt::RateLimit) of how fast bytes are read
+    /// from connections.
+    ///
+    /// All connections of this `Client` share the limit.
+    ///
+    /// Default is no limit (`None`).
+    #[cfg(feature = "runtime")]
+    pub fn read_rate_limit(&mut self, limit: impl Into<Option<RateLimit>>) -> &mut Self {
+        self.conn_builder.read_rate_limit(limit);
+        self
+    }
+
+    /// Set a [`RateLimit`](crate::rt::RateLimit) of how fast bytes are
+    /// written to connections, such as to keep uploads from saturating a
+    /// link.
+    ///
+    /// All connections of this `Client` share the limit.
+    ///
+    /// Default is no limit (`None`).
+    #[cfg(feature = "runtime")]
+    pub fn write_rate_limit(&mut self, limit: impl Into<Option<RateLimit>>) -> &mut Self {
+        self.conn_builder.write_rate_limit(limit);
+        self
+    }
+
+    /// Set whether HTTP/1 connections should record the original case and
+    /// order of received header names.
+    ///
+    /// If enabled, a [`HeaderCaseMap`](crate::ext::HeaderCaseMap) is
+    /// inserted in the extensions of each `Response`. A `HeaderCaseMap` in
+    /// the extensions of a `Request` is always used to write its headers.
+    ///
+    /// Default is false.
+    pub fn http1_preserve_header_case(&mut self, val: bool) -> &mut Self {
+        self.conn_builder.h1_preserve_header_case(val);
+        self
+    }
+
+    /// Set whether HTTP/1 connections accept response header values
+    /// continued on the next line with obsolete line folding.
+    ///
+    /// If enabled, folded lines are joined to the header value with a
+    /// single space. Otherwise, such responses fail with a parse error.
+    ///
+    /// Default is false.
+    pub fn http1_allow_obsolete_multiline_headers(&mut self, val: bool) -> &mut Self {
+        self.conn_builder.h1_allow_obsolete_multiline_headers(val);
+        self
+    }
+
+    /// Set whether HTTP/1 connections accept whitespace between a response
+    /// header name and its colon.
+    ///
+    /// If enabled, the whitespace is removed. Otherwise, such responses
+    /// fail with a parse error.
+    ///
+    /// Default is false.
+    pub fn http1_allow_spaces_before_header_colon(&mut self, val: bool) -> &mut Self {
+        self.conn_builder.h1_allow_spaces_before_header_colon(val);
+        self
+    }
+
+    /// Set whether HTTP/1 connections accept a bare LF, without a CR, as a
+    /// line ending in the response head.
+    ///
+    /// If enabled, bare LFs are rewritten as CRLFs. Otherwise, such
+    /// responses fail with a parse error.
+    ///
+    /// Default is false.
+    pub fn http1_allow_bare_lf(&mut self, val: bool) -> &mut Self {
+        self.conn_builder.h1_allow_bare_lf(val);
+        self
+    }
+
+    /// Set whether HTTP/1 connections accept invalid bytes, such as control
+    /// characters, in the reason phrase of a response.
+    ///
+    /// If enabled, the invalid bytes are replaced with spaces. Otherwise,
+    /// such responses fail with a parse error.
+    ///
+    /// Default is false.
+    pub fn http1_allow_invalid_reason_phrase(&mut self, val: bool) -> &mut Self {
+        self.conn_builder.h1_allow_invalid_reason_phrase(val);
+        self
+    }
+
+    /// Set how long an HTTP/1 request with `Expect: 100-continue` waits for
+    /// the server before sending its body anyway.
+    ///
+    /// Such a request sends its head first, and only sends the body once the
+    /// server answers with `100 Continue`, or this timeout elapses. If the
+    /// server sends a final response instead, the body isn't sent at all.
+    ///
+    /// Pass `None` to wait for the server's answer without a timeout. The
+    /// timeout needs the `runtime` feature, and is ignored without it.
+    ///
+    /// Default is 1 second.
+    pub fn http1_expect_continue_timeout<D>(&mut self, val: D) -> &mut Self
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.conn_builder.h1_expect_continue_timeout(val.into());
+        self
+    }
+
+    /// Set the maximum number of headers in an HTTP/1 response.
+    ///
+    /// Responses with more headers fail with an error for which
+    /// `is_too_many_headers()` is true.
+    ///
+    /// Default is 100.
+    pub fn http1_max_headers(&mut self, max: usize) -> &mut Self {
+        self.conn_builder.http1_max_headers(max);
+        self
+    }
+
+    /// Set the maximum size in bytes of an HTTP/1 response head, including
+    /// the status line and all headers.
+    ///
+    /// Default is no limit, other than `http1_max_buf_size`.
+    pub fn http1_max_head_size(&mut self, max: usize) -> &mut Self {
+        self.conn_builder.http1_max_head_size(max);
+        self
+    }
+
+    /// Set the maximum size in bytes of a single HTTP/1 response header value.
+    ///
+    /// Responses with a longer value fail with an error for which
+    /// `is_header_value_too_long()` is true.
+    ///
+    /// Default is no limit, other than `http1_max_buf_size`.
+    pub fn http1_max_header_value_size(&mut self, max: usize) -> &mut Self {
+        self.conn_builder.http1_max_header_value_size(max);
+        self
+    }
+
     /// Set whether HTTP/1 connections will write header names as title case at
     /// the socket level.
     ///
@@ -989,6 +1225,24 @@ impl Builder {
     /// connection, and then encounters an error immediately as the idle
     /// connection was found to be unusable.
     ///
+    /// Idempotent requests that were already started are also retried, up
+    /// to 3 times, if the reused connection closed before a response, if
+    /// their body was created all at once, such as with `Body::from`, or
+    /// was made with [`Body::replayable`](::Body::replayable) and is still
+    /// within its limit.
+    ///
+    /// When the connection closed before a complete response, an error
+    /// where [`is_incomplete_message`](::Error::is_incomplete_message) is
+    /// true, the server may already have received, and even processed, the
+    /// request that is sent again. That is only done because idempotent
+    /// methods are meant to be safe to repeat: set this to `false` if the
+    /// server doesn't treat them that way.
+    ///
+    /// A retried request is sent with the same method, URI, version and
+    /// headers. `Extensions` can't be cloned, so of its extensions only
+    /// hyper's own are sent again: a `HeaderCaseMap`, an `OnInformational`
+    /// and an `OnProgress`.
+    ///
     /// When this is set to `false`, the related `ResponseFuture` would instead
     /// resolve to an `Error::Cancel`.
     ///
@@ -30,6 +30,12 @@ pub(super) trait Poolable: Unpin + Send + Sized + 'static {
     /// Allows for HTTP/2 to return a shared reservation.
     fn reserve(self) -> Reservation<Self>;
     fn can_share(&self) -> bool;
+    /// How long the remote keeps this connection open while idle, if it
+    /// said so. The connection expires after the shorter of this, less a
+    /// safety margin, and the pool's own timeout.
+    fn idle_timeout(&self) -> Option<Duration> {
+        None
+    }
 }
 
 /// When checking out a pooled connection, it might be that the connection
@@ -283,7 +289,7 @@ impl<'a, T: Poolable + 'a> IdlePopper<'a, T> {
             //
             // In that case, we could just break out of the loop and drop the
             // whole list...
-            if expiration.expires(entry.idle_at) {
+            if expiration.expires(&entry) {
                 trace!("removing expired connection for {:?}", self.key);
                 continue;
             }
@@ -292,6 +298,7 @@ impl<'a, T: Poolable + 'a> IdlePopper<'a, T> {
                 Reservation::Shared(to_reinsert, to_checkout) => {
                     self.list.push(Idle {
                         idle_at: Instant::now(),
+                        timeout: to_reinsert.idle_timeout(),
                         value: to_reinsert,
                     });
                     to_checkout
@@ -303,6 +310,7 @@ impl<'a, T: Poolable + 'a> IdlePopper<'a, T> {
 
             return Some(Idle {
                 idle_at: entry.idle_at,
+                timeout: entry.timeout,
                 value,
             });
         }
@@ -368,8 +376,9 @@ impl<T: Poolable> PoolInner<T> {
 
                     debug!("pooling idle connection for {:?}", key);
                     idle_list.push(Idle {
-                        value: value,
                         idle_at: Instant::now(),
+                        timeout: value.idle_timeout(),
+                        value: value,
                     });
                 }
 
@@ -450,10 +459,7 @@ impl<T> PoolInner<T> {
 impl<T: Poolable> PoolInner<T> {
     /// This should *only* be called by the IdleTask
     fn clear_expired(&mut self) {
-        let dur = self.timeout.expect("interval assumes timeout");
-
-        let now = Instant::now();
-        //self.last_idle_check_at = now;
+        let expiration = Expiration::new(self.timeout);
 
         self.idle.retain(|key, values| {
             values.retain(|entry| {
@@ -461,7 +467,7 @@ impl<T: Poolable> PoolInner<T> {
                     trace!("idle interval evicting closed for {:?}", key);
                     return false;
                 }
-                if now - entry.idle_at > dur {
+                if expiration.expires(entry) {
                     trace!("idle interval evicting expired for {:?}", key);
                     return false;
                 }
@@ -556,6 +562,8 @@ impl<T: Poolable> fmt::Debug for Pooled<T> {
 
 struct Idle<T> {
     idle_at: Instant,
+    /// The remote's idle timeout for this connection, if it gave one.
+    timeout: Option<Duration>,
     value: T,
 }
 
@@ -701,14 +709,26 @@ impl<T: Poolable> Drop for Connecting<T> {
 
 struct Expiration(Option<Duration>);
 
+/// The most a remote's idle timeout is cut short by.
+const REMOTE_TIMEOUT_MARGIN: Duration = Duration::from_secs(1);
+
 impl Expiration {
     fn new(dur: Option<Duration>) -> Expiration {
         Expiration(dur)
     }
 
-    fn expires(&self, instant: Instant) -> bool {
-        match self.0 {
-            Some(timeout) => instant.elapsed() > timeout,
+    fn expires<T>(&self, entry: &Idle<T>) -> bool {
+        // The remote's timeout is cut short by a margin, so a connection
+        // isn't used just as the remote is closing it.
+        let theirs = entry.timeout.map(|theirs| {
+            theirs - ::std::cmp::min(theirs / 4, REMOTE_TIMEOUT_MARGIN)
+        });
+        let timeout = match (self.0, theirs) {
+            (Some(ours), Some(theirs)) => Some(::std::cmp::min(ours, theirs)),
+            (ours, theirs) => ours.or(theirs),
+        };
+        match timeout {
+            Some(timeout) => entry.idle_at.elapsed() > timeout,
             None => false,
         }
     }
@@ -778,6 +798,31 @@ mod tests {
     // trigger a warning to remind us
     use crate::Error;
 
+    #[test]
+    fn expiration_cuts_remote_timeout_short() {
+        use std::time::{Duration, Instant};
+        use super::{Expiration, Idle};
+
+        let idle_for = |idle: u64, timeout: u64| Idle {
+            idle_at: Instant::now() - Duration::from_millis(idle),
+            timeout: Some(Duration::from_millis(timeout)),
+            value: (),
+        };
+        let expiration = Expiration::new(None);
+
+        // a second is taken off long timeouts
+        assert!(!expiration.expires(&idle_for(3_900, 5_000)));
+        assert!(expiration.expires(&idle_for(4_100, 5_000)));
+        // and a quarter of short ones
+        assert!(!expiration.expires(&idle_for(1_400, 2_000)));
+        assert!(expiration.expires(&idle_for(1_600, 2_000)));
+        // the pool's own timeout is used as is
+        assert!(!Expiration::new(Some(Duration::from_millis(2_000))).expires(&Idle {
+            timeout: None,
+            ..idle_for(1_900, 0)
+        }));
+    }
+
     /*
     use std::sync::Arc;
     use std::time::Duration;
@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 use bytes::Buf;
 use iovec::IoVec;
 
@@ -32,3 +34,76 @@ impl Buf for StaticBuf {
     }
 }
 
+
+/// A `Buf` over a queue of `Buf`s, consumed in order.
+pub(crate) struct BufList<T> {
+    bufs: VecDeque<T>,
+}
+
+impl<T> BufList<T> {
+    pub(crate) fn new() -> BufList<T> {
+        BufList {
+            bufs: VecDeque::new(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn push(&mut self, buf: T) {
+        self.bufs.push_back(buf);
+    }
+
+    #[inline]
+    pub(crate) fn bufs_cnt(&self) -> usize {
+        self.bufs.len()
+    }
+}
+
+impl<T: Buf> Buf for BufList<T> {
+    #[inline]
+    fn remaining(&self) -> usize {
+        self.bufs.iter()
+            .map(|buf| buf.remaining())
+            .sum()
+    }
+
+    #[inline]
+    fn bytes(&self) -> &[u8] {
+        for buf in &self.bufs {
+            return buf.bytes();
+        }
+        &[]
+    }
+
+    #[inline]
+    fn advance(&mut self, mut cnt: usize) {
+        while cnt > 0 {
+            {
+                let front = &mut self.bufs[0];
+                let rem = front.remaining();
+                if rem > cnt {
+                    front.advance(cnt);
+                    return;
+                } else {
+                    front.advance(rem);
+                    cnt -= rem;
+                }
+            }
+            self.bufs.pop_front();
+        }
+    }
+
+    #[inline]
+    fn bytes_vec<'t>(&'t self, dst: &mut [&'t IoVec]) -> usize {
+        if dst.is_empty() {
+            return 0;
+        }
+        let mut vecs = 0;
+        for buf in &self.bufs {
+            vecs += buf.bytes_vec(&mut dst[vecs..]);
+            if vecs == dst.len() {
+                break;
+            }
+        }
+        vecs
+    }
+}
@@ -1,3 +1,5 @@
 mod rewind;
+mod throttle;
 
 pub(crate) use self::rewind::Rewind;
+pub(crate) use self::throttle::Throttled;
@@ -0,0 +1,74 @@
+use std::io;
+use std::marker::Unpin;
+
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use crate::common::{Pin, Poll, task};
+use crate::common::rate::{Pacer, RateLimits};
+
+/// Paces the reads and writes of an IO with `RateLimit`s.
+///
+/// Without any limits, it passes everything through.
+#[derive(Debug)]
+pub(crate) struct Throttled<T> {
+    inner: T,
+    read: Option<Pacer>,
+    write: Option<Pacer>,
+}
+
+impl<T> Throttled<T> {
+    pub(crate) fn new(io: T, limits: RateLimits) -> Self {
+        Throttled {
+            inner: io,
+            read: limits.read.map(Pacer::new),
+            write: limits.write.map(Pacer::new),
+        }
+    }
+}
+
+impl<T> AsyncRead for Throttled<T>
+where
+    T: AsyncRead + Unpin,
+{
+    #[inline]
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.inner.prepare_uninitialized_buffer(buf)
+    }
+
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let me = &mut *self;
+        let pacer = match me.read {
+            Some(ref mut pacer) => pacer,
+            None => return Pin::new(&mut me.inner).poll_read(cx, buf),
+        };
+        let allowed = ready!(pacer.poll_acquire(cx, buf.len()));
+        let n = ready!(Pin::new(&mut me.inner).poll_read(cx, &mut buf[..allowed]))?;
+        pacer.consume(n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl<T> AsyncWrite for Throttled<T>
+where
+    T: AsyncWrite + Unpin,
+{
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let me = &mut *self;
+        let pacer = match me.write {
+            Some(ref mut pacer) => pacer,
+            None => return Pin::new(&mut me.inner).poll_write(cx, buf),
+        };
+        let allowed = ready!(pacer.poll_acquire(cx, buf.len()));
+        let n = ready!(Pin::new(&mut me.inner).poll_write(cx, &buf[..allowed]))?;
+        pacer.consume(n);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
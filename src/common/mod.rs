@@ -13,9 +13,12 @@ pub(crate) mod exec;
 pub(crate) mod io;
 mod lazy;
 mod never;
+// A `RateLimit` can only be made with the runtime.
+#[cfg_attr(not(feature = "runtime"), allow(dead_code))]
+pub(crate) mod rate;
 pub(crate) mod task;
 
-pub(crate) use self::buf::StaticBuf;
+pub(crate) use self::buf::{BufList, StaticBuf};
 pub(crate) use self::exec::Exec;
 pub(crate) use self::lazy::{lazy, Started as Lazy};
 pub use self::never::Never;
@@ -0,0 +1,242 @@
+use std::cmp;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "runtime")]
+use tokio_timer::Delay;
+
+#[cfg(feature = "runtime")]
+use crate::common::{Future, Pin};
+use crate::common::{Poll, task};
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// A limit of bandwidth, in bytes per second.
+///
+/// This is a token bucket: it holds up to `burst` bytes, and is refilled
+/// at `bytes_per_second`. Sending or receiving bytes takes them from the
+/// bucket, and waits while it is empty.
+///
+/// Clones of a `RateLimit` share the same bucket, so the same limit can be
+/// given to many bodies or connections to cap all of them together.
+#[derive(Clone)]
+pub struct RateLimit {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+struct Bucket {
+    rate: u64,
+    burst: u64,
+    /// Can be negative, if more was taken than was available.
+    tokens: i64,
+    last: Instant,
+}
+
+/// Paces one user of a `RateLimit`.
+pub(crate) struct Pacer {
+    limit: RateLimit,
+    #[cfg(feature = "runtime")]
+    delay: Option<Delay>,
+}
+
+/// The limits of a connection, for reading and writing.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RateLimits {
+    pub(crate) read: Option<RateLimit>,
+    pub(crate) write: Option<RateLimit>,
+}
+
+impl RateLimit {
+    /// Creates a limit of `bytes_per_second`, which allows bursts of up to
+    /// one second's worth of bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_second` is 0.
+    pub fn new(bytes_per_second: u64) -> RateLimit {
+        RateLimit::with_burst(bytes_per_second, bytes_per_second)
+    }
+
+    /// Creates a limit of `bytes_per_second`, which allows bursts of up to
+    /// `burst` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_second` or `burst` is 0.
+    pub fn with_burst(bytes_per_second: u64, burst: u64) -> RateLimit {
+        assert!(bytes_per_second > 0, "rate limit must be above 0 bytes per second");
+        assert!(burst > 0, "rate limit burst must be above 0 bytes");
+        let burst = cmp::min(burst, i64::max_value() as u64);
+        RateLimit {
+            bucket: Arc::new(Mutex::new(Bucket {
+                rate: bytes_per_second,
+                burst,
+                tokens: burst as i64,
+                last: Instant::now(),
+            })),
+        }
+    }
+
+    /// Returns the rate of this limit, in bytes per second.
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bucket.lock().unwrap().rate
+    }
+
+    /// Returns the most bytes that can be sent at once after being idle.
+    pub fn burst(&self) -> u64 {
+        self.bucket.lock().unwrap().burst
+    }
+}
+
+impl fmt::Debug for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bucket = self.bucket.lock().unwrap();
+        f.debug_struct("RateLimit")
+            .field("bytes_per_second", &bucket.rate)
+            .field("burst", &bucket.burst)
+            .finish()
+    }
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let burst = self.burst as i64;
+        if self.tokens >= burst {
+            self.last = now;
+            return;
+        }
+        let elapsed = now.duration_since(self.last).as_nanos();
+        let added = elapsed * self.rate as u128 / NANOS_PER_SEC;
+        if added == 0 {
+            return;
+        }
+        let room = (burst as i128 - self.tokens as i128) as u128;
+        let added = cmp::min(added, room);
+        self.tokens = (self.tokens as i128 + added as i128) as i64;
+        if self.tokens == burst {
+            self.last = now;
+        } else {
+            // Only move forward by the time used for whole tokens, so the
+            // fractions aren't lost.
+            let used = added as u128 * NANOS_PER_SEC / self.rate as u128;
+            self.last += Duration::from_nanos(used as u64);
+        }
+    }
+
+    /// How long until there are `need` tokens.
+    fn wait(&self, need: i64) -> Duration {
+        let missing = (need - self.tokens) as u128;
+        let rate = self.rate as u128;
+        Duration::from_nanos(((missing * NANOS_PER_SEC + rate - 1) / rate) as u64)
+    }
+}
+
+impl Pacer {
+    pub(crate) fn new(limit: RateLimit) -> Pacer {
+        Pacer {
+            limit,
+            #[cfg(feature = "runtime")]
+            delay: None,
+        }
+    }
+
+    /// Waits until bytes are available, and returns how many, up to `want`.
+    ///
+    /// The bytes aren't taken until `consume` is called.
+    pub(crate) fn poll_acquire(&mut self, cx: &mut task::Context<'_>, want: usize) -> Poll<usize> {
+        if want == 0 {
+            return Poll::Ready(0);
+        }
+        loop {
+            let available = {
+                let mut bucket = self.limit.bucket.lock().unwrap();
+                let now = Instant::now();
+                bucket.refill(now);
+                if bucket.tokens > 0 {
+                    Ok(cmp::min(bucket.tokens as u64, want as u64) as usize)
+                } else {
+                    // Wait for enough to be worth it, about 50ms at most, so
+                    // reads and writes aren't split in tiny pieces.
+                    let step = cmp::max(bucket.rate / 20, 1);
+                    let need = cmp::min(cmp::min(want as u64, bucket.burst), step) as i64;
+                    Err(now + bucket.wait(need))
+                }
+            };
+            match available {
+                Ok(n) => {
+                    self.clear_sleep();
+                    return Poll::Ready(n);
+                },
+                Err(deadline) => ready!(self.poll_sleep(cx, deadline)),
+            }
+        }
+    }
+
+    /// Takes `n` bytes from the bucket, even if that is more than were
+    /// available.
+    pub(crate) fn consume(&self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        let mut bucket = self.limit.bucket.lock().unwrap();
+        let n = cmp::min(n as u64, i64::max_value() as u64) as i64;
+        bucket.tokens = bucket.tokens.saturating_sub(n);
+    }
+
+    #[cfg(feature = "runtime")]
+    fn poll_sleep(&mut self, cx: &mut task::Context<'_>, deadline: Instant) -> Poll<()> {
+        match self.delay {
+            Some(ref mut delay) if delay.deadline() == deadline => (),
+            _ => self.delay = Some(Delay::new(deadline)),
+        }
+        Pin::new(self.delay.as_mut().expect("delay just set")).poll(cx)
+    }
+
+    #[cfg(feature = "runtime")]
+    fn clear_sleep(&mut self) {
+        self.delay = None;
+    }
+
+    // A `RateLimit` can't be created without the runtime.
+    #[cfg(not(feature = "runtime"))]
+    fn poll_sleep(&mut self, _cx: &mut task::Context<'_>, _deadline: Instant) -> Poll<()> {
+        Poll::Ready(())
+    }
+
+    #[cfg(not(feature = "runtime"))]
+    fn clear_sleep(&mut self) {}
+}
+
+impl fmt::Debug for Pacer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pacer")
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::Bucket;
+
+    #[test]
+    fn bucket_refills_at_rate_up_to_burst() {
+        let start = Instant::now();
+        let mut bucket = Bucket {
+            rate: 1000,
+            burst: 500,
+            tokens: -200,
+            last: start,
+        };
+
+        bucket.refill(start + Duration::from_millis(100));
+        assert_eq!(bucket.tokens, -100);
+        assert_eq!(bucket.wait(100), Duration::from_millis(200));
+
+        bucket.refill(start + Duration::from_secs(10));
+        assert_eq!(bucket.tokens, 500);
+    }
+}
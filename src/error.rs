@@ -47,6 +47,12 @@ pub(crate) enum Kind {
     Body,
     /// Error while writing a body to connection.
     BodyWrite,
+    /// A body was longer than the limit it was read with.
+    BodyTooLarge,
+    /// A body received no data for longer than its stall timeout.
+    BodyStalled,
+    /// A `multipart/form-data` body was malformed.
+    Multipart,
     /// Error calling AsyncWrite::shutdown()
     Shutdown,
 
@@ -60,7 +66,11 @@ pub(crate) enum Parse {
     Version,
     VersionH2,
     Uri,
+    UriTooLong,
     Header,
+    HeaderValueTooLong,
+    TooManyHeaders,
+    TransferEncoding,
     TooLarge,
     Status,
 }
@@ -85,6 +95,8 @@ pub(crate) enum User {
     UnsupportedStatusCode,
     /// User tried to send a Request with Client with non-absolute URI.
     AbsoluteUriRequired,
+    /// User asked for informational responses on an HTTP/2 request.
+    UnsupportedInformational,
 
     /// User tried polling for an upgrade that doesn't exist.
     NoUpgrade,
@@ -123,6 +135,26 @@ impl Error {
         self.inner.kind == Kind::ChannelClosed
     }
 
+    /// Returns true if a message head had more headers than allowed.
+    pub fn is_too_many_headers(&self) -> bool {
+        self.inner.kind == Kind::Parse(Parse::TooManyHeaders)
+    }
+
+    /// Returns true if a header value was longer than allowed.
+    pub fn is_header_value_too_long(&self) -> bool {
+        self.inner.kind == Kind::Parse(Parse::HeaderValueTooLong)
+    }
+
+    /// Returns true if a request line was longer than allowed.
+    pub fn is_uri_too_long(&self) -> bool {
+        self.inner.kind == Kind::Parse(Parse::UriTooLong)
+    }
+
+    /// Returns true if a message head was larger than allowed.
+    pub fn is_head_too_large(&self) -> bool {
+        self.inner.kind == Kind::Parse(Parse::TooLarge)
+    }
+
     /// Returns true if this was an error from `Connect`.
     pub fn is_connect(&self) -> bool {
         self.inner.kind == Kind::Connect
@@ -133,6 +165,22 @@ impl Error {
         self.inner.kind == Kind::IncompleteMessage
     }
 
+    /// Returns true if a body was longer than the limit it was read with.
+    pub fn is_body_too_large(&self) -> bool {
+        self.inner.kind == Kind::BodyTooLarge
+    }
+
+    /// Returns true if a body received no data for longer than its stall
+    /// timeout.
+    pub fn is_body_stalled(&self) -> bool {
+        self.inner.kind == Kind::BodyStalled
+    }
+
+    /// Returns true if a `multipart/form-data` body was malformed.
+    pub fn is_multipart(&self) -> bool {
+        self.inner.kind == Kind::Multipart
+    }
+
     /// Consumes the error, returning its cause.
     pub fn into_cause(self) -> Option<Box<dyn StdError + Send + Sync>> {
         self.inner.cause
@@ -185,6 +233,10 @@ impl Error {
         Error::new(Kind::Parse(Parse::TooLarge))
     }
 
+    pub(crate) fn new_uri_too_long() -> Error {
+        Error::new(Kind::Parse(Parse::UriTooLong))
+    }
+
     pub(crate) fn new_version_h2() -> Error {
         Error::new(Kind::Parse(Parse::VersionH2))
     }
@@ -222,6 +274,19 @@ impl Error {
         Error::new(Kind::BodyWrite).with(cause)
     }
 
+    pub(crate) fn new_body_too_large() -> Error {
+        Error::new(Kind::BodyTooLarge)
+    }
+
+    #[cfg(feature = "runtime")]
+    pub(crate) fn new_body_stalled() -> Error {
+        Error::new(Kind::BodyStalled)
+    }
+
+    pub(crate) fn new_multipart<E: Into<Cause>>(cause: E) -> Error {
+        Error::new(Kind::Multipart).with(cause)
+    }
+
     fn new_user(user: User) -> Error {
         Error::new(Kind::User(user))
     }
@@ -246,6 +311,10 @@ impl Error {
         Error::new_user(User::AbsoluteUriRequired)
     }
 
+    pub(crate) fn new_user_unsupported_informational() -> Error {
+        Error::new_user(User::UnsupportedInformational)
+    }
+
     pub(crate) fn new_user_no_upgrade() -> Error {
         Error::new_user(User::NoUpgrade)
     }
@@ -311,7 +380,11 @@ impl StdError for Error {
             Kind::Parse(Parse::Version) => "invalid HTTP version parsed",
             Kind::Parse(Parse::VersionH2) => "invalid HTTP version parsed (found HTTP2 preface)",
             Kind::Parse(Parse::Uri) => "invalid URI",
+            Kind::Parse(Parse::UriTooLong) => "URI too long",
             Kind::Parse(Parse::Header) => "invalid HTTP header parsed",
+            Kind::Parse(Parse::HeaderValueTooLong) => "header value is too long",
+            Kind::Parse(Parse::TooManyHeaders) => "message head has too many headers",
+            Kind::Parse(Parse::TransferEncoding) => "unsupported transfer-encoding parsed",
             Kind::Parse(Parse::TooLarge) => "message head is too large",
             Kind::Parse(Parse::Status) => "invalid HTTP status-code parsed",
             Kind::IncompleteMessage => "connection closed before message completed",
@@ -324,6 +397,9 @@ impl StdError for Error {
             Kind::Accept => "error accepting connection",
             Kind::Body => "error reading a body from connection",
             Kind::BodyWrite => "error writing a body to connection",
+            Kind::BodyTooLarge => "body is larger than the limit",
+            Kind::BodyStalled => "body received no data before the stall timeout",
+            Kind::Multipart => "invalid multipart/form-data body",
             Kind::Shutdown => "error shutting down connection",
             Kind::Http2 => "http2 error",
             Kind::Io => "connection error",
@@ -336,6 +412,7 @@ impl StdError for Error {
             Kind::User(User::UnsupportedRequestMethod) => "request has unsupported HTTP method",
             Kind::User(User::UnsupportedStatusCode) => "response has 1xx status code, not supported by server",
             Kind::User(User::AbsoluteUriRequired) => "client requires absolute-form URIs",
+            Kind::User(User::UnsupportedInformational) => "informational responses are not supported over HTTP/2",
             Kind::User(User::NoUpgrade) => "no upgrade available",
             Kind::User(User::ManualUpgrade) => "upgrade expected but low level API in use",
             Kind::User(User::Execute) => "executor failed to spawn task",
@@ -366,7 +443,7 @@ impl From<httparse::Error> for Parse {
             httparse::Error::NewLine |
             httparse::Error::Token => Parse::Header,
             httparse::Error::Status => Parse::Status,
-            httparse::Error::TooManyHeaders => Parse::TooLarge,
+            httparse::Error::TooManyHeaders => Parse::TooManyHeaders,
             httparse::Error::Version => Parse::Version,
         }
     }
@@ -417,6 +494,18 @@ mod tests {
         assert_eq!(mem::size_of::<Error>(), mem::size_of::<usize>());
     }
 
+    #[test]
+    fn head_limit_predicates() {
+        let err = Error::from(Parse::TooManyHeaders);
+        assert!(err.is_parse() && err.is_too_many_headers() && !err.is_header_value_too_long());
+        let err = Error::from(Parse::HeaderValueTooLong);
+        assert!(err.is_parse() && err.is_header_value_too_long() && !err.is_too_many_headers());
+        let err = Error::from(Parse::UriTooLong);
+        assert!(err.is_parse() && err.is_uri_too_long() && !err.is_head_too_large());
+        let err = Error::from(Parse::TooLarge);
+        assert!(err.is_parse() && err.is_head_too_large() && !err.is_uri_too_long());
+    }
+
     #[test]
     fn h2_reason_unknown() {
         let closed = Error::new_closed();
@@ -0,0 +1,382 @@
+//! Extensions for HTTP messages.
+//!
+//! These types are found in, or can be added to, the extensions of a
+//! `Request` or `Response` to change how hyper handles the message.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_channel::mpsc;
+use http::header::{HeaderName, InvalidHeaderName};
+use http::{Response, StatusCode, Version};
+
+use crate::body::Transfer;
+use crate::proto::MessageHead;
+
+/// The original case and order of the header names of an HTTP/1 message.
+///
+/// When enabled with `http1_preserve_header_case`, hyper records this for
+/// every received message, and inserts it in the extensions of the
+/// `Request` or `Response`.
+///
+/// When found in the extensions of an outgoing HTTP/1 message, hyper writes
+/// the headers with the recorded case, in the recorded order. Headers that
+/// aren't in the map are written after those that are. This makes it
+/// possible to forward a message to peers that care about header case.
+///
+/// A `HeaderCaseMap` has no effect on HTTP/2 messages.
+#[derive(Clone, Default)]
+pub struct HeaderCaseMap {
+    entries: Vec<(HeaderName, Bytes)>,
+}
+
+impl HeaderCaseMap {
+    /// Creates an empty `HeaderCaseMap`.
+    pub fn new() -> HeaderCaseMap {
+        HeaderCaseMap::default()
+    }
+
+    /// Appends a header name, with its original case, to the end of the map.
+    ///
+    /// Returns an error if `name` isn't a valid header name.
+    pub fn append(&mut self, name: &[u8]) -> Result<(), InvalidHeaderName> {
+        let key = HeaderName::from_bytes(name)?;
+        self.append_raw(key, Bytes::from(name));
+        Ok(())
+    }
+
+    /// Returns the original cases of `name`, in the order they appeared.
+    pub fn get_all<'a>(&'a self, name: &'a HeaderName) -> impl Iterator<Item = &'a [u8]> + 'a {
+        self.entries
+            .iter()
+            .filter(move |&&(ref key, _)| key == name)
+            .map(|&(_, ref orig)| &orig[..])
+    }
+
+    /// Returns an iterator over the header names, in the order they
+    /// appeared, along with their original case.
+    pub fn iter(&self) -> impl Iterator<Item = (&HeaderName, &[u8])> {
+        self.entries
+            .iter()
+            .map(|&(ref key, ref orig)| (key, &orig[..]))
+    }
+
+    /// Returns the number of header names in the map.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the map contains no header names.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // `orig` must be equal to `key`, ignoring ASCII case.
+    pub(crate) fn append_raw(&mut self, key: HeaderName, orig: Bytes) {
+        debug_assert!(orig.eq_ignore_ascii_case(key.as_str().as_bytes()));
+        self.entries.push((key, orig));
+    }
+}
+
+impl fmt::Debug for HeaderCaseMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.entries.iter().map(|&(_, ref orig)| orig))
+            .finish()
+    }
+}
+
+/// A handle to send informational (1xx) responses before the final response.
+///
+/// Servers insert an `Informational` in the extensions of every `Request`.
+/// It can be used to send any number of informational responses, such as
+/// `103 Early Hints`, while the final `Response` is being prepared.
+///
+/// When a request has `Expect: 100-continue`, hyper sends `100 Continue`
+/// once the request body is polled, so a service can instead reply with
+/// `417 Expectation Failed` without reading the body. A `100 Continue`
+/// sent with this handle replaces the automatic one.
+///
+/// Informational responses can only be sent to HTTP/1.1 peers. For
+/// HTTP/1.0 requests, whose peers don't understand them, and for HTTP/2
+/// requests, since the underlying h2 library can't send them yet,
+/// [`send`](Informational::send) always returns an error.
+///
+/// # Example
+///
+/// ```
+/// # use hyper::{Body, Request, Response, StatusCode};
+/// use hyper::ext::Informational;
+///
+/// # fn handle(req: Request<Body>) -> Response<Body> {
+/// if let Some(info) = req.extensions().get::<Informational>() {
+///     let hints = Response::builder()
+///         .status(StatusCode::from_u16(103).unwrap())
+///         .header("link", "</style.css>; rel=preload; as=style")
+///         .body(())
+///         .unwrap();
+///     let _ = info.send(hints);
+/// }
+/// # Response::new(Body::empty())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Informational {
+    tx: mpsc::UnboundedSender<MessageHead<StatusCode>>,
+    version: Version,
+}
+
+impl Informational {
+    pub(crate) fn channel(version: Version) -> (Informational, mpsc::UnboundedReceiver<MessageHead<StatusCode>>) {
+        let (tx, rx) = mpsc::unbounded();
+        (Informational { tx, version }, rx)
+    }
+
+    /// An `Informational` for requests that can't have informational
+    /// responses, whose `send` always fails.
+    pub(crate) fn unsupported(version: Version) -> Informational {
+        Informational::channel(version).0
+    }
+
+    /// Sends an informational response.
+    ///
+    /// The response is written before the final response, in the order
+    /// that informational responses are sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the status code isn't 1xx, or is `101 Switching
+    /// Protocols`, or if the final response has already been sent.
+    ///
+    /// Always returns a user error if the request wasn't HTTP/1.1: HTTP/1.0
+    /// peers must not be sent informational responses, and HTTP/2 ones
+    /// aren't supported yet.
+    pub fn send(&self, res: Response<()>) -> crate::Result<()> {
+        if !res.status().is_informational() || res.status() == StatusCode::SWITCHING_PROTOCOLS {
+            return Err(crate::Error::new_user_unsupported_status_code());
+        }
+        if self.version != Version::HTTP_11 {
+            return Err(crate::Error::new_user_unsupported_version());
+        }
+        let (parts, ()) = res.into_parts();
+        let head = MessageHead {
+            version: parts.version,
+            subject: parts.status,
+            headers: parts.headers,
+            extensions: parts.extensions,
+        };
+        self.tx
+            .unbounded_send(head)
+            .map_err(|_| crate::Error::new_closed())
+    }
+}
+
+impl fmt::Debug for Informational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Informational")
+            .field("version", &self.version)
+            .field("closed", &self.tx.is_closed())
+            .finish()
+    }
+}
+
+/// A callback for the informational (1xx) responses to a client request.
+///
+/// Insert an `OnInformational` in the extensions of a `Request` sent with
+/// a `Client` or a `SendRequest`, and it is called with each interim
+/// response received before the final one, such as `103 Early Hints` or
+/// `102 Processing`. The final `Response` is returned as usual.
+///
+/// Informational responses are only reported for HTTP/1 connections. The
+/// underlying h2 library has no way to expose them yet, so a request with
+/// an `OnInformational` sent on an HTTP/2 connection fails with a user
+/// error instead of being sent without its callback.
+///
+/// # Example
+///
+/// ```
+/// # use hyper::{Body, Request};
+/// use hyper::ext::OnInformational;
+///
+/// let mut req = Request::new(Body::empty());
+/// req.extensions_mut().insert(OnInformational::new(|res| {
+///     for link in res.headers().get_all("link") {
+///         println!("preload: {:?}", link);
+///     }
+/// }));
+/// ```
+#[derive(Clone)]
+pub struct OnInformational(Arc<dyn Fn(Response<()>) + Send + Sync>);
+
+impl OnInformational {
+    /// Creates an `OnInformational` that calls `f` with each informational
+    /// response.
+    pub fn new<F>(f: F) -> OnInformational
+    where
+        F: Fn(Response<()>) + Send + Sync + 'static,
+    {
+        OnInformational(Arc::new(f))
+    }
+
+    pub(crate) fn call(&self, head: MessageHead<StatusCode>) {
+        let mut res = Response::new(());
+        *res.status_mut() = head.subject;
+        *res.version_mut() = head.version;
+        *res.headers_mut() = head.headers;
+        *res.extensions_mut() = head.extensions;
+        (self.0)(res);
+    }
+}
+
+impl fmt::Debug for OnInformational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OnInformational").finish()
+    }
+}
+
+/// A callback for the progress of a body, with an optional stall timeout.
+///
+/// Insert an `OnProgress` in the extensions of a `Request` sent with a
+/// `Client`, and it is called as the body of the `Response` is received.
+/// Any other body can be observed by wrapping it in a
+/// [`Progress`](body::Progress), such as to follow an upload.
+///
+/// The callback is given a [`Transfer`](body::Transfer) after every
+/// buffer of data, and once more when the body ends.
+///
+/// # Example
+///
+/// ```
+/// # use std::time::Duration;
+/// # use hyper::{Body, Request};
+/// use hyper::ext::OnProgress;
+///
+/// let mut req = Request::new(Body::empty());
+/// req.extensions_mut().insert(OnProgress::new(|transfer| {
+///     if let Some(total) = transfer.total() {
+///         println!("{}/{} bytes", transfer.transferred(), total);
+///     }
+/// }));
+/// ```
+#[derive(Clone)]
+pub struct OnProgress {
+    f: Arc<dyn Fn(&Transfer) + Send + Sync>,
+    stall_timeout: Option<Duration>,
+}
+
+impl OnProgress {
+    /// Creates an `OnProgress` that calls `f` as data is transferred.
+    pub fn new<F>(f: F) -> OnProgress
+    where
+        F: Fn(&Transfer) + Send + Sync + 'static,
+    {
+        OnProgress {
+            f: Arc::new(f),
+            stall_timeout: None,
+        }
+    }
+
+    /// Fails the body if no data arrives for `timeout`.
+    ///
+    /// The time is counted from when the body is first found waiting for
+    /// more data, so a consumer that is slow to poll it isn't counted as a
+    /// stall. The body then returns an error for which
+    /// [`Error::is_body_stalled`](::Error::is_body_stalled) is true, once,
+    /// and ends.
+    #[cfg(feature = "runtime")]
+    pub fn stall_timeout(mut self, timeout: Duration) -> OnProgress {
+        self.stall_timeout = Some(timeout);
+        self
+    }
+
+    pub(crate) fn call(&self, transfer: &Transfer) {
+        (self.f)(transfer);
+    }
+
+    #[cfg(feature = "runtime")]
+    pub(crate) fn get_stall_timeout(&self) -> Option<Duration> {
+        self.stall_timeout
+    }
+}
+
+impl fmt::Debug for OnProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OnProgress")
+            .field("stall_timeout", &self.stall_timeout)
+            .finish()
+    }
+}
+
+/// The parameters of a `Keep-Alive` response header.
+///
+/// HTTP/1 client connections insert this in the extensions of a persistent
+/// `Response` whose `Keep-Alive` header advertises how long the server keeps
+/// the idle connection open, or how many more requests it accepts on it.
+/// The `Client` pool follows it, so that connections the server is about to
+/// close aren't reused. An idle connection is retired up to a second before
+/// the server's `timeout`, to allow for the time a request takes to arrive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeepAlive {
+    timeout: Option<Duration>,
+    max: Option<u32>,
+}
+
+impl KeepAlive {
+    pub(crate) fn new(timeout: Option<Duration>, max: Option<u32>) -> KeepAlive {
+        KeepAlive {
+            timeout,
+            max,
+        }
+    }
+
+    /// How long the server keeps the connection open while idle, from the
+    /// `timeout` parameter.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// How many more requests the server accepts on the connection, from
+    /// the `max` parameter.
+    pub fn max(&self) -> Option<u32> {
+        self.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::{HeaderName, CONTENT_TYPE, HOST};
+    use http::{Response, Version};
+
+    use super::{HeaderCaseMap, Informational};
+
+    #[test]
+    fn header_case_map_keeps_order_and_case() {
+        let mut map = HeaderCaseMap::new();
+        map.append(b"Host").unwrap();
+        map.append(b"X-Custom").unwrap();
+        map.append(b"HOST").unwrap();
+        map.append(b"bad header").unwrap_err();
+
+        assert_eq!(map.len(), 3);
+        let hosts: Vec<&[u8]> = map.get_all(&HOST).collect();
+        assert_eq!(hosts, vec![&b"Host"[..], &b"HOST"[..]]);
+        assert_eq!(map.get_all(&CONTENT_TYPE).count(), 0);
+
+        let custom = HeaderName::from_static("x-custom");
+        let names: Vec<(&HeaderName, &[u8])> = map.iter().collect();
+        assert_eq!(names, vec![(&HOST, &b"Host"[..]), (&custom, &b"X-Custom"[..]), (&HOST, &b"HOST"[..])]);
+    }
+
+    #[test]
+    fn informational_only_for_http11() {
+        let early_hints = || Response::builder().status(103).body(()).unwrap();
+
+        let (info, _rx) = Informational::channel(Version::HTTP_11);
+        info.send(early_hints()).expect("HTTP/1.1");
+
+        let (info, _rx) = Informational::channel(Version::HTTP_10);
+        info.send(early_hints()).expect_err("HTTP/1.0");
+    }
+}
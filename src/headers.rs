@@ -1,8 +1,14 @@
+use std::time::Duration;
+
 use bytes::BytesMut;
 use http::HeaderMap;
-use http::header::{CONTENT_LENGTH, TRANSFER_ENCODING};
+use http::header::{CONTENT_LENGTH, EXPECT, TRANSFER_ENCODING};
 use http::header::{HeaderValue, OccupiedEntry, ValueIter};
 
+use crate::ext::KeepAlive;
+
+pub const KEEP_ALIVE: &str = "keep-alive";
+
 pub fn connection_keep_alive(value: &HeaderValue) -> bool {
     connection_has(value, "keep-alive")
 }
@@ -22,6 +28,47 @@ fn connection_has(value: &HeaderValue, needle: &str) -> bool {
     false
 }
 
+pub fn expect_continue(headers: &HeaderMap) -> bool {
+    headers
+        .get(EXPECT)
+        .map(|value| value.as_bytes().eq_ignore_ascii_case(b"100-continue"))
+        .unwrap_or(false)
+}
+
+pub fn keep_alive_parse_all(headers: &HeaderMap) -> Option<KeepAlive> {
+    let mut timeout = None;
+    let mut max = None;
+    for value in headers.get_all(KEEP_ALIVE) {
+        let s = match value.to_str() {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        for param in s.split(',') {
+            let mut parts = param.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let value = match parts.next() {
+                Some(value) => value.trim().trim_matches('"'),
+                None => continue,
+            };
+            if name.eq_ignore_ascii_case("timeout") {
+                if let Ok(secs) = value.parse() {
+                    timeout = Some(Duration::from_secs(secs));
+                }
+            } else if name.eq_ignore_ascii_case("max") {
+                if let Ok(n) = value.parse() {
+                    max = Some(n);
+                }
+            }
+        }
+    }
+
+    if timeout.is_some() || max.is_some() {
+        Some(KeepAlive::new(timeout, max))
+    } else {
+        None
+    }
+}
+
 pub fn content_length_parse(value: &HeaderValue) -> Option<u64> {
     value
         .to_str()
@@ -94,6 +141,37 @@ pub fn is_chunked_(value: &HeaderValue) -> bool {
     false
 }
 
+pub fn is_chunked_final(value: &HeaderValue) -> bool {
+    // true if `chunked` is only found as the last coding, if at all
+    match value.to_str() {
+        Ok(s) => {
+            let mut codings = s.rsplit(',').map(|coding| coding.trim());
+            codings.next();
+            !codings.any(|coding| coding.eq_ignore_ascii_case("chunked"))
+        },
+        Err(_) => false,
+    }
+}
+
+pub fn is_content_length_digits(value: &HeaderValue) -> bool {
+    let bytes = value.as_bytes();
+    !bytes.is_empty() && bytes.iter().all(|b| b.is_ascii_digit())
+}
+
+pub fn is_known_transfer_encoding(value: &HeaderValue) -> bool {
+    // the codings registered with IANA, plus their legacy aliases
+    const KNOWN: &[&str] = &["chunked", "compress", "deflate", "gzip", "identity", "x-compress", "x-gzip"];
+
+    match value.to_str() {
+        Ok(s) => s
+            .split(',')
+            .map(|coding| coding.trim())
+            .filter(|coding| !coding.is_empty())
+            .all(|coding| KNOWN.iter().any(|known| coding.eq_ignore_ascii_case(known))),
+        Err(_) => false,
+    }
+}
+
 pub fn add_chunked(mut entry: OccupiedEntry<HeaderValue>) {
     const CHUNKED: &'static str = "chunked";
 
@@ -49,9 +49,12 @@ mod mock;
 pub mod body;
 pub mod client;
 pub mod error;
+pub mod ext;
 mod headers;
+pub mod multipart;
 mod proto;
 pub mod server;
 pub mod service;
+pub mod sse;
 #[cfg(feature = "runtime")] pub mod rt;
 pub mod upgrade;
@@ -0,0 +1,359 @@
+use std::collections::VecDeque;
+use std::error::Error as StdError;
+use std::fmt;
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::body::{BoxBody, Chunk, Payload};
+use crate::common::{Pin, Poll, task};
+use super::random_boundary;
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+/// A `multipart/form-data` form to send.
+///
+/// # Example
+///
+/// ```
+/// use hyper::{Body, Request};
+/// use hyper::header::CONTENT_TYPE;
+/// use hyper::multipart::{Form, Part};
+///
+/// let form = Form::new()
+///     .text("title", "Holiday")
+///     .part("photo", Part::bytes(vec![0xFF, 0xD8, 0xFF])
+///         .file_name("beach.jpg")
+///         .content_type("image/jpeg"));
+///
+/// let req = Request::post("http://example.local/upload")
+///     .header(CONTENT_TYPE, form.content_type())
+///     .body(form.into_body())
+///     .unwrap();
+/// # drop(req);
+/// ```
+pub struct Form {
+    boundary: String,
+    parts: Vec<(String, Part)>,
+}
+
+/// A part of a `Form`.
+pub struct Part {
+    file_name: Option<String>,
+    content_type: Option<String>,
+    contents: Contents,
+}
+
+enum Contents {
+    Bytes(Bytes),
+    Stream(BoxBody<Chunk, BoxError>, Option<u64>),
+}
+
+/// The `Payload` of a `Form`.
+///
+/// Created with [`Form::into_body`](Form::into_body).
+#[must_use = "streams do nothing unless polled"]
+pub struct FormBody {
+    parts: VecDeque<(Bytes, Contents)>,
+    current: Option<BoxBody<Chunk, BoxError>>,
+    end: Option<Bytes>,
+    remaining: Option<u64>,
+}
+
+impl Form {
+    /// Creates an empty `Form`, with a random boundary.
+    pub fn new() -> Form {
+        Form {
+            boundary: random_boundary(),
+            parts: Vec::new(),
+        }
+    }
+
+    /// Returns the boundary that separates the parts.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Returns the value of the `Content-Type` header to send this form with.
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// Adds a text field.
+    pub fn text<N, V>(self, name: N, value: V) -> Form
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        self.part(name, Part::text(value))
+    }
+
+    /// Adds a part.
+    pub fn part<N: Into<String>>(mut self, name: N, part: Part) -> Form {
+        self.parts.push((name.into(), part));
+        self
+    }
+
+    /// Turns this form into a body.
+    pub fn into_body(self) -> FormBody {
+        let mut remaining = Some(0u64);
+        let mut parts = VecDeque::with_capacity(self.parts.len());
+        for (name, part) in self.parts {
+            let head = part.head(&self.boundary, &name);
+            let len = match part.contents {
+                Contents::Bytes(ref bytes) => Some(bytes.len() as u64),
+                Contents::Stream(_, len) => len,
+            };
+            // the head, the contents, and the CRLF after them.
+            remaining = match (remaining, len) {
+                (Some(total), Some(len)) => Some(total + head.len() as u64 + len + 2),
+                _ => None,
+            };
+            parts.push_back((head, part.contents));
+        }
+        let end = Bytes::from(format!("--{}--\r\n", self.boundary));
+        FormBody {
+            parts,
+            current: None,
+            remaining: remaining.map(|total| total + end.len() as u64),
+            end: Some(end),
+        }
+    }
+}
+
+impl Default for Form {
+    fn default() -> Form {
+        Form::new()
+    }
+}
+
+impl fmt::Debug for Form {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Form")
+            .field("boundary", &self.boundary)
+            .field("parts", &self.parts)
+            .finish()
+    }
+}
+
+impl Part {
+    /// Creates a part of text.
+    pub fn text<V: Into<String>>(value: V) -> Part {
+        Part::new(Contents::Bytes(Bytes::from(value.into())))
+    }
+
+    /// Creates a part of bytes.
+    pub fn bytes<B: Into<Bytes>>(bytes: B) -> Part {
+        Part::new(Contents::Bytes(bytes.into()))
+    }
+
+    /// Creates a part streamed from a body.
+    ///
+    /// Its `content_length` hint is used as the size of the part, so it
+    /// must be exact if given.
+    pub fn stream<B>(body: B) -> Part
+    where
+        B: Payload<Data = Chunk>,
+    {
+        let len = body.content_length();
+        Part::new(Contents::Stream(body.map_err(Into::into).boxed(), len))
+    }
+
+    fn new(contents: Contents) -> Part {
+        Part {
+            file_name: None,
+            content_type: None,
+            contents,
+        }
+    }
+
+    /// Sets the file name of this part.
+    pub fn file_name<N: Into<String>>(mut self, name: N) -> Part {
+        self.file_name = Some(name.into());
+        self
+    }
+
+    /// Sets the `Content-Type` of this part.
+    pub fn content_type<T: Into<String>>(mut self, content_type: T) -> Part {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    fn head(&self, boundary: &str, name: &str) -> Bytes {
+        let mut head = BytesMut::with_capacity(boundary.len() + name.len() + 64);
+        put_str(&mut head, "--");
+        put_str(&mut head, boundary);
+        put_str(&mut head, "\r\nContent-Disposition: form-data; name=\"");
+        put_escaped(&mut head, name);
+        put_str(&mut head, "\"");
+        if let Some(ref file_name) = self.file_name {
+            put_str(&mut head, "; filename=\"");
+            put_escaped(&mut head, file_name);
+            put_str(&mut head, "\"");
+        }
+        put_str(&mut head, "\r\n");
+        if let Some(ref content_type) = self.content_type {
+            put_str(&mut head, "Content-Type: ");
+            put_escaped(&mut head, content_type);
+            put_str(&mut head, "\r\n");
+        }
+        put_str(&mut head, "\r\n");
+        head.freeze()
+    }
+}
+
+impl fmt::Debug for Part {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Part")
+            .field("file_name", &self.file_name)
+            .field("content_type", &self.content_type)
+            .finish()
+    }
+}
+
+/// Writes `value`, escaping what would end a quoted string or a header,
+/// like browsers do.
+fn put_escaped(dst: &mut BytesMut, value: &str) {
+    for &b in value.as_bytes() {
+        match b {
+            b'"' => put_str(dst, "%22"),
+            b'\r' => put_str(dst, "%0D"),
+            b'\n' => put_str(dst, "%0A"),
+            _ => {
+                dst.reserve(1);
+                dst.put_u8(b);
+            },
+        }
+    }
+}
+
+/// Writes `s`, growing `dst` first since `BufMut::put` doesn't.
+fn put_str(dst: &mut BytesMut, s: &str) {
+    dst.reserve(s.len());
+    dst.put(s);
+}
+
+impl FormBody {
+    fn sent(&mut self, len: usize) {
+        if let Some(ref mut remaining) = self.remaining {
+            *remaining = remaining.saturating_sub(len as u64);
+        }
+    }
+}
+
+impl Payload for FormBody {
+    type Data = Chunk;
+    type Error = BoxError;
+
+    fn poll_data(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let me = &mut *self;
+        if let Some(ref mut current) = me.current {
+            match ready!(Pin::new(current).poll_data(cx)) {
+                Some(Ok(chunk)) => {
+                    me.sent(chunk.len());
+                    return Poll::Ready(Some(Ok(chunk)));
+                },
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => {
+                    me.current = None;
+                    me.sent(2);
+                    return Poll::Ready(Some(Ok(Chunk::from("\r\n"))));
+                },
+            }
+        }
+
+        match me.parts.pop_front() {
+            Some((head, Contents::Bytes(bytes))) => {
+                let mut buf = BytesMut::with_capacity(head.len() + bytes.len() + 2);
+                buf.put(head);
+                buf.put(bytes);
+                buf.put("\r\n");
+                me.sent(buf.len());
+                Poll::Ready(Some(Ok(Chunk::from(buf.freeze()))))
+            },
+            Some((head, Contents::Stream(body, _))) => {
+                me.current = Some(body);
+                me.sent(head.len());
+                Poll::Ready(Some(Ok(Chunk::from(head))))
+            },
+            None => match me.end.take() {
+                Some(end) => {
+                    me.sent(end.len());
+                    Poll::Ready(Some(Ok(Chunk::from(end))))
+                },
+                None => Poll::Ready(None),
+            },
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.end.is_none()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.remaining
+    }
+}
+
+impl fmt::Debug for FormBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FormBody")
+            .field("remaining", &self.remaining)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Body;
+    use crate::body::{to_bytes, Payload};
+    use super::{Form, Part};
+
+    #[tokio::test]
+    async fn form_body_encodes_parts_with_length() {
+        let form = Form::new()
+            .text("title", "hi")
+            .part("file", Part::stream(Body::from("abc"))
+                .file_name("a\"b.txt")
+                .content_type("text/plain"));
+        let boundary = form.boundary().to_owned();
+        let body = form.into_body();
+        let len = body.content_length().expect("sized");
+
+        let bytes = to_bytes(body, 4096).await.expect("to_bytes");
+        assert_eq!(bytes.len() as u64, len);
+        let expected = format!(
+            "--{b}\r\n\
+             Content-Disposition: form-data; name=\"title\"\r\n\
+             \r\n\
+             hi\r\n\
+             --{b}\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"a%22b.txt\"\r\n\
+             Content-Type: text/plain\r\n\
+             \r\n\
+             abc\r\n\
+             --{b}--\r\n",
+            b = boundary,
+        );
+        assert_eq!(bytes, expected);
+
+        let unknown = Form::new().part("x", Part::stream(Body::wrap_stream(
+            futures_util::stream::iter(vec![Ok::<_, ::std::io::Error>("abc")])
+        )));
+        assert_eq!(unknown.into_body().content_length(), None);
+    }
+
+    #[test]
+    fn part_head_grows_for_long_escaped_values() {
+        let file_name = "\"".repeat(100);
+        let part = Part::text("").file_name(file_name).content_type("application/octet-stream");
+        let head = part.head("b", "n");
+        let expected = format!(
+            "--b\r\n\
+             Content-Disposition: form-data; name=\"n\"; filename=\"{}\"\r\n\
+             Content-Type: application/octet-stream\r\n\
+             \r\n",
+            "%22".repeat(100),
+        );
+        assert_eq!(head, expected.as_bytes());
+    }
+}
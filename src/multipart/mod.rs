@@ -0,0 +1,137 @@
+//! Streaming `multipart/form-data` bodies
+//!
+//! HTML forms that upload files send their fields as `multipart/form-data`.
+//! This module can both build and parse such bodies, without buffering the
+//! contents of the parts:
+//!
+//! - A [`Form`](multipart::Form) collects text fields and [`Part`](multipart::Part)s,
+//!   whose contents can be any `Payload`, such as a file. It turns into a
+//!   [`FormBody`](multipart::FormBody) to send, which knows its exact
+//!   `content_length` when all of its parts do.
+//! - A [`Multipart`](multipart::Multipart) reads a received `Body`, one
+//!   [`Field`](multipart::Field) at a time, and the contents of each field
+//!   as `Chunk`s, as they arrive. It is also a `Stream` of its fields. The
+//!   size of each field and of the whole body can be limited.
+//!
+//! # Example
+//!
+//! ```
+//! # #![feature(async_await)]
+//! # fn main() {}
+//! # async fn run(req: hyper::Request<hyper::Body>) -> hyper::Result<()> {
+//! use hyper::multipart::Multipart;
+//!
+//! let mut form = Multipart::from_request(req)?
+//!     .max_field_size(10 * 1024 * 1024);
+//!
+//! while let Some(field) = form.next_field().await {
+//!     let field = field?;
+//!     println!("field {:?}, file {:?}", field.name(), field.file_name());
+//!     while let Some(chunk) = form.next_chunk().await {
+//!         let chunk = chunk?;
+//!         println!("  {} bytes", chunk.len());
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+pub use self::form::{Form, FormBody, Part};
+pub use self::parse::{Field, Multipart};
+
+mod form;
+mod parse;
+
+/// Creates a boundary that is very unlikely to be found in any part.
+fn random_boundary() -> String {
+    // Each `RandomState` is seeded with random keys.
+    let a = RandomState::new().build_hasher().finish();
+    let b = RandomState::new().build_hasher().finish();
+    format!("{:016x}{:016x}", a, b)
+}
+
+/// Finds the value of the parameter `name` in a header value like
+/// `form-data; name="field"; filename="a.txt"`.
+///
+/// Quoted values may contain `;`, and `\` escapes the next character.
+fn param(value: &str, name: &str) -> Option<String> {
+    let mut rest = value;
+    // skip the value before the parameters
+    match split_param(rest) {
+        (_, Some(next)) => rest = next,
+        (_, None) => return None,
+    }
+    loop {
+        let (param, next) = split_param(rest);
+        let mut kv = param.splitn(2, '=');
+        let key = kv.next().unwrap_or("").trim();
+        if key.eq_ignore_ascii_case(name) {
+            return kv.next().map(unquote);
+        }
+        match next {
+            Some(next) => rest = next,
+            None => return None,
+        }
+    }
+}
+
+/// Splits `s` at the first `;` that isn't quoted.
+fn split_param(s: &str) -> (&str, Option<&str>) {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if quoted && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        } else if c == ';' && !quoted {
+            return (&s[..i], Some(&s[i + 1..]));
+        }
+    }
+    (s, None)
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return value.to_owned();
+    }
+    let mut unquoted = String::with_capacity(value.len() - 2);
+    let mut chars = value[1..value.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(next) = chars.next() {
+                unquoted.push(next);
+            }
+        } else {
+            unquoted.push(c);
+        }
+    }
+    unquoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{param, random_boundary};
+
+    #[test]
+    fn param_handles_quotes_and_case() {
+        let value = r#"form-data; name="a;b"; FILENAME="c \"d\".txt""#;
+        assert_eq!(param(value, "name").as_ref().map(|s| &**s), Some("a;b"));
+        assert_eq!(param(value, "filename").as_ref().map(|s| &**s), Some("c \"d\".txt"));
+        assert_eq!(param(value, "other"), None);
+        assert_eq!(param("multipart/form-data; boundary=abc", "boundary").as_ref().map(|s| &**s), Some("abc"));
+        assert_eq!(param("form-data", "name"), None);
+    }
+
+    #[test]
+    fn random_boundaries_differ() {
+        let a = random_boundary();
+        assert_eq!(a.len(), 32);
+        assert_ne!(a, random_boundary());
+    }
+}
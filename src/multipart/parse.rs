@@ -0,0 +1,474 @@
+use std::fmt;
+
+use bytes::{Bytes, BytesMut};
+use http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use http::Request;
+use httparse;
+
+use futures_core::Stream;
+
+use crate::body::{Body, Chunk, Payload};
+use crate::common::{Pin, Poll, task};
+use super::param;
+
+/// The most bytes the headers of a single field may take.
+const MAX_HEADERS_SIZE: usize = 8 * 1024;
+
+/// The most headers a single field may have.
+const MAX_HEADERS: usize = 32;
+
+/// A received `multipart/form-data` body, read one field at a time.
+///
+/// The contents of each field are read as they arrive, with
+/// [`next_chunk`](Multipart::next_chunk), after the field has been
+/// returned by [`next_field`](Multipart::next_field).
+///
+/// A `Multipart` is also a `Stream` of its fields. Polling it for the
+/// next field skips whatever contents of the current field weren't read.
+pub struct Multipart {
+    body: Body,
+    buf: BytesMut,
+    /// `\r\n--` and the boundary.
+    delimiter: Bytes,
+    state: State,
+    field_size: u64,
+    total_size: u64,
+    max_field_size: Option<u64>,
+    max_total_size: Option<u64>,
+}
+
+/// A field of a `Multipart` body.
+#[derive(Debug)]
+pub struct Field {
+    headers: HeaderMap,
+    name: String,
+    file_name: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Preamble,
+    AfterDelimiter,
+    Headers,
+    Body,
+    End,
+}
+
+impl Multipart {
+    /// Reads a body whose parts are separated by `boundary`.
+    pub fn new<S: Into<String>>(body: Body, boundary: S) -> Multipart {
+        let delimiter = Bytes::from(format!("\r\n--{}", boundary.into()));
+        // The first delimiter doesn't need to follow a line, so pretend
+        // that the body starts with one.
+        let mut buf = BytesMut::with_capacity(8192);
+        buf.extend_from_slice(b"\r\n");
+        Multipart {
+            body,
+            buf,
+            delimiter,
+            state: State::Preamble,
+            field_size: 0,
+            total_size: 0,
+            max_field_size: None,
+            max_total_size: None,
+        }
+    }
+
+    /// Reads the body of a request, using the boundary of its
+    /// `Content-Type` header.
+    ///
+    /// # Error
+    ///
+    /// Fails if the request isn't `multipart`, or has no boundary.
+    pub fn from_request(req: Request<Body>) -> crate::Result<Multipart> {
+        let boundary = req.headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| {
+                value.len() > 10 && value[..10].eq_ignore_ascii_case("multipart/")
+            })
+            .and_then(|value| param(value, "boundary"))
+            .filter(|boundary| !boundary.is_empty());
+        match boundary {
+            Some(boundary) => Ok(Multipart::new(req.into_body(), boundary)),
+            None => Err(crate::Error::new_multipart("missing multipart boundary")),
+        }
+    }
+
+    /// Sets the most bytes the contents of a single field may have.
+    ///
+    /// Reading a larger field fails with an error where
+    /// `is_body_too_large` is true.
+    ///
+    /// Default is no limit.
+    pub fn max_field_size(mut self, max: u64) -> Multipart {
+        self.max_field_size = Some(max);
+        self
+    }
+
+    /// Sets the most bytes the whole body may have.
+    ///
+    /// Reading a larger body fails with an error where
+    /// `is_body_too_large` is true.
+    ///
+    /// Default is no limit.
+    pub fn max_total_size(mut self, max: u64) -> Multipart {
+        self.max_total_size = Some(max);
+        self
+    }
+
+    /// Reads the headers of the next field.
+    ///
+    /// Any contents of the current field that haven't been read are
+    /// skipped. Returns `None` after the last field, or after an error.
+    pub async fn next_field(&mut self) -> Option<crate::Result<Field>> {
+        futures_util::future::poll_fn(|cx| self.poll_next_field(cx)).await
+    }
+
+    /// Reads the next chunk of the contents of the current field.
+    ///
+    /// Returns `None` at the end of the field.
+    pub async fn next_chunk(&mut self) -> Option<crate::Result<Chunk>> {
+        futures_util::future::poll_fn(|cx| self.poll_next_chunk(cx)).await
+    }
+
+    /// Polls for the headers of the next field.
+    ///
+    /// This is the polling version of [`next_field`](Multipart::next_field),
+    /// which the `Stream` implementation also uses.
+    pub fn poll_next_field(&mut self, cx: &mut task::Context<'_>) -> Poll<Option<crate::Result<Field>>> {
+        match ready!(self.poll_field(cx)) {
+            Ok(Some(field)) => Poll::Ready(Some(Ok(field))),
+            Ok(None) => Poll::Ready(None),
+            Err(err) => {
+                self.state = State::End;
+                Poll::Ready(Some(Err(err)))
+            },
+        }
+    }
+
+    /// Polls for the next chunk of the contents of the current field.
+    ///
+    /// This is the polling version of [`next_chunk`](Multipart::next_chunk).
+    pub fn poll_next_chunk(&mut self, cx: &mut task::Context<'_>) -> Poll<Option<crate::Result<Chunk>>> {
+        match ready!(self.poll_chunk(cx)) {
+            Ok(Some(chunk)) => Poll::Ready(Some(Ok(chunk))),
+            Ok(None) => Poll::Ready(None),
+            Err(err) => {
+                self.state = State::End;
+                Poll::Ready(Some(Err(err)))
+            },
+        }
+    }
+
+    fn poll_field(&mut self, cx: &mut task::Context<'_>) -> Poll<crate::Result<Option<Field>>> {
+        loop {
+            match self.state {
+                State::Preamble => {
+                    if let Some(pos) = self.find_delimiter() {
+                        let _ = self.buf.split_to(pos + self.delimiter.len());
+                        self.state = State::AfterDelimiter;
+                        continue;
+                    }
+                    // keep what may be the start of the delimiter
+                    let keep = self.delimiter.len() - 1;
+                    if self.buf.len() > keep {
+                        let discard = self.buf.len() - keep;
+                        let _ = self.buf.split_to(discard);
+                    }
+                    if !ready!(self.poll_fill(cx))? {
+                        return Poll::Ready(Err(crate::Error::new_multipart("no multipart boundary found")));
+                    }
+                },
+                State::AfterDelimiter => {
+                    if self.buf.len() >= 2 && &self.buf[..2] == b"--" {
+                        trace!("multipart end");
+                        self.state = State::End;
+                        return Poll::Ready(Ok(None));
+                    }
+                    // the delimiter may be followed by whitespace before its line ends
+                    let end = self.buf.iter().position(|&b| b != b' ' && b != b'\t');
+                    match end {
+                        Some(end) if self.buf.len() >= end + 2 => {
+                            if &self.buf[end..end + 2] != b"\r\n" {
+                                return Poll::Ready(Err(crate::Error::new_multipart("invalid multipart boundary line")));
+                            }
+                            let _ = self.buf.split_to(end + 2);
+                            self.state = State::Headers;
+                            continue;
+                        },
+                        _ if self.buf.len() > MAX_HEADERS_SIZE => {
+                            return Poll::Ready(Err(crate::Error::new_multipart("invalid multipart boundary line")));
+                        },
+                        _ => (),
+                    }
+                    if !ready!(self.poll_fill(cx))? {
+                        return Poll::Ready(Err(crate::Error::new_multipart("unexpected end of multipart body")));
+                    }
+                },
+                State::Headers => {
+                    if let Some(field) = self.parse_headers()? {
+                        self.state = State::Body;
+                        self.field_size = 0;
+                        return Poll::Ready(Ok(Some(field)));
+                    }
+                    if self.buf.len() > MAX_HEADERS_SIZE {
+                        return Poll::Ready(Err(crate::Error::new_multipart("multipart headers too large")));
+                    }
+                    if !ready!(self.poll_fill(cx))? {
+                        return Poll::Ready(Err(crate::Error::new_multipart("unexpected end of multipart body")));
+                    }
+                },
+                State::Body => {
+                    while let Some(chunk) = ready!(self.poll_chunk(cx))? {
+                        trace!("skipping {} bytes of multipart field", chunk.len());
+                    }
+                },
+                State::End => return Poll::Ready(Ok(None)),
+            }
+        }
+    }
+
+    fn poll_chunk(&mut self, cx: &mut task::Context<'_>) -> Poll<crate::Result<Option<Chunk>>> {
+        if self.state != State::Body {
+            return Poll::Ready(Ok(None));
+        }
+        loop {
+            let len = match self.find_delimiter() {
+                Some(0) => {
+                    let _ = self.buf.split_to(self.delimiter.len());
+                    self.state = State::AfterDelimiter;
+                    return Poll::Ready(Ok(None));
+                },
+                Some(pos) => pos,
+                // what may be the start of the delimiter has to wait
+                None => self.buf.len().saturating_sub(self.delimiter.len() - 1),
+            };
+            if len > 0 {
+                self.field_size += len as u64;
+                if let Some(max) = self.max_field_size {
+                    if self.field_size > max {
+                        debug!("multipart field larger than {} bytes", max);
+                        return Poll::Ready(Err(crate::Error::new_body_too_large()));
+                    }
+                }
+                return Poll::Ready(Ok(Some(Chunk::from(self.buf.split_to(len).freeze()))));
+            }
+            if !ready!(self.poll_fill(cx))? {
+                return Poll::Ready(Err(crate::Error::new_multipart("unexpected end of multipart body")));
+            }
+        }
+    }
+
+    /// Reads more of the body into the buffer, returning false at its end.
+    fn poll_fill(&mut self, cx: &mut task::Context<'_>) -> Poll<crate::Result<bool>> {
+        let chunk = match ready!(Pin::new(&mut self.body).poll_data(cx)) {
+            Some(chunk) => chunk?,
+            None => return Poll::Ready(Ok(false)),
+        };
+        self.total_size += chunk.len() as u64;
+        if let Some(max) = self.max_total_size {
+            if self.total_size > max {
+                debug!("multipart body larger than {} bytes", max);
+                return Poll::Ready(Err(crate::Error::new_body_too_large()));
+            }
+        }
+        self.buf.extend_from_slice(&chunk);
+        Poll::Ready(Ok(true))
+    }
+
+    fn find_delimiter(&self) -> Option<usize> {
+        self.buf
+            .windows(self.delimiter.len())
+            .position(|window| window == &self.delimiter[..])
+    }
+
+    fn parse_headers(&mut self) -> crate::Result<Option<Field>> {
+        let mut parsed = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let (len, parsed) = match httparse::parse_headers(&self.buf, &mut parsed) {
+            Ok(httparse::Status::Complete(complete)) => complete,
+            Ok(httparse::Status::Partial) => return Ok(None),
+            Err(err) => return Err(crate::Error::new_multipart(err)),
+        };
+
+        let mut headers = HeaderMap::with_capacity(parsed.len());
+        for header in parsed {
+            let name = HeaderName::from_bytes(header.name.as_bytes())
+                .map_err(crate::Error::new_multipart)?;
+            let value = HeaderValue::from_bytes(header.value)
+                .map_err(crate::Error::new_multipart)?;
+            headers.append(name, value);
+        }
+        let _ = self.buf.split_to(len);
+
+        let disposition = headers
+            .get(CONTENT_DISPOSITION)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| crate::Error::new_multipart("multipart field without Content-Disposition"))?;
+        let name = param(disposition, "name")
+            .ok_or_else(|| crate::Error::new_multipart("multipart field without name"))?;
+        let file_name = param(disposition, "filename");
+        trace!("multipart field {:?}", name);
+
+        Ok(Some(Field {
+            headers,
+            name,
+            file_name,
+        }))
+    }
+}
+
+impl Stream for Multipart {
+    type Item = crate::Result<Field>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_next_field(cx)
+    }
+}
+
+impl fmt::Debug for Multipart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Multipart")
+            .field("state", &self.state)
+            .field("max_field_size", &self.max_field_size)
+            .field("max_total_size", &self.max_total_size)
+            .finish()
+    }
+}
+
+impl Field {
+    /// Returns the name of this field.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the file name of this field, if it is a file.
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_ref().map(|name| &**name)
+    }
+
+    /// Returns the `Content-Type` of this field, if any.
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+    }
+
+    /// Returns the headers of this field.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::Body;
+    use crate::body::to_bytes;
+    use super::super::{Form, Part};
+    use super::Multipart;
+
+    /// Sends `bytes` in chunks of `size`, so fields and delimiters are split.
+    fn body_in_chunks(bytes: Bytes, size: usize) -> Body {
+        let chunks = bytes
+            .chunks(size)
+            .map(|chunk| Ok::<_, ::std::io::Error>(chunk.to_vec()))
+            .collect::<Vec<_>>();
+        Body::wrap_stream(futures_util::stream::iter(chunks))
+    }
+
+    async fn encode(form: Form) -> (String, Bytes) {
+        let boundary = form.boundary().to_owned();
+        let bytes = to_bytes(form.into_body(), 1024 * 1024).await.expect("to_bytes");
+        (boundary, bytes)
+    }
+
+    #[tokio::test]
+    async fn multipart_parses_form_streamed_in_pieces() {
+        let file = vec![b'x'; 10_000];
+        let (boundary, bytes) = encode(Form::new()
+            .text("title", "hello\r\nworld")
+            .part("skipped", Part::text("not read"))
+            .part("upload", Part::bytes(file.clone())
+                .file_name("x.bin")
+                .content_type("application/octet-stream"))).await;
+
+        for &size in &[1, 7, 4096] {
+            let mut form = Multipart::new(body_in_chunks(bytes.clone(), size), &*boundary);
+
+            let field = form.next_field().await.expect("title").expect("title");
+            assert_eq!(field.name(), "title");
+            assert_eq!(field.file_name(), None);
+            let mut title = Vec::new();
+            while let Some(chunk) = form.next_chunk().await {
+                title.extend_from_slice(&chunk.expect("chunk"));
+            }
+            assert_eq!(title, b"hello\r\nworld");
+
+            let field = form.next_field().await.expect("skipped").expect("skipped");
+            assert_eq!(field.name(), "skipped");
+
+            let field = form.next_field().await.expect("upload").expect("upload");
+            assert_eq!(field.name(), "upload");
+            assert_eq!(field.file_name(), Some("x.bin"));
+            assert_eq!(field.content_type(), Some("application/octet-stream"));
+            let mut upload = Vec::new();
+            while let Some(chunk) = form.next_chunk().await {
+                upload.extend_from_slice(&chunk.expect("chunk"));
+            }
+            assert_eq!(upload, file);
+
+            assert!(form.next_field().await.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn multipart_is_a_stream_of_fields() {
+        use futures_util::StreamExt;
+
+        let (boundary, bytes) = encode(Form::new()
+            .text("a", "1")
+            .text("b", "2")).await;
+
+        let mut form = Multipart::new(body_in_chunks(bytes, 5), &*boundary);
+        let field = form.next().await.expect("a").expect("a");
+        assert_eq!(field.name(), "a");
+        let chunk = form.next_chunk().await.expect("chunk").expect("chunk");
+        assert_eq!(&chunk[..], b"1");
+        let field = form.next().await.expect("b").expect("b");
+        assert_eq!(field.name(), "b");
+        assert!(form.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn multipart_limits_field_and_total_size() {
+        let (boundary, bytes) = encode(Form::new()
+            .text("small", "ok")
+            .part("big", Part::bytes(vec![0; 1000]))).await;
+
+        let mut form = Multipart::new(body_in_chunks(bytes.clone(), 64), &*boundary)
+            .max_field_size(100);
+        let _small = form.next_field().await.expect("small").expect("small");
+        let _big = form.next_field().await.expect("big").expect("big");
+        let mut err = None;
+        while let Some(chunk) = form.next_chunk().await {
+            if let Err(e) = chunk {
+                err = Some(e);
+            }
+        }
+        assert!(err.expect("too large").is_body_too_large());
+        assert!(form.next_field().await.is_none());
+
+        let mut form = Multipart::new(body_in_chunks(bytes, 64), &*boundary)
+            .max_total_size(500);
+        let mut err = None;
+        while let Some(field) = form.next_field().await {
+            if let Err(e) = field {
+                err = Some(e);
+            }
+        }
+        assert!(err.expect("too large").is_body_too_large());
+    }
+}
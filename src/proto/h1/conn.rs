@@ -1,18 +1,32 @@
+use std::collections::VecDeque;
 use std::fmt;
 use std::io::{self};
 use std::marker::PhantomData;
+#[cfg(all(feature = "runtime", target_os = "linux"))]
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+#[cfg(feature = "runtime")]
+use std::time::Instant;
 
 use bytes::{Buf, Bytes};
 use http::{HeaderMap, Method, Version};
-use http::header::{HeaderValue, CONNECTION};
+use http::header::{HeaderName, HeaderValue, CONNECTION};
 use tokio_io::{AsyncRead, AsyncWrite};
+#[cfg(feature = "runtime")]
+use tokio_timer::Delay;
 
 use crate::Chunk;
+use crate::body::internal::FileRegion;
+use crate::ext::OnInformational;
+#[cfg(feature = "runtime")]
+use crate::common::Future;
 use crate::common::{Pin, Poll, Unpin, task};
+use crate::common::rate::RateLimits;
 use crate::proto::{BodyLength, DecodedLength, MessageHead};
-use crate::headers::connection_keep_alive;
+use crate::headers::{self, connection_close, connection_keep_alive};
+use crate::server::conn::ErrorResponder;
 use super::io::{Buffered};
-use super::{EncodedBuf, Encode, Encoder, /*Decode,*/ Decoder, Http1Transaction, ParseContext};
+use super::{EncodedBuf, Encode, Encoder, /*Decode,*/ Decoder, HeadLimits, HeaderSlots, Http1Transaction, Leniency, ParseContext};
 
 const H2_PREFACE: &'static [u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
 
@@ -40,7 +54,25 @@ where I: AsyncRead + AsyncWrite + Unpin,
             state: State {
                 allow_half_close: true,
                 cached_headers: None,
+                header_slots: HeaderSlots::default(),
                 error: None,
+                error_responder: None,
+                head_limits: HeadLimits::default(),
+                preserve_header_case: false,
+                leniency: Leniency::default(),
+                strict_framing: false,
+                on_informational: None,
+                expect_continue_timeout: Some(Duration::from_secs(1)),
+                pipeline: false,
+                pipelined: VecDeque::new(),
+                keep_alive_timeout: None,
+                keep_alive_header: false,
+                max_body_size: None,
+                body_limit: None,
+                #[cfg(feature = "runtime")]
+                keep_alive_delay: None,
+                #[cfg(feature = "runtime")]
+                expect_continue_delay: None,
                 keep_alive: KA::Busy,
                 method: None,
                 title_case_headers: false,
@@ -64,6 +96,22 @@ where I: AsyncRead + AsyncWrite + Unpin,
         self.io.set_max_buf_size(max);
     }
 
+    pub(crate) fn set_rate_limits(&mut self, limits: RateLimits) {
+        if let Some(limit) = limits.read {
+            self.io.set_read_rate_limit(limit);
+        }
+        if let Some(limit) = limits.write {
+            self.io.set_write_rate_limit(limit);
+        }
+    }
+
+    /// Allows bodies that are files to be sent with `sendfile`, straight to
+    /// `socket`, which must be the socket of the IO.
+    #[cfg(all(feature = "runtime", target_os = "linux"))]
+    pub(crate) fn set_sendfile_socket(&mut self, socket: RawFd) {
+        self.io.set_sendfile_socket(socket);
+    }
+
     pub fn set_read_buf_exact_size(&mut self, sz: usize) {
         self.io.set_read_buf_exact_size(sz);
     }
@@ -76,6 +124,46 @@ where I: AsyncRead + AsyncWrite + Unpin,
         self.state.title_case_headers = true;
     }
 
+    pub(crate) fn set_head_limits(&mut self, limits: HeadLimits) {
+        self.state.head_limits = limits;
+    }
+
+    pub(crate) fn set_preserve_header_case(&mut self) {
+        self.state.preserve_header_case = true;
+    }
+
+    pub(crate) fn set_leniency(&mut self, leniency: Leniency) {
+        self.state.leniency = leniency;
+    }
+
+    pub(crate) fn set_strict_framing(&mut self) {
+        self.state.strict_framing = true;
+    }
+
+    pub(crate) fn set_expect_continue_timeout(&mut self, timeout: Option<Duration>) {
+        self.state.expect_continue_timeout = timeout;
+    }
+
+    pub(crate) fn set_keep_alive_timeout(&mut self, timeout: Duration) {
+        self.state.keep_alive_timeout = Some(timeout);
+    }
+
+    pub(crate) fn set_keep_alive_header(&mut self) {
+        self.state.keep_alive_header = true;
+    }
+
+    pub(crate) fn set_max_body_size(&mut self, max: u64) {
+        self.state.max_body_size = Some(max);
+    }
+
+    pub(crate) fn set_pipeline(&mut self) {
+        self.state.pipeline = true;
+    }
+
+    pub(crate) fn set_error_responder(&mut self, responder: ErrorResponder) {
+        self.state.error_responder = Some(responder);
+    }
+
     pub(crate) fn set_disable_half_close(&mut self) {
         self.state.allow_half_close = false;
     }
@@ -103,7 +191,9 @@ where I: AsyncRead + AsyncWrite + Unpin,
                     true
                 } else {
                     match self.state.writing {
-                        Writing::Init => false,
+                        // when pipelining, a request may still be waiting
+                        // for its response
+                        Writing::Init => self.state.method.is_some(),
                         _ => true,
                     }
                 }
@@ -114,7 +204,16 @@ where I: AsyncRead + AsyncWrite + Unpin,
 
     pub fn can_read_body(&self) -> bool {
         match self.state.reading {
-            Reading::Body(..) => true,
+            Reading::Body(..) |
+            Reading::Continue(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the body of the current message waits for `100 Continue`.
+    pub(crate) fn is_read_continue(&self) -> bool {
+        match self.state.reading {
+            Reading::Continue(..) => true,
             _ => false,
         }
     }
@@ -133,14 +232,41 @@ where I: AsyncRead + AsyncWrite + Unpin,
         debug_assert!(self.can_read_head());
         trace!("Conn::read_head");
 
-        let msg = match ready!(self.io.parse::<T>(cx, ParseContext {
+        let mut received_continue = false;
+        let parsed = self.io.parse::<T>(cx, ParseContext {
             cached_headers: &mut self.state.cached_headers,
+            header_slots: &mut self.state.header_slots,
             req_method: &mut self.state.method,
-        })) {
-            Ok(msg) => msg,
-            Err(e) => return self.on_read_head_error(e),
+            limits: self.state.head_limits,
+            preserve_header_case: self.state.preserve_header_case,
+            leniency: self.state.leniency,
+            strict_framing: self.state.strict_framing,
+            on_informational: &mut self.state.on_informational,
+            received_continue: &mut received_continue,
+        });
+
+        if received_continue {
+            self.on_continue();
+        }
+
+        let msg = match parsed {
+            Poll::Ready(Ok(msg)) => msg,
+            Poll::Ready(Err(e)) => return self.on_read_head_error(e),
+            Poll::Pending => {
+                if self.poll_keep_alive_timeout(cx).is_ready() {
+                    debug!("idle keep-alive connection timed out");
+                    self.state.close();
+                    return Poll::Ready(None);
+                }
+                return Poll::Pending;
+            },
         };
 
+        #[cfg(feature = "runtime")]
+        {
+            self.state.keep_alive_delay = None;
+        }
+
         // Note: don't deconstruct `msg` into local variables, it appears
         // the optimizer doesn't remove the extra copies.
 
@@ -150,6 +276,23 @@ where I: AsyncRead + AsyncWrite + Unpin,
         self.state.keep_alive &= msg.keep_alive;
         self.state.version = msg.head.version;
 
+        if let Writing::Continue(..) = self.state.writing {
+            debug!("final response received before 100 Continue, skipping body");
+            self.skip_continue_body();
+        }
+
+        if let Some(max) = self.state.max_body_size {
+            if msg.decode.into_opt().map_or(false, |len| len > max) {
+                debug!("incoming {} is larger than the limit ({} bytes)", msg.decode, max);
+                self.state.close_read();
+                return match self.on_parse_error(crate::Error::new_body_too_large()) {
+                    Ok(()) => Poll::Pending,
+                    Err(e) => Poll::Ready(Some(Err(e))),
+                };
+            }
+        }
+        self.state.body_limit = self.state.max_body_size;
+
         if msg.decode == DecodedLength::ZERO {
             debug_assert!(!msg.expect_continue, "expect-continue needs a body");
             self.state.reading = Reading::KeepAlive;
@@ -157,16 +300,44 @@ where I: AsyncRead + AsyncWrite + Unpin,
                 self.try_keep_alive(cx);
             }
         } else {
-            if msg.expect_continue {
-                let cont = b"HTTP/1.1 100 Continue\r\n\r\n";
-                self.io.headers_buf().extend_from_slice(cont);
+            let mut decoder = Decoder::new(msg.decode);
+            if self.state.strict_framing {
+                decoder.set_strict();
             }
-            self.state.reading = Reading::Body(Decoder::new(msg.decode));
+            // `100 Continue` is only sent once the body is read, so that
+            // a final response can be sent instead.
+            self.state.reading = if msg.expect_continue {
+                Reading::Continue(decoder)
+            } else {
+                Reading::Body(decoder)
+            };
         };
 
         Poll::Ready(Some(Ok((msg.head, msg.decode, msg.wants_upgrade))))
     }
 
+    // Polls the timeout of an idle server connection, waiting for the
+    // next request.
+    #[cfg(feature = "runtime")]
+    fn poll_keep_alive_timeout(&mut self, cx: &mut task::Context<'_>) -> Poll<()> {
+        let timeout = match self.state.keep_alive_timeout {
+            Some(timeout) => timeout,
+            None => return Poll::Pending,
+        };
+        if !T::should_read_first() || !self.state.is_idle() || !self.io.read_buf().is_empty() {
+            self.state.keep_alive_delay = None;
+            return Poll::Pending;
+        }
+        let delay = self.state.keep_alive_delay
+            .get_or_insert_with(|| Delay::new(Instant::now() + timeout));
+        Pin::new(delay).poll(cx)
+    }
+
+    #[cfg(not(feature = "runtime"))]
+    fn poll_keep_alive_timeout(&mut self, _cx: &mut task::Context<'_>) -> Poll<()> {
+        Poll::Pending
+    }
+
     fn on_read_head_error<Z>(&mut self, e: crate::Error) -> Poll<Option<crate::Result<Z>>> {
         // If we are currently waiting on a message, then an empty
         // message should be reported as an error. If not, it is just
@@ -189,13 +360,33 @@ where I: AsyncRead + AsyncWrite + Unpin,
         }
     }
 
-    pub fn poll_read_body(&mut self, cx: &mut task::Context<'_>) -> Poll<Option<io::Result<Chunk>>> {
+    pub fn poll_read_body(&mut self, cx: &mut task::Context<'_>) -> Poll<Option<crate::Result<Chunk>>> {
         debug_assert!(self.can_read_body());
 
+        if let Reading::Continue(ref decoder) = self.state.reading {
+            let decoder = decoder.clone();
+            // A final response may have been sent already, then the peer
+            // decides whether to send the body.
+            if let Writing::Init = self.state.writing {
+                trace!("automatically sending 100 Continue");
+                let cont = b"HTTP/1.1 100 Continue\r\n\r\n";
+                self.io.headers_buf().extend_from_slice(cont);
+            }
+            self.state.reading = Reading::Body(decoder);
+        }
+
         let (reading, ret) = match self.state.reading {
             Reading::Body(ref mut decoder) => {
                 match decoder.decode(cx, &mut self.io) {
                     Poll::Ready(Ok(slice)) => {
+                        if let Some(ref mut remaining) = self.state.body_limit {
+                            if slice.len() as u64 > *remaining {
+                                debug!("incoming body is larger than the limit");
+                                self.state.close_read();
+                                return Poll::Ready(Some(Err(crate::Error::new_body_too_large())));
+                            }
+                            *remaining -= slice.len() as u64;
+                        }
                         let (reading, chunk) = if decoder.is_eof() {
                             debug!("incoming body completed");
                             (Reading::KeepAlive, if !slice.is_empty() {
@@ -217,7 +408,7 @@ where I: AsyncRead + AsyncWrite + Unpin,
                     Poll::Pending => return Poll::Pending,
                     Poll::Ready(Err(e)) => {
                         debug!("decode stream error: {}", e);
-                        (Reading::Closed, Poll::Ready(Some(Err(e))))
+                        (Reading::Closed, Poll::Ready(Some(Err(crate::Error::new_body(e)))))
                     },
                 }
             },
@@ -324,6 +515,7 @@ where I: AsyncRead + AsyncWrite + Unpin,
 
 
         match self.state.reading {
+            Reading::Continue(..) |
             Reading::Body(..) |
             Reading::KeepAlive |
             Reading::Closed => return,
@@ -331,6 +523,7 @@ where I: AsyncRead + AsyncWrite + Unpin,
         };
 
         match self.state.writing {
+            Writing::Continue(..) |
             Writing::Body(..) => return,
             Writing::Init |
             Writing::KeepAlive |
@@ -361,6 +554,10 @@ where I: AsyncRead + AsyncWrite + Unpin,
     }
 
     pub fn can_write_head(&self) -> bool {
+        // The next message can't be buffered in front of a file.
+        if self.io.is_sending_file() {
+            return false;
+        }
         if !T::should_read_first() {
             match self.state.reading {
                 Reading::Closed => return false,
@@ -377,6 +574,7 @@ where I: AsyncRead + AsyncWrite + Unpin,
         match self.state.writing {
             Writing::Body(..) => true,
             Writing::Init |
+            Writing::Continue(..) |
             Writing::KeepAlive |
             Writing::Closed => false,
         }
@@ -387,9 +585,15 @@ where I: AsyncRead + AsyncWrite + Unpin,
     }
 
     pub fn write_head(&mut self, head: MessageHead<T::Outgoing>, body: Option<BodyLength>) {
+        let expect_continue = T::is_client() && headers::expect_continue(&head.headers);
         if let Some(encoder) = self.encode_head(head, body) {
             self.state.writing = if !encoder.is_eof() {
-                Writing::Body(encoder)
+                if expect_continue {
+                    self.start_expect_continue();
+                    Writing::Continue(encoder)
+                } else {
+                    Writing::Body(encoder)
+                }
             } else if encoder.is_last() {
                 Writing::Closed
             } else {
@@ -414,6 +618,147 @@ where I: AsyncRead + AsyncWrite + Unpin,
         }
     }
 
+    /// Whether `write_file_msg` can be used.
+    pub(crate) fn can_send_file(&self) -> bool {
+        self.io.can_send_file()
+    }
+
+    /// Like `write_full_msg`, but the body is a file, which is written
+    /// straight from it to the socket when flushing.
+    pub(crate) fn write_file_msg(&mut self, head: MessageHead<T::Outgoing>, file: FileRegion) {
+        debug_assert!(self.can_send_file());
+        if let Some(encoder) = self.encode_head(head, Some(BodyLength::Known(file.len))) {
+            let is_last = encoder.is_last();
+            if !encoder.is_eof() {
+                let suffix = encoder.frame_unencoded(file.len, self.io.headers_buf());
+                self.io.buffer_file(file, suffix);
+            }
+            self.state.writing = if is_last {
+                Writing::Closed
+            } else {
+                Writing::KeepAlive
+            }
+        }
+    }
+
+    /// Whether the request body waits for a `100 Continue` from the server.
+    pub(crate) fn is_write_continue(&self) -> bool {
+        match self.state.writing {
+            Writing::Continue(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Polls until the request body may be sent without having received
+    /// a `100 Continue`, because the expect-continue timeout elapsed.
+    ///
+    /// Receiving a `100 Continue`, or a final response, is noticed when
+    /// reading instead.
+    pub(crate) fn poll_write_continue(&mut self, cx: &mut task::Context<'_>) -> Poll<()> {
+        debug_assert!(self.is_write_continue());
+
+        #[cfg(feature = "runtime")]
+        {
+            if let Some(ref mut delay) = self.state.expect_continue_delay {
+                ready!(Pin::new(delay).poll(cx));
+                debug!("expect-continue timeout elapsed, sending body");
+                self.on_continue();
+                return Poll::Ready(());
+            }
+        }
+
+        #[cfg(not(feature = "runtime"))]
+        {
+            let _ = cx;
+        }
+
+        Poll::Pending
+    }
+
+    #[cfg(feature = "runtime")]
+    fn start_expect_continue(&mut self) {
+        self.state.expect_continue_delay = self.state.expect_continue_timeout
+            .map(|timeout| Delay::new(Instant::now() + timeout));
+    }
+
+    #[cfg(not(feature = "runtime"))]
+    fn start_expect_continue(&mut self) {}
+
+    // The server asked for the body, or didn't answer in time.
+    fn on_continue(&mut self) {
+        if let Writing::Continue(ref encoder) = self.state.writing {
+            trace!("sending body after expect-continue");
+            self.state.writing = Writing::Body(encoder.clone());
+        }
+        #[cfg(feature = "runtime")]
+        {
+            self.state.expect_continue_delay = None;
+        }
+    }
+
+    // The server sent a final response without asking for the body, so
+    // the body is skipped. A chunked body can be ended right away and the
+    // connection reused, but otherwise the server expects bytes that won't
+    // be sent, and the connection must be closed.
+    fn skip_continue_body(&mut self) {
+        let state = match self.state.writing {
+            Writing::Continue(ref encoder) => match encoder.end() {
+                Ok(end) => {
+                    if let Some(end) = end {
+                        self.io.buffer(end);
+                    }
+                    if encoder.is_last() {
+                        Writing::Closed
+                    } else {
+                        Writing::KeepAlive
+                    }
+                },
+                Err(_not_eof) => Writing::Closed,
+            },
+            _ => return,
+        };
+
+        self.state.writing = state;
+        #[cfg(feature = "runtime")]
+        {
+            self.state.expect_continue_delay = None;
+        }
+    }
+
+    /// Writes an informational (1xx) response head, before the final one.
+    pub(crate) fn write_informational(&mut self, head: MessageHead<T::Outgoing>) {
+        debug_assert!(self.can_write_head());
+
+        // RFC 7231: a server must not send a 1xx response to an HTTP/1.0
+        // client, which `Informational::send` already refused.
+        debug_assert_eq!(self.state.version, Version::HTTP_11);
+
+        let is_continue = T::encode_informational(head, self.io.headers_buf());
+        if is_continue {
+            if let Reading::Continue(ref decoder) = self.state.reading {
+                // the user sent it, so don't send another when reading
+                let decoder = decoder.clone();
+                self.state.reading = Reading::Body(decoder);
+            }
+        }
+    }
+
+    fn write_error_msg(&mut self, head: MessageHead<T::Outgoing>, body: Bytes) {
+        if let Some(encoder) = self.encode_head(head, Some(BodyLength::Known(body.len() as u64))) {
+            let is_last = encoder.is_last();
+            // The body is small and known up front, so it can be sent
+            // along with the head instead of needing a `B`.
+            if !encoder.is_eof() {
+                self.io.headers_buf().extend_from_slice(&body);
+            }
+            self.state.writing = if is_last {
+                Writing::Closed
+            } else {
+                Writing::KeepAlive
+            }
+        }
+    }
+
     fn encode_head(&mut self, mut head: MessageHead<T::Outgoing>, body: Option<BodyLength>) -> Option<Encoder> {
         debug_assert!(self.can_write_head());
 
@@ -421,20 +766,52 @@ where I: AsyncRead + AsyncWrite + Unpin,
             self.state.busy();
         }
 
+        let on_informational = if T::is_client() {
+            head.extensions.remove::<OnInformational>()
+        } else {
+            None
+        };
+
+        // If a previous request is still waiting for its response, this
+        // one is pipelined, and its response is read after that one.
+        let pipelined = T::is_client() && self.state.method.is_some();
+        let mut pipelined_method = None;
+
         self.enforce_version(&mut head);
 
+        if T::should_read_first() && self.state.keep_alive_header {
+            self.advertise_keep_alive(&mut head);
+        }
+
+        let keep_alive = self.state.wants_keep_alive();
         let buf = self.io.headers_buf();
         match T::encode(Encode {
             head: &mut head,
             body,
-            keep_alive: self.state.wants_keep_alive(),
-            req_method: &mut self.state.method,
+            keep_alive,
+            req_method: if pipelined {
+                &mut pipelined_method
+            } else {
+                &mut self.state.method
+            },
             title_case_headers: self.state.title_case_headers,
         }, buf) {
             Ok(encoder) => {
-                debug_assert!(self.state.cached_headers.is_none());
                 debug_assert!(head.headers.is_empty());
-                self.state.cached_headers = Some(head.headers);
+                if self.state.cached_headers.is_none() {
+                    self.state.cached_headers = Some(head.headers);
+                } else {
+                    // an earlier pipelined response hasn't reused them yet
+                    debug_assert!(pipelined);
+                }
+                if pipelined {
+                    self.state.pipelined.push_back(Pipelined {
+                        method: pipelined_method,
+                        on_informational,
+                    });
+                } else {
+                    self.state.on_informational = on_informational;
+                }
                 Some(encoder)
             },
             Err(err) => {
@@ -445,6 +822,33 @@ where I: AsyncRead + AsyncWrite + Unpin,
         }
     }
 
+    // Tell the client how long the idle connection is kept open, so it
+    // doesn't reuse it after it's closed.
+    fn advertise_keep_alive(&mut self, head: &mut MessageHead<T::Outgoing>) {
+        let secs = match self.state.keep_alive_timeout {
+            Some(timeout) => timeout.as_secs(),
+            None => return,
+        };
+        if secs == 0 || !self.state.wants_keep_alive() {
+            return;
+        }
+        let is_close = head
+            .headers
+            .get(CONNECTION)
+            .map(connection_close)
+            .unwrap_or(false);
+        if is_close {
+            return;
+        }
+        head.headers
+            .entry(HeaderName::from_static(headers::KEEP_ALIVE))
+            .expect("keep-alive is a valid header name")
+            .or_insert_with(|| {
+                HeaderValue::from_str(&format!("timeout={}", secs))
+                    .expect("timeout is a valid header value")
+            });
+    }
+
     // Fix keep-alives when Connection: keep-alive header is not present
     fn fix_keep_alive(&mut self, head: &mut MessageHead<T::Outgoing>) {
         let outgoing_is_keep_alive = head
@@ -561,6 +965,30 @@ where I: AsyncRead + AsyncWrite + Unpin,
 
     }
 
+    pub fn end_body_with_trailers(&mut self, trailers: HeaderMap) {
+        debug_assert!(self.can_write_body());
+
+        let state = match self.state.writing {
+            Writing::Body(ref encoder) => match encoder.encode_trailers(trailers) {
+                Some(end) => {
+                    self.io.buffer(end);
+                    if encoder.is_last() {
+                        Writing::Closed
+                    } else {
+                        Writing::KeepAlive
+                    }
+                },
+                None => {
+                    debug!("dropping trailers, body isn't chunked");
+                    return self.end_body();
+                },
+            },
+            _ => return,
+        };
+
+        self.state.writing = state;
+    }
+
     // When we get a parse error, depending on what side we are, we might be able
     // to write a response before closing the connection.
     //
@@ -573,11 +1001,11 @@ where I: AsyncRead + AsyncWrite + Unpin,
                 if self.has_h2_prefix() {
                     return Err(crate::Error::new_version_h2())
                 }
-                if let Some(msg) = T::on_error(&err) {
+                if let Some((msg, body)) = T::on_error(&err, self.state.error_responder.as_ref()) {
                     // Drop the cached headers so as to not trigger a debug
                     // assert in `write_head`...
                     self.state.cached_headers.take();
-                    self.write_head(msg, None);
+                    self.write_error_msg(msg, body);
                     self.state.error = Some(err);
                     return Ok(());
                 }
@@ -661,9 +1089,46 @@ struct State {
     allow_half_close: bool,
     /// Re-usable HeaderMap to reduce allocating new ones.
     cached_headers: Option<HeaderMap>,
+    /// Re-usable slots for parsing more headers than fit on the stack.
+    header_slots: HeaderSlots,
     /// If an error occurs when there wasn't a direct way to return it
     /// back to the user, this is set.
     error: Option<crate::Error>,
+    /// Builds the response sent for a parse error, if customized.
+    error_responder: Option<ErrorResponder>,
+    /// Limits checked when parsing a message head.
+    head_limits: HeadLimits,
+    /// Record the original case and order of received header names.
+    preserve_header_case: bool,
+    /// Deviations from the spec accepted when parsing a message head.
+    leniency: Leniency,
+    /// Reject requests with ambiguous message framing.
+    strict_framing: bool,
+    /// Called with informational responses to the current request.
+    on_informational: Option<OnInformational>,
+    /// How long a request with `Expect: 100-continue` waits before
+    /// sending its body anyway. `None` waits for the server's answer.
+    expect_continue_timeout: Option<Duration>,
+    /// Running while the request body waits for `100 Continue`.
+    #[cfg(feature = "runtime")]
+    expect_continue_delay: Option<Delay>,
+    /// Client only: write requests before the responses to previous ones
+    /// are read.
+    pipeline: bool,
+    /// Client only: requests written after the one whose response is
+    /// being read, oldest first.
+    pipelined: VecDeque<Pipelined>,
+    /// Server only: how long an idle connection waits for a request.
+    keep_alive_timeout: Option<Duration>,
+    /// Server only: advertise `keep_alive_timeout` in a `Keep-Alive` header.
+    keep_alive_header: bool,
+    /// Server only: the largest request body allowed.
+    max_body_size: Option<u64>,
+    /// How much more of the body being read is allowed.
+    body_limit: Option<u64>,
+    /// Server only: running while the connection is idle.
+    #[cfg(feature = "runtime")]
+    keep_alive_delay: Option<Delay>,
     /// Current keep-alive status.
     keep_alive: KA,
     /// If mid-message, the HTTP Method that started it.
@@ -685,9 +1150,16 @@ struct State {
     version: Version,
 }
 
+/// What's needed to read the response to a pipelined request.
+struct Pipelined {
+    method: Option<Method>,
+    on_informational: Option<OnInformational>,
+}
+
 #[derive(Debug)]
 enum Reading {
     Init,
+    Continue(Decoder),
     Body(Decoder),
     KeepAlive,
     Closed,
@@ -695,6 +1167,9 @@ enum Reading {
 
 enum Writing {
     Init,
+    /// Client only: the head was sent with `Expect: 100-continue`, and the
+    /// body waits for the server to ask for it.
+    Continue(Encoder),
     Body(Encoder),
     KeepAlive,
     Closed,
@@ -723,6 +1198,9 @@ impl fmt::Debug for Writing {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Writing::Init => f.write_str("Init"),
+            Writing::Continue(ref enc) => f.debug_tuple("Continue")
+                .field(enc)
+                .finish(),
             Writing::Body(ref enc) => f.debug_tuple("Body")
                 .field(enc)
                 .finish(),
@@ -801,6 +1279,9 @@ impl State {
     }
 
     fn try_keep_alive<T: Http1Transaction>(&mut self) {
+        if self.pipeline {
+            self.try_pipeline();
+        }
         match (&self.reading, &self.writing) {
             (&Reading::KeepAlive, &Writing::KeepAlive) => {
                 if let KA::Busy = self.keep_alive.status() {
@@ -810,6 +1291,14 @@ impl State {
                     self.close();
                 }
             },
+            (&Reading::KeepAlive, &Writing::Init) if self.pipeline => {
+                // every pipelined request has its response
+                if let KA::Busy = self.keep_alive.status() {
+                    self.idle::<T>();
+                } else {
+                    self.close();
+                }
+            },
             (&Reading::Closed, &Writing::KeepAlive) |
             (&Reading::KeepAlive, &Writing::Closed) => {
                 self.close()
@@ -818,6 +1307,45 @@ impl State {
         }
     }
 
+    // With pipelining, reading moves on to the response of the next
+    // pipelined request, and writing moves on to the next request while
+    // responses are outstanding.
+    fn try_pipeline(&mut self) {
+        if let Reading::KeepAlive = self.reading {
+            if let Some(next) = self.pipelined.pop_front() {
+                if let KA::Disabled = self.keep_alive.status() {
+                    trace!("keep-alive disabled with {} unanswered pipelined requests", self.pipelined.len() + 1);
+                    self.pipelined.clear();
+                    if self.error.is_none() {
+                        self.error = Some(crate::Error::new_canceled()
+                            .with("connection closed before responding to pipelined requests"));
+                    }
+                    self.close();
+                    return;
+                }
+                self.method = next.method;
+                self.on_informational = next.on_informational;
+                self.reading = Reading::Init;
+            }
+        }
+
+        if let Writing::KeepAlive = self.writing {
+            let outstanding = match self.reading {
+                Reading::KeepAlive |
+                Reading::Closed => false,
+                _ => true,
+            };
+            if outstanding {
+                if let KA::Busy = self.keep_alive.status() {
+                    trace!("pipelining, ready to write next request");
+                    self.writing = Writing::Init;
+                    // poll the pending requests stream, like when idle
+                    self.notify_read = true;
+                }
+            }
+        }
+    }
+
     fn disable_keep_alive(&mut self) {
         self.keep_alive.disable()
     }
@@ -12,6 +12,10 @@ use super::{DecodedLength};
 
 use self::Kind::{Length, Chunked, Eof};
 
+/// The most bytes of chunk extensions accepted on a chunk size line, in
+/// strict mode.
+const CHUNK_EXTENSIONS_LIMIT: usize = 1024;
+
 /// Decoders to handle different Transfer-Encodings.
 ///
 /// If a message body does not include a Transfer-Encoding, it *should*
@@ -19,6 +23,8 @@ use self::Kind::{Length, Chunked, Eof};
 #[derive(Clone, PartialEq)]
 pub struct Decoder {
     kind: Kind,
+    /// Reject whitespace in chunk sizes and oversized chunk extensions.
+    strict: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -26,7 +32,10 @@ enum Kind {
     /// A Reader used when a Content-Length header is passed with a positive integer.
     Length(u64),
     /// A Reader used when Transfer-Encoding is `chunked`.
-    Chunked(ChunkedState, u64),
+    ///
+    /// Tracks the size of the current chunk, and the bytes of chunk
+    /// extensions read on its size line.
+    Chunked(ChunkedState, u64, usize),
     /// A Reader used for responses that don't indicate a length or chunked.
     ///
     /// The bool tracks when EOF is seen on the transport.
@@ -64,15 +73,15 @@ impl Decoder {
     // constructors
 
     pub fn length(x: u64) -> Decoder {
-        Decoder { kind: Kind::Length(x) }
+        Decoder { kind: Kind::Length(x), strict: false }
     }
 
     pub fn chunked() -> Decoder {
-        Decoder { kind: Kind::Chunked(ChunkedState::Size, 0) }
+        Decoder { kind: Kind::Chunked(ChunkedState::Size, 0, 0), strict: false }
     }
 
     pub fn eof() -> Decoder {
-        Decoder { kind: Kind::Eof(false) }
+        Decoder { kind: Kind::Eof(false), strict: false }
     }
 
     pub(super) fn new(len: DecodedLength) -> Self {
@@ -83,12 +92,16 @@ impl Decoder {
         }
     }
 
+    pub(super) fn set_strict(&mut self) {
+        self.strict = true;
+    }
+
     // methods
 
     pub fn is_eof(&self) -> bool {
         match self.kind {
             Length(0) |
-            Chunked(ChunkedState::End, _) |
+            Chunked(ChunkedState::End, _, _) |
             Eof(true) => true,
             _ => false,
         }
@@ -114,11 +127,12 @@ impl Decoder {
                     Poll::Ready(Ok(buf))
                 }
             }
-            Chunked(ref mut state, ref mut size) => {
+            Chunked(ref mut state, ref mut size, ref mut ext_len) => {
+                let strict = self.strict;
                 loop {
                     let mut buf = None;
                     // advances the chunked state
-                    *state = ready!(state.step(cx, body, size, &mut buf))?;
+                    *state = ready!(state.step(cx, body, size, ext_len, strict, &mut buf))?;
                     if *state == ChunkedState::End {
                         trace!("end of chunked");
                         return Poll::Ready(Ok(Bytes::new()));
@@ -169,13 +183,15 @@ impl ChunkedState {
                         cx: &mut task::Context<'_>,
                         body: &mut R,
                         size: &mut u64,
+                        ext_len: &mut usize,
+                        strict: bool,
                         buf: &mut Option<Bytes>)
                         -> Poll<Result<ChunkedState, io::Error>> {
         use self::ChunkedState::*;
         match *self {
-            Size => ChunkedState::read_size(cx, body, size),
+            Size => ChunkedState::read_size(cx, body, size, strict),
             SizeLws => ChunkedState::read_size_lws(cx, body),
-            Extension => ChunkedState::read_extension(cx, body),
+            Extension => ChunkedState::read_extension(cx, body, ext_len, strict),
             SizeLf => ChunkedState::read_size_lf(cx, body, *size),
             Body => ChunkedState::read_body(cx, body, size, buf),
             BodyCr => ChunkedState::read_body_cr(cx, body),
@@ -185,21 +201,16 @@ impl ChunkedState {
             End => Poll::Ready(Ok(ChunkedState::End)),
         }
     }
-    fn read_size<R: MemRead>(cx: &mut task::Context<'_>, rdr: &mut R, size: &mut u64) -> Poll<Result<ChunkedState, io::Error>> {
+    fn read_size<R: MemRead>(cx: &mut task::Context<'_>, rdr: &mut R, size: &mut u64, strict: bool) -> Poll<Result<ChunkedState, io::Error>> {
         trace!("Read chunk hex size");
         let radix = 16;
-        match byte!(rdr, cx) {
-            b @ b'0'..=b'9' => {
-                *size *= radix;
-                *size += (b - b'0') as u64;
-            }
-            b @ b'a'..=b'f' => {
-                *size *= radix;
-                *size += (b + 10 - b'a') as u64;
-            }
-            b @ b'A'..=b'F' => {
-                *size *= radix;
-                *size += (b + 10 - b'A') as u64;
+        let digit = match byte!(rdr, cx) {
+            b @ b'0'..=b'9' => b - b'0',
+            b @ b'a'..=b'f' => b + 10 - b'a',
+            b @ b'A'..=b'F' => b + 10 - b'A',
+            b'\t' | b' ' if strict => {
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "Invalid chunk size line: whitespace")));
             }
             b'\t' | b' ' => return Poll::Ready(Ok(ChunkedState::SizeLws)),
             b';' => return Poll::Ready(Ok(ChunkedState::Extension)),
@@ -208,6 +219,13 @@ impl ChunkedState {
                 return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidInput,
                                           "Invalid chunk size line: Invalid Size")));
             }
+        };
+        match size.checked_mul(radix).and_then(|size| size.checked_add(digit as u64)) {
+            Some(new_size) => *size = new_size,
+            None => {
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "Invalid chunk size line: Size overflow")));
+            }
         }
         Poll::Ready(Ok(ChunkedState::Size))
     }
@@ -224,11 +242,21 @@ impl ChunkedState {
             }
         }
     }
-    fn read_extension<R: MemRead>(cx: &mut task::Context<'_>, rdr: &mut R) -> Poll<Result<ChunkedState, io::Error>> {
+    fn read_extension<R: MemRead>(cx: &mut task::Context<'_>, rdr: &mut R, ext_len: &mut usize, strict: bool) -> Poll<Result<ChunkedState, io::Error>> {
         trace!("read_extension");
         match byte!(rdr, cx) {
-            b'\r' => Poll::Ready(Ok(ChunkedState::SizeLf)),
-            _ => Poll::Ready(Ok(ChunkedState::Extension)), // no supported extensions
+            b'\r' => {
+                *ext_len = 0;
+                Poll::Ready(Ok(ChunkedState::SizeLf))
+            },
+            _ => {
+                *ext_len += 1;
+                if strict && *ext_len > CHUNK_EXTENSIONS_LIMIT {
+                    return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData,
+                                              "chunk extensions over limit")));
+                }
+                Poll::Ready(Ok(ChunkedState::Extension)) // no supported extensions
+            },
         }
     }
     fn read_size_lf<R: MemRead>(cx: &mut task::Context<'_>, rdr: &mut R, size: u64) -> Poll<Result<ChunkedState, io::Error>> {
@@ -1,12 +1,18 @@
+use std::collections::VecDeque;
 use std::error::Error as StdError;
 
 use bytes::{Buf, Bytes};
+use futures_channel::mpsc;
+use futures_core::Stream;
 use http::{Request, Response, StatusCode};
 use tokio_io::{AsyncRead, AsyncWrite};
 
 use crate::body::{Body, Payload};
-use crate::body::internal::FullDataArg;
+use crate::body::internal::{FileRegionArg, FullDataArg};
 use crate::common::{Future, Never, Poll, Pin, Unpin, task};
+use crate::ext::Informational;
+use crate::headers;
+use crate::server::conn::ConnectionInfo;
 use crate::proto::{BodyLength, DecodedLength, Conn, Dispatched, MessageHead, RequestHead, RequestLine, ResponseHead};
 use super::Http1Transaction;
 use crate::service::Service;
@@ -16,6 +22,9 @@ pub(crate) struct Dispatcher<D, Bs: Payload, I, T> {
     dispatch: D,
     body_tx: Option<crate::body::Sender>,
     body_rx: Pin<Box<Option<Bs>>>,
+    /// Whether `body_rx` has yielded all its data, and only its trailers
+    /// are left.
+    body_data_done: bool,
     is_closing: bool,
 }
 
@@ -28,19 +37,40 @@ pub(crate) trait Dispatch {
     fn recv_msg(&mut self, msg: crate::Result<(Self::RecvItem, Body)>) -> crate::Result<()>;
     fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), ()>>;
     fn should_poll(&self) -> bool;
+
+    /// Polls for an informational message to write before the item of
+    /// `poll_msg`. `Pending` means none is ready yet.
+    fn poll_informational(&mut self, _cx: &mut task::Context<'_>) -> Poll<Option<Self::PollItem>> {
+        Poll::Ready(None)
+    }
 }
 
 pub struct Server<S: Service<B>, B> {
     in_flight: Pin<Box<Option<S::Future>>>,
     pub(crate) service: S,
+    conn_info: ConnectionInfo,
+    informational: Option<mpsc::UnboundedReceiver<MessageHead<StatusCode>>>,
 }
 
 pub struct Client<B> {
-    callback: Option<crate::client::dispatch::Callback<Request<B>, Response<Body>>>,
+    /// Callbacks of the requests waiting for responses, oldest first, along
+    /// with a copy of each pipelined request to give back if its response
+    /// never comes.
+    callbacks: VecDeque<(ClientCallback<B>, Option<Request<B>>)>,
     rx: ClientRx<B>,
+    /// How many requests may wait for responses at once. More than one
+    /// means requests are pipelined.
+    pipeline_depth: usize,
+    /// Whether all requests waiting for responses are idempotent, so
+    /// another idempotent request can be pipelined after them.
+    pipeline_idempotent: bool,
+    /// A request that can't be pipelined, waiting for the responses
+    /// before it.
+    pending: Option<(Request<B>, ClientCallback<B>)>,
 }
 
 type ClientRx<B> = crate::client::dispatch::Receiver<Request<B>, Response<Body>>;
+type ClientCallback<B> = crate::client::dispatch::Callback<Request<B>, Response<Body>>;
 
 impl<D, Bs, I, T> Dispatcher<D, Bs, I, T>
 where
@@ -56,6 +86,7 @@ where
             dispatch: dispatch,
             body_tx: None,
             body_rx: Box::pin(None),
+            body_data_done: false,
             is_closing: false,
         }
     }
@@ -64,6 +95,11 @@ where
         self.conn.disable_keep_alive()
     }
 
+    #[cfg(all(feature = "runtime", target_os = "linux"))]
+    pub(crate) fn set_sendfile_socket(&mut self, socket: ::std::os::unix::io::RawFd) {
+        self.conn.set_sendfile_socket(socket)
+    }
+
     pub fn into_inner(self) -> (I, Bytes, D) {
         let (io, buf) = self.conn.into_inner();
         (io, buf, self.dispatch)
@@ -206,7 +242,7 @@ where
                             return Poll::Pending;
                         }
                         Poll::Ready(Some(Err(e))) => {
-                            body.send_error(crate::Error::new_body(e));
+                            body.send_error(e);
                         }
                     }
                 } else {
@@ -234,7 +270,7 @@ where
                 let mut body = match body_len {
                     DecodedLength::ZERO => Body::empty(),
                     other => {
-                        let (tx, rx) = Body::new_channel(other.into_opt());
+                        let (tx, rx) = Body::new_channel(other.into_opt(), self.conn.is_read_continue());
                         self.body_tx = Some(tx);
                         rx
                     },
@@ -265,16 +301,31 @@ where
             if self.is_closing {
                 return Poll::Ready(Ok(()));
             } else if self.body_rx.is_none() && self.conn.can_write_head() && self.dispatch.should_poll() {
+                self.write_informational(cx);
                 if let Some(msg) = ready!(self.dispatch.poll_msg(cx)) {
                     let (head, mut body) = msg.map_err(crate::Error::new_user_service)?;
+                    // sent while the message was being prepared
+                    self.write_informational(cx);
 
                     // Check if the body knows its full data immediately.
                     //
                     // If so, we can skip a bit of bookkeeping that streaming
-                    // bodies need to do.
-                    if let Some(full) = body.__hyper_full_data(FullDataArg(())).0 {
-                        self.conn.write_full_msg(head, full);
-                        return Poll::Ready(Ok(()));
+                    // bodies need to do. A request expecting `100 Continue`
+                    // must not send its body along with the head, though.
+                    let expect_continue = T::is_client() && headers::expect_continue(&head.headers);
+                    if !expect_continue {
+                        if let Some(full) = body.__hyper_full_data(FullDataArg(())).0 {
+                            self.conn.write_full_msg(head, full);
+                            return Poll::Ready(Ok(()));
+                        }
+                        // Or if it is a file that can be sent without
+                        // copying it through userspace.
+                        if self.conn.can_send_file() {
+                            if let Some(file) = body.__hyper_file_region(FileRegionArg(())).0 {
+                                self.conn.write_file_msg(head, file);
+                                return Poll::Ready(Ok(()));
+                            }
+                        }
                     }
                     let body_type = if body.is_end_stream() {
                         self.body_rx.set(None);
@@ -284,6 +335,7 @@ where
                             .map(BodyLength::Known)
                             .or_else(|| Some(BodyLength::Unknown));
                         self.body_rx.set(Some(body));
+                        self.body_data_done = false;
                         btype
                     };
                     self.conn.write_head(head, body_type);
@@ -297,6 +349,10 @@ where
                 // A new scope is needed :(
                 if let (Some(mut body), clear_body) = OptGuard::new(self.body_rx.as_mut()).guard_mut() {
                     debug_assert!(!*clear_body, "opt guard defaults to keeping body");
+                    if self.conn.is_write_continue() {
+                        ready!(self.conn.poll_write_continue(cx));
+                        continue;
+                    }
                     if !self.conn.can_write_body() {
                         trace!(
                             "no more write body allowed, user body is_end_stream = {}",
@@ -306,6 +362,17 @@ where
                         continue;
                     }
 
+                    if self.body_data_done {
+                        let trailers = ready!(body.as_mut().poll_trailers(cx));
+                        *clear_body = true;
+                        match trailers {
+                            Some(Ok(trailers)) => self.conn.end_body_with_trailers(trailers),
+                            Some(Err(e)) => return Poll::Ready(Err(crate::Error::new_user_body(e))),
+                            None => self.conn.end_body(),
+                        }
+                        continue;
+                    }
+
                     let item = ready!(body.as_mut().poll_data(cx));
                     if let Some(item) = item {
                         let chunk = item.map_err(|e| {
@@ -329,8 +396,7 @@ where
                             self.conn.write_body(chunk);
                         }
                     } else {
-                        *clear_body = true;
-                        self.conn.end_body();
+                        self.body_data_done = true;
                     }
                 } else {
                     return Poll::Pending;
@@ -339,6 +405,12 @@ where
         }
     }
 
+    fn write_informational(&mut self, cx: &mut task::Context<'_>) {
+        while let Poll::Ready(Some(head)) = self.dispatch.poll_informational(cx) {
+            self.conn.write_informational(head);
+        }
+    }
+
     fn poll_flush(&mut self, cx: &mut task::Context<'_>) -> Poll<crate::Result<()>> {
         self.conn.poll_flush(cx).map_err(|err| {
             debug!("error writing: {}", err);
@@ -416,16 +488,22 @@ impl<S, B> Server<S, B>
 where
     S: Service<B>,
 {
-    pub fn new(service: S) -> Server<S, B> {
+    pub fn new(service: S, conn_info: ConnectionInfo) -> Server<S, B> {
         Server {
             in_flight: Box::pin(None),
             service: service,
+            conn_info,
+            informational: None,
         }
     }
 
     pub fn into_service(self) -> S {
         self.service
     }
+
+    pub fn into_parts(self) -> (S, ConnectionInfo) {
+        (self.service, self.conn_info)
+    }
 }
 
 // Service is never pinned
@@ -445,11 +523,17 @@ where
     fn poll_msg(&mut self, cx: &mut task::Context<'_>) -> Poll<Option<Result<(Self::PollItem, Self::PollBody), Self::PollError>>> {
         let ret = if let Some(ref mut fut) = self.in_flight.as_mut().as_pin_mut() {
             let resp = ready!(fut.as_mut().poll(cx)?);
+            // Informational responses already sent are still written,
+            // but later ones are an error.
+            if let Some(ref mut rx) = self.informational {
+                rx.close();
+            }
             let (parts, body) = resp.into_parts();
             let head = MessageHead {
                 version: parts.version,
                 subject: parts.status,
                 headers: parts.headers,
+                extensions: parts.extensions,
             };
             Poll::Ready(Some(Ok((head, body))))
         } else {
@@ -468,6 +552,11 @@ where
         *req.uri_mut() = msg.subject.1;
         *req.headers_mut() = msg.headers;
         *req.version_mut() = msg.version;
+        *req.extensions_mut() = msg.extensions;
+        req.extensions_mut().insert(self.conn_info.next_request(msg.version));
+        let (informational, rx) = Informational::channel(msg.version);
+        req.extensions_mut().insert(informational);
+        self.informational = Some(rx);
         let fut = self.service.call(req);
         self.in_flight.set(Some(fut));
         Ok(())
@@ -488,16 +577,49 @@ where
     fn should_poll(&self) -> bool {
         self.in_flight.is_some()
     }
+
+    fn poll_informational(&mut self, cx: &mut task::Context<'_>) -> Poll<Option<Self::PollItem>> {
+        let ret = match self.informational {
+            Some(ref mut rx) => Pin::new(rx).poll_next(cx),
+            None => return Poll::Ready(None),
+        };
+        if let Poll::Ready(None) = ret {
+            self.informational = None;
+        }
+        ret
+    }
 }
 
 // ===== impl Client =====
 
 
+impl<B> Unpin for Client<B> {}
+
 impl<B> Client<B> {
     pub fn new(rx: ClientRx<B>) -> Client<B> {
         Client {
-            callback: None,
+            callbacks: VecDeque::new(),
             rx: rx,
+            pipeline_depth: 1,
+            pipeline_idempotent: false,
+            pending: None,
+        }
+    }
+
+    pub(crate) fn set_pipeline_depth(&mut self, depth: usize) {
+        debug_assert!(depth > 0);
+        self.pipeline_depth = depth;
+    }
+
+    // The connection won't answer the requests waiting for responses, but
+    // they are idempotent, so they can be sent again.
+    fn cancel_pipelined(&mut self) {
+        for (cb, req) in self.callbacks.drain(..) {
+            trace!("canceling pipelined request");
+            let _ = cb.send(Err((crate::Error::new_canceled().with("connection closed before response"), req)));
+        }
+        if let Some((req, cb)) = self.pending.take() {
+            let _ = cb.send(Err((crate::Error::new_canceled().with("connection closed"), Some(req))));
         }
     }
 }
@@ -512,43 +634,79 @@ where
     type RecvItem = ResponseHead;
 
     fn poll_msg(&mut self, cx: &mut task::Context<'_>) -> Poll<Option<Result<(Self::PollItem, Self::PollBody), Never>>> {
-        match self.rx.poll_next(cx) {
-            Poll::Ready(Some((req, mut cb))) => {
-                // check that future hasn't been canceled already
-                match cb.poll_cancel(cx) {
-                    Poll::Ready(()) => {
-                        trace!("request canceled");
-                        Poll::Ready(None)
-                    },
-                    Poll::Pending => {
-                        let (parts, body) = req.into_parts();
-                        let head = RequestHead {
-                            version: parts.version,
-                            subject: RequestLine(parts.method, parts.uri),
-                            headers: parts.headers,
-                        };
-                        self.callback = Some(cb);
-                        Poll::Ready(Some(Ok((head, body))))
+        loop {
+            let next = match self.pending.take() {
+                Some(pending) => Some(pending),
+                None => ready!(self.rx.poll_next(cx)),
+            };
+            match next {
+                Some((req, mut cb)) => {
+                    // check that future hasn't been canceled already
+                    match cb.poll_cancel(cx) {
+                        Poll::Ready(()) => {
+                            trace!("request canceled");
+                            if self.callbacks.is_empty() {
+                                return Poll::Ready(None);
+                            }
+                            // responses to pipelined requests are still
+                            // expected, so just skip this one
+                            continue;
+                        },
+                        Poll::Pending => {
+                            let is_idempotent = req.method().is_idempotent();
+                            if !self.callbacks.is_empty() {
+                                if !(self.pipeline_idempotent && is_idempotent) {
+                                    trace!("request can't be pipelined, waiting for responses");
+                                    self.pending = Some((req, cb));
+                                    return Poll::Pending;
+                                }
+                            } else {
+                                self.pipeline_idempotent = is_idempotent;
+                            }
+
+                            // Only a request behind others can be canceled
+                            // after it was written, see `cancel_pipelined`.
+                            let replay = if self.callbacks.is_empty() {
+                                None
+                            } else {
+                                req.body()
+                                    .__hyper_replay()
+                                    .map(|body| crate::client::replay_head(&req).map(|()| body))
+                            };
+                            let (parts, body) = req.into_parts();
+                            let head = RequestHead {
+                                version: parts.version,
+                                subject: RequestLine(parts.method, parts.uri),
+                                headers: parts.headers,
+                                extensions: parts.extensions,
+                            };
+                            self.callbacks.push_back((cb, replay));
+                            return Poll::Ready(Some(Ok((head, body))));
+                        }
                     }
-                }
-            },
-            Poll::Ready(None) => {
-                trace!("client tx closed");
-                // user has dropped sender handle
-                Poll::Ready(None)
-            },
-            Poll::Pending => Poll::Pending,
+                },
+                None => {
+                    trace!("client tx closed");
+                    if !self.callbacks.is_empty() {
+                        // wait for the responses to pipelined requests
+                        return Poll::Pending;
+                    }
+                    // user has dropped sender handle
+                    return Poll::Ready(None);
+                },
+            }
         }
     }
 
     fn recv_msg(&mut self, msg: crate::Result<(Self::RecvItem, Body)>) -> crate::Result<()> {
         match msg {
             Ok((msg, body)) => {
-                if let Some(cb) = self.callback.take() {
+                if let Some((cb, _)) = self.callbacks.pop_front() {
                     let mut res = Response::new(body);
                     *res.status_mut() = msg.subject;
                     *res.headers_mut() = msg.headers;
                     *res.version_mut() = msg.version;
+                    *res.extensions_mut() = msg.extensions;
                     let _ = cb.send(Ok(res));
                     Ok(())
                 } else {
@@ -559,10 +717,15 @@ where
                 }
             },
             Err(err) => {
-                if let Some(cb) = self.callback.take() {
-                    let _ = cb.send(Err((err, None)));
+                if let Some((cb, replay)) = self.callbacks.pop_front() {
+                    // a pipelined request canceled by the connection closing
+                    // was never answered, so it can be sent again
+                    let req = if err.is_canceled() { replay } else { None };
+                    let _ = cb.send(Err((err, req)));
+                    self.cancel_pipelined();
                     Ok(())
                 } else {
+                    self.cancel_pipelined();
                     self.rx.close();
                     if let Some((req, cb)) = self.rx.try_recv() {
                         trace!("canceling queued request with connection error: {}", err);
@@ -579,20 +742,22 @@ where
     }
 
     fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), ()>> {
-        match self.callback {
-            Some(ref mut cb) => match cb.poll_cancel(cx) {
-                Poll::Ready(()) => {
-                    trace!("callback receiver has dropped");
-                    Poll::Ready(Err(()))
-                },
-                Poll::Pending => Poll::Ready(Ok(())),
-            },
-            None => Poll::Ready(Err(())),
+        let canceled = match self.callbacks.front_mut() {
+            Some(&mut (ref mut cb, _)) => cb.poll_cancel(cx).is_ready(),
+            None => return Poll::Ready(Err(())),
+        };
+        if canceled {
+            trace!("callback receiver has dropped");
+            self.callbacks.pop_front();
+            self.cancel_pipelined();
+            Poll::Ready(Err(()))
+        } else {
+            Poll::Ready(Ok(()))
         }
     }
 
     fn should_poll(&self) -> bool {
-        self.callback.is_none()
+        self.callbacks.len() < self.pipeline_depth
     }
 }
 
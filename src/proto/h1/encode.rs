@@ -1,7 +1,9 @@
 use std::fmt;
+use std::io::Cursor;
 
-use bytes::{Buf, IntoBuf};
+use bytes::{Buf, Bytes, IntoBuf};
 use bytes::buf::{Chain, Take};
+use http::header::{self, HeaderMap, HeaderName};
 use iovec::IoVec;
 
 use crate::common::StaticBuf;
@@ -43,6 +45,7 @@ enum BufKind<B> {
     Limited(Take<B>),
     Chunked(Chain<Chain<ChunkSize, B>, StaticBuf>),
     ChunkedEnd(StaticBuf),
+    Trailers(Cursor<Bytes>),
 }
 
 impl Encoder {
@@ -90,6 +93,35 @@ impl Encoder {
         }
     }
 
+    /// Ends a chunked body with trailers.
+    ///
+    /// Returns `None` if this isn't a chunked encoder, since only chunked
+    /// bodies can carry trailers. Fields that aren't allowed in trailers
+    /// are dropped.
+    pub fn encode_trailers<B>(&self, trailers: HeaderMap) -> Option<EncodedBuf<B>> {
+        match self.kind {
+            Kind::Chunked => {
+                let mut buf = Vec::new();
+                buf.extend_from_slice(b"0\r\n");
+                for (name, value) in trailers.iter() {
+                    if is_forbidden_trailer(name) {
+                        debug!("trailer field is not allowed: {:?}", name);
+                        continue;
+                    }
+                    buf.extend_from_slice(name.as_str().as_bytes());
+                    buf.extend_from_slice(b": ");
+                    buf.extend_from_slice(value.as_bytes());
+                    buf.extend_from_slice(b"\r\n");
+                }
+                buf.extend_from_slice(b"\r\n");
+                Some(EncodedBuf {
+                    kind: BufKind::Trailers(Cursor::new(Bytes::from(buf))),
+                })
+            },
+            _ => None,
+        }
+    }
+
     pub fn encode<B>(&mut self, msg: B) -> EncodedBuf<B::Buf>
     where
         B: IntoBuf,
@@ -201,6 +233,61 @@ impl Encoder {
             },
         }
     }
+
+    /// Frames a body of `len` bytes that is written without this encoder,
+    /// like a file sent with `sendfile`.
+    ///
+    /// The start of the framing is written to `dst`, and the end of it is
+    /// returned, to be written after the body.
+    pub(super) fn frame_unencoded(&self, len: u64, dst: &mut Vec<u8>) -> &'static [u8] {
+        debug_assert!(len > 0, "frame_unencoded() called with empty body");
+        debug_assert!(match self.kind {
+            Kind::Length(n) => n == len,
+            _ => true,
+        }, "frame_unencoded length mismatches");
+
+        match self.kind {
+            Kind::Chunked => {
+                dst.extend_from_slice(format!("{:X}\r\n", len).as_bytes());
+                b"\r\n0\r\n\r\n"
+            },
+            _ => b"",
+        }
+    }
+}
+
+/// Fields that must not be sent in trailers, because they are needed to
+/// frame, route or process the message before its body (RFC 7230, section
+/// 4.1.2).
+fn is_forbidden_trailer(name: &HeaderName) -> bool {
+    match *name {
+        header::AUTHORIZATION |
+        header::CACHE_CONTROL |
+        header::CONNECTION |
+        header::CONTENT_ENCODING |
+        header::CONTENT_LENGTH |
+        header::CONTENT_RANGE |
+        header::CONTENT_TYPE |
+        header::EXPECT |
+        header::HOST |
+        header::IF_MATCH |
+        header::IF_MODIFIED_SINCE |
+        header::IF_NONE_MATCH |
+        header::IF_RANGE |
+        header::IF_UNMODIFIED_SINCE |
+        header::MAX_FORWARDS |
+        header::PRAGMA |
+        header::PROXY_AUTHENTICATE |
+        header::PROXY_AUTHORIZATION |
+        header::RANGE |
+        header::SET_COOKIE |
+        header::TE |
+        header::TRAILER |
+        header::TRANSFER_ENCODING |
+        header::UPGRADE |
+        header::WWW_AUTHENTICATE => true,
+        _ => *name == "keep-alive" || *name == "proxy-connection",
+    }
 }
 
 impl<B> Buf for EncodedBuf<B>
@@ -214,6 +301,7 @@ where
             BufKind::Limited(ref b) => b.remaining(),
             BufKind::Chunked(ref b) => b.remaining(),
             BufKind::ChunkedEnd(ref b) => b.remaining(),
+            BufKind::Trailers(ref b) => b.remaining(),
         }
     }
 
@@ -224,6 +312,7 @@ where
             BufKind::Limited(ref b) => b.bytes(),
             BufKind::Chunked(ref b) => b.bytes(),
             BufKind::ChunkedEnd(ref b) => b.bytes(),
+            BufKind::Trailers(ref b) => b.bytes(),
         }
     }
 
@@ -234,6 +323,7 @@ where
             BufKind::Limited(ref mut b) => b.advance(cnt),
             BufKind::Chunked(ref mut b) => b.advance(cnt),
             BufKind::ChunkedEnd(ref mut b) => b.advance(cnt),
+            BufKind::Trailers(ref mut b) => b.advance(cnt),
         }
     }
 
@@ -244,6 +334,7 @@ where
             BufKind::Limited(ref b) => b.bytes_vec(dst),
             BufKind::Chunked(ref b) => b.bytes_vec(dst),
             BufKind::ChunkedEnd(ref b) => b.bytes_vec(dst),
+            BufKind::Trailers(ref b) => b.bytes_vec(dst),
         }
     }
 }
@@ -343,6 +434,7 @@ impl<B: Buf> From<Chain<Chain<ChunkSize, B>, StaticBuf>> for EncodedBuf<B> {
 #[cfg(test)]
 mod tests {
     use bytes::{BufMut};
+    use http::HeaderMap;
 
     use super::super::io::Cursor;
     use super::Encoder;
@@ -369,6 +461,26 @@ mod tests {
         assert_eq!(dst, b"7\r\nfoo bar\r\nD\r\nbaz quux herp\r\n0\r\n\r\n".as_ref());
     }
 
+    #[test]
+    fn chunked_trailers() {
+        let mut encoder = Encoder::chunked();
+        let mut dst = Vec::new();
+
+        dst.put(encoder.encode(b"foo bar".as_ref()));
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+        trailers.insert("content-length", "7".parse().unwrap());
+        trailers.insert("transfer-encoding", "chunked".parse().unwrap());
+        let end = encoder.encode_trailers::<Cursor<Vec<u8>>>(trailers).unwrap();
+        dst.put(end);
+
+        assert_eq!(dst, b"7\r\nfoo bar\r\n0\r\ngrpc-status: 0\r\n\r\n".as_ref());
+
+        let encoder = Encoder::length(7);
+        assert!(encoder.encode_trailers::<Cursor<Vec<u8>>>(HeaderMap::new()).is_none());
+    }
+
     #[test]
     fn length() {
         let max_len = 8;
@@ -1,14 +1,19 @@
 use std::cell::Cell;
 use std::cmp;
-use std::collections::VecDeque;
 use std::fmt;
 use std::io;
+#[cfg(all(feature = "runtime", target_os = "linux"))]
+use std::os::unix::io::RawFd;
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use iovec::IoVec;
 use tokio_io::{AsyncRead, AsyncWrite};
 
-use crate::common::{Pin, Poll, Unpin, task};
+use crate::body::internal::FileRegion;
+use crate::common::{BufList, Pin, Poll, Unpin, task};
+use crate::common::rate::{Pacer, RateLimit};
+#[cfg(all(feature = "runtime", target_os = "linux"))]
+use super::sendfile::SendFile;
 use super::{Http1Transaction, ParseContext, ParsedMessage};
 
 /// The initial buffer size allocated before trying to read from IO.
@@ -35,7 +40,11 @@ pub struct Buffered<T, B> {
     read_blocked: bool,
     read_buf: BytesMut,
     read_buf_strategy: ReadStrategy,
+    read_limit: Option<Pacer>,
+    #[cfg(all(feature = "runtime", target_os = "linux"))]
+    send_file: Option<SendFile>,
     write_buf: WriteBuf<B>,
+    write_limit: Option<Pacer>,
 }
 
 impl<T, B> fmt::Debug for Buffered<T, B>
@@ -62,7 +71,29 @@ where
             read_blocked: false,
             read_buf: BytesMut::with_capacity(0),
             read_buf_strategy: ReadStrategy::default(),
+            read_limit: None,
+            #[cfg(all(feature = "runtime", target_os = "linux"))]
+            send_file: None,
             write_buf: WriteBuf::new(),
+            write_limit: None,
+        }
+    }
+
+    pub fn set_read_rate_limit(&mut self, limit: RateLimit) {
+        self.read_limit = Some(Pacer::new(limit));
+    }
+
+    pub fn set_write_rate_limit(&mut self, limit: RateLimit) {
+        self.write_limit = Some(Pacer::new(limit));
+    }
+
+    /// Allows files to be sent with `sendfile`, straight to `socket`, which
+    /// must be the socket that the IO writes to.
+    #[cfg(all(feature = "runtime", target_os = "linux"))]
+    pub fn set_sendfile_socket(&mut self, socket: RawFd) {
+        match SendFile::new(socket) {
+            Ok(send_file) => self.send_file = Some(send_file),
+            Err(err) => debug!("sendfile disabled, socket error: {}", err),
         }
     }
 
@@ -91,7 +122,7 @@ where
     pub fn set_write_strategy_flatten(&mut self) {
         // this should always be called only at construction time,
         // so this assert is here to catch myself
-        debug_assert!(self.write_buf.queue.bufs.is_empty());
+        debug_assert!(self.write_buf.queue.bufs_cnt() == 0);
         self.write_buf.set_strategy(WriteStrategy::Flatten);
     }
 
@@ -119,7 +150,47 @@ where
     }
 
     pub fn can_buffer(&self) -> bool {
-        self.flush_pipeline || self.write_buf.can_buffer()
+        (self.flush_pipeline || self.write_buf.can_buffer()) && !self.is_sending_file()
+    }
+
+    /// Whether `buffer_file` can be used.
+    #[cfg(all(feature = "runtime", target_os = "linux"))]
+    pub fn can_send_file(&self) -> bool {
+        // Paced writes need to go through `poll_write_allowed`.
+        self.send_file.is_some() && self.write_limit.is_none()
+    }
+
+    #[cfg(not(all(feature = "runtime", target_os = "linux")))]
+    pub fn can_send_file(&self) -> bool {
+        false
+    }
+
+    /// Queues a file to be written after everything buffered so far, and
+    /// then `suffix`.
+    #[cfg(all(feature = "runtime", target_os = "linux"))]
+    pub fn buffer_file(&mut self, file: FileRegion, suffix: &'static [u8]) {
+        debug_assert!(self.can_send_file());
+        self.send_file
+            .as_mut()
+            .expect("buffer_file without a sendfile socket")
+            .start(file, suffix);
+    }
+
+    #[cfg(not(all(feature = "runtime", target_os = "linux")))]
+    pub fn buffer_file(&mut self, _file: FileRegion, _suffix: &'static [u8]) {
+        unreachable!("buffer_file without sendfile support");
+    }
+
+    /// Whether a file is queued, and nothing more can be written until it
+    /// has been flushed.
+    #[cfg(all(feature = "runtime", target_os = "linux"))]
+    pub fn is_sending_file(&self) -> bool {
+        self.send_file.as_ref().map_or(false, SendFile::is_sending)
+    }
+
+    #[cfg(not(all(feature = "runtime", target_os = "linux")))]
+    pub fn is_sending_file(&self) -> bool {
+        false
     }
 
     pub fn consume_leading_lines(&mut self) {
@@ -143,7 +214,14 @@ where
         loop {
             match S::parse(&mut self.read_buf, ParseContext {
                 cached_headers: parse_ctx.cached_headers,
+                header_slots: parse_ctx.header_slots,
                 req_method: parse_ctx.req_method,
+                limits: parse_ctx.limits,
+                preserve_header_case: parse_ctx.preserve_header_case,
+                leniency: parse_ctx.leniency,
+                strict_framing: parse_ctx.strict_framing,
+                on_informational: parse_ctx.on_informational,
+                received_continue: parse_ctx.received_continue,
             })? {
                 Some(msg) => {
                     debug!("parsed {} headers", msg.head.headers.len());
@@ -153,6 +231,10 @@ where
                     let max = self.read_buf_strategy.max();
                     if self.read_buf.len() >= max {
                         debug!("max_buf_size ({}) reached, closing", max);
+                        // If not even the request line fit, blame the URI.
+                        if S::is_server() && !self.read_buf.contains(&b'\n') {
+                            return Poll::Ready(Err(crate::Error::new_uri_too_long()));
+                        }
                         return Poll::Ready(Err(crate::Error::new_too_large()));
                     }
                 },
@@ -173,10 +255,28 @@ where
         if self.read_buf.remaining_mut() < next {
             self.read_buf.reserve(next);
         }
-        match Pin::new(&mut self.io).poll_read_buf(cx, &mut self.read_buf) {
+        let allowed = match self.read_limit {
+            Some(ref mut pacer) => match pacer.poll_acquire(cx, self.read_buf.remaining_mut()) {
+                Poll::Ready(n) => n,
+                Poll::Pending => {
+                    self.read_blocked = true;
+                    return Poll::Pending;
+                },
+            },
+            None => self.read_buf.remaining_mut(),
+        };
+        let res = if allowed < self.read_buf.remaining_mut() {
+            self.poll_read_at_most(cx, allowed)
+        } else {
+            Pin::new(&mut self.io).poll_read_buf(cx, &mut self.read_buf)
+        };
+        match res {
             Poll::Ready(Ok(n)) => {
                     debug!("read {} bytes", n);
                     self.read_buf_strategy.record(n);
+                    if let Some(ref pacer) = self.read_limit {
+                        pacer.consume(n);
+                    }
                     Poll::Ready(Ok(n))
                 },
             Poll::Pending => {
@@ -187,6 +287,19 @@ where
         }
     }
 
+    /// Reads at most `max` bytes into the read buffer.
+    fn poll_read_at_most(&mut self, cx: &mut task::Context<'_>, max: usize) -> Poll<io::Result<usize>> {
+        let n = unsafe {
+            let buf = &mut self.read_buf.bytes_mut()[..max];
+            self.io.prepare_uninitialized_buffer(buf);
+            ready!(Pin::new(&mut self.io).poll_read(cx, buf))?
+        };
+        unsafe {
+            self.read_buf.advance_mut(n);
+        }
+        Poll::Ready(Ok(n))
+    }
+
     pub fn into_inner(self) -> (T, Bytes) {
         (self.io, self.read_buf.freeze())
     }
@@ -200,9 +313,10 @@ where
     }
 
     pub fn poll_flush(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
-        if self.flush_pipeline && !self.read_buf.is_empty() {
+        if self.flush_pipeline && !self.read_buf.is_empty() && !self.is_sending_file() {
             Poll::Ready(Ok(()))
         } else if self.write_buf.remaining() == 0 {
+            ready!(self.poll_send_file(cx))?;
             Pin::new(&mut self.io).poll_flush(cx)
         } else {
             match self.write_buf.strategy {
@@ -210,7 +324,14 @@ where
                 _ => (),
             }
             loop {
-                let n = ready!(Pin::new(&mut self.io).poll_write_buf(cx, &mut self.write_buf.auto()))?;
+                let remaining = self.write_buf.remaining();
+                let allowed = ready!(self.poll_write_allowed(cx, remaining));
+                let n = if allowed < remaining {
+                    ready!(Pin::new(&mut self.io).poll_write_buf(cx, &mut self.write_buf.auto().take(allowed)))?
+                } else {
+                    ready!(Pin::new(&mut self.io).poll_write_buf(cx, &mut self.write_buf.auto()))?
+                };
+                self.record_written(n);
                 debug!("flushed {} bytes", n);
                 if self.write_buf.remaining() == 0 {
                     break;
@@ -219,6 +340,7 @@ where
                     return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
                 }
             }
+            ready!(self.poll_send_file(cx))?;
             Pin::new(&mut self.io).poll_flush(cx)
         }
     }
@@ -229,7 +351,10 @@ where
     /// that skips some bookkeeping around using multiple buffers.
     fn poll_flush_flattened(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
         loop {
-            let n = ready!(Pin::new(&mut self.io).poll_write(cx, self.write_buf.headers.bytes()))?;
+            let remaining = self.write_buf.headers.remaining();
+            let allowed = ready!(self.poll_write_allowed(cx, remaining));
+            let n = ready!(Pin::new(&mut self.io).poll_write(cx, &self.write_buf.headers.bytes()[..allowed]))?;
+            self.record_written(n);
             debug!("flushed {} bytes", n);
             self.write_buf.headers.advance(n);
             if self.write_buf.headers.remaining() == 0 {
@@ -240,9 +365,37 @@ where
                 return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
             }
         }
+        ready!(self.poll_send_file(cx))?;
         Pin::new(&mut self.io).poll_flush(cx)
     }
 
+    #[cfg(all(feature = "runtime", target_os = "linux"))]
+    fn poll_send_file(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        match self.send_file {
+            Some(ref mut send_file) => send_file.poll_send(cx, &mut self.io),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    #[cfg(not(all(feature = "runtime", target_os = "linux")))]
+    fn poll_send_file(&mut self, _cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// How many of `want` bytes can be written now.
+    fn poll_write_allowed(&mut self, cx: &mut task::Context<'_>, want: usize) -> Poll<usize> {
+        match self.write_limit {
+            Some(ref mut pacer) => pacer.poll_acquire(cx, want),
+            None => Poll::Ready(want),
+        }
+    }
+
+    fn record_written(&mut self, n: usize) {
+        if let Some(ref pacer) = self.write_limit {
+            pacer.consume(n);
+        }
+    }
+
     #[cfg(test)]
     fn flush<'a>(&'a mut self) -> impl std::future::Future<Output = io::Result<()>> + 'a {
         futures_util::future::poll_fn(move |cx| self.poll_flush(cx))
@@ -408,7 +561,7 @@ pub(super) struct WriteBuf<B> {
     headers: Cursor<Vec<u8>>,
     max_buf_size: usize,
     /// Deque of user buffers if strategy is Queue
-    queue: BufDeque<B>,
+    queue: BufList<B>,
     strategy: WriteStrategy,
 }
 
@@ -417,7 +570,7 @@ impl<B> WriteBuf<B> {
         WriteBuf {
             headers: Cursor::new(Vec::with_capacity(INIT_BUFFER_SIZE)),
             max_buf_size: DEFAULT_MAX_BUFFER_SIZE,
-            queue: BufDeque::new(),
+            queue: BufList::new(),
             strategy: WriteStrategy::Auto,
         }
     }
@@ -457,7 +610,7 @@ where
                 }
             },
             WriteStrategy::Auto | WriteStrategy::Queue => {
-                self.queue.bufs.push_back(buf.into());
+                self.queue.push(buf.into());
             },
         }
     }
@@ -468,7 +621,7 @@ where
                 self.remaining() < self.max_buf_size
             },
             WriteStrategy::Auto | WriteStrategy::Queue => {
-                self.queue.bufs.len() < MAX_BUF_LIST_BUFFERS
+                self.queue.bufs_cnt() < MAX_BUF_LIST_BUFFERS
                     && self.remaining() < self.max_buf_size
             },
         }
@@ -590,69 +743,6 @@ enum WriteStrategy {
     Queue,
 }
 
-struct BufDeque<T> {
-    bufs: VecDeque<T>,
-}
-
-
-impl<T> BufDeque<T> {
-    fn new() -> BufDeque<T> {
-        BufDeque {
-            bufs: VecDeque::new(),
-        }
-    }
-}
-
-impl<T: Buf> Buf for BufDeque<T> {
-    #[inline]
-    fn remaining(&self) -> usize {
-        self.bufs.iter()
-            .map(|buf| buf.remaining())
-            .sum()
-    }
-
-    #[inline]
-    fn bytes(&self) -> &[u8] {
-        for buf in &self.bufs {
-            return buf.bytes();
-        }
-        &[]
-    }
-
-    #[inline]
-    fn advance(&mut self, mut cnt: usize) {
-        while cnt > 0 {
-            {
-                let front = &mut self.bufs[0];
-                let rem = front.remaining();
-                if rem > cnt {
-                    front.advance(cnt);
-                    return;
-                } else {
-                    front.advance(rem);
-                    cnt -= rem;
-                }
-            }
-            self.bufs.pop_front();
-        }
-    }
-
-    #[inline]
-    fn bytes_vec<'t>(&'t self, dst: &mut [&'t IoVec]) -> usize {
-        if dst.is_empty() {
-            return 0;
-        }
-        let mut vecs = 0;
-        for buf in &self.bufs {
-            vecs += buf.bytes_vec(&mut dst[vecs..]);
-            if vecs == dst.len() {
-                break;
-            }
-        }
-        vecs
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -692,7 +782,7 @@ mod tests {
 
     #[tokio::test]
     async fn parse_reads_until_blocked() {
-        use crate::proto::h1::ClientTransaction;
+        use crate::proto::h1::{ClientTransaction, ParseState};
 
         let mock = Mock::new()
             // Split over multiple reads will read all of it
@@ -707,11 +797,8 @@ mod tests {
         // We expect a `parse` to be not ready, and so can't await it directly.
         // Rather, this `poll_fn` will wrap the `Poll` result.
         futures_util::future::poll_fn(|cx| {
-            let parse_ctx = ParseContext {
-                cached_headers: &mut None,
-                req_method: &mut None,
-            };
-            assert!(buffered.parse::<ClientTransaction>(cx, parse_ctx).is_pending());
+            let mut state = ParseState::default();
+            assert!(buffered.parse::<ClientTransaction>(cx, state.ctx()).is_pending());
             Poll::Ready(())
         }).await;
 
@@ -833,12 +920,12 @@ mod tests {
         buffered.buffer(Cursor::new(b"world, ".to_vec()));
         buffered.buffer(Cursor::new(b"it's ".to_vec()));
         buffered.buffer(Cursor::new(b"hyper!".to_vec()));
-        assert_eq!(buffered.write_buf.queue.bufs.len(), 3);
+        assert_eq!(buffered.write_buf.queue.bufs_cnt(), 3);
         buffered.flush().unwrap();
 
         assert_eq!(buffered.io, b"hello world, it's hyper!");
         assert_eq!(buffered.io.num_writes(), 1);
-        assert_eq!(buffered.write_buf.queue.bufs.len(), 0);
+        assert_eq!(buffered.write_buf.queue.bufs_cnt(), 0);
     }
     */
 
@@ -859,7 +946,7 @@ mod tests {
         buffered.buffer(Cursor::new(b"world, ".to_vec()));
         buffered.buffer(Cursor::new(b"it's ".to_vec()));
         buffered.buffer(Cursor::new(b"hyper!".to_vec()));
-        assert_eq!(buffered.write_buf.queue.bufs.len(), 0);
+        assert_eq!(buffered.write_buf.queue.bufs_cnt(), 0);
 
         buffered.flush().await.expect("flush");
     }
@@ -885,11 +972,11 @@ mod tests {
         buffered.buffer(Cursor::new(b"world, ".to_vec()));
         buffered.buffer(Cursor::new(b"it's ".to_vec()));
         buffered.buffer(Cursor::new(b"hyper!".to_vec()));
-        assert_eq!(buffered.write_buf.queue.bufs.len(), 3);
+        assert_eq!(buffered.write_buf.queue.bufs_cnt(), 3);
 
         buffered.flush().await.expect("flush");
 
-        assert_eq!(buffered.write_buf.queue.bufs.len(), 0);
+        assert_eq!(buffered.write_buf.queue.bufs_cnt(), 0);
     }
 
     #[tokio::test]
@@ -914,11 +1001,11 @@ mod tests {
         buffered.buffer(Cursor::new(b"world, ".to_vec()));
         buffered.buffer(Cursor::new(b"it's ".to_vec()));
         buffered.buffer(Cursor::new(b"hyper!".to_vec()));
-        assert_eq!(buffered.write_buf.queue.bufs.len(), 3);
+        assert_eq!(buffered.write_buf.queue.bufs_cnt(), 3);
 
         buffered.flush().await.expect("flush");
 
-        assert_eq!(buffered.write_buf.queue.bufs.len(), 0);
+        assert_eq!(buffered.write_buf.queue.bufs_cnt(), 0);
     }
 
     #[cfg(feature = "nightly")]
@@ -1,10 +1,13 @@
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use http::{HeaderMap, Method};
 
+use crate::ext::OnInformational;
 use crate::proto::{MessageHead, BodyLength, DecodedLength};
+use crate::server::conn::ErrorResponder;
 
 pub(crate) use self::conn::Conn;
 pub(crate) use self::dispatch::Dispatcher;
+pub(crate) use self::role::HeaderSlots;
 pub use self::decode::Decoder;
 pub use self::encode::{EncodedBuf, Encoder};
 pub use self::io::Cursor; //TODO: move out of h1::io
@@ -17,8 +20,15 @@ pub(crate) mod dispatch;
 mod encode;
 mod io;
 mod role;
+#[cfg(all(feature = "runtime", target_os = "linux"))]
+mod sendfile;
 
 
+/// The default maximum number of headers in a message head, and the most
+/// that are parsed without allocating.
+// Note: if this changes, update server::conn::Http::http1_max_headers docs.
+pub(crate) const DEFAULT_MAX_HEADERS: usize = 100;
+
 pub(crate) type ServerTransaction = role::Server;
 pub(crate) type ClientTransaction = role::Client;
 
@@ -29,7 +39,13 @@ pub(crate) trait Http1Transaction {
     fn parse(bytes: &mut BytesMut, ctx: ParseContext) -> ParseResult<Self::Incoming>;
     fn encode(enc: Encode<Self::Outgoing>, dst: &mut Vec<u8>) -> crate::Result<Encoder>;
 
-    fn on_error(err: &crate::Error) -> Option<MessageHead<Self::Outgoing>>;
+    fn on_error(err: &crate::Error, responder: Option<&ErrorResponder>) -> Option<(MessageHead<Self::Outgoing>, Bytes)>;
+
+    /// Encodes an informational (1xx) head, returning true if it was a
+    /// `100 Continue`. Only servers send these.
+    fn encode_informational(_head: MessageHead<Self::Outgoing>, _dst: &mut Vec<u8>) -> bool {
+        unreachable!("only servers send informational responses")
+    }
 
     fn is_client() -> bool {
         !Self::is_server()
@@ -64,7 +80,95 @@ pub(crate) struct ParsedMessage<T> {
 
 pub(crate) struct ParseContext<'a> {
     cached_headers: &'a mut Option<HeaderMap>,
+    header_slots: &'a mut HeaderSlots,
     req_method: &'a mut Option<Method>,
+    limits: HeadLimits,
+    preserve_header_case: bool,
+    leniency: Leniency,
+    strict_framing: bool,
+    on_informational: &'a mut Option<OnInformational>,
+    received_continue: &'a mut bool,
+}
+
+/// What a `ParseContext` borrows, so tests can build one in a single call.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct ParseState {
+    cached_headers: Option<HeaderMap>,
+    header_slots: HeaderSlots,
+    req_method: Option<Method>,
+    limits: HeadLimits,
+    preserve_header_case: bool,
+    leniency: Leniency,
+    strict_framing: bool,
+    on_informational: Option<OnInformational>,
+    received_continue: bool,
+}
+
+#[cfg(test)]
+impl ParseState {
+    /// The state for parsing a response to a request with `method`.
+    fn response_to(method: Method) -> ParseState {
+        ParseState {
+            req_method: Some(method),
+            ..ParseState::default()
+        }
+    }
+
+    fn ctx(&mut self) -> ParseContext<'_> {
+        ParseContext {
+            cached_headers: &mut self.cached_headers,
+            header_slots: &mut self.header_slots,
+            req_method: &mut self.req_method,
+            limits: self.limits,
+            preserve_header_case: self.preserve_header_case,
+            leniency: self.leniency,
+            strict_framing: self.strict_framing,
+            on_informational: &mut self.on_informational,
+            received_continue: &mut self.received_continue,
+        }
+    }
+}
+
+/// Limits checked while parsing a message head.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct HeadLimits {
+    pub(crate) max_headers: usize,
+    pub(crate) max_head_size: Option<usize>,
+    pub(crate) max_request_line_size: Option<usize>,
+    pub(crate) max_header_value_size: Option<usize>,
+}
+
+impl Default for HeadLimits {
+    fn default() -> HeadLimits {
+        HeadLimits {
+            max_headers: DEFAULT_MAX_HEADERS,
+            max_head_size: None,
+            max_request_line_size: None,
+            max_header_value_size: None,
+        }
+    }
+}
+
+/// Deviations from RFC 7230 accepted while parsing a message head.
+///
+/// Each accepted deviation is normalized before the head is parsed.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Leniency {
+    /// Unfold header values continued with obsolete line folding.
+    pub(crate) obs_fold: bool,
+    /// Remove whitespace between a header name and its colon.
+    pub(crate) space_before_colon: bool,
+    /// Rewrite bare LF line endings as CRLF.
+    pub(crate) bare_lf: bool,
+    /// Replace invalid bytes in a response reason phrase with spaces.
+    pub(crate) invalid_reason: bool,
+}
+
+impl Leniency {
+    pub(crate) fn is_lenient(&self) -> bool {
+        self.obs_fold || self.space_before_colon || self.bare_lf || self.invalid_reason
+    }
 }
 
 /// Passed to Http1Transaction::encode
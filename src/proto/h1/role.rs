@@ -2,20 +2,22 @@
 // can't upgrade yet
 #![allow(deprecated)]
 
+use std::collections::{HashMap, VecDeque};
 use std::fmt::{self, Write};
 use std::mem;
 
-use bytes::{BytesMut};
+use bytes::{Bytes, BytesMut};
 use http::header::{self, Entry, HeaderName, HeaderValue};
-use http::{HeaderMap, Method, StatusCode, Version};
+use http::{Extensions, HeaderMap, Method, StatusCode, Version};
 use httparse;
 
 use crate::error::Parse;
+use crate::ext::HeaderCaseMap;
 use crate::headers;
 use crate::proto::{BodyLength, DecodedLength, MessageHead, RequestLine, RequestHead};
-use crate::proto::h1::{Encode, Encoder, Http1Transaction, ParseResult, ParseContext, ParsedMessage, date};
+use crate::proto::h1::{DEFAULT_MAX_HEADERS, Encode, Encoder, HeadLimits, Http1Transaction, Leniency, ParseResult, ParseContext, ParsedMessage, date};
+use crate::server::conn::ErrorResponder;
 
-const MAX_HEADERS: usize = 100;
 const AVERAGE_HEADER_SIZE: usize = 30; // totally scientific
 
 macro_rules! header_name {
@@ -73,6 +75,12 @@ impl Http1Transaction for Server {
             return Ok(None);
         }
 
+        if ctx.leniency.is_lenient() && !normalize_head(buf, &ctx.leniency, false) {
+            check_request_line_size(buf, &ctx.limits)?;
+            check_head_size(buf.len(), &ctx.limits)?;
+            return Ok(None);
+        }
+
         let mut keep_alive;
         let is_http_11;
         let subject;
@@ -84,15 +92,31 @@ impl Http1Transaction for Server {
         // but we *never* read any of it until after httparse has assigned
         // values into it. By not zeroing out the stack memory, this saves
         // a good ~5% on pipeline benchmarks.
-        let mut headers_indices: [HeaderIndices; MAX_HEADERS] = unsafe { mem::uninitialized() };
+        let mut headers_indices_stack: [HeaderIndices; DEFAULT_MAX_HEADERS] = unsafe { mem::uninitialized() };
+        let headers_indices = header_slots(
+            &mut headers_indices_stack,
+            &mut ctx.header_slots.indices,
+            ctx.limits.max_headers,
+            HeaderIndices::EMPTY,
+        );
         {
-            let mut headers: [httparse::Header; MAX_HEADERS] = unsafe { mem::uninitialized() };
+            let mut headers_stack: [httparse::Header; DEFAULT_MAX_HEADERS] = unsafe { mem::uninitialized() };
+            let mut headers_heap = lend_headers(&mut ctx.header_slots.headers);
+            let headers = header_slots(
+                &mut headers_stack,
+                &mut headers_heap,
+                ctx.limits.max_headers,
+                httparse::EMPTY_HEADER,
+            );
             trace!("Request.parse([Header; {}], [u8; {}])", headers.len(), buf.len());
-            let mut req = httparse::Request::new(&mut headers);
+            let mut req = httparse::Request::new(headers);
             let bytes = buf.as_ref();
             match req.parse(bytes) {
                 Ok(httparse::Status::Complete(parsed_len)) => {
                     trace!("Request.parse Complete({})", parsed_len);
+                    check_request_line_size(&bytes[..parsed_len], &ctx.limits)?;
+                    check_line_endings(&bytes[..parsed_len])?;
+                    check_head_size(parsed_len, &ctx.limits)?;
                     len = parsed_len;
                     subject = RequestLine(
                         Method::from_bytes(req.method.unwrap().as_bytes())?,
@@ -108,10 +132,14 @@ impl Http1Transaction for Server {
                         Version::HTTP_10
                     };
 
-                    record_header_indices(bytes, &req.headers, &mut headers_indices)?;
+                    record_header_indices(bytes, &req.headers, headers_indices, &ctx.limits)?;
                     headers_len = req.headers.len();
                 }
-                Ok(httparse::Status::Partial) => return Ok(None),
+                Ok(httparse::Status::Partial) => {
+                    check_request_line_size(bytes, &ctx.limits)?;
+                    check_head_size(bytes.len(), &ctx.limits)?;
+                    return Ok(None);
+                },
                 Err(err) => return Err(match err {
                     // if invalid Token, try to determine if for method or path
                     httparse::Error::Token => {
@@ -125,6 +153,7 @@ impl Http1Transaction for Server {
                     other => other.into(),
                 }),
             }
+            ctx.header_slots.headers = recycle_headers(headers_heap);
         };
 
         let slice = buf.split_to(len).freeze();
@@ -144,6 +173,8 @@ impl Http1Transaction for Server {
         let mut con_len = None;
         let mut is_te = false;
         let mut is_te_chunked = false;
+        let mut is_te_known = true;
+        let mut has_con_len = false;
         let mut wants_upgrade = subject.0 == Method::CONNECT;
 
         let mut headers = ctx.cached_headers
@@ -152,10 +183,20 @@ impl Http1Transaction for Server {
 
         headers.reserve(headers_len);
 
+        let mut header_case_map = if ctx.preserve_header_case {
+            Some(HeaderCaseMap::new())
+        } else {
+            None
+        };
+
         for header in &headers_indices[..headers_len] {
             let name = header_name!(&slice[header.name.0..header.name.1]);
             let value = header_value!(slice.slice(header.value.0, header.value.1));
 
+            if let Some(ref mut map) = header_case_map {
+                map.append_raw(name.clone(), slice.slice(header.name.0, header.name.1));
+            }
+
             match name {
                 header::TRANSFER_ENCODING => {
                     // https://tools.ietf.org/html/rfc7230#section-3.3.3
@@ -166,13 +207,25 @@ impl Http1Transaction for Server {
                         debug!("HTTP/1.0 cannot have Transfer-Encoding header");
                         return Err(Parse::Header);
                     }
+                    if ctx.strict_framing && (is_te_chunked || !headers::is_chunked_final(&value)) {
+                        debug!("request with chunked before the final transfer-coding");
+                        return Err(Parse::Header);
+                    }
                     is_te = true;
-                    if headers::is_chunked_(&value) {
-                        is_te_chunked = true;
+                    is_te_known &= headers::is_known_transfer_encoding(&value);
+                    // chunked must be the final encoding, so only the
+                    // last Transfer-Encoding header decides
+                    is_te_chunked = headers::is_chunked_(&value);
+                    if is_te_chunked {
                         decoder = DecodedLength::CHUNKED;
                     }
                 },
                 header::CONTENT_LENGTH => {
+                    has_con_len = true;
+                    if ctx.strict_framing && !headers::is_content_length_digits(&value) {
+                        debug!("request with invalid content-length: {:?}", value);
+                        return Err(Parse::Header);
+                    }
                     if is_te {
                         continue;
                     }
@@ -219,18 +272,38 @@ impl Http1Transaction for Server {
             headers.append(name, value);
         }
 
+        // A server that doesn't understand a transfer-coding should respond
+        // with 501 Not Implemented.
+        if !is_te_known {
+            debug!("request with unknown transfer-encoding, not implemented");
+            return Err(Parse::TransferEncoding);
+        }
+
         if is_te && !is_te_chunked {
             debug!("request with transfer-encoding header, but not chunked, bad request");
             return Err(Parse::Header);
         }
 
+        // Without strict framing, Transfer-Encoding takes precedence over
+        // Content-Length, as RFC 7230 allows.
+        if ctx.strict_framing && is_te && has_con_len {
+            debug!("request with both content-length and transfer-encoding");
+            return Err(Parse::Header);
+        }
+
         *ctx.req_method = Some(subject.0.clone());
 
+        let mut extensions = Extensions::new();
+        if let Some(map) = header_case_map {
+            extensions.insert(map);
+        }
+
         Ok(Some(ParsedMessage {
             head: MessageHead {
                 version,
                 subject,
                 headers,
+                extensions,
             },
             decode: decoder,
             expect_continue,
@@ -250,10 +323,9 @@ impl Http1Transaction for Server {
 
         let mut wrote_len = false;
 
-        // hyper currently doesn't support returning 1xx status codes as a Response
-        // This is because Service only allows returning a single Response, and
-        // so if you try to reply with a e.g. 100 Continue, you have no way of
-        // replying with the latter status code response.
+        // A Service only returns a single, final Response, so 1xx status
+        // codes are rejected here. They can be sent before it with the
+        // `ext::Informational` handle instead.
         let (ret, mut is_last) = if msg.head.subject == StatusCode::SWITCHING_PROTOCOLS {
             (Ok(()), true)
         } else if msg.req_method == &Some(Method::CONNECT) && msg.head.subject.is_success() {
@@ -301,6 +373,12 @@ impl Http1Transaction for Server {
             extend(dst, b"\r\n");
         }
 
+        let header_case_map = msg.head.extensions.remove::<HeaderCaseMap>();
+        let headers_start = dst.len();
+        // With a `HeaderCaseMap`, where each line is written is recorded,
+        // to put them in the recorded order after.
+        let mut lines = header_case_map.as_ref().map(|_| Vec::new());
+
         let mut encoder = Encoder::length(0);
         let mut wrote_date = false;
         'headers: for (name, mut values) in msg.head.headers.drain() {
@@ -346,9 +424,11 @@ impl Http1Transaction for Server {
                                         known_len,
                                         len,
                                     );
+                                    let line_start = dst.len();
                                     extend(dst, b"content-length: ");
                                     extend(dst, value.as_bytes());
                                     extend(dst, b"\r\n");
+                                    record_line(&mut lines, &header::CONTENT_LENGTH, line_start, dst.len());
                                     wrote_len = true;
                                     continue 'headers;
                                 } else {
@@ -383,9 +463,11 @@ impl Http1Transaction for Server {
                             }
                             if let Some((len, value)) = folded {
                                 encoder = Encoder::length(len);
+                                let line_start = dst.len();
                                 extend(dst, b"content-length: ");
                                 extend(dst, value.as_bytes());
                                 extend(dst, b"\r\n");
+                                record_line(&mut lines, &header::CONTENT_LENGTH, line_start, dst.len());
                                 wrote_len = true;
                                 continue 'headers;
                             } else {
@@ -427,6 +509,7 @@ impl Http1Transaction for Server {
                     wrote_len = true;
                     encoder = Encoder::chunked();
 
+                    let line_start = dst.len();
                     extend(dst, b"transfer-encoding: ");
 
                     let mut saw_chunked;
@@ -447,15 +530,18 @@ impl Http1Transaction for Server {
                         // zero lines? add a chunked line then
                         extend(dst, b"chunked\r\n");
                     }
+                    record_line(&mut lines, &header::TRANSFER_ENCODING, line_start, dst.len());
                     continue 'headers;
                 },
                 header::CONNECTION => {
                     if !is_last {
                         for value in values {
+                            let line_start = dst.len();
                             extend(dst, name.as_str().as_bytes());
                             extend(dst, b": ");
                             extend(dst, value.as_bytes());
                             extend(dst, b"\r\n");
+                            record_line(&mut lines, &name, line_start, dst.len());
 
                             if headers::connection_close(&value) {
                                 is_last = true;
@@ -472,10 +558,12 @@ impl Http1Transaction for Server {
             //TODO: this should perhaps instead combine them into
             //single lines, as RFC7230 suggests is preferable.
             for value in values {
+                let line_start = dst.len();
                 extend(dst, name.as_str().as_bytes());
                 extend(dst, b": ");
                 extend(dst, value.as_bytes());
                 extend(dst, b"\r\n");
+                record_line(&mut lines, &name, line_start, dst.len());
             }
         }
 
@@ -485,14 +573,18 @@ impl Http1Transaction for Server {
                     if msg.head.version == Version::HTTP_10 || !Server::can_chunked(msg.req_method, msg.head.subject) {
                         Encoder::close_delimited()
                     } else {
+                        let line_start = dst.len();
                         extend(dst, b"transfer-encoding: chunked\r\n");
+                        record_line(&mut lines, &header::TRANSFER_ENCODING, line_start, dst.len());
                         Encoder::chunked()
                     }
                 },
                 None |
                 Some(BodyLength::Known(0)) => {
                     if msg.head.subject != StatusCode::NOT_MODIFIED {
+                        let line_start = dst.len();
                         extend(dst, b"content-length: 0\r\n");
+                        record_line(&mut lines, &header::CONTENT_LENGTH, line_start, dst.len());
                     }
                     Encoder::length(0)
                 },
@@ -500,9 +592,11 @@ impl Http1Transaction for Server {
                     if msg.head.subject == StatusCode::NOT_MODIFIED {
                         Encoder::length(0)
                     } else {
+                        let line_start = dst.len();
                         extend(dst, b"content-length: ");
                         let _ = ::itoa::write(&mut dst, len);
                         extend(dst, b"\r\n");
+                        record_line(&mut lines, &header::CONTENT_LENGTH, line_start, dst.len());
                         Encoder::length(len)
                     }
                 },
@@ -521,35 +615,52 @@ impl Http1Transaction for Server {
         // cached date is much faster than formatting every request
         if !wrote_date {
             dst.reserve(date::DATE_VALUE_LENGTH + 8);
+            let line_start = dst.len();
             extend(dst, b"date: ");
             date::extend(dst);
-            extend(dst, b"\r\n\r\n");
-        } else {
             extend(dst, b"\r\n");
+            record_line(&mut lines, &header::DATE, line_start, dst.len());
+        }
+
+        if let (Some(map), Some(lines)) = (header_case_map, lines) {
+            reorder_lines(dst, headers_start, &lines, &map);
         }
+        extend(dst, b"\r\n");
 
         ret.map(|()| encoder.set_last(is_last))
     }
 
-    fn on_error(err: &crate::Error) -> Option<MessageHead<Self::Outgoing>> {
-        use crate::error::Kind;
-        let status = match *err.kind() {
-            Kind::Parse(Parse::Method) |
-            Kind::Parse(Parse::Header) |
-            Kind::Parse(Parse::Uri)    |
-            Kind::Parse(Parse::Version) => {
-                StatusCode::BAD_REQUEST
-            },
-            Kind::Parse(Parse::TooLarge) => {
-                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
-            },
-            _ => return None,
-        };
+    fn encode_informational(head: MessageHead<Self::Outgoing>, dst: &mut Vec<u8>) -> bool {
+        trace!("Server::encode_informational status={:?}", head.subject);
+        debug_assert!(head.subject.is_informational());
+
+        dst.reserve(30 + head.headers.len() * AVERAGE_HEADER_SIZE);
+        extend(dst, b"HTTP/1.1 ");
+        extend(dst, head.subject.as_str().as_bytes());
+        extend(dst, b" ");
+        extend(dst, head.subject.canonical_reason().unwrap_or("<none>").as_bytes());
+        extend(dst, b"\r\n");
 
-        debug!("sending automatic response ({}) for parse error", status);
-        let mut msg = MessageHead::default();
-        msg.subject = status;
-        Some(msg)
+        match head.extensions.get::<HeaderCaseMap>() {
+            Some(map) => write_headers_original_case(&head.headers, map, false, dst),
+            None => write_headers(&head.headers, dst),
+        }
+        extend(dst, b"\r\n");
+
+        head.subject == StatusCode::CONTINUE
+    }
+
+    fn on_error(err: &crate::Error, responder: Option<&ErrorResponder>) -> Option<(MessageHead<Self::Outgoing>, Bytes)> {
+        let res = ErrorResponder::respond(responder, err)?;
+        debug!("sending automatic response ({}) for parse error", res.status());
+        let (parts, body) = res.into_parts();
+        let msg = MessageHead {
+            version: parts.version,
+            subject: parts.status,
+            headers: parts.headers,
+            extensions: parts.extensions,
+        };
+        Some((msg, body))
     }
 
     fn is_server() -> bool {
@@ -595,28 +706,52 @@ impl Http1Transaction for Client {
             if buf.is_empty() {
                 return Ok(None);
             }
+            if ctx.leniency.is_lenient() && !normalize_head(buf, &ctx.leniency, true) {
+                check_head_size(buf.len(), &ctx.limits)?;
+                return Ok(None);
+            }
             // Unsafe: see comment in Server Http1Transaction, above.
-            let mut headers_indices: [HeaderIndices; MAX_HEADERS] = unsafe { mem::uninitialized() };
+            let mut headers_indices_stack: [HeaderIndices; DEFAULT_MAX_HEADERS] = unsafe { mem::uninitialized() };
+            let headers_indices = header_slots(
+                &mut headers_indices_stack,
+                &mut ctx.header_slots.indices,
+                ctx.limits.max_headers,
+                HeaderIndices::EMPTY,
+            );
             let (len, status, version, headers_len) = {
-                let mut headers: [httparse::Header; MAX_HEADERS] = unsafe { mem::uninitialized() };
+                let mut headers_stack: [httparse::Header; DEFAULT_MAX_HEADERS] = unsafe { mem::uninitialized() };
+                let mut headers_heap = lend_headers(&mut ctx.header_slots.headers);
+                let headers = header_slots(
+                    &mut headers_stack,
+                    &mut headers_heap,
+                    ctx.limits.max_headers,
+                    httparse::EMPTY_HEADER,
+                );
                 trace!("Response.parse([Header; {}], [u8; {}])", headers.len(), buf.len());
-                let mut res = httparse::Response::new(&mut headers);
+                let mut res = httparse::Response::new(headers);
                 let bytes = buf.as_ref();
-                match res.parse(bytes)? {
+                let parsed = match res.parse(bytes)? {
                     httparse::Status::Complete(len) => {
                         trace!("Response.parse Complete({})", len);
+                        check_line_endings(&bytes[..len])?;
+                        check_head_size(len, &ctx.limits)?;
                         let status = StatusCode::from_u16(res.code.unwrap())?;
                         let version = if res.version.unwrap() == 1 {
                             Version::HTTP_11
                         } else {
                             Version::HTTP_10
                         };
-                        record_header_indices(bytes, &res.headers, &mut headers_indices)?;
+                        record_header_indices(bytes, &res.headers, headers_indices, &ctx.limits)?;
                         let headers_len = res.headers.len();
                         (len, status, version, headers_len)
                     },
-                    httparse::Status::Partial => return Ok(None),
-                }
+                    httparse::Status::Partial => {
+                        check_head_size(bytes.len(), &ctx.limits)?;
+                        return Ok(None);
+                    },
+                };
+                ctx.header_slots.headers = recycle_headers(headers_heap);
+                parsed
             };
 
             let slice = buf.split_to(len).freeze();
@@ -628,10 +763,21 @@ impl Http1Transaction for Client {
             let mut keep_alive = version == Version::HTTP_11;
 
             headers.reserve(headers_len);
+
+            let mut header_case_map = if ctx.preserve_header_case {
+                Some(HeaderCaseMap::new())
+            } else {
+                None
+            };
+
             for header in &headers_indices[..headers_len] {
                 let name = header_name!(&slice[header.name.0..header.name.1]);
                 let value = header_value!(slice.slice(header.value.0, header.value.1));
 
+                if let Some(ref mut map) = header_case_map {
+                    map.append_raw(name.clone(), slice.slice(header.name.0, header.name.1));
+                }
+
                 if let header::CONNECTION = name {
                     // keep_alive was previously set to default for Version
                         if keep_alive {
@@ -646,12 +792,25 @@ impl Http1Transaction for Client {
                 headers.append(name, value);
             }
 
+            let mut extensions = Extensions::new();
+            if let Some(map) = header_case_map {
+                extensions.insert(map);
+            }
+            if keep_alive {
+                if let Some(params) = headers::keep_alive_parse_all(&headers) {
+                    extensions.insert(params);
+                }
+            }
+
             let head = MessageHead {
                 version,
                 subject: status,
                 headers,
+                extensions,
             };
             if let Some((decode, is_upgrade)) = Client::decoder(&head, ctx.req_method)? {
+                // no more informational responses for this request
+                *ctx.on_informational = None;
                 return Ok(Some(ParsedMessage {
                     head,
                     decode,
@@ -663,6 +822,12 @@ impl Http1Transaction for Client {
                 }));
             }
 
+            if head.subject == StatusCode::CONTINUE {
+                *ctx.received_continue = true;
+            }
+            if let Some(ref callback) = *ctx.on_informational {
+                callback.call(head);
+            }
         }
     }
 
@@ -693,7 +858,9 @@ impl Http1Transaction for Client {
         }
         extend(dst, b"\r\n");
 
-        if msg.title_case_headers {
+        if let Some(map) = msg.head.extensions.get::<HeaderCaseMap>() {
+            write_headers_original_case(&msg.head.headers, map, msg.title_case_headers, dst);
+        } else if msg.title_case_headers {
             write_headers_title_case(&msg.head.headers, dst);
         } else {
             write_headers(&msg.head.headers, dst);
@@ -704,7 +871,7 @@ impl Http1Transaction for Client {
         Ok(body)
     }
 
-    fn on_error(_err: &crate::Error) -> Option<MessageHead<Self::Outgoing>> {
+    fn on_error(_err: &crate::Error, _responder: Option<&ErrorResponder>) -> Option<(MessageHead<Self::Outgoing>, Bytes)> {
         // we can't tell the server about any errors it creates
         None
     }
@@ -933,10 +1100,260 @@ struct HeaderIndices {
     value: (usize, usize),
 }
 
+impl HeaderIndices {
+    const EMPTY: HeaderIndices = HeaderIndices {
+        name: (0, 0),
+        value: (0, 0),
+    };
+}
+
+/// Header slots for limits too large for the stack, kept by a connection
+/// so that they are only allocated once.
+#[derive(Default)]
+pub(crate) struct HeaderSlots {
+    indices: Vec<HeaderIndices>,
+    // Only the allocation is kept: it is emptied after each parse, so it
+    // never holds headers borrowed from a read buffer.
+    headers: Vec<httparse::Header<'static>>,
+}
+
+// Returns `max` slots for headers, only using `heap` if the stack array is
+// too small to hold them.
+fn header_slots<'a, T: Copy>(
+    stack: &'a mut [T; DEFAULT_MAX_HEADERS],
+    heap: &'a mut Vec<T>,
+    max: usize,
+    empty: T,
+) -> &'a mut [T] {
+    if max <= DEFAULT_MAX_HEADERS {
+        &mut stack[..max]
+    } else {
+        if heap.len() < max {
+            heap.resize(max, empty);
+        }
+        &mut heap[..max]
+    }
+}
+
+// Takes the kept allocation for httparse headers, to hold headers of a new
+// buffer. A parse that fails gives it up, so the next one allocates again.
+//
+// SAFETY: the returned `Vec` is empty, so no header borrows anything yet.
+// The parse fills it with headers borrowing the read buffer, and only puts
+// it back into `kept` through `recycle_headers`, which empties it first. A
+// parse that returns early drops it instead. Either way, no header that
+// borrows the read buffer outlives the parse.
+fn lend_headers<'b>(kept: &mut Vec<httparse::Header<'static>>) -> Vec<httparse::Header<'b>> {
+    recycle_headers(mem::replace(kept, Vec::new()))
+}
+
+// Empties `headers`, to reuse its allocation with a different lifetime.
+fn recycle_headers<'a, 'b>(headers: Vec<httparse::Header<'a>>) -> Vec<httparse::Header<'b>> {
+    let mut headers = mem::ManuallyDrop::new(headers);
+    headers.clear();
+    // SAFETY: only the lifetime of the element type changes, and the
+    // `Vec` was just cleared, so no header borrowing `'a` data is left to
+    // be seen as `'b`. The pointer, capacity and layout are those of the
+    // same allocation, which `ManuallyDrop` keeps from being freed twice.
+    unsafe {
+        Vec::from_raw_parts(headers.as_mut_ptr() as *mut httparse::Header<'b>, 0, headers.capacity())
+    }
+}
+
+fn check_head_size(len: usize, limits: &HeadLimits) -> Result<(), crate::error::Parse> {
+    match limits.max_head_size {
+        Some(max) if len > max => {
+            debug!("message head larger than {} bytes", max);
+            Err(crate::error::Parse::TooLarge)
+        },
+        _ => Ok(()),
+    }
+}
+
+// Checks the request line in `bytes`, which may not be complete yet.
+fn check_request_line_size(bytes: &[u8], limits: &HeadLimits) -> Result<(), crate::error::Parse> {
+    if let Some(max) = limits.max_request_line_size {
+        let line_len = bytes
+            .iter()
+            .position(|&b| b == b'\n')
+            .map(|i| i + 1)
+            .unwrap_or(bytes.len());
+        if line_len > max {
+            debug!("request line larger than {} bytes", max);
+            return Err(crate::error::Parse::UriTooLong);
+        }
+    }
+    Ok(())
+}
+
+// Rejects a head that uses a bare LF, without a CR, to end a line.
+//
+// httparse accepts bare LFs, but RFC 7230 doesn't. When they are allowed by
+// the leniency, they have been rewritten as CRLFs already.
+fn check_line_endings(head: &[u8]) -> Result<(), crate::error::Parse> {
+    // httparse skips empty lines before the start-line.
+    let start = head
+        .iter()
+        .position(|&b| b != b'\r' && b != b'\n')
+        .unwrap_or(head.len());
+    let bare_lf = head[start..]
+        .iter()
+        .enumerate()
+        .any(|(i, &b)| b == b'\n' && (i == 0 || head[start + i - 1] != b'\r'));
+    if bare_lf {
+        debug!("message head uses a bare LF line ending");
+        return Err(crate::error::Parse::Header);
+    }
+    Ok(())
+}
+
+// Finds the end of the message head at the start of `buf`, allowing bare LF
+// line endings.
+fn find_head_end(buf: &[u8]) -> Option<usize> {
+    let start = buf
+        .iter()
+        .position(|&b| b != b'\r' && b != b'\n')?;
+    let mut i = start;
+    while let Some(pos) = buf[i..].iter().position(|&b| b == b'\n') {
+        let nl = i + pos;
+        match buf.get(nl + 1).cloned() {
+            Some(b'\n') => return Some(nl + 2),
+            Some(b'\r') => match buf.get(nl + 2).cloned() {
+                Some(b'\n') => return Some(nl + 3),
+                Some(_) => (),
+                None => return None,
+            },
+            Some(_) => (),
+            None => return None,
+        }
+        i = nl + 1;
+    }
+    None
+}
+
+// Rewrites the message head at the start of `buf` so that the deviations
+// accepted by `leniency` are replaced with what httparse expects.
+//
+// Returns false if the head isn't complete yet.
+fn normalize_head(buf: &mut BytesMut, leniency: &Leniency, is_response: bool) -> bool {
+    let end = match find_head_end(buf) {
+        Some(end) => end,
+        None => return false,
+    };
+
+    let normalized = {
+        let head = &buf[..end];
+        let mut lines: Vec<(Vec<u8>, &'static [u8])> = Vec::new();
+        // index of the start-line in `lines`, once seen
+        let mut start_line = None;
+        let mut pos = 0;
+        while pos < head.len() {
+            let nl = pos + head[pos..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .expect("head ends with a newline");
+            let (content_end, ending): (usize, &'static [u8]) = if nl > pos && head[nl - 1] == b'\r' {
+                (nl - 1, b"\r\n")
+            } else {
+                (nl, b"\n")
+            };
+            let ending: &'static [u8] = if leniency.bare_lf { b"\r\n" } else { ending };
+            let mut line = head[pos..content_end].to_vec();
+            pos = nl + 1;
+
+            let start = match start_line {
+                Some(start) => start,
+                None => {
+                    if !line.is_empty() {
+                        if is_response && leniency.invalid_reason {
+                            normalize_reason(&mut line);
+                        }
+                        start_line = Some(lines.len());
+                    }
+                    lines.push((line, ending));
+                    continue;
+                }
+            };
+
+            if line.is_empty() {
+                // the empty line ending the head
+                lines.push((line, ending));
+                continue;
+            }
+
+            let is_folded = line[0] == b' ' || line[0] == b'\t';
+            if is_folded && leniency.obs_fold && lines.len() > start + 1 {
+                let prev = &mut lines.last_mut().expect("previous header line").0;
+                while prev.last().map_or(false, |&b| b == b' ' || b == b'\t') {
+                    prev.pop();
+                }
+                prev.push(b' ');
+                let value_start = line
+                    .iter()
+                    .position(|&b| b != b' ' && b != b'\t')
+                    .unwrap_or(line.len());
+                prev.extend_from_slice(&line[value_start..]);
+                continue;
+            }
+
+            if leniency.space_before_colon {
+                if let Some(colon) = line.iter().position(|&b| b == b':') {
+                    let name_end = line[..colon]
+                        .iter()
+                        .rposition(|&b| b != b' ' && b != b'\t')
+                        .map_or(0, |i| i + 1);
+                    let is_name = name_end > 0
+                        && !line[..name_end].iter().any(|&b| b == b' ' || b == b'\t');
+                    if is_name && name_end < colon {
+                        line.drain(name_end..colon);
+                    }
+                }
+            }
+
+            lines.push((line, ending));
+        }
+
+        let mut out = Vec::with_capacity(head.len());
+        for (line, ending) in lines {
+            out.extend_from_slice(&line);
+            out.extend_from_slice(ending);
+        }
+        out
+    };
+
+    if normalized[..] != buf[..end] {
+        trace!("normalized lenient message head");
+        let mut rewritten = BytesMut::with_capacity(normalized.len() + buf.len() - end);
+        rewritten.extend_from_slice(&normalized);
+        rewritten.extend_from_slice(&buf[end..]);
+        *buf = rewritten;
+    }
+    true
+}
+
+// Replaces bytes that aren't allowed in a reason phrase with spaces.
+fn normalize_reason(status_line: &mut [u8]) {
+    // HTTP-version SP status-code SP reason-phrase
+    let reason_start = status_line
+        .iter()
+        .enumerate()
+        .filter(|&(_, &b)| b == b' ')
+        .nth(1)
+        .map(|(i, _)| i + 1);
+    if let Some(start) = reason_start {
+        for b in &mut status_line[start..] {
+            if !(*b == b'\t' || (*b >= b' ' && *b <= b'~')) {
+                *b = b' ';
+            }
+        }
+    }
+}
+
 fn record_header_indices(
     bytes: &[u8],
     headers: &[httparse::Header],
-    indices: &mut [HeaderIndices]
+    indices: &mut [HeaderIndices],
+    limits: &HeadLimits,
 ) -> Result<(), crate::error::Parse> {
     let bytes_ptr = bytes.as_ptr() as usize;
 
@@ -973,6 +1390,12 @@ fn record_header_indices(
                 indices.name = (name_start, name_end);
             }
             {
+                if let Some(max) = limits.max_header_value_size {
+                    if header.value.len() > max {
+                        debug!("header value larger than {} bytes: {:?}", max, header.name);
+                        return Err(crate::error::Parse::HeaderValueTooLong);
+                    }
+                }
                 let value_start = header.value.as_ptr() as usize - bytes_ptr;
                 let value_end = value_start + header.value.len();
                 indices.value = (value_start, value_end);
@@ -1034,6 +1457,81 @@ fn write_headers(headers: &HeaderMap, dst: &mut Vec<u8>) {
     }
 }
 
+// Writes the headers with the case, and in the order, recorded in `map`.
+// Headers that aren't in the map are written after the others.
+fn write_headers_original_case(headers: &HeaderMap, map: &HeaderCaseMap, title_case_headers: bool, dst: &mut Vec<u8>) {
+    // the values of each name not written yet
+    let mut values = HashMap::new();
+
+    for (name, orig) in map.iter() {
+        let value = values
+            .entry(name)
+            .or_insert_with(|| headers.get_all(name).iter())
+            .next();
+        if let Some(value) = value {
+            extend(dst, orig);
+            extend(dst, b": ");
+            extend(dst, value.as_bytes());
+            extend(dst, b"\r\n");
+        }
+    }
+
+    for name in headers.keys() {
+        let rest = values
+            .entry(name)
+            .or_insert_with(|| headers.get_all(name).iter());
+        for value in rest {
+            if title_case_headers {
+                title_case(dst, name.as_str().as_bytes());
+            } else {
+                extend(dst, name.as_str().as_bytes());
+            }
+            extend(dst, b": ");
+            extend(dst, value.as_bytes());
+            extend(dst, b"\r\n");
+        }
+    }
+}
+
+// Records that the line of a header `name` was written at `dst[start..end]`.
+fn record_line(lines: &mut Option<Vec<(HeaderName, usize, usize)>>, name: &HeaderName, start: usize, end: usize) {
+    if let Some(ref mut lines) = *lines {
+        lines.push((name.clone(), start, end));
+    }
+}
+
+// Moves the header `lines` written after `start` in the order recorded in
+// `map`, writing their names with the recorded case. Lines for names
+// missing from the map keep their relative order, and are written after the
+// others.
+fn reorder_lines(dst: &mut Vec<u8>, start: usize, lines: &[(HeaderName, usize, usize)], map: &HeaderCaseMap) {
+    let block = dst.split_off(start);
+    let mut by_name = HashMap::with_capacity(lines.len());
+    for (i, &(ref name, _, _)) in lines.iter().enumerate() {
+        by_name.entry(name).or_insert_with(VecDeque::new).push_back(i);
+    }
+
+    let mut written = vec![false; lines.len()];
+    for (name, orig) in map.iter() {
+        let i = match by_name.get_mut(name).and_then(|indices| indices.pop_front()) {
+            Some(i) => i,
+            None => continue,
+        };
+        let (_, line_start, line_end) = lines[i];
+        // the line without the name, which was written in lowercase
+        let after_name = line_start - start + name.as_str().len();
+        extend(dst, orig);
+        extend(dst, &block[after_name..line_end - start]);
+        written[i] = true;
+    }
+
+    for (i, &(_, line_start, line_end)) in lines.iter().enumerate() {
+        if !written[i] {
+            extend(dst, &block[line_start - start..line_end - start]);
+        }
+    }
+}
+
 struct FastWrite<'a>(&'a mut Vec<u8>);
 
 impl<'a> fmt::Write for FastWrite<'a> {
@@ -1058,6 +1556,7 @@ fn extend(dst: &mut Vec<u8>, data: &[u8]) {
 mod tests {
     use bytes::BytesMut;
 
+    use crate::proto::h1::ParseState;
     use super::*;
 
     #[test]
@@ -1065,18 +1564,15 @@ mod tests {
         extern crate pretty_env_logger;
         let _ = pretty_env_logger::try_init();
         let mut raw = BytesMut::from(b"GET /echo HTTP/1.1\r\nHost: hyper.rs\r\n\r\n".to_vec());
-        let mut method = None;
-        let msg = Server::parse(&mut raw, ParseContext {
-            cached_headers: &mut None,
-            req_method: &mut method,
-        }).unwrap().unwrap();
+        let mut state = ParseState::default();
+        let msg = Server::parse(&mut raw, state.ctx()).unwrap().unwrap();
         assert_eq!(raw.len(), 0);
         assert_eq!(msg.head.subject.0, crate::Method::GET);
         assert_eq!(msg.head.subject.1, "/echo");
         assert_eq!(msg.head.version, crate::Version::HTTP_11);
         assert_eq!(msg.head.headers.len(), 1);
         assert_eq!(msg.head.headers["Host"], "hyper.rs");
-        assert_eq!(method, Some(crate::Method::GET));
+        assert_eq!(state.req_method, Some(crate::Method::GET));
     }
 
 
@@ -1085,11 +1581,7 @@ mod tests {
         extern crate pretty_env_logger;
         let _ = pretty_env_logger::try_init();
         let mut raw = BytesMut::from(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec());
-        let ctx = ParseContext {
-            cached_headers: &mut None,
-            req_method: &mut Some(crate::Method::GET),
-        };
-        let msg = Client::parse(&mut raw, ctx).unwrap().unwrap();
+        let msg = Client::parse(&mut raw, ParseState::response_to(crate::Method::GET).ctx()).unwrap().unwrap();
         assert_eq!(raw.len(), 0);
         assert_eq!(msg.head.subject, crate::StatusCode::OK);
         assert_eq!(msg.head.version, crate::Version::HTTP_11);
@@ -1097,35 +1589,178 @@ mod tests {
         assert_eq!(msg.head.headers["Content-Length"], "0");
     }
 
+    #[test]
+    fn test_parse_response_keep_alive_params() {
+        use std::time::Duration;
+        use crate::ext::KeepAlive;
+
+        fn parse(s: &str) -> ParsedMessage<StatusCode> {
+            let mut bytes = BytesMut::from(s);
+            Client::parse(&mut bytes, ParseState::response_to(Method::GET).ctx())
+                .expect("parse ok")
+                .expect("parse complete")
+        }
+
+        let msg = parse("HTTP/1.1 200 OK\r\nKeep-Alive: timeout=5, max=100\r\nContent-Length: 0\r\n\r\n");
+        let params = msg.head.extensions.get::<KeepAlive>().expect("keep-alive params");
+        assert_eq!(params.timeout(), Some(Duration::from_secs(5)));
+        assert_eq!(params.max(), Some(100));
+
+        let msg = parse("HTTP/1.1 200 OK\r\nKeep-Alive: MAX=\"3\"\r\nContent-Length: 0\r\n\r\n");
+        let params = msg.head.extensions.get::<KeepAlive>().expect("keep-alive params");
+        assert_eq!(params.timeout(), None);
+        assert_eq!(params.max(), Some(3));
+
+        let msg = parse("HTTP/1.1 200 OK\r\nKeep-Alive: timeout=nope\r\nContent-Length: 0\r\n\r\n");
+        assert!(msg.head.extensions.get::<KeepAlive>().is_none());
+
+        // ignored when the connection isn't kept alive
+        let msg = parse("HTTP/1.1 200 OK\r\nConnection: close\r\nKeep-Alive: timeout=5\r\nContent-Length: 0\r\n\r\n");
+        assert!(msg.head.extensions.get::<KeepAlive>().is_none());
+    }
+
     #[test]
     fn test_parse_request_errors() {
         let mut raw = BytesMut::from(b"GET htt:p// HTTP/1.1\r\nHost: hyper.rs\r\n\r\n".to_vec());
-        let ctx = ParseContext {
-            cached_headers: &mut None,
-            req_method: &mut None,
+        Server::parse(&mut raw, ParseState::default().ctx()).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_request_head_limits() {
+        fn parse(s: &str, limits: HeadLimits) -> Result<Option<ParsedMessage<RequestLine>>, Parse> {
+            let mut bytes = BytesMut::from(s);
+            Server::parse(&mut bytes, ParseState { limits, ..ParseState::default() }.ctx())
+        }
+
+        let mut many = String::from("GET / HTTP/1.1\r\n");
+        for i in 0..150 {
+            many.push_str(&format!("x-header-{}: {}\r\n", i, i));
+        }
+        many.push_str("\r\n");
+
+        // more than the default, but parsed once the limit is raised
+        assert_eq!(parse(&many, HeadLimits::default()).unwrap_err(), Parse::TooManyHeaders);
+        let limits = HeadLimits { max_headers: 200, ..HeadLimits::default() };
+        let msg = parse(&many, limits).unwrap().unwrap();
+        assert_eq!(msg.head.headers.len(), 150);
+
+        let limits = HeadLimits { max_headers: 2, ..HeadLimits::default() };
+        let req = "GET / HTTP/1.1\r\nHost: hyper.rs\r\nAccept: */*\r\nUser-Agent: hyper-test\r\n\r\n";
+        assert_eq!(parse(req, limits).unwrap_err(), Parse::TooManyHeaders);
+
+        let limits = HeadLimits { max_head_size: Some(32), ..HeadLimits::default() };
+        assert_eq!(parse(req, limits).unwrap_err(), Parse::TooLarge);
+        // partial heads are rejected as soon as they are too big
+        assert_eq!(parse(&req[..40], limits).unwrap_err(), Parse::TooLarge);
+
+        let limits = HeadLimits { max_request_line_size: Some(16), ..HeadLimits::default() };
+        assert!(parse(req, limits).unwrap().is_some());
+        let long = "GET /a/long/path HTTP/1.1\r\n\r\n";
+        assert_eq!(parse(long, limits).unwrap_err(), Parse::UriTooLong);
+        assert_eq!(parse(&long[..20], limits).unwrap_err(), Parse::UriTooLong);
+
+        let limits = HeadLimits { max_header_value_size: Some(8), ..HeadLimits::default() };
+        assert!(parse("GET / HTTP/1.1\r\nHost: hyper.rs\r\n\r\n", limits).unwrap().is_some());
+        assert_eq!(parse(req, limits).unwrap_err(), Parse::HeaderValueTooLong);
+    }
+
+    #[test]
+    fn test_parse_reuses_header_slots() {
+        let mut many = String::from("GET / HTTP/1.1\r\n");
+        for i in 0..150 {
+            many.push_str(&format!("x-header-{}: {}\r\n", i, i));
+        }
+        many.push_str("\r\n");
+
+        let mut state = ParseState {
+            limits: HeadLimits { max_headers: 200, ..HeadLimits::default() },
+            ..ParseState::default()
         };
-        Server::parse(&mut raw, ctx).unwrap_err();
+        let mut parse = |state: &mut ParseState| {
+            Server::parse(&mut BytesMut::from(many.as_str()), state.ctx()).unwrap().unwrap()
+        };
+
+        assert_eq!(parse(&mut state).head.headers.len(), 150);
+        let indices = state.header_slots.indices.as_ptr();
+        let headers = state.header_slots.headers.as_ptr();
+        assert_eq!(state.header_slots.headers.capacity(), 200);
+        assert!(state.header_slots.headers.is_empty());
+
+        // a second parse on the connection allocates nothing new
+        assert_eq!(parse(&mut state).head.headers.len(), 150);
+        assert_eq!(state.header_slots.indices.as_ptr(), indices);
+        assert_eq!(state.header_slots.headers.as_ptr(), headers);
     }
 
 
+    #[test]
+    fn test_parse_lenient() {
+        fn parse_req(s: &str, leniency: Leniency) -> ParseResult<RequestLine> {
+            let mut bytes = BytesMut::from(s);
+            Server::parse(&mut bytes, ParseState { leniency, ..ParseState::default() }.ctx())
+        }
+
+        fn parse_res(s: &str, leniency: Leniency) -> ParseResult<StatusCode> {
+            let mut bytes = BytesMut::from(s);
+            Client::parse(&mut bytes, ParseState { leniency, ..ParseState::response_to(Method::GET) }.ctx())
+        }
+
+        let strict = Leniency::default();
+
+        // obsolete line folding
+        let folded = "GET / HTTP/1.1\r\nx-folded: one\r\n  two\r\n\ttwo-and-a-half\r\nhost: a\r\n\r\n";
+        parse_req(folded, strict).unwrap_err();
+        let msg = parse_req(folded, Leniency { obs_fold: true, ..strict }).unwrap().unwrap();
+        assert_eq!(msg.head.headers["x-folded"], "one two two-and-a-half");
+        assert_eq!(msg.head.headers["host"], "a");
+
+        // whitespace before the colon
+        let spaced = "HTTP/1.1 200 OK\r\ncontent-length : 0\r\nserver\t: x\r\n\r\n";
+        parse_res(spaced, strict).unwrap_err();
+        let msg = parse_res(spaced, Leniency { space_before_colon: true, ..strict }).unwrap().unwrap();
+        assert_eq!(msg.head.headers["content-length"], "0");
+        assert_eq!(msg.head.headers["server"], "x");
+
+        // bare LF line endings
+        let bare = "GET / HTTP/1.1\nhost: a\r\nx-bare: b\n\n";
+        parse_req(bare, strict).unwrap_err();
+        let msg = parse_req(bare, Leniency { bare_lf: true, ..strict }).unwrap().unwrap();
+        assert_eq!(msg.head.headers["host"], "a");
+        assert_eq!(msg.head.headers["x-bare"], "b");
+        let bare = "HTTP/1.1 200 OK\ncontent-length: 0\n\n";
+        parse_res(bare, strict).unwrap_err();
+        let msg = parse_res(bare, Leniency { bare_lf: true, ..strict }).unwrap().unwrap();
+        assert_eq!(msg.head.headers["content-length"], "0");
+
+        // invalid bytes in the reason phrase
+        let reason = "HTTP/1.1 200 O\x01K\r\ncontent-length: 0\r\n\r\n";
+        parse_res(reason, strict).unwrap_err();
+        let msg = parse_res(reason, Leniency { invalid_reason: true, ..strict }).unwrap().unwrap();
+        assert_eq!(msg.head.subject, StatusCode::OK);
+
+        // an incomplete head waits for more bytes when lenient
+        let partial = "GET / HTTP/1.1\r\nhost : a\r\n";
+        assert!(parse_req(partial, Leniency { space_before_colon: true, ..strict }).unwrap().is_none());
+
+        // the body after a normalized head is left in the buffer
+        let mut bytes = BytesMut::from("POST / HTTP/1.1\ncontent-length: 5\n\nhello");
+        let leniency = Leniency { bare_lf: true, ..strict };
+        Server::parse(&mut bytes, ParseState { leniency, ..ParseState::default() }.ctx()).unwrap().unwrap();
+        assert_eq!(&bytes[..], b"hello");
+    }
+
     #[test]
     fn test_decoder_request() {
         fn parse(s: &str) -> ParsedMessage<RequestLine> {
             let mut bytes = BytesMut::from(s);
-            Server::parse(&mut bytes, ParseContext {
-                cached_headers: &mut None,
-                req_method: &mut None,
-            })
+            Server::parse(&mut bytes, ParseState::default().ctx())
                 .expect("parse ok")
                 .expect("parse complete")
         }
 
         fn parse_err(s: &str, comment: &str) -> crate::error::Parse {
             let mut bytes = BytesMut::from(s);
-            Server::parse(&mut bytes, ParseContext {
-                cached_headers: &mut None,
-                req_method: &mut None,
-            })
+            Server::parse(&mut bytes, ParseState::default().ctx())
                 .expect_err(comment)
         }
 
@@ -1221,6 +1856,13 @@ mod tests {
             \r\n\
         ", "transfer-encoding doesn't end in chunked");
 
+        // unknown transfer-codings are not implemented, rather than bad
+        assert_eq!(parse_err("\
+            POST / HTTP/1.1\r\n\
+            transfer-encoding: foo, chunked\r\n\
+            \r\n\
+        ", "unknown transfer-encoding"), crate::error::Parse::TransferEncoding);
+
 
         // http/1.0
 
@@ -1248,30 +1890,21 @@ mod tests {
 
         fn parse_ignores(s: &str) {
             let mut bytes = BytesMut::from(s);
-            assert!(Client::parse(&mut bytes, ParseContext {
-                cached_headers: &mut None,
-                req_method: &mut Some(Method::GET),
-            })
+            assert!(Client::parse(&mut bytes, ParseState::response_to(Method::GET).ctx())
                 .expect("parse ok")
                 .is_none())
         }
 
         fn parse_with_method(s: &str, m: Method) -> ParsedMessage<StatusCode> {
             let mut bytes = BytesMut::from(s);
-            Client::parse(&mut bytes, ParseContext {
-                cached_headers: &mut None,
-                req_method: &mut Some(m),
-            })
+            Client::parse(&mut bytes, ParseState::response_to(m).ctx())
                 .expect("parse ok")
                 .expect("parse complete")
         }
 
         fn parse_err(s: &str) -> crate::error::Parse {
             let mut bytes = BytesMut::from(s);
-            Client::parse(&mut bytes, ParseContext {
-                cached_headers: &mut None,
-                req_method: &mut Some(Method::GET),
-            })
+            Client::parse(&mut bytes, ParseState::response_to(Method::GET).ctx())
                 .expect_err("parse should err")
         }
 
@@ -1442,6 +2075,38 @@ mod tests {
         assert_eq!(vec, b"GET / HTTP/1.1\r\nContent-Length: 10\r\nContent-Type: application/json\r\n*-*: o_o\r\n\r\n".to_vec());
     }
 
+    #[test]
+    fn test_header_case_map_round_trip() {
+        use crate::proto::BodyLength;
+
+        let mut raw = BytesMut::from(b"GET / HTTP/1.1\r\nHOST: hyper.rs\r\nx-Custom: a\r\nContent-Length: 0\r\nX-custom: b\r\n\r\n".to_vec());
+        let mut msg = Server::parse(&mut raw, ParseState { preserve_header_case: true, ..ParseState::default() }.ctx()).unwrap().unwrap();
+        {
+            let map = msg.head.extensions.get::<HeaderCaseMap>().expect("HeaderCaseMap");
+            let names: Vec<&[u8]> = map.iter().map(|(_, orig)| orig).collect();
+            assert_eq!(names, vec![&b"HOST"[..], b"x-Custom", b"Content-Length", b"X-custom"]);
+        }
+
+        let mut head = MessageHead::default();
+        head.headers = msg.head.headers;
+        head.extensions = msg.head.extensions;
+        let mut vec = Vec::new();
+        Client::encode(Encode {
+            head: &mut head,
+            body: Some(BodyLength::Known(0)),
+            keep_alive: true,
+            req_method: &mut None,
+            title_case_headers: false,
+        }, &mut vec).unwrap();
+
+        assert_eq!(vec, b"GET / HTTP/1.1\r\nHOST: hyper.rs\r\nx-Custom: a\r\nContent-Length: 0\r\nX-custom: b\r\n\r\n".to_vec());
+
+        // without preserve_header_case, nothing is recorded
+        let mut raw = BytesMut::from(b"GET / HTTP/1.1\r\nHOST: hyper.rs\r\n\r\n".to_vec());
+        msg = Server::parse(&mut raw, ParseState::default().ctx()).unwrap().unwrap();
+        assert!(msg.head.extensions.get::<HeaderCaseMap>().is_none());
+    }
+
     #[test]
     fn test_server_encode_connect_method() {
         let mut head = MessageHead::default();
@@ -1461,10 +2126,7 @@ mod tests {
     #[test]
     fn parse_header_htabs() {
         let mut bytes = BytesMut::from("HTTP/1.1 200 OK\r\nserver: hello\tworld\r\n\r\n");
-        let parsed = Client::parse(&mut bytes, ParseContext {
-            cached_headers: &mut None,
-            req_method: &mut Some(Method::GET),
-        })
+        let parsed = Client::parse(&mut bytes, ParseState::response_to(Method::GET).ctx())
             .expect("parse ok")
             .expect("parse complete");
 
@@ -1497,17 +2159,17 @@ mod tests {
             \r\n\r\n".to_vec()
         );
         let len = raw.len();
-        let mut headers = Some(HeaderMap::new());
+        let mut state = ParseState {
+            cached_headers: Some(HeaderMap::new()),
+            ..ParseState::default()
+        };
 
         b.bytes = len as u64;
         b.iter(|| {
-            let mut msg = Server::parse(&mut raw, ParseContext {
-                cached_headers: &mut headers,
-                req_method: &mut None,
-            }).unwrap().unwrap();
+            let mut msg = Server::parse(&mut raw, state.ctx()).unwrap().unwrap();
             ::test::black_box(&msg);
             msg.head.headers.clear();
-            headers = Some(msg.head.headers);
+            state.cached_headers = Some(msg.head.headers);
             restart(&mut raw, len);
         });
 
@@ -1526,17 +2188,17 @@ mod tests {
         let s = &b"GET / HTTP/1.1\r\nHost: localhost:8080\r\n\r\n"[..];
         let mut raw = BytesMut::from(s.to_vec());
         let len = raw.len();
-        let mut headers = Some(HeaderMap::new());
+        let mut state = ParseState {
+            cached_headers: Some(HeaderMap::new()),
+            ..ParseState::default()
+        };
 
         b.bytes = len as u64;
         b.iter(|| {
-            let mut msg = Server::parse(&mut raw, ParseContext {
-                cached_headers: &mut headers,
-                req_method: &mut None,
-            }).unwrap().unwrap();
+            let mut msg = Server::parse(&mut raw, state.ctx()).unwrap().unwrap();
             ::test::black_box(&msg);
             msg.head.headers.clear();
-            headers = Some(msg.head.headers);
+            state.cached_headers = Some(msg.head.headers);
             restart(&mut raw, len);
         });
 
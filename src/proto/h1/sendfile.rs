@@ -0,0 +1,126 @@
+use std::cmp;
+use std::fmt;
+use std::io;
+use std::mem::ManuallyDrop;
+use std::net::TcpStream as StdTcpStream;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+use tokio_io::AsyncWrite;
+use tokio_net::util::PollEvented;
+
+use crate::body::internal::FileRegion;
+use crate::common::{Pin, Poll, Unpin, task};
+
+/// The most bytes given to a single `sendfile` call.
+const MAX_SEND_SIZE: usize = 1024 * 1024;
+
+/// Writes files straight from the page cache to a socket, with
+/// `sendfile(2)`.
+pub(super) struct SendFile {
+    /// A duplicate of the socket, registered with the reactor on its own,
+    /// to wait for the socket to be writable again once it is full.
+    socket: PollEvented<mio::net::TcpStream>,
+    pending: Option<Pending>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    region: FileRegion,
+    /// Framing to write after the file, like the end of a chunked body.
+    suffix: &'static [u8],
+}
+
+impl SendFile {
+    pub(super) fn new(socket: RawFd) -> io::Result<SendFile> {
+        // The IO keeps owning `socket`, so only its duplicate is closed
+        // when this is dropped.
+        let socket = ManuallyDrop::new(unsafe { StdTcpStream::from_raw_fd(socket) });
+        let dup = mio::net::TcpStream::from_stream(socket.try_clone()?)?;
+        Ok(SendFile {
+            socket: PollEvented::new(dup),
+            pending: None,
+        })
+    }
+
+    pub(super) fn is_sending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub(super) fn start(&mut self, region: FileRegion, suffix: &'static [u8]) {
+        debug_assert!(self.pending.is_none(), "a file is already being sent");
+        self.pending = Some(Pending {
+            region,
+            suffix,
+        });
+    }
+
+    /// Sends the pending file, if any, to the socket, and then its suffix
+    /// through `io`.
+    pub(super) fn poll_send<T>(&mut self, cx: &mut task::Context<'_>, io: &mut T) -> Poll<io::Result<()>>
+    where
+        T: AsyncWrite + Unpin,
+    {
+        loop {
+            let pending = match self.pending {
+                Some(ref mut pending) => pending,
+                None => return Poll::Ready(Ok(())),
+            };
+
+            if pending.region.len == 0 {
+                if pending.suffix.is_empty() {
+                    trace!("sendfile complete");
+                    self.pending = None;
+                    return Poll::Ready(Ok(()));
+                }
+                let n = ready!(Pin::new(&mut *io).poll_write(cx, pending.suffix))?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+                pending.suffix = &pending.suffix[n..];
+                continue;
+            }
+
+            ready!(self.socket.poll_write_ready(cx))?;
+            let count = cmp::min(pending.region.len, MAX_SEND_SIZE as u64) as usize;
+            let mut offset = pending.region.offset as libc::off_t;
+            let ret = unsafe {
+                libc::sendfile(
+                    self.socket.get_ref().as_raw_fd(),
+                    pending.region.file.as_raw_fd(),
+                    &mut offset,
+                    count,
+                )
+            };
+            if ret < 0 {
+                let err = io::Error::last_os_error();
+                match err.kind() {
+                    io::ErrorKind::WouldBlock => {
+                        trace!("socket is full, waiting to send more of file");
+                        self.socket.clear_write_ready(cx)?;
+                    },
+                    io::ErrorKind::Interrupted => (),
+                    _ => return Poll::Ready(Err(err)),
+                }
+                continue;
+            }
+            if ret == 0 {
+                return Poll::Ready(Err(file_ended()));
+            }
+            trace!("sendfile wrote {} bytes", ret);
+            pending.region.offset += ret as u64;
+            pending.region.len -= ret as u64;
+        }
+    }
+}
+
+impl fmt::Debug for SendFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SendFile")
+            .field("pending", &self.pending)
+            .finish()
+    }
+}
+
+fn file_ended() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "file ended before the end of the body")
+}
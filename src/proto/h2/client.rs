@@ -11,6 +11,7 @@ use tokio_io::{AsyncRead, AsyncWrite};
 use crate::headers::content_length_parse_all;
 use crate::body::Payload;
 use crate::common::{Exec, Future, Never, Pin, Poll, task};
+use crate::ext::OnInformational;
 use crate::headers;
 use crate::proto::Dispatched;
 use super::{PipeToSendStream, SendBuf};
@@ -127,6 +128,12 @@ where
                     }
                     let (head, body) = req.into_parts();
                     let mut req = ::http::Request::from_parts(head, ());
+                    if req.extensions().get::<OnInformational>().is_some() {
+                        // h2 doesn't expose 1xx responses, see `OnInformational`
+                        debug!("client request with OnInformational over HTTP/2");
+                        cb.send(Err((crate::Error::new_user_unsupported_informational(), None)));
+                        continue;
+                    }
                     super::strip_connection_headers(req.headers_mut(), true);
                     if let Some(len) = body.content_length() {
                         headers::set_content_length_if_missing(req.headers_mut(), len);
@@ -170,7 +177,7 @@ where
                                 Ok(res) => {
                                     let content_length = content_length_parse_all(res.headers());
                                     let res = res.map(|stream|
-                                        crate::Body::h2(stream, content_length));
+                                        crate::Body::h2(stream, content_length, None));
                                     Ok(res)
                                 },
                                 Err(err) => {
@@ -1,4 +1,6 @@
-use bytes::Buf;
+use std::io::Cursor;
+
+use bytes::{Buf, Bytes};
 use h2::{SendStream};
 use http::header::{
     HeaderName, CONNECTION, PROXY_AUTHENTICATE, PROXY_AUTHORIZATION, TE, TRAILER,
@@ -101,7 +103,7 @@ where
     fn send_eos_frame(&mut self) -> crate::Result<()> {
         trace!("send body eos");
         self.body_tx
-            .send_data(SendBuf(None), true)
+            .send_data(SendBuf::None, true)
             .map_err(crate::Error::new_body_write)
     }
 }
@@ -148,7 +150,7 @@ where
                             is_eos,
                         );
 
-                        let buf = SendBuf(Some(chunk));
+                        let buf = SendBuf::Buf(chunk);
                         self.body_tx
                             .send_data(buf, is_eos)
                             .map_err(crate::Error::new_body_write)?;
@@ -195,21 +197,38 @@ where
     }
 }
 
-struct SendBuf<B>(Option<B>);
+enum SendBuf<B> {
+    Buf(B),
+    /// Bytes produced by hyper itself, rather than the user's body.
+    Bytes(Cursor<Bytes>),
+    None,
+}
 
 impl<B: Buf> Buf for SendBuf<B> {
     #[inline]
     fn remaining(&self) -> usize {
-        self.0.as_ref().map(|b| b.remaining()).unwrap_or(0)
+        match *self {
+            SendBuf::Buf(ref b) => b.remaining(),
+            SendBuf::Bytes(ref b) => b.remaining(),
+            SendBuf::None => 0,
+        }
     }
 
     #[inline]
     fn bytes(&self) -> &[u8] {
-        self.0.as_ref().map(|b| b.bytes()).unwrap_or(&[])
+        match *self {
+            SendBuf::Buf(ref b) => b.bytes(),
+            SendBuf::Bytes(ref b) => b.bytes(),
+            SendBuf::None => &[],
+        }
     }
 
     #[inline]
     fn advance(&mut self, cnt: usize) {
-        self.0.as_mut().map(|b| b.advance(cnt));
+        match *self {
+            SendBuf::Buf(ref mut b) => b.advance(cnt),
+            SendBuf::Bytes(ref mut b) => b.advance(cnt),
+            SendBuf::None => {},
+        }
     }
 }
@@ -1,8 +1,13 @@
 use std::error::Error as StdError;
+use std::io::Cursor;
 use std::marker::Unpin;
 
+use bytes::Buf;
+
+use futures_channel::oneshot;
 use futures_core::Stream;
 use h2::Reason;
+use http::header::CONTENT_LENGTH;
 use h2::server::{Builder, Connection, Handshake, SendResponse};
 use tokio_io::{AsyncRead, AsyncWrite};
 
@@ -10,8 +15,11 @@ use crate::body::Payload;
 use crate::body::internal::FullDataArg;
 use crate::common::exec::H2Exec;
 use crate::common::{Future, Pin, Poll, task};
+use crate::error::Parse;
+use crate::ext::Informational;
 use crate::headers;
 use crate::headers::content_length_parse_all;
+use crate::server::conn::{ConnectionInfo, ErrorResponder};
 use crate::service::Service;
 use crate::proto::Dispatched;
 use super::{PipeToSendStream, SendBuf};
@@ -25,6 +33,9 @@ where
 {
     exec: E,
     service: S,
+    conn_info: ConnectionInfo,
+    error_responder: Option<ErrorResponder>,
+    max_body_size: Option<u64>,
     state: State<T, B>,
 }
 
@@ -58,12 +69,23 @@ where
     B::Data: Unpin,
     E: H2Exec<S::Future, B>,
 {
-    pub(crate) fn new(io: T, service: S, builder: &Builder, exec: E) -> Server<T, S, B, E> {
+    pub(crate) fn new(
+        io: T,
+        service: S,
+        conn_info: ConnectionInfo,
+        builder: &Builder,
+        error_responder: Option<ErrorResponder>,
+        max_body_size: Option<u64>,
+        exec: E,
+    ) -> Server<T, S, B, E> {
         let handshake = builder.handshake(io);
         Server {
             exec,
             state: State::Handshaking(handshake),
             service,
+            conn_info,
+            error_responder,
+            max_body_size,
         }
     }
 
@@ -110,7 +132,7 @@ where
                     })
                 },
                 State::Serving(ref mut srv) => {
-                    ready!(srv.poll_server(cx, &mut me.service, &mut me.exec))?;
+                    ready!(srv.poll_server(cx, &mut me.service, &mut me.conn_info, me.error_responder.as_ref(), me.max_body_size, &mut me.exec))?;
                     return Poll::Ready(Ok(Dispatched::Shutdown));
                 }
                 State::Closed => {
@@ -130,7 +152,15 @@ where
     B: Payload,
     B::Data: Unpin,
 {
-    fn poll_server<S, E>(&mut self, cx: &mut task::Context<'_>, service: &mut S, exec: &mut E) -> Poll<crate::Result<()>>
+    fn poll_server<S, E>(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        service: &mut S,
+        conn_info: &mut ConnectionInfo,
+        error_responder: Option<&ErrorResponder>,
+        max_body_size: Option<u64>,
+        exec: &mut E,
+    ) -> Poll<crate::Result<()>>
     where
         S: Service<
             Body,
@@ -170,13 +200,37 @@ where
 
                 // When the service is ready, accepts an incoming request.
                 match ready!(self.conn.poll_accept(cx)) {
-                    Some(Ok((req, respond))) => {
+                    Some(Ok((req, mut respond))) => {
                         trace!("incoming request");
                         let content_length = content_length_parse_all(req.headers());
-                        let req = req.map(|stream| {
-                            crate::Body::h2(stream, content_length)
+                        if content_length.is_none() && req.headers().contains_key(CONTENT_LENGTH) {
+                            debug!("malformed request: invalid content-length");
+                            let err = crate::Error::from(Parse::Header);
+                            respond_with_error(&mut respond, &err, error_responder);
+                            continue;
+                        }
+                        if let (Some(len), Some(max)) = (content_length, max_body_size) {
+                            if len > max {
+                                debug!("incoming content-length ({} bytes) is larger than the limit ({} bytes)", len, max);
+                                let err = crate::Error::new_body_too_large();
+                                respond_with_error(&mut respond, &err, error_responder);
+                                continue;
+                            }
+                        }
+                        let (limit, body_reset) = match max_body_size {
+                            Some(max) => {
+                                let (tx, rx) = oneshot::channel();
+                                (Some((max, tx)), Some(rx))
+                            },
+                            None => (None, None),
+                        };
+                        let mut req = req.map(|stream| {
+                            crate::Body::h2(stream, content_length, limit)
                         });
-                        let fut = H2Stream::new(service.call(req), respond);
+                        req.extensions_mut().insert(conn_info.next_request(::http::Version::HTTP_2));
+                        // h2 can't send 1xx responses yet
+                        req.extensions_mut().insert(Informational::unsupported(::http::Version::HTTP_2));
+                        let fut = H2Stream::new(service.call(req), respond, body_reset, error_responder.cloned());
                         exec.execute_h2stream(fut)?;
                     },
                     Some(Err(e)) => {
@@ -199,12 +253,42 @@ where
     }
 }
 
+fn respond_with_error<B: Buf>(respond: &mut SendResponse<SendBuf<B>>, err: &crate::Error, responder: Option<&ErrorResponder>) {
+    let res = match ErrorResponder::respond(responder, err) {
+        Some(res) => res,
+        None => {
+            respond.send_reset(Reason::PROTOCOL_ERROR);
+            return;
+        }
+    };
+    let (head, body) = res.into_parts();
+    let mut res = ::http::Response::from_parts(head, ());
+    super::strip_connection_headers(res.headers_mut(), false);
+    headers::set_content_length_if_missing(res.headers_mut(), body.len() as u64);
+
+    let eos = body.is_empty();
+    match respond.send_response(res, eos) {
+        Ok(mut body_tx) => if !eos {
+            if let Err(e) = body_tx.send_data(SendBuf::Bytes(Cursor::new(body)), true) {
+                debug!("send error response body error: {}", e);
+            }
+        },
+        Err(e) => {
+            debug!("send error response error: {}", e);
+        }
+    }
+}
+
 #[allow(missing_debug_implementations)]
 pub struct H2Stream<F, B>
 where
     B: Payload,
 {
     reply: SendResponse<SendBuf<B::Data>>,
+    /// Fires when the request body went over the limit, and the stream
+    /// should be answered with a 413 or reset.
+    body_reset: Option<oneshot::Receiver<Reason>>,
+    error_responder: Option<ErrorResponder>,
     state: H2StreamState<F, B>,
 }
 
@@ -222,12 +306,47 @@ where
     //F::Error: Into<Box<dyn StdError + Send + Sync>>,
     B: Payload,
 {
-    fn new(fut: F, respond: SendResponse<SendBuf<B::Data>>) -> H2Stream<F, B> {
+    fn new(
+        fut: F,
+        respond: SendResponse<SendBuf<B::Data>>,
+        body_reset: Option<oneshot::Receiver<Reason>>,
+        error_responder: Option<ErrorResponder>,
+    ) -> H2Stream<F, B> {
         H2Stream {
             reply: respond,
+            body_reset,
+            error_responder,
             state: H2StreamState::Service(fut),
         }
     }
+
+    /// Ends the stream if the request body went over the limit: with a
+    /// `413` if the response hasn't started yet, or else a reset.
+    fn poll_body_reset(&mut self, cx: &mut task::Context<'_>) -> crate::Result<()> {
+        if let Some(ref mut rx) = self.body_reset {
+            match Pin::new(rx).poll(cx) {
+                Poll::Ready(Ok(reason)) => {
+                    self.body_reset = None;
+                    let err = crate::Error::new_body_too_large();
+                    match self.state {
+                        H2StreamState::Service(_) => {
+                            debug!("request body is larger than the limit, responding with an error");
+                            respond_with_error(&mut self.reply, &err, self.error_responder.as_ref());
+                        },
+                        H2StreamState::Body(_) => {
+                            debug!("request body is larger than the limit, resetting stream: {:?}", reason);
+                            self.reply.send_reset(reason);
+                        },
+                    }
+                    return Err(err);
+                },
+                // the body was dropped without going over the limit
+                Poll::Ready(Err(_canceled)) => self.body_reset = None,
+                Poll::Pending => (),
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<F, B, E> H2Stream<F, B>
@@ -240,6 +359,7 @@ where
     fn poll2(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<crate::Result<()>> {
         // Safety: State::{Service, Body} futures are never moved
         let me = unsafe { self.get_unchecked_mut() };
+        me.poll_body_reset(cx)?;
         loop {
             let next = match me.state {
                 H2StreamState::Service(ref mut h) => {
@@ -264,6 +384,10 @@ where
                         },
                     };
 
+                    // The service may have gone over the body limit while
+                    // making this response, which is then never sent.
+                    me.poll_body_reset(cx)?;
+
                     let (head, mut body) = res.into_parts();
                     let mut res = ::http::Response::from_parts(head, ());
                     super::strip_connection_headers(res.headers_mut(), false);
@@ -295,7 +419,7 @@ where
 
                     if let Some(full) = body.__hyper_full_data(FullDataArg(())).0 {
                         let mut body_tx = reply!(false);
-                        let buf = SendBuf(Some(full));
+                        let buf = SendBuf::Buf(full);
                         body_tx
                             .send_data(buf, true)
                             .map_err(crate::Error::new_body_write)?;
@@ -1,5 +1,5 @@
 //! Pieces pertaining to the HTTP message protocol.
-use http::{HeaderMap, Method, StatusCode, Uri, Version};
+use http::{Extensions, HeaderMap, Method, StatusCode, Uri, Version};
 
 pub(crate) use self::h1::{dispatch, Conn, ServerTransaction};
 use self::body_length::DecodedLength;
@@ -8,7 +8,7 @@ pub(crate) mod h1;
 pub(crate) mod h2;
 
 /// An Incoming Message head. Includes request/status line, and headers.
-#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Debug, Default)]
 pub struct MessageHead<S> {
     /// HTTP version of the message.
     pub version: Version,
@@ -16,6 +16,17 @@ pub struct MessageHead<S> {
     pub subject: S,
     /// Headers of the Incoming message.
     pub headers: HeaderMap,
+    /// Extensions of the message.
+    pub extensions: Extensions,
+}
+
+// Extensions can't be compared, so they are ignored.
+impl<S: PartialEq> PartialEq for MessageHead<S> {
+    fn eq(&self, other: &MessageHead<S>) -> bool {
+        self.version == other.version
+            && self.subject == other.subject
+            && self.headers == other.headers
+    }
 }
 
 /// An incoming request message.
@@ -9,6 +9,8 @@
 pub use std::future::Future;
 pub use futures_core::Stream;
 
+pub use crate::common::rate::RateLimit;
+
 use self::inner::Spawn;
 
 /// Spawns a future on the default executor.
@@ -12,19 +12,23 @@ use std::error::Error as StdError;
 use std::fmt;
 use std::mem;
 #[cfg(feature = "runtime")] use std::net::SocketAddr;
+#[cfg(all(feature = "runtime", target_os = "linux"))] use std::os::unix::io::RawFd;
 use std::sync::Arc;
-#[cfg(feature = "runtime")] use std::time::Duration;
+use std::time::Duration;
 
 use bytes::Bytes;
 use futures_core::Stream;
 use h2;
+use http::{Response, StatusCode};
 use pin_utils::{unsafe_pinned, unsafe_unpinned};
 use tokio_io::{AsyncRead, AsyncWrite};
 #[cfg(feature = "runtime")] use tokio_net::driver::Handle;
 
 use crate::body::{Body, Payload};
 use crate::common::exec::{Exec, H2Exec, NewSvcExec};
-use crate::common::io::Rewind;
+use crate::common::io::{Rewind, Throttled};
+use crate::common::rate::RateLimits;
+#[cfg(feature = "runtime")] use crate::rt::RateLimit;
 use crate::common::{Future, Pin, Poll, Unpin, task};
 use crate::error::{Kind, Parse};
 use crate::proto;
@@ -36,7 +40,14 @@ use self::spawn_all::NewSvcTask;
 pub(super) use self::spawn_all::Watcher;
 pub(super) use self::upgrades::UpgradeableConnection;
 
-#[cfg(feature = "runtime")] pub use super::tcp::{AddrIncoming, AddrStream};
+pub use self::info::{ConnectionInfo, Transport};
+#[cfg(all(feature = "runtime", target_os = "linux"))]
+pub(super) use self::info::SendfileSocket;
+
+#[cfg(feature = "runtime")] pub use super::tcp::{AddrIncoming, AddrStream, ProxyIncoming, ProxyStream, Tlv};
+#[cfg(all(feature = "runtime", unix))] pub use super::tcp::{ActivatedIncoming, ActivatedStream, Handoff, Successor};
+
+mod info;
 
 /// A lower-level configuration of the HTTP protocol.
 ///
@@ -49,13 +60,28 @@ pub struct Http<E = Exec> {
     exec: E,
     h1_half_close: bool,
     h1_writev: bool,
+    h1_head_limits: proto::h1::HeadLimits,
+    h1_preserve_header_case: bool,
+    h1_leniency: proto::h1::Leniency,
+    h1_strict_framing: bool,
+    h1_keep_alive_timeout: Option<Duration>,
+    h1_keep_alive_header: bool,
     h2_builder: h2::server::Builder,
     mode: ConnectionMode,
     keep_alive: bool,
     max_buf_size: Option<usize>,
+    max_request_body_size: Option<u64>,
     pipeline_flush: bool,
+    error_responder: Option<ErrorResponder>,
+    rate_limits: RateLimits,
+    #[cfg(all(feature = "runtime", target_os = "linux"))]
+    h1_sendfile: bool,
 }
 
+/// Builds the response sent for a request that failed at the protocol level.
+#[derive(Clone)]
+pub(crate) struct ErrorResponder(Arc<dyn Fn(&crate::Error, StatusCode) -> Response<Bytes> + Send + Sync>);
+
 /// The internal mode of HTTP protocol which indicates the behavior when a parse error occurs.
 #[derive(Clone, Debug, PartialEq)]
 enum ConnectionMode {
@@ -87,6 +113,9 @@ pub struct Serve<I, S, E = Exec> {
 pub struct Connecting<I, F, E = Exec> {
     future: F,
     io: Option<I>,
+    conn_info: Option<ConnectionInfo>,
+    #[cfg(all(feature = "runtime", target_os = "linux"))]
+    sendfile_socket: Option<RawFd>,
     protocol: Http<E>,
 }
 
@@ -112,7 +141,7 @@ where
             proto::ServerTransaction,
         >,
         proto::h2::Server<
-            Rewind<T>,
+            Throttled<Rewind<T>>,
             S,
             S::ResBody,
             E,
@@ -128,7 +157,7 @@ pub(super) enum Either<A, B> {
 
 #[derive(Clone, Debug)]
 enum Fallback<E> {
-    ToHttp2(h2::server::Builder, E),
+    ToHttp2(h2::server::Builder, Option<ErrorResponder>, Option<u64>, RateLimits, E),
     Http1Only,
 }
 
@@ -175,11 +204,22 @@ impl Http {
             exec: Exec::Default,
             h1_half_close: true,
             h1_writev: true,
+            h1_head_limits: proto::h1::HeadLimits::default(),
+            h1_preserve_header_case: false,
+            h1_leniency: proto::h1::Leniency::default(),
+            h1_strict_framing: false,
+            h1_keep_alive_timeout: None,
+            h1_keep_alive_header: false,
             h2_builder: h2::server::Builder::default(),
             mode: ConnectionMode::Fallback,
             keep_alive: true,
             max_buf_size: None,
+            max_request_body_size: None,
             pipeline_flush: false,
+            error_responder: None,
+            rate_limits: RateLimits::default(),
+            #[cfg(all(feature = "runtime", target_os = "linux"))]
+            h1_sendfile: false,
         }
     }
 }
@@ -225,6 +265,156 @@ impl<E> Http<E> {
         self
     }
 
+    /// Set the maximum number of headers in an HTTP/1 request.
+    ///
+    /// Requests with more headers are rejected with `431 Request Header
+    /// Fields Too Large`.
+    ///
+    /// Default is 100.
+    pub fn http1_max_headers(&mut self, max: usize) -> &mut Self {
+        self.h1_head_limits.max_headers = max;
+        self
+    }
+
+    /// Set the maximum size in bytes of an HTTP/1 request head, including
+    /// the request line and all headers.
+    ///
+    /// Requests with a larger head are rejected with `431 Request Header
+    /// Fields Too Large`. Unlike `max_buf_size`, this doesn't affect
+    /// buffering of bodies.
+    ///
+    /// Default is no limit, other than `max_buf_size`.
+    pub fn http1_max_head_size(&mut self, max: usize) -> &mut Self {
+        self.h1_head_limits.max_head_size = Some(max);
+        self
+    }
+
+    /// Set the maximum size in bytes of an HTTP/1 request line, which
+    /// mostly consists of the URI.
+    ///
+    /// Requests with a longer request line are rejected with `414 URI Too
+    /// Long`.
+    ///
+    /// Default is no limit, other than `max_buf_size`.
+    pub fn http1_max_request_line_size(&mut self, max: usize) -> &mut Self {
+        self.h1_head_limits.max_request_line_size = Some(max);
+        self
+    }
+
+    /// Set the maximum size in bytes of a single HTTP/1 header value.
+    ///
+    /// Requests with a longer header value are rejected with `431 Request
+    /// Header Fields Too Large`.
+    ///
+    /// Default is no limit, other than `max_buf_size`.
+    pub fn http1_max_header_value_size(&mut self, max: usize) -> &mut Self {
+        self.h1_head_limits.max_header_value_size = Some(max);
+        self
+    }
+
+    /// Set whether HTTP/1 connections should record the original case and
+    /// order of received header names.
+    ///
+    /// If enabled, a [`HeaderCaseMap`](crate::ext::HeaderCaseMap) is
+    /// inserted in the extensions of each `Request`. A `HeaderCaseMap` in
+    /// the extensions of a `Response` is always used to write its headers.
+    ///
+    /// Default is false.
+    pub fn http1_preserve_header_case(&mut self, enabled: bool) -> &mut Self {
+        self.h1_preserve_header_case = enabled;
+        self
+    }
+
+    /// Set whether HTTP/1 connections accept header values continued on
+    /// the next line with obsolete line folding.
+    ///
+    /// If enabled, folded lines are joined to the header value with a
+    /// single space. Otherwise, such requests are rejected.
+    ///
+    /// Default is false.
+    pub fn http1_allow_obsolete_multiline_headers(&mut self, enabled: bool) -> &mut Self {
+        self.h1_leniency.obs_fold = enabled;
+        self
+    }
+
+    /// Set whether HTTP/1 connections accept whitespace between a header
+    /// name and its colon.
+    ///
+    /// If enabled, the whitespace is removed. Otherwise, such requests are
+    /// rejected.
+    ///
+    /// Default is false.
+    pub fn http1_allow_spaces_before_header_colon(&mut self, enabled: bool) -> &mut Self {
+        self.h1_leniency.space_before_colon = enabled;
+        self
+    }
+
+    /// Set whether HTTP/1 connections accept a bare LF, without a CR, as a
+    /// line ending in the request head.
+    ///
+    /// If enabled, bare LFs are rewritten as CRLFs. Otherwise, such
+    /// requests are rejected.
+    ///
+    /// Default is false.
+    pub fn http1_allow_bare_lf(&mut self, enabled: bool) -> &mut Self {
+        self.h1_leniency.bare_lf = enabled;
+        self
+    }
+
+    /// Set whether HTTP/1 connections reject requests with ambiguous
+    /// message framing, as used for request smuggling.
+    ///
+    /// Requests are always rejected if they have multiple differing
+    /// `Content-Length` headers, or a `Transfer-Encoding` that doesn't end
+    /// in `chunked`. Otherwise, `Transfer-Encoding` takes precedence over
+    /// `Content-Length`.
+    ///
+    /// If enabled, these requests are rejected too:
+    ///
+    /// - both `Content-Length` and `Transfer-Encoding` are present,
+    /// - `chunked` is found before the final transfer-coding,
+    /// - a `Content-Length` isn't only digits,
+    /// - a chunk size is followed by whitespace,
+    /// - the chunk extensions of a chunk are larger than 1024 bytes.
+    ///
+    /// A rejected request head gets a `400 Bad Request` response, and a
+    /// rejected body is an error. Either way, the connection is closed.
+    ///
+    /// Default is false.
+    pub fn http1_strict_framing(&mut self, enabled: bool) -> &mut Self {
+        self.h1_strict_framing = enabled;
+        self
+    }
+
+    /// Set how long an idle HTTP/1 keep-alive connection is kept open,
+    /// waiting for the next request, before it's closed.
+    ///
+    /// Pass `None` to keep idle connections open until the client closes
+    /// them.
+    ///
+    /// Default is `None`.
+    #[cfg(feature = "runtime")]
+    pub fn http1_keep_alive_timeout<D>(&mut self, val: D) -> &mut Self
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.h1_keep_alive_timeout = val.into();
+        self
+    }
+
+    /// Set whether HTTP/1 keep-alive responses advertise the keep-alive
+    /// timeout with a `Keep-Alive: timeout=<seconds>` header.
+    ///
+    /// Clients that honor it stop reusing an idle connection before the
+    /// server closes it. Nothing is sent without a keep-alive timeout, or
+    /// if the response already has a `Keep-Alive` header.
+    ///
+    /// Default is false.
+    pub fn http1_keep_alive_header(&mut self, enabled: bool) -> &mut Self {
+        self.h1_keep_alive_header = enabled;
+        self
+    }
+
     /// Sets whether HTTP2 is required.
     ///
     /// Default is false
@@ -297,6 +487,42 @@ impl<E> Http<E> {
         self
     }
 
+    /// Set the maximum size of a request body, in bytes.
+    ///
+    /// A request with a larger `Content-Length` is answered with `413
+    /// Payload Too Large` before the service is called. A body without a
+    /// length, such as a chunked HTTP/1 body, instead yields an error where
+    /// `Error::is_body_too_large` is true once it grows past the limit.
+    /// Either way, an HTTP/1 connection is closed afterwards. For HTTP/2,
+    /// only the stream ends: with a `413` if the response hasn't started
+    /// yet, or else with an `ENHANCE_YOUR_CALM` reset.
+    ///
+    /// Default is no limit (`None`).
+    pub fn max_request_body_size(&mut self, max: impl Into<Option<u64>>) -> &mut Self {
+        self.max_request_body_size = max.into();
+        self
+    }
+
+    /// Set a [`RateLimit`](crate::rt::RateLimit) of how fast bytes are read
+    /// from connections.
+    ///
+    /// Default is no limit (`None`).
+    #[cfg(feature = "runtime")]
+    pub fn read_rate_limit(&mut self, limit: impl Into<Option<RateLimit>>) -> &mut Self {
+        self.rate_limits.read = limit.into();
+        self
+    }
+
+    /// Set a [`RateLimit`](crate::rt::RateLimit) of how fast bytes are
+    /// written to connections, such as to cap the download speed of clients.
+    ///
+    /// Default is no limit (`None`).
+    #[cfg(feature = "runtime")]
+    pub fn write_rate_limit(&mut self, limit: impl Into<Option<RateLimit>>) -> &mut Self {
+        self.rate_limits.write = limit.into();
+        self
+    }
+
     /// Aggregates flushes to better support pipelined responses.
     ///
     /// Experimental, may have bugs.
@@ -307,6 +533,49 @@ impl<E> Http<E> {
         self
     }
 
+    /// Set whether HTTP/1 connections accepted by a `Server` send file
+    /// bodies with `sendfile(2)`.
+    #[cfg(all(feature = "runtime", target_os = "linux"))]
+    pub(super) fn http1_sendfile(&mut self, enabled: bool) -> &mut Self {
+        self.h1_sendfile = enabled;
+        self
+    }
+
+    /// Set a function to build the response sent when a request fails at
+    /// the protocol level, such as when it can't be parsed.
+    ///
+    /// The function receives the error and the status hyper picked for it:
+    ///
+    /// - `414 URI Too Long` if the request line didn't fit in the buffer.
+    /// - `431 Request Header Fields Too Large` if the headers didn't.
+    /// - `413 Payload Too Large` if the `Content-Length` is over the
+    ///   `max_request_body_size`.
+    /// - `501 Not Implemented` for an unknown `Transfer-Encoding`.
+    /// - `505 HTTP Version Not Supported` for an invalid HTTP version.
+    /// - `400 Bad Request` for any other malformed request.
+    ///
+    /// The returned response is sent as is, and the connection is closed
+    /// afterwards.
+    ///
+    /// Over HTTP/2, this is only called for an invalid `content-length`
+    /// (`400`), and for a body over the `max_request_body_size` (`413`),
+    /// either from its `content-length` or, if the response hasn't started
+    /// yet, once it is read. Only that stream is answered, and the
+    /// connection stays open. A body found over the limit after the
+    /// response started resets the stream with `ENHANCE_YOUR_CALM`. Every
+    /// other malformed HTTP/2 request is refused by the `h2` library with
+    /// a stream reset before hyper sees it, so it never gets a response
+    /// from this function.
+    ///
+    /// Default sends an empty response with the status above.
+    pub fn error_response<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&crate::Error, StatusCode) -> Response<Bytes> + Send + Sync + 'static,
+    {
+        self.error_responder = Some(ErrorResponder(Arc::new(f)));
+        self
+    }
+
     /// Set the executor used to spawn background tasks.
     ///
     /// Default uses implicit default (like `tokio::spawn`).
@@ -315,11 +584,22 @@ impl<E> Http<E> {
             exec,
             h1_half_close: self.h1_half_close,
             h1_writev: self.h1_writev,
+            h1_head_limits: self.h1_head_limits,
+            h1_preserve_header_case: self.h1_preserve_header_case,
+            h1_leniency: self.h1_leniency,
+            h1_strict_framing: self.h1_strict_framing,
+            h1_keep_alive_timeout: self.h1_keep_alive_timeout,
+            h1_keep_alive_header: self.h1_keep_alive_header,
             h2_builder: self.h2_builder,
             mode: self.mode,
             keep_alive: self.keep_alive,
             max_buf_size: self.max_buf_size,
+            max_request_body_size: self.max_request_body_size,
             pipeline_flush: self.pipeline_flush,
+            error_responder: self.error_responder,
+            rate_limits: self.rate_limits,
+            #[cfg(all(feature = "runtime", target_os = "linux"))]
+            h1_sendfile: self.h1_sendfile,
         }
     }
 
@@ -354,6 +634,47 @@ impl<E> Http<E> {
     /// # fn main() {}
     /// ```
     pub fn serve_connection<S, I, Bd>(&self, io: I, service: S) -> Connection<I, S, E>
+    where
+        S: Service<Body, ResBody=Bd>,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        Bd: Payload,
+        Bd::Data: Unpin,
+        I: AsyncRead + AsyncWrite + Unpin,
+        E: H2Exec<S::Future, Bd>,
+    {
+        self.serve_connection_with_info(io, service, ConnectionInfo::new(None, None))
+    }
+
+    /// Bind a connection together with a `Service`, describing it with
+    /// `conn_info`.
+    ///
+    /// The `ConnectionInfo` inserted into each request starts from
+    /// `conn_info`, so it reports its addresses. `serve_connection` leaves
+    /// them out, since it knows nothing about the IO type.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #![feature(async_await)]
+    /// # use hyper::{Body, Request, Response};
+    /// # use hyper::service::Service;
+    /// use hyper::server::conn::{AddrStream, ConnectionInfo, Http};
+    /// # #[cfg(feature = "runtime")]
+    /// # async fn run<S>(stream: AddrStream, some_service: S)
+    /// # where
+    /// #     S: Service<Body, ResBody=Body> + Send + 'static,
+    /// #     S::Future: Send
+    /// # {
+    /// let info = ConnectionInfo::from_transport(&stream);
+    /// let conn = Http::new().serve_connection_with_info(stream, some_service, info);
+    ///
+    /// if let Err(e) = conn.await {
+    ///     eprintln!("server connection error: {}", e);
+    /// }
+    /// # }
+    /// # fn main() {}
+    /// ```
+    pub fn serve_connection_with_info<S, I, Bd>(&self, io: I, service: S, conn_info: ConnectionInfo) -> Connection<I, S, E>
     where
         S: Service<Body, ResBody=Bd>,
         S::Error: Into<Box<dyn StdError + Send + Sync>>,
@@ -375,15 +696,44 @@ impl<E> Http<E> {
                     conn.set_write_strategy_flatten();
                 }
                 conn.set_flush_pipeline(self.pipeline_flush);
+                conn.set_head_limits(self.h1_head_limits);
+                if self.h1_preserve_header_case {
+                    conn.set_preserve_header_case();
+                }
+                conn.set_leniency(self.h1_leniency);
+                if self.h1_strict_framing {
+                    conn.set_strict_framing();
+                }
+                if let Some(timeout) = self.h1_keep_alive_timeout {
+                    conn.set_keep_alive_timeout(timeout);
+                }
+                if self.h1_keep_alive_header {
+                    conn.set_keep_alive_header();
+                }
                 if let Some(max) = self.max_buf_size {
                     conn.set_max_buf_size(max);
                 }
-                let sd = proto::h1::dispatch::Server::new(service);
+                if let Some(max) = self.max_request_body_size {
+                    conn.set_max_body_size(max);
+                }
+                if let Some(ref responder) = self.error_responder {
+                    conn.set_error_responder(responder.clone());
+                }
+                conn.set_rate_limits(self.rate_limits.clone());
+                let sd = proto::h1::dispatch::Server::new(service, conn_info);
                 Either::A(proto::h1::Dispatcher::new(sd, conn))
             }
             ConnectionMode::H2Only => {
                 let rewind_io = Rewind::new(io);
-                let h2 = proto::h2::Server::new(rewind_io, service, &self.h2_builder, self.exec.clone());
+                let h2 = proto::h2::Server::new(
+                    Throttled::new(rewind_io, self.rate_limits.clone()),
+                    service,
+                    conn_info,
+                    &self.h2_builder,
+                    self.error_responder.clone(),
+                    self.max_request_body_size,
+                    self.exec.clone(),
+                );
                 Either::B(h2)
             }
         };
@@ -391,7 +741,13 @@ impl<E> Http<E> {
         Connection {
             conn: Some(either),
             fallback: if self.mode == ConnectionMode::Fallback {
-                Fallback::ToHttp2(self.h2_builder.clone(), self.exec.clone())
+                Fallback::ToHttp2(
+                    self.h2_builder.clone(),
+                    self.error_responder.clone(),
+                    self.max_request_body_size,
+                    self.rate_limits.clone(),
+                    self.exec.clone(),
+                )
             } else {
                 Fallback::Http1Only
             },
@@ -472,6 +828,44 @@ impl<E> Http<E> {
 }
 
 
+// ===== impl ErrorResponder =====
+
+impl ErrorResponder {
+    /// Returns the response for `err`, or `None` if the error can't be
+    /// answered with one.
+    pub(crate) fn respond(responder: Option<&ErrorResponder>, err: &crate::Error) -> Option<Response<Bytes>> {
+        let status = match *err.kind() {
+            Kind::Parse(Parse::Method) |
+            Kind::Parse(Parse::Header) |
+            Kind::Parse(Parse::Uri) => StatusCode::BAD_REQUEST,
+            Kind::Parse(Parse::UriTooLong) => StatusCode::URI_TOO_LONG,
+            Kind::Parse(Parse::TooLarge) |
+            Kind::Parse(Parse::TooManyHeaders) |
+            Kind::Parse(Parse::HeaderValueTooLong) => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            Kind::BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Kind::Parse(Parse::TransferEncoding) => StatusCode::NOT_IMPLEMENTED,
+            Kind::Parse(Parse::Version) => StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+            _ => return None,
+        };
+
+        Some(match responder {
+            Some(responder) => (responder.0)(err, status),
+            None => {
+                let mut res = Response::new(Bytes::new());
+                *res.status_mut() = status;
+                res
+            }
+        })
+    }
+}
+
+impl fmt::Debug for ErrorResponder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ErrorResponder")
+            .finish()
+    }
+}
+
 // ===== impl Connection =====
 
 impl<I, B, S, E> Connection<I, S, E>
@@ -597,14 +991,20 @@ where
         };
         let mut rewind_io = Rewind::new(io);
         rewind_io.rewind(read_buf);
-        let (builder, exec) = match self.fallback {
-            Fallback::ToHttp2(ref builder, ref exec) => (builder, exec),
+        let (builder, responder, max_body_size, limits, exec) = match self.fallback {
+            Fallback::ToHttp2(ref builder, ref responder, max_body_size, ref limits, ref exec) => {
+                (builder, responder, max_body_size, limits, exec)
+            },
             Fallback::Http1Only => unreachable!("upgrade_h2 with Fallback::Http1Only"),
         };
+        let (service, conn_info) = dispatch.into_parts();
         let h2 = proto::h2::Server::new(
-            rewind_io,
-            dispatch.into_service(),
+            Throttled::new(rewind_io, limits.clone()),
+            service,
+            conn_info,
             builder,
+            responder.clone(),
+            max_body_size,
             exec.clone(),
         );
 
@@ -726,6 +1126,9 @@ where
             Poll::Ready(Some(Ok(Connecting {
                 future: new_fut,
                 io: Some(io),
+                conn_info: Some(ConnectionInfo::new(None, None)),
+                #[cfg(all(feature = "runtime", target_os = "linux"))]
+                sendfile_socket: None,
                 protocol: self.protocol.clone(),
             })))
         } else {
@@ -739,6 +1142,9 @@ where
 impl<I, F, E> Connecting<I, F, E> {
     unsafe_pinned!(future: F);
     unsafe_unpinned!(io: Option<I>);
+    unsafe_unpinned!(conn_info: Option<ConnectionInfo>);
+    #[cfg(all(feature = "runtime", target_os = "linux"))]
+    unsafe_unpinned!(sendfile_socket: Option<RawFd>);
 }
 
 impl<I, F, S, FE, E, B> Future for Connecting<I, F, E>
@@ -755,7 +1161,15 @@ where
     fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
         let service = ready!(self.as_mut().future().poll(cx))?;
         let io = self.as_mut().io().take().expect("polled after complete");
-        Poll::Ready(Ok(self.protocol.serve_connection(io, service)))
+        let conn_info = self.as_mut().conn_info().take().expect("polled after complete");
+        let mut conn = self.protocol.serve_connection_with_info(io, service, conn_info);
+        #[cfg(all(feature = "runtime", target_os = "linux"))]
+        {
+            if let (Some(socket), Some(Either::A(ref mut h1))) = (self.as_mut().sendfile_socket().take(), conn.conn.as_mut()) {
+                h1.set_sendfile_socket(socket);
+            }
+        }
+        Poll::Ready(Ok(conn))
     }
 }
 
@@ -795,7 +1209,17 @@ where
         // Safety: futures are never moved... lolwtf
         let me = unsafe { self.get_unchecked_mut() };
         loop {
-            if let Some(connecting) = ready!(unsafe { Pin::new_unchecked(&mut me.serve) }.poll_next(cx)?) {
+            if let Some(mut connecting) = ready!(unsafe { Pin::new_unchecked(&mut me.serve) }.poll_next(cx)?) {
+                // the `Server`'s own incoming types know their addresses
+                if let Some(ref io) = connecting.io {
+                    connecting.conn_info = Some(self::info::accepted_info(io));
+                    #[cfg(all(feature = "runtime", target_os = "linux"))]
+                    {
+                        if me.serve.protocol.h1_sendfile {
+                            connecting.sendfile_socket = self::info::sendfile_socket(io);
+                        }
+                    }
+                }
                 let fut = NewSvcTask::new(connecting, watcher.clone());
                 me.serve.protocol.exec.execute_new_svc(fut)?;
             } else {
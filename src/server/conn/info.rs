@@ -0,0 +1,215 @@
+use std::any::Any;
+use std::net::SocketAddr;
+#[cfg(all(feature = "runtime", target_os = "linux"))]
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use http::Version;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Information about the connection a request was received on.
+///
+/// A `ConnectionInfo` is inserted into the extensions of every `Request`
+/// handed to a service, and can be retrieved with
+/// `req.extensions().get::<ConnectionInfo>()`.
+///
+/// Connections accepted by a `Server` from hyper's own incoming types,
+/// such as `AddrIncoming`, report their addresses. A connection served with
+/// [`Http::serve_connection_with_info`](super::Http::serve_connection_with_info)
+/// reports the addresses it was given, while other connections report
+/// none.
+#[derive(Clone, Debug)]
+pub struct ConnectionInfo {
+    id: u64,
+    remote_addr: Option<SocketAddr>,
+    local_addr: Option<SocketAddr>,
+    version: Version,
+    request_count: u64,
+    accepted_at: Instant,
+}
+
+/// A transport that can describe its own addresses.
+///
+/// A [`ConnectionInfo`] can be made from any `Transport` with
+/// [`ConnectionInfo::from_transport`], to serve it with
+/// [`Http::serve_connection_with_info`](super::Http::serve_connection_with_info).
+/// `AddrStream`, `TcpStream` and `UnixStream` implement it already. The
+/// default methods report nothing.
+pub trait Transport {
+    /// Returns the remote (peer) address of this transport, if known.
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    /// Returns the local address of this transport, if known.
+    fn local_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        (**self).remote_addr()
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        (**self).local_addr()
+    }
+}
+
+#[cfg(feature = "runtime")]
+impl Transport for tokio_net::tcp::TcpStream {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr().ok()
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        tokio_net::tcp::TcpStream::local_addr(self).ok()
+    }
+}
+
+#[cfg(all(feature = "runtime", unix))]
+impl Transport for tokio_net::uds::UnixStream {}
+
+/// An IO type whose writes go straight to a TCP socket, so files can be
+/// sent to that socket with `sendfile`.
+///
+/// This is apart from `Transport` so that wrappers forwarding `Transport`,
+/// such as a TLS stream, never have files sent past them.
+#[cfg(all(feature = "runtime", target_os = "linux"))]
+pub(crate) trait SendfileSocket {
+    fn sendfile_socket(&self) -> Option<RawFd>;
+}
+
+#[cfg(all(feature = "runtime", target_os = "linux"))]
+impl SendfileSocket for tokio_net::tcp::TcpStream {
+    fn sendfile_socket(&self) -> Option<RawFd> {
+        Some(self.as_raw_fd())
+    }
+}
+
+/// Returns the socket of `io` if it is one of the `SendfileSocket` types.
+///
+/// Only a `Server`, which accepts hyper's own stream types, uses this, and
+/// only once `http1_sendfile` is enabled.
+#[cfg(all(feature = "runtime", target_os = "linux"))]
+pub(crate) fn sendfile_socket<T: Any>(io: &T) -> Option<RawFd> {
+    let io = io as &dyn Any;
+    if let Some(io) = io.downcast_ref::<tokio_net::tcp::TcpStream>() {
+        io.sendfile_socket()
+    } else if let Some(io) = io.downcast_ref::<super::AddrStream>() {
+        io.sendfile_socket()
+    } else if let Some(io) = io.downcast_ref::<super::ActivatedStream>() {
+        io.sendfile_socket()
+    } else {
+        None
+    }
+}
+
+/// Returns the info of a connection accepted by one of hyper's own incoming
+/// types, which know their addresses, or an empty one for any other IO.
+pub(crate) fn accepted_info<T: Any>(io: &T) -> ConnectionInfo {
+    let io = io as &dyn Any;
+    #[cfg(feature = "runtime")]
+    {
+        if let Some(io) = io.downcast_ref::<super::AddrStream>() {
+            return ConnectionInfo::from_transport(io);
+        }
+        if let Some(io) = io.downcast_ref::<super::ProxyStream>() {
+            return ConnectionInfo::from_transport(io);
+        }
+        if let Some(io) = io.downcast_ref::<tokio_net::tcp::TcpStream>() {
+            return ConnectionInfo::from_transport(io);
+        }
+    }
+    #[cfg(all(feature = "runtime", unix))]
+    {
+        if let Some(io) = io.downcast_ref::<super::ActivatedStream>() {
+            return ConnectionInfo::from_transport(io);
+        }
+    }
+    let _ = io;
+    ConnectionInfo::new(None, None)
+}
+
+impl ConnectionInfo {
+    /// Creates a `ConnectionInfo` for a new connection with these
+    /// addresses.
+    pub fn new(remote_addr: Option<SocketAddr>, local_addr: Option<SocketAddr>) -> ConnectionInfo {
+        ConnectionInfo {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            remote_addr,
+            local_addr,
+            version: Version::HTTP_11,
+            request_count: 0,
+            accepted_at: Instant::now(),
+        }
+    }
+
+    /// Creates a `ConnectionInfo` for a new connection over `io`, with the
+    /// addresses it reports.
+    pub fn from_transport<T: Transport + ?Sized>(io: &T) -> ConnectionInfo {
+        ConnectionInfo::new(io.remote_addr(), io.local_addr())
+    }
+
+    /// Returns an identifier for this connection, unique within the process.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the remote (peer) address of this connection, if known.
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    /// Returns the local address of this connection, if known.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// Returns the HTTP version negotiated for this connection.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Returns how many requests have been received on this connection,
+    /// including the current one.
+    pub fn request_count(&self) -> u64 {
+        self.request_count
+    }
+
+    /// Returns when this connection was accepted.
+    pub fn accepted_at(&self) -> Instant {
+        self.accepted_at
+    }
+
+    /// Records a new request of `version`, returning a snapshot for it.
+    pub(crate) fn next_request(&mut self, version: Version) -> ConnectionInfo {
+        self.version = version;
+        self.request_count += 1;
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_request_counts_and_keeps_id() {
+        let mut info = ConnectionInfo::new(None, None);
+        let other = ConnectionInfo::new(None, None);
+        assert_ne!(info.id(), other.id());
+
+        let first = info.next_request(Version::HTTP_10);
+        assert_eq!(first.request_count(), 1);
+        assert_eq!(first.version(), Version::HTTP_10);
+
+        let second = info.next_request(Version::HTTP_2);
+        assert_eq!(second.request_count(), 2);
+        assert_eq!(second.version(), Version::HTTP_2);
+        assert_eq!(first.id(), second.id());
+    }
+}
@@ -56,22 +56,28 @@ mod shutdown;
 use std::error::Error as StdError;
 use std::fmt;
 #[cfg(feature = "runtime")] use std::net::{SocketAddr, TcpListener as StdTcpListener};
+#[cfg(all(feature = "runtime", unix))] use std::path::Path;
 
 #[cfg(feature = "runtime")] use std::time::Duration;
 
+use bytes::Bytes;
 use futures_core::Stream;
+use futures_util::future::{FutureExt as _, Shared};
+use http::{Response, StatusCode};
 use pin_utils::unsafe_pinned;
 use tokio_io::{AsyncRead, AsyncWrite};
 
 use crate::body::{Body, Payload};
 use crate::common::exec::{Exec, H2Exec, NewSvcExec};
 use crate::common::{Future, Pin, Poll, Unpin, task};
+#[cfg(feature = "runtime")] use crate::rt::RateLimit;
 use crate::service::{MakeServiceRef, Service};
 // Renamed `Http` as `Http_` for now so that people upgrading don't see an
 // error that `hyper::server::Http` is private...
 use self::conn::{Http as Http_, NoopWatcher, SpawnAll};
 use self::shutdown::{Graceful, GracefulWatcher};
 #[cfg(feature = "runtime")] use self::tcp::AddrIncoming;
+#[cfg(all(feature = "runtime", unix))] use self::tcp::Handoff;
 
 /// A listening HTTP server that accepts connections in both HTTP1 and HTTP2 by default.
 ///
@@ -135,6 +141,27 @@ impl Server<AddrIncoming, ()> {
         AddrIncoming::from_std(listener, &handle)
             .map(Server::builder)
     }
+
+    /// Binds `n` listeners to the same address using `SO_REUSEPORT`, and
+    /// returns a [`Builder`](Builder) for each.
+    ///
+    /// Running each resulting server on its own thread gives every core its
+    /// own accept loop. They can share a single graceful shutdown with
+    /// [`Server::with_shared_graceful_shutdown`](Server::with_shared_graceful_shutdown).
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `n` is `0`.
+    #[cfg(unix)]
+    pub fn bind_reuseport(addr: &SocketAddr, n: usize) -> crate::Result<Vec<Builder<AddrIncoming>>> {
+        AddrIncoming::bind_reuseport(addr, n)
+            .map(|incomings| {
+                incomings
+                    .into_iter()
+                    .map(Server::builder)
+                    .collect()
+            })
+    }
 }
 
 #[cfg(feature = "runtime")]
@@ -145,6 +172,23 @@ impl<S> Server<AddrIncoming, S> {
     }
 }
 
+#[cfg(all(feature = "runtime", unix))]
+impl<S, E> Server<AddrIncoming, S, E> {
+    /// Prepares a server to hand its listener over to a new process, and
+    /// then shut down gracefully.
+    ///
+    /// The listening socket is offered on a Unix socket bound at `path`.
+    /// Once a new process has adopted it with [`Successor`](conn::Successor)
+    /// and signaled that it is ready, this server stops accepting and
+    /// shuts down just like with `with_graceful_shutdown`. Connections that
+    /// arrive in the meantime are accepted by either process, so none are
+    /// dropped.
+    pub fn with_handoff<P: AsRef<Path>>(self, path: P) -> crate::Result<Graceful<AddrIncoming, S, Handoff, E>> {
+        let handoff = Handoff::offer(path, &[self.spawn_all.incoming_ref()])?;
+        Ok(Graceful::new(self.spawn_all, handoff))
+    }
+}
+
 impl<I, IO, IE, S, E, B> Server<I, S, E>
 where
     I: Stream<Item=Result<IO, IE>>,
@@ -201,6 +245,22 @@ where
     {
         Graceful::new(self.spawn_all, signal)
     }
+
+    /// Prepares several servers to handle graceful shutdown when a single
+    /// provided future completes.
+    ///
+    /// Each returned future drives one of the servers, and can be run on
+    /// its own thread. This pairs well with [`Server::bind_reuseport`](Server::bind_reuseport).
+    pub fn with_shared_graceful_shutdown<F>(servers: Vec<Self>, signal: F) -> Vec<Graceful<I, S, Shared<F>, E>>
+    where
+        F: Future<Output=()>
+    {
+        let signal = signal.shared();
+        servers
+            .into_iter()
+            .map(|server| server.with_graceful_shutdown(signal.clone()))
+            .collect()
+    }
 }
 
 impl<I, IO, IE, S, B, E> Future for Server<I, S, E>
@@ -299,6 +359,131 @@ impl<I, E> Builder<I, E> {
         self
     }
 
+    /// Set the maximum number of headers in an HTTP/1 request.
+    ///
+    /// Default is 100.
+    pub fn http1_max_headers(mut self, max: usize) -> Self {
+        self.protocol.http1_max_headers(max);
+        self
+    }
+
+    /// Set the maximum size in bytes of an HTTP/1 request head.
+    ///
+    /// Default is no limit, other than `http1_max_buf_size`.
+    pub fn http1_max_head_size(mut self, max: usize) -> Self {
+        self.protocol.http1_max_head_size(max);
+        self
+    }
+
+    /// Set the maximum size in bytes of an HTTP/1 request line.
+    ///
+    /// Default is no limit, other than `http1_max_buf_size`.
+    pub fn http1_max_request_line_size(mut self, max: usize) -> Self {
+        self.protocol.http1_max_request_line_size(max);
+        self
+    }
+
+    /// Set the maximum size in bytes of a single HTTP/1 header value.
+    ///
+    /// Default is no limit, other than `http1_max_buf_size`.
+    pub fn http1_max_header_value_size(mut self, max: usize) -> Self {
+        self.protocol.http1_max_header_value_size(max);
+        self
+    }
+
+    /// Set whether HTTP/1 connections should record the original case and
+    /// order of received header names.
+    ///
+    /// See [`Http::http1_preserve_header_case`](conn::Http::http1_preserve_header_case).
+    ///
+    /// Default is `false`.
+    pub fn http1_preserve_header_case(mut self, val: bool) -> Self {
+        self.protocol.http1_preserve_header_case(val);
+        self
+    }
+
+    /// Set whether HTTP/1 connections accept obsolete line folding in
+    /// header values.
+    ///
+    /// See [`Http::http1_allow_obsolete_multiline_headers`](conn::Http::http1_allow_obsolete_multiline_headers).
+    ///
+    /// Default is `false`.
+    pub fn http1_allow_obsolete_multiline_headers(mut self, val: bool) -> Self {
+        self.protocol.http1_allow_obsolete_multiline_headers(val);
+        self
+    }
+
+    /// Set whether HTTP/1 connections accept whitespace between a header
+    /// name and its colon.
+    ///
+    /// See [`Http::http1_allow_spaces_before_header_colon`](conn::Http::http1_allow_spaces_before_header_colon).
+    ///
+    /// Default is `false`.
+    pub fn http1_allow_spaces_before_header_colon(mut self, val: bool) -> Self {
+        self.protocol.http1_allow_spaces_before_header_colon(val);
+        self
+    }
+
+    /// Set whether HTTP/1 connections accept a bare LF line ending in the
+    /// request head.
+    ///
+    /// See [`Http::http1_allow_bare_lf`](conn::Http::http1_allow_bare_lf).
+    ///
+    /// Default is `false`.
+    pub fn http1_allow_bare_lf(mut self, val: bool) -> Self {
+        self.protocol.http1_allow_bare_lf(val);
+        self
+    }
+
+    /// Set whether HTTP/1 connections reject requests with ambiguous
+    /// message framing.
+    ///
+    /// See [`Http::http1_strict_framing`](conn::Http::http1_strict_framing).
+    ///
+    /// Default is `false`.
+    pub fn http1_strict_framing(mut self, val: bool) -> Self {
+        self.protocol.http1_strict_framing(val);
+        self
+    }
+
+    /// Set how long an idle HTTP/1 keep-alive connection is kept open,
+    /// waiting for the next request, before it's closed.
+    ///
+    /// Default is `None`, keeping idle connections open.
+    #[cfg(feature = "runtime")]
+    pub fn http1_keep_alive_timeout<D>(mut self, val: D) -> Self
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.protocol.http1_keep_alive_timeout(val);
+        self
+    }
+
+    /// Set whether HTTP/1 connections send `FileBody`s with `sendfile(2)`.
+    ///
+    /// This only applies to connections accepted as one of hyper's own TCP
+    /// streams: an `AddrStream`, a `TcpStream` or an activated TCP stream.
+    /// Any other IO, such as a TLS stream wrapping a socket, always reads
+    /// the file into `Chunk`s.
+    ///
+    /// Default is `false`.
+    #[cfg(all(feature = "runtime", target_os = "linux"))]
+    pub fn http1_sendfile(mut self, val: bool) -> Self {
+        self.protocol.http1_sendfile(val);
+        self
+    }
+
+    /// Set whether HTTP/1 keep-alive responses advertise the keep-alive
+    /// timeout with a `Keep-Alive` header.
+    ///
+    /// See [`Http::http1_keep_alive_header`](conn::Http::http1_keep_alive_header).
+    ///
+    /// Default is `false`.
+    pub fn http1_keep_alive_header(mut self, val: bool) -> Self {
+        self.protocol.http1_keep_alive_header(val);
+        self
+    }
+
     /// Sets whether HTTP/2 is required.
     ///
     /// Default is `false`.
@@ -345,6 +530,51 @@ impl<I, E> Builder<I, E> {
         self
     }
 
+    /// Set the maximum size of a request body, in bytes.
+    ///
+    /// See [`Http::max_request_body_size`](conn::Http::max_request_body_size).
+    ///
+    /// Default is no limit (`None`).
+    pub fn max_request_body_size(mut self, max: impl Into<Option<u64>>) -> Self {
+        self.protocol.max_request_body_size(max);
+        self
+    }
+
+    /// Set a limit of how fast bytes are read from connections.
+    ///
+    /// See [`Http::read_rate_limit`](conn::Http::read_rate_limit).
+    ///
+    /// Default is no limit (`None`).
+    #[cfg(feature = "runtime")]
+    pub fn read_rate_limit(mut self, limit: impl Into<Option<RateLimit>>) -> Self {
+        self.protocol.read_rate_limit(limit);
+        self
+    }
+
+    /// Set a limit of how fast bytes are written to connections.
+    ///
+    /// See [`Http::write_rate_limit`](conn::Http::write_rate_limit).
+    ///
+    /// Default is no limit (`None`).
+    #[cfg(feature = "runtime")]
+    pub fn write_rate_limit(mut self, limit: impl Into<Option<RateLimit>>) -> Self {
+        self.protocol.write_rate_limit(limit);
+        self
+    }
+
+    /// Set a function to build the response sent when a request fails at
+    /// the protocol level, such as when it can't be parsed.
+    ///
+    /// See [`Http::error_response`](conn::Http::error_response) for the
+    /// statuses picked by default.
+    pub fn error_response<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&crate::Error, StatusCode) -> Response<Bytes> + Send + Sync + 'static,
+    {
+        self.protocol.error_response(f);
+        self
+    }
+
     /// Sets the `Executor` to deal with connection tasks.
     ///
     /// Default is `tokio::spawn`.
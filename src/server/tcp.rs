@@ -5,6 +5,8 @@ use std::time::{Duration, Instant};
 
 use futures_core::Stream;
 use futures_util::FutureExt as _;
+#[cfg(unix)] use net2::TcpBuilder;
+#[cfg(unix)] use net2::unix::UnixTcpBuilderExt;
 use tokio_net::driver::Handle;
 use tokio_net::tcp::TcpListener;
 use tokio_timer::Delay;
@@ -12,6 +14,13 @@ use tokio_timer::Delay;
 use crate::common::{Future, Pin, Poll, task};
 
 pub use self::addr_stream::AddrStream;
+pub use self::proxy::{ProxyIncoming, ProxyStream, Tlv};
+#[cfg(unix)] pub use self::activation::{ActivatedIncoming, ActivatedStream};
+#[cfg(unix)] pub use self::handoff::{Handoff, Successor};
+
+#[cfg(unix)] mod activation;
+#[cfg(unix)] mod handoff;
+mod proxy;
 
 /// A stream of connections from binding to an address.
 #[must_use = "streams do nothing unless polled"]
@@ -56,6 +65,34 @@ impl AddrIncoming {
         AddrIncoming::new(addr, None)
     }
 
+    /// Creates `n` listeners bound to the same address with `SO_REUSEPORT`.
+    ///
+    /// The kernel distributes new connections between the listeners, so
+    /// each can be driven by its own thread without contending on a shared
+    /// accept queue. If the port of `addr` is `0`, all listeners share the
+    /// port picked for the first one.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `n` is `0`.
+    #[cfg(unix)]
+    pub fn bind_reuseport(addr: &SocketAddr, n: usize) -> crate::Result<Vec<Self>> {
+        assert!(n > 0, "bind_reuseport needs at least one listener");
+
+        let mut addr = *addr;
+        let mut incomings = Vec::with_capacity(n);
+        for _ in 0..n {
+            let std_listener = reuseport_listener(&addr)
+                .map_err(crate::Error::new_listen)?;
+            // Each listener gets its own handle, so it registers with the
+            // reactor of whichever thread ends up polling it.
+            let incoming = AddrIncoming::from_std(std_listener, &Handle::default())?;
+            addr = incoming.local_addr();
+            incomings.push(incoming);
+        }
+        Ok(incomings)
+    }
+
     /// Get the local address bound to this listener.
     pub fn local_addr(&self) -> SocketAddr {
         self.addr
@@ -96,6 +133,13 @@ impl AddrIncoming {
         self.sleep_on_errors = val;
     }
 
+    /// Expect a PROXY protocol header on every accepted connection.
+    ///
+    /// See [`ProxyIncoming`](ProxyIncoming) for details.
+    pub fn proxy_protocol(self) -> ProxyIncoming {
+        ProxyIncoming::new(self)
+    }
+
     fn poll_next_(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Result<AddrStream>> {
         // Check if a previous timeout is active that was set by IO errors.
         if let Some(ref mut to) = self.timeout {
@@ -119,7 +163,8 @@ impl AddrIncoming {
                     if let Err(e) = socket.set_nodelay(self.tcp_nodelay) {
                         trace!("error trying to set TCP nodelay: {}", e);
                     }
-                    return Poll::Ready(Ok(AddrStream::new(socket, addr)));
+                    let local_addr = socket.local_addr().unwrap_or(self.addr);
+                    return Poll::Ready(Ok(AddrStream::new(socket, addr, local_addr)));
                 },
                 Poll::Pending => return Poll::Pending,
                 Poll::Ready(Err(e)) => {
@@ -165,6 +210,19 @@ impl Stream for AddrIncoming {
     }
 }
 
+#[cfg(unix)]
+fn reuseport_listener(addr: &SocketAddr) -> io::Result<StdTcpListener> {
+    let builder = match *addr {
+        SocketAddr::V4(_) => TcpBuilder::new_v4()?,
+        SocketAddr::V6(_) => TcpBuilder::new_v6()?,
+    };
+    builder.reuse_address(true)?;
+    builder.reuse_port(true)?;
+    builder.bind(addr)?;
+    // Same backlog as `std::net::TcpListener::bind`.
+    builder.listen(128)
+}
+
 /// This function defines errors that are per-connection. Which basically
 /// means that if we get this error from `accept()` system call it means
 /// next connection might be ready to be accepted.
@@ -195,11 +253,16 @@ impl fmt::Debug for AddrIncoming {
 mod addr_stream {
     use std::io;
     use std::net::SocketAddr;
+    #[cfg(target_os = "linux")]
+    use std::os::unix::io::RawFd;
     use bytes::{Buf, BufMut};
     use tokio_net::tcp::TcpStream;
     use tokio_io::{AsyncRead, AsyncWrite};
 
     use crate::common::{Pin, Poll, task};
+    use crate::server::conn::Transport;
+    #[cfg(target_os = "linux")]
+    use crate::server::conn::SendfileSocket;
 
 
     /// A transport returned yieled by `AddrIncoming`.
@@ -207,13 +270,15 @@ mod addr_stream {
     pub struct AddrStream {
         inner: TcpStream,
         pub(super) remote_addr: SocketAddr,
+        pub(super) local_addr: SocketAddr,
     }
 
     impl AddrStream {
-        pub(super) fn new(tcp: TcpStream, addr: SocketAddr) -> AddrStream {
+        pub(super) fn new(tcp: TcpStream, remote_addr: SocketAddr, local_addr: SocketAddr) -> AddrStream {
             AddrStream {
                 inner: tcp,
-                remote_addr: addr,
+                remote_addr,
+                local_addr,
             }
         }
 
@@ -223,6 +288,12 @@ mod addr_stream {
             self.remote_addr
         }
 
+        /// Returns the local address of this connection.
+        #[inline]
+        pub fn local_addr(&self) -> SocketAddr {
+            self.local_addr
+        }
+
         /// Consumes the AddrStream and returns the underlying IO object
         #[inline]
         pub fn into_inner(self) -> TcpStream {
@@ -230,6 +301,23 @@ mod addr_stream {
         }
     }
 
+    impl Transport for AddrStream {
+        fn remote_addr(&self) -> Option<SocketAddr> {
+            Some(self.remote_addr)
+        }
+
+        fn local_addr(&self) -> Option<SocketAddr> {
+            Some(self.local_addr)
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    impl SendfileSocket for AddrStream {
+        fn sendfile_socket(&self) -> Option<RawFd> {
+            self.inner.sendfile_socket()
+        }
+    }
+
     impl AsyncRead for AddrStream {
         #[inline]
         unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
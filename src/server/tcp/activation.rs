@@ -0,0 +1,561 @@
+use std::env;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::net::{SocketAddr, TcpListener as StdTcpListener};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::net::UnixListener as StdUnixListener;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::{Buf, BufMut};
+use futures_core::Stream;
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_net::driver::Handle;
+use tokio_net::uds::{Incoming as UnixIncoming, UnixListener, UnixStream};
+
+use crate::common::{Pin, Poll, task};
+use crate::server::conn::Transport;
+#[cfg(target_os = "linux")]
+use crate::server::conn::SendfileSocket;
+use super::{is_connection_error, AddrIncoming, AddrStream};
+
+// The first file descriptor passed by the service manager, see sd_listen_fds(3).
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A stream of connections from sockets passed by systemd socket activation.
+///
+/// The service manager passes listening sockets to the process using the
+/// `LISTEN_PID`, `LISTEN_FDS` and `LISTEN_FDNAMES` environment variables.
+/// Both TCP and Unix domain stream sockets are supported, and connections
+/// from all of them are yielded by this single stream.
+///
+/// Creating an `ActivatedIncoming` removes those variables from the
+/// environment, so that they aren't inherited by child processes.
+#[must_use = "streams do nothing unless polled"]
+pub struct ActivatedIncoming {
+    listeners: Vec<Listener>,
+    next: usize,
+}
+
+enum Listener {
+    Tcp(AddrIncoming, Option<Arc<str>>),
+    // Owns the listener, along with its pending accept.
+    Unix(UnixIncoming, Option<Arc<str>>),
+}
+
+impl ActivatedIncoming {
+    /// Creates an `ActivatedIncoming` from all sockets passed to this process.
+    pub fn from_env() -> crate::Result<Self> {
+        ActivatedIncoming::from_env_(None)
+    }
+
+    /// Creates an `ActivatedIncoming` from the sockets with the given names.
+    ///
+    /// Names are configured with `FileDescriptorName=` in the socket unit.
+    /// Sockets with other names are left untouched. It is an error if any
+    /// of the names wasn't passed to this process.
+    pub fn from_env_named(names: &[&str]) -> crate::Result<Self> {
+        ActivatedIncoming::from_env_(Some(names))
+    }
+
+    fn from_env_(names: Option<&[&str]>) -> crate::Result<Self> {
+        let pid = env::var("LISTEN_PID").ok();
+        let fds = env::var("LISTEN_FDS").ok();
+        let fdnames = env::var("LISTEN_FDNAMES").ok();
+        env::remove_var("LISTEN_PID");
+        env::remove_var("LISTEN_FDS");
+        env::remove_var("LISTEN_FDNAMES");
+
+        let passed = parse_env(
+            process::id(),
+            pid.as_ref().map(String::as_str),
+            fds.as_ref().map(String::as_str),
+            fdnames.as_ref().map(String::as_str),
+        ).map_err(crate::Error::new_listen)?;
+
+        let selected = match names {
+            Some(names) => {
+                let mut selected: Vec<(RawFd, Option<String>)> = Vec::with_capacity(names.len());
+                for name in names {
+                    let mut found = false;
+                    for &(fd, ref n) in &passed {
+                        if n.as_ref().map(String::as_str) != Some(*name) {
+                            continue;
+                        }
+                        found = true;
+                        // A name may be given twice, but each socket can
+                        // only have one owner.
+                        if selected.iter().all(|&(other, _)| other != fd) {
+                            selected.push((fd, n.clone()));
+                        }
+                    }
+                    if !found {
+                        let msg = format!("no socket named {:?} was passed", name);
+                        return Err(crate::Error::new_listen(io::Error::new(io::ErrorKind::NotFound, msg)));
+                    }
+                }
+                selected
+            },
+            None => passed,
+        };
+
+        // Like sd_listen_fds(3), don't leak the sockets we take into
+        // processes we might spawn. The others are left as they were.
+        for &(fd, _) in &selected {
+            set_cloexec(fd).map_err(crate::Error::new_listen)?;
+        }
+
+        let handle = Handle::default();
+        let mut listeners = Vec::with_capacity(selected.len());
+        for (fd, name) in selected {
+            let name: Option<Arc<str>> = name.map(Arc::from);
+            match socket_family(fd).map_err(crate::Error::new_listen)? {
+                Family::Inet => {
+                    let std_listener = unsafe { StdTcpListener::from_raw_fd(fd) };
+                    let incoming = AddrIncoming::from_std(std_listener, &handle)?;
+                    listeners.push(Listener::Tcp(incoming, name));
+                },
+                Family::Unix => {
+                    let std_listener = unsafe { StdUnixListener::from_raw_fd(fd) };
+                    let listener = UnixListener::from_std(std_listener, &handle)
+                        .map_err(crate::Error::new_listen)?;
+                    listeners.push(Listener::Unix(listener.incoming(), name));
+                },
+            }
+        }
+
+        Ok(ActivatedIncoming {
+            listeners,
+            next: 0,
+        })
+    }
+
+    /// Get the local addresses of the TCP sockets.
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.listeners
+            .iter()
+            .filter_map(|listener| match *listener {
+                Listener::Tcp(ref incoming, _) => Some(incoming.local_addr()),
+                Listener::Unix(..) => None,
+            })
+            .collect()
+    }
+
+    /// Set whether TCP keepalive messages are enabled on accepted TCP
+    /// connections.
+    ///
+    /// See [`AddrIncoming::set_keepalive`](AddrIncoming::set_keepalive).
+    pub fn set_keepalive(&mut self, keepalive: Option<Duration>) -> &mut Self {
+        for listener in &mut self.listeners {
+            if let Listener::Tcp(ref mut incoming, _) = *listener {
+                incoming.set_keepalive(keepalive);
+            }
+        }
+        self
+    }
+
+    /// Set the value of `TCP_NODELAY` option for accepted TCP connections.
+    pub fn set_nodelay(&mut self, enabled: bool) -> &mut Self {
+        for listener in &mut self.listeners {
+            if let Listener::Tcp(ref mut incoming, _) = *listener {
+                incoming.set_nodelay(enabled);
+            }
+        }
+        self
+    }
+}
+
+impl Stream for ActivatedIncoming {
+    type Item = io::Result<ActivatedStream>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
+        let len = self.listeners.len();
+        // Start with a different listener each time, so a busy one
+        // can't starve the others.
+        for i in 0..len {
+            let idx = (self.next + i) % len;
+            let polled = match self.listeners[idx] {
+                Listener::Tcp(ref mut incoming, ref name) => {
+                    Pin::new(incoming)
+                        .poll_next(cx)
+                        .map(|opt| opt.map(|res| res.map(|io| ActivatedStream {
+                            inner: Inner::Tcp(io),
+                            name: name.clone(),
+                        })))
+                },
+                Listener::Unix(ref mut incoming, ref name) => {
+                    poll_accept_unix(incoming, cx)
+                        .map(|opt| opt.map(|res| res.map(|io| ActivatedStream {
+                            inner: Inner::Unix(io),
+                            name: name.clone(),
+                        })))
+                },
+            };
+            if let Poll::Ready(item) = polled {
+                self.next = (idx + 1) % len;
+                return Poll::Ready(item);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+fn poll_accept_unix(incoming: &mut UnixIncoming, cx: &mut task::Context<'_>) -> Poll<Option<io::Result<UnixStream>>> {
+    loop {
+        match ready!(Pin::new(&mut *incoming).poll_next(cx)) {
+            Some(Err(ref e)) if is_connection_error(e) => {
+                debug!("accepted connection already errored: {}", e);
+                continue;
+            },
+            item => return Poll::Ready(item),
+        }
+    }
+}
+
+impl fmt::Debug for ActivatedIncoming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ActivatedIncoming")
+            .field("listeners", &self.listeners.len())
+            .finish()
+    }
+}
+
+/// A transport yielded by `ActivatedIncoming`.
+pub struct ActivatedStream {
+    inner: Inner,
+    name: Option<Arc<str>>,
+}
+
+enum Inner {
+    Tcp(AddrStream),
+    Unix(UnixStream),
+}
+
+impl ActivatedStream {
+    /// Returns the remote (peer) address of this connection.
+    ///
+    /// This is `None` for connections on Unix domain sockets.
+    #[inline]
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        match self.inner {
+            Inner::Tcp(ref io) => Some(io.remote_addr()),
+            Inner::Unix(_) => None,
+        }
+    }
+
+    /// Returns the name of the socket this connection was accepted on,
+    /// as set by `FileDescriptorName=`.
+    #[inline]
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|name| &**name)
+    }
+}
+
+impl Transport for ActivatedStream {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        ActivatedStream::remote_addr(self)
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        match self.inner {
+            Inner::Tcp(ref io) => Some(io.local_addr()),
+            Inner::Unix(_) => None,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl SendfileSocket for ActivatedStream {
+    fn sendfile_socket(&self) -> Option<RawFd> {
+        match self.inner {
+            Inner::Tcp(ref io) => io.sendfile_socket(),
+            Inner::Unix(_) => None,
+        }
+    }
+}
+
+impl fmt::Debug for ActivatedStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ActivatedStream")
+            .field("remote_addr", &self.remote_addr())
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl AsyncRead for ActivatedStream {
+    #[inline]
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        match self.inner {
+            Inner::Tcp(ref io) => io.prepare_uninitialized_buffer(buf),
+            Inner::Unix(ref io) => io.prepare_uninitialized_buffer(buf),
+        }
+    }
+
+    #[inline]
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        match self.inner {
+            Inner::Tcp(ref mut io) => Pin::new(io).poll_read(cx, buf),
+            Inner::Unix(ref mut io) => Pin::new(io).poll_read(cx, buf),
+        }
+    }
+
+    #[inline]
+    fn poll_read_buf<B: BufMut>(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &mut B) -> Poll<io::Result<usize>> {
+        match self.inner {
+            Inner::Tcp(ref mut io) => Pin::new(io).poll_read_buf(cx, buf),
+            Inner::Unix(ref mut io) => Pin::new(io).poll_read_buf(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ActivatedStream {
+    #[inline]
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.inner {
+            Inner::Tcp(ref mut io) => Pin::new(io).poll_write(cx, buf),
+            Inner::Unix(ref mut io) => Pin::new(io).poll_write(cx, buf),
+        }
+    }
+
+    #[inline]
+    fn poll_write_buf<B: Buf>(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &mut B) -> Poll<io::Result<usize>> {
+        match self.inner {
+            Inner::Tcp(ref mut io) => Pin::new(io).poll_write_buf(cx, buf),
+            Inner::Unix(ref mut io) => Pin::new(io).poll_write_buf(cx, buf),
+        }
+    }
+
+    #[inline]
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        match self.inner {
+            Inner::Tcp(ref mut io) => Pin::new(io).poll_flush(cx),
+            Inner::Unix(ref mut io) => Pin::new(io).poll_flush(cx),
+        }
+    }
+
+    #[inline]
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        match self.inner {
+            Inner::Tcp(ref mut io) => Pin::new(io).poll_shutdown(cx),
+            Inner::Unix(ref mut io) => Pin::new(io).poll_shutdown(cx),
+        }
+    }
+}
+
+// ===== env and fd helpers =====
+
+fn parse_env(
+    own_pid: u32,
+    pid: Option<&str>,
+    fds: Option<&str>,
+    names: Option<&str>,
+) -> io::Result<Vec<(RawFd, Option<String>)>> {
+    let pid = pid.ok_or_else(|| not_found("LISTEN_PID is not set"))?;
+    let pid = pid.parse::<u32>().map_err(|_| invalid("LISTEN_PID is not a number"))?;
+    if pid != own_pid {
+        return Err(invalid("LISTEN_PID is not this process"));
+    }
+
+    let count = fds.ok_or_else(|| not_found("LISTEN_FDS is not set"))?;
+    let count = count.parse::<usize>().map_err(|_| invalid("LISTEN_FDS is not a number"))?;
+    if count == 0 {
+        return Err(not_found("LISTEN_FDS is zero"));
+    }
+
+    let names = match names {
+        Some(names) => {
+            let names = names
+                .split(':')
+                .map(|name| Some(name.to_owned()))
+                .collect::<Vec<_>>();
+            if names.len() != count {
+                return Err(invalid("LISTEN_FDNAMES doesn't match LISTEN_FDS"));
+            }
+            names
+        },
+        None => vec![None; count],
+    };
+
+    Ok(names
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| (LISTEN_FDS_START + i as RawFd, name))
+        .collect())
+}
+
+#[derive(Debug)]
+pub(super) enum Family {
+    Inet,
+    Unix,
+}
+
+// Checks that `fd` is a listening stream socket, and which family it is.
+pub(super) fn socket_family(fd: RawFd) -> io::Result<Family> {
+    let mut ty: libc::c_int = 0;
+    getsockopt_int(fd, libc::SO_TYPE, &mut ty)?;
+    if ty != libc::SOCK_STREAM {
+        return Err(invalid("passed socket is not a stream socket"));
+    }
+
+    let mut listening: libc::c_int = 0;
+    getsockopt_int(fd, libc::SO_ACCEPTCONN, &mut listening)?;
+    if listening == 0 {
+        return Err(invalid("passed socket is not listening"));
+    }
+
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut len = mem::size_of_val(&storage) as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockname(fd, &mut storage as *mut _ as *mut libc::sockaddr, &mut len)
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    match storage.ss_family as libc::c_int {
+        libc::AF_INET | libc::AF_INET6 => Ok(Family::Inet),
+        libc::AF_UNIX => Ok(Family::Unix),
+        _ => Err(invalid("passed socket has unsupported address family")),
+    }
+}
+
+fn getsockopt_int(fd: RawFd, opt: libc::c_int, val: &mut libc::c_int) -> io::Result<()> {
+    let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(fd, libc::SOL_SOCKET, opt, val as *mut _ as *mut libc::c_void, &mut len)
+    };
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+pub(super) fn set_cloexec(fd: RawFd) -> io::Result<()> {
+    let ret = unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn not_found(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::net::TcpListener as StdTcpListener;
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixListener as StdUnixListener;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    use super::*;
+
+    // Set for the child process spawned by `from_env_in_child_process`.
+    const CHILD_ENV: &str = "__HYPER_TEST_SOCKET_ACTIVATION_CHILD";
+
+    #[test]
+    fn test_parse_env() {
+        let fds = parse_env(42, Some("42"), Some("2"), Some("http:admin")).unwrap();
+        assert_eq!(fds, vec![
+            (3, Some("http".to_owned())),
+            (4, Some("admin".to_owned())),
+        ]);
+
+        let fds = parse_env(42, Some("42"), Some("1"), None).unwrap();
+        assert_eq!(fds, vec![(3, None)]);
+    }
+
+    #[test]
+    fn test_parse_env_errors() {
+        // missing
+        parse_env(42, None, Some("1"), None).unwrap_err();
+        parse_env(42, Some("42"), None, None).unwrap_err();
+        // meant for another process
+        parse_env(42, Some("43"), Some("1"), None).unwrap_err();
+        // garbage
+        parse_env(42, Some("abc"), Some("1"), None).unwrap_err();
+        parse_env(42, Some("42"), Some("-1"), None).unwrap_err();
+        parse_env(42, Some("42"), Some("0"), None).unwrap_err();
+        // names don't line up
+        parse_env(42, Some("42"), Some("2"), Some("http")).unwrap_err();
+    }
+
+    #[test]
+    fn test_socket_family_rejects_non_sockets() {
+        let file = ::std::fs::File::open("/dev/null").unwrap();
+        socket_family(file.as_raw_fd()).unwrap_err();
+
+        let udp = ::std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket_family(udp.as_raw_fd()).unwrap_err();
+    }
+
+    #[test]
+    fn from_env_in_child_process() {
+        if env::var_os(CHILD_ENV).is_some() {
+            return child();
+        }
+
+        let tcp = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let path = env::temp_dir().join(format!("hyper-activation-{}.sock", process::id()));
+        let _ = ::std::fs::remove_file(&path);
+        let unix = StdUnixListener::bind(&path).unwrap();
+
+        // Move both out of the way of the fds they need to end up at.
+        let tcp_fd = unsafe { libc::fcntl(tcp.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 10) };
+        let unix_fd = unsafe { libc::fcntl(unix.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 10) };
+        assert!(tcp_fd >= 10 && unix_fd >= 10);
+
+        // `sh` sets LISTEN_PID to its own pid, which `exec` keeps.
+        let mut cmd = Command::new("sh");
+        cmd
+            .arg("-c")
+            .arg("LISTEN_PID=$$ exec \"$0\" \"$@\"")
+            .arg(env::current_exe().unwrap())
+            .arg("--exact")
+            .arg("server::tcp::activation::tests::from_env_in_child_process")
+            .arg("--nocapture")
+            .env(CHILD_ENV, tcp.local_addr().unwrap().to_string())
+            .env("LISTEN_FDS", "2")
+            .env("LISTEN_FDNAMES", "web:local");
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::dup2(tcp_fd, 3) == -1 || libc::dup2(unix_fd, 4) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let status = cmd.status().expect("spawn child");
+        let _ = ::std::fs::remove_file(&path);
+        assert!(status.success(), "child failed: {}", status);
+    }
+
+    fn child() {
+        let expected_addr = env::var(CHILD_ENV).unwrap().parse::<SocketAddr>().unwrap();
+
+        // asking for a name twice still takes its socket once
+        let incoming = ActivatedIncoming::from_env_named(&["web", "local", "web"]).expect("from_env_named");
+        assert_eq!(incoming.listeners.len(), 2);
+        assert_eq!(incoming.local_addrs(), vec![expected_addr]);
+        match incoming.listeners[1] {
+            Listener::Unix(_, ref name) => assert_eq!(name.as_ref().map(|n| &**n), Some("local")),
+            _ => panic!("second listener should be unix"),
+        }
+
+        for var in &["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+            assert!(env::var_os(var).is_none(), "{} should be cleared", var);
+        }
+    }
+}
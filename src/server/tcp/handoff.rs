@@ -0,0 +1,562 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::TcpListener as StdTcpListener;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::{UnixListener as StdUnixListener, UnixStream as StdUnixStream};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::thread;
+
+use futures_channel::oneshot;
+
+use crate::common::{Future, Pin, Poll, task};
+use super::AddrIncoming;
+use super::activation::{set_cloexec, socket_family, Family};
+
+// Sent along with the file descriptors.
+const LISTENERS_MSG: u8 = b'L';
+// Sent back by the successor once it is serving.
+const READY_MSG: u8 = b'R';
+// The most listeners a single handoff can carry.
+const MAX_FDS: usize = 64;
+
+/// A future that completes once a successor process has taken over the
+/// listening sockets.
+///
+/// This is usually created with [`Server::with_handoff`](crate::Server::with_handoff),
+/// which uses it to start a graceful shutdown.
+///
+/// The handoff itself happens on a background thread, which waits for a
+/// successor to connect to the Unix socket. Only processes of the same
+/// user can connect. If a successor disconnects before signaling it is
+/// ready, the sockets are offered again to the next one.
+///
+/// Dropping the `Handoff` stops the background thread, which closes its
+/// copies of the sockets and removes the Unix socket.
+#[must_use = "futures do nothing unless polled"]
+pub struct Handoff {
+    rx: Option<oneshot::Receiver<()>>,
+    // The background thread stops once this is closed.
+    _cancel: StdUnixStream,
+}
+
+impl Handoff {
+    /// Offer the listening sockets of `listeners` on a Unix socket at `path`.
+    ///
+    /// Any existing file at `path` is removed first.
+    ///
+    /// # Errors
+    ///
+    /// At most 64 listeners can be handed off at once. Offering more
+    /// returns an error, as does failing to set up the Unix socket.
+    pub fn offer<P: AsRef<Path>>(path: P, listeners: &[&AddrIncoming]) -> crate::Result<Handoff> {
+        if listeners.len() > MAX_FDS {
+            let msg = format!("cannot hand off more than {} listeners", MAX_FDS);
+            return Err(crate::Error::new_listen(io::Error::new(io::ErrorKind::InvalidInput, msg)));
+        }
+        let path = path.as_ref().to_owned();
+        let (cancel, canceled) = StdUnixStream::pair().map_err(crate::Error::new_listen)?;
+        let unix = bind(&path).map_err(crate::Error::new_listen)?;
+
+        // The server may be dropped while the background thread is
+        // still waiting, so it gets its own copies of the sockets.
+        let mut fds = Vec::with_capacity(listeners.len());
+        for incoming in listeners {
+            match dup(incoming.listener.as_raw_fd()) {
+                Ok(fd) => fds.push(fd),
+                Err(e) => {
+                    close_all(&fds);
+                    return Err(crate::Error::new_listen(e));
+                }
+            }
+        }
+
+        let (tx, rx) = oneshot::channel();
+        thread::Builder::new()
+            .name("hyper-handoff".to_owned())
+            .spawn(move || offer_until_adopted(unix, path, fds, canceled, tx))
+            .map_err(crate::Error::new_listen)?;
+
+        Ok(Handoff {
+            rx: Some(rx),
+            _cancel: cancel,
+        })
+    }
+}
+
+impl Future for Handoff {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let res = match self.rx {
+            Some(ref mut rx) => ready!(Pin::new(rx).poll(cx)),
+            // The handoff thread gave up, keep serving forever.
+            None => return Poll::Pending,
+        };
+        match res {
+            Ok(()) => Poll::Ready(()),
+            Err(_canceled) => {
+                self.rx = None;
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Handoff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Handoff")
+            .finish()
+    }
+}
+
+enum Offer {
+    Adopted,
+    Canceled,
+}
+
+fn offer_until_adopted(
+    mut unix: StdUnixListener,
+    path: PathBuf,
+    fds: Vec<RawFd>,
+    canceled: StdUnixStream,
+    tx: oneshot::Sender<()>,
+) {
+    loop {
+        match offer_once(&unix, &path, &fds, &canceled) {
+            Ok(Offer::Adopted) => {
+                debug!("listeners adopted by successor");
+                close_all(&fds);
+                let _ = tx.send(());
+                return;
+            },
+            Ok(Offer::Canceled) => {
+                debug!("listener handoff canceled");
+                break;
+            },
+            Err(e) => {
+                warn!("listener handoff failed: {}", e);
+            }
+        }
+
+        if tx.is_canceled() {
+            break;
+        }
+
+        // `offer_once` removed the socket file, so the next successor
+        // needs a new one.
+        unix = match bind(&path) {
+            Ok(unix) => unix,
+            Err(e) => {
+                error!("listener handoff cannot rebind {:?}: {}", path, e);
+                break;
+            }
+        };
+    }
+    close_all(&fds);
+}
+
+fn offer_once(
+    unix: &StdUnixListener,
+    path: &Path,
+    fds: &[RawFd],
+    canceled: &StdUnixStream,
+) -> io::Result<Offer> {
+    let mut stream = loop {
+        if !wait_readable(unix.as_raw_fd(), canceled)? {
+            let _ = fs::remove_file(path);
+            return Ok(Offer::Canceled);
+        }
+        match unix.accept() {
+            Ok((stream, _)) => break stream,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(e) => return Err(e),
+        }
+    };
+    // Some platforms pass the listener's O_NONBLOCK on.
+    stream.set_nonblocking(false)?;
+
+    let uid = peer_uid(&stream)?;
+    if uid != unsafe { libc::geteuid() } {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "handoff successor is owned by another user",
+        ));
+    }
+
+    // Only one successor at a time. Removing the file also lets the
+    // successor bind the same path for its own future handoff.
+    let _ = fs::remove_file(path);
+
+    send_fds(&stream, fds)?;
+
+    if !wait_readable(stream.as_raw_fd(), canceled)? {
+        return Ok(Offer::Canceled);
+    }
+    let mut ready = [0];
+    stream.read_exact(&mut ready)?;
+    if ready[0] != READY_MSG {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected handoff message"));
+    }
+    Ok(Offer::Adopted)
+}
+
+/// The receiving side of a listener handoff, used by the new process.
+///
+/// # Example
+///
+/// ```no_run
+/// # #![feature(async_await)]
+/// # #[cfg(feature = "runtime")]
+/// # fn run() -> hyper::Result<()> {
+/// use hyper::Server;
+/// use hyper::server::conn::Successor;
+///
+/// let mut successor = Successor::connect("/run/my-app/handoff.sock")?;
+/// let listener = successor.take_listeners().remove(0);
+/// let builder = Server::from_tcp(listener)?;
+/// // ... start serving with `builder`, and then:
+/// successor.ready()?;
+/// # Ok(())
+/// # }
+/// # fn main() {}
+/// ```
+pub struct Successor {
+    listeners: Vec<StdTcpListener>,
+    stream: StdUnixStream,
+}
+
+impl Successor {
+    /// Connect to the old process at `path`, and receive its listeners.
+    pub fn connect<P: AsRef<Path>>(path: P) -> crate::Result<Successor> {
+        let stream = StdUnixStream::connect(path).map_err(crate::Error::new_listen)?;
+        let fds = recv_fds(&stream).map_err(crate::Error::new_listen)?;
+
+        let mut listeners = Vec::with_capacity(fds.len());
+        let mut fds = fds.into_iter();
+        while let Some(fd) = fds.next() {
+            // Whatever happens, this process now owns `fd`.
+            let listener = unsafe { StdTcpListener::from_raw_fd(fd) };
+            let checked = set_cloexec(fd)
+                .and_then(|()| socket_family(fd))
+                .and_then(|family| match family {
+                    Family::Inet => Ok(()),
+                    Family::Unix => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "handed off socket is not a TCP listener",
+                    )),
+                });
+            if let Err(e) = checked {
+                close_all(&fds.collect::<Vec<_>>());
+                return Err(crate::Error::new_listen(e));
+            }
+            listeners.push(listener);
+        }
+
+        Ok(Successor {
+            listeners,
+            stream,
+        })
+    }
+
+    /// Take the received listeners, in the order they were offered.
+    ///
+    /// These can be used with [`Server::from_tcp`](crate::Server::from_tcp).
+    pub fn take_listeners(&mut self) -> Vec<StdTcpListener> {
+        mem::replace(&mut self.listeners, Vec::new())
+    }
+
+    /// Signal the old process that this one is serving.
+    ///
+    /// The old process will then start shutting down gracefully.
+    pub fn ready(mut self) -> crate::Result<()> {
+        self.stream
+            .write_all(&[READY_MSG])
+            .map_err(crate::Error::new_listen)
+    }
+}
+
+impl fmt::Debug for Successor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Successor")
+            .field("listeners", &self.listeners)
+            .finish()
+    }
+}
+
+// ===== fd helpers =====
+
+fn bind(path: &Path) -> io::Result<StdUnixListener> {
+    match fs::remove_file(path) {
+        Ok(()) => (),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e),
+    }
+    let unix = StdUnixListener::bind(path)?;
+    // The peer's user is checked again once it connects.
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    // Accepting waits in `wait_readable`, so it can be canceled.
+    unix.set_nonblocking(true)?;
+    Ok(unix)
+}
+
+// Waits until `fd` is readable, or returns `false` if the `Handoff` was
+// dropped first.
+fn wait_readable(fd: RawFd, canceled: &StdUnixStream) -> io::Result<bool> {
+    let mut fds = [
+        libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: canceled.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    loop {
+        let ret = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+        if ret == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        // nothing is ever written to the cancel socket, only closed
+        if fds[1].revents != 0 {
+            return Ok(false);
+        }
+        if fds[0].revents != 0 {
+            return Ok(true);
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &StdUnixStream) -> io::Result<libc::uid_t> {
+    let mut cred: libc::ucred = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(cred.uid)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &StdUnixStream) -> io::Result<libc::uid_t> {
+    let mut uid = 0;
+    let mut gid = 0;
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(uid)
+    }
+}
+
+fn dup(fd: RawFd) -> io::Result<RawFd> {
+    let ret = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+fn close_all(fds: &[RawFd]) {
+    for &fd in fds {
+        unsafe {
+            libc::close(fd);
+        }
+    }
+}
+
+// A control message buffer, aligned for `cmsghdr`.
+fn cmsg_buffer(fds: usize) -> (Vec<u64>, usize) {
+    let space = unsafe {
+        libc::CMSG_SPACE((fds * mem::size_of::<RawFd>()) as u32)
+    } as usize;
+    let words = (space + mem::size_of::<u64>() - 1) / mem::size_of::<u64>();
+    (vec![0; words], space)
+}
+
+fn send_fds(stream: &StdUnixStream, fds: &[RawFd]) -> io::Result<()> {
+    let mut byte = [LISTENERS_MSG];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr() as *mut libc::c_void,
+        iov_len: byte.len(),
+    };
+    let (mut buf, space) = cmsg_buffer(fds.len());
+    let data_len = fds.len() * mem::size_of::<RawFd>();
+
+    unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        if !fds.is_empty() {
+            msg.msg_control = buf.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = space as _;
+
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(data_len as u32) as _;
+            ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg) as *mut RawFd, fds.len());
+        }
+
+        if libc::sendmsg(stream.as_raw_fd(), &msg, 0) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn recv_fds(stream: &StdUnixStream) -> io::Result<Vec<RawFd>> {
+    let mut byte = [0];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr() as *mut libc::c_void,
+        iov_len: byte.len(),
+    };
+    let (mut buf, space) = cmsg_buffer(MAX_FDS);
+    let mut fds = Vec::new();
+
+    unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = buf.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = space as _;
+
+        let n = libc::recvmsg(stream.as_raw_fd(), &mut msg, 0);
+        if n == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data_len = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                for i in 0..data_len / mem::size_of::<RawFd>() {
+                    fds.push(ptr::read_unaligned(data.add(i)));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+
+        if n == 0 {
+            close_all(&fds);
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "handoff socket closed"));
+        }
+        if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+            close_all(&fds);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "too many handed off sockets"));
+        }
+    }
+
+    if byte[0] != LISTENERS_MSG {
+        close_all(&fds);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected handoff message"));
+    }
+    Ok(fds)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::net::TcpListener as StdTcpListener;
+    use std::process;
+
+    use tokio_net::driver::Handle;
+
+    use super::*;
+
+    #[test]
+    fn offer_rejects_too_many_listeners() {
+        let path = env::temp_dir().join(format!("hyper-handoff-many-{}.sock", process::id()));
+        let incomings = (0..MAX_FDS + 1)
+            .map(|_| {
+                let std_listener = StdTcpListener::bind("127.0.0.1:0").unwrap();
+                AddrIncoming::from_std(std_listener, &Handle::default()).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let listeners = incomings.iter().collect::<Vec<_>>();
+
+        Handoff::offer(&path, &listeners).expect_err("too many listeners");
+        assert!(!path.exists());
+        assert!(Handoff::offer(&path, &listeners[..MAX_FDS]).is_ok());
+    }
+
+    #[test]
+    fn handoff_to_successor() {
+        let path = env::temp_dir().join(format!("hyper-handoff-{}.sock", process::id()));
+        let std_listener = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let incoming = AddrIncoming::from_std(std_listener, &Handle::default()).unwrap();
+        let addr = incoming.local_addr();
+
+        let handoff = Handoff::offer(&path, &[&incoming]).unwrap();
+        // the old listener can go away before the handoff happens
+        drop(incoming);
+
+        let successor = thread::spawn(move || {
+            // a successor that dies before being ready doesn't count
+            let mut failed = Successor::connect(&path).expect("connect 1");
+            assert_eq!(failed.take_listeners().len(), 1);
+            drop(failed);
+
+            let mut successor = loop {
+                match Successor::connect(&path) {
+                    Ok(successor) => break successor,
+                    Err(_) => thread::sleep(::std::time::Duration::from_millis(10)),
+                }
+            };
+            let listeners = successor.take_listeners();
+            assert_eq!(listeners.len(), 1);
+            assert_eq!(listeners[0].local_addr().unwrap(), addr);
+            successor.ready().expect("ready");
+            listeners
+        });
+
+        let mut rt = ::tokio::runtime::current_thread::Runtime::new().unwrap();
+        rt.block_on(handoff);
+
+        let listeners = successor.join().unwrap();
+        // still accepting on the adopted socket
+        let _client = ::std::net::TcpStream::connect(addr).unwrap();
+        listeners[0].accept().unwrap();
+    }
+
+    #[test]
+    fn dropped_handoff_stops_offering() {
+        let path = env::temp_dir().join(format!("hyper-handoff-drop-{}.sock", process::id()));
+        let std_listener = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let incoming = AddrIncoming::from_std(std_listener, &Handle::default()).unwrap();
+
+        let handoff = Handoff::offer(&path, &[&incoming]).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        drop(handoff);
+        for _ in 0..100 {
+            if !path.exists() {
+                return;
+            }
+            thread::sleep(::std::time::Duration::from_millis(10));
+        }
+        panic!("handoff socket still exists after drop");
+    }
+}
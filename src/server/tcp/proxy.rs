@@ -0,0 +1,651 @@
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str;
+use std::time::{Duration, Instant};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use futures_core::Stream;
+use futures_util::stream::FuturesUnordered;
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_timer::Delay;
+
+use crate::common::io::Rewind;
+use crate::common::{Future, Pin, Poll, task};
+use crate::server::conn::Transport;
+use super::{AddrIncoming, AddrStream};
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+// A v1 header, including the trailing CRLF, is never longer than this.
+const V1_MAX_LEN: usize = 107;
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+// signature + version/command + family/transport + length
+const V2_FIXED_LEN: usize = 16;
+const INIT_BUF_SIZE: usize = 128;
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A stream of connections that start with a PROXY protocol header.
+///
+/// Load balancers such as HAProxy or AWS NLB can prefix each connection
+/// with a header describing the original client. Both the text (v1) and
+/// binary (v2) versions of the protocol are understood.
+///
+/// A connection is only yielded once its header has been read, so the
+/// addresses reported by the proxy are already available to
+/// `make_service_fn`. Connections sending a malformed header, or not
+/// sending it in time, are closed.
+///
+/// By default, connections without any header are passed through as-is.
+/// Since any client able to reach the listener directly could then claim
+/// an arbitrary address, consider enabling [`set_strict`](ProxyIncoming::set_strict)
+/// if all traffic is expected to come through the proxy.
+#[must_use = "streams do nothing unless polled"]
+pub struct ProxyIncoming {
+    incoming: AddrIncoming,
+    handshakes: FuturesUnordered<Handshake>,
+    strict: bool,
+    handshake_timeout: Option<Duration>,
+}
+
+impl ProxyIncoming {
+    pub(super) fn new(incoming: AddrIncoming) -> Self {
+        ProxyIncoming {
+            incoming,
+            handshakes: FuturesUnordered::new(),
+            strict: false,
+            handshake_timeout: Some(DEFAULT_HANDSHAKE_TIMEOUT),
+        }
+    }
+
+    /// Get the local address bound to this listener.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.incoming.local_addr()
+    }
+
+    /// Set whether connections without a PROXY protocol header are rejected.
+    ///
+    /// Default is `false`.
+    pub fn set_strict(&mut self, strict: bool) -> &mut Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Set how long a connection has to send its PROXY protocol header
+    /// after being accepted, before it is closed.
+    ///
+    /// If `None` is specified, connections can take as long as they like.
+    ///
+    /// Default is 10 seconds.
+    pub fn set_handshake_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+}
+
+impl Stream for ProxyIncoming {
+    type Item = io::Result<ProxyStream>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match Pin::new(&mut self.incoming).poll_next(cx) {
+                Poll::Ready(Some(Ok(io))) => {
+                    let handshake = Handshake::new(io, self.strict, self.handshake_timeout);
+                    self.handshakes.push(handshake);
+                },
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) | Poll::Pending => break,
+            }
+        }
+
+        loop {
+            match Pin::new(&mut self.handshakes).poll_next(cx) {
+                Poll::Ready(Some(Ok(io))) => return Poll::Ready(Some(Ok(io))),
+                Poll::Ready(Some(Err(e))) => {
+                    // Only this connection is bad, keep accepting others.
+                    debug!("PROXY protocol error: {}", e);
+                },
+                Poll::Ready(None) | Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ProxyIncoming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProxyIncoming")
+            .field("incoming", &self.incoming)
+            .field("strict", &self.strict)
+            .field("handshake_timeout", &self.handshake_timeout)
+            .finish()
+    }
+}
+
+/// A transport yielded by `ProxyIncoming`.
+#[derive(Debug)]
+pub struct ProxyStream {
+    inner: Rewind<AddrStream>,
+    remote_addr: SocketAddr,
+    local_addr: SocketAddr,
+    tlvs: Vec<Tlv>,
+}
+
+impl ProxyStream {
+    fn new(io: AddrStream, header: Option<Header>, read_buf: Bytes) -> ProxyStream {
+        let (addrs, tlvs) = match header {
+            Some(header) => (header.addrs, header.tlvs),
+            None => (None, Vec::new()),
+        };
+        let (remote_addr, local_addr) = addrs
+            .unwrap_or((io.remote_addr(), io.local_addr()));
+        ProxyStream {
+            inner: Rewind::new_buffered(io, read_buf),
+            remote_addr,
+            local_addr,
+            tlvs,
+        }
+    }
+
+    /// Returns the remote address of the original client.
+    ///
+    /// If the proxy did not report any addresses, this is the address of
+    /// the peer of the TCP connection.
+    #[inline]
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+
+    /// Returns the local address the original client connected to.
+    ///
+    /// If the proxy did not report any addresses, this is the local address
+    /// of the TCP connection.
+    #[inline]
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Returns the TLVs sent in a v2 header.
+    #[inline]
+    pub fn tlvs(&self) -> &[Tlv] {
+        &self.tlvs
+    }
+
+    /// Consumes the `ProxyStream`, returning the underlying `AddrStream`
+    /// and any bytes already read after the PROXY protocol header.
+    pub fn into_inner(self) -> (AddrStream, Bytes) {
+        self.inner.into_inner()
+    }
+}
+
+impl Transport for ProxyStream {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        Some(self.remote_addr)
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        Some(self.local_addr)
+    }
+}
+
+impl AsyncRead for ProxyStream {
+    #[inline]
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.inner.prepare_uninitialized_buffer(buf)
+    }
+
+    #[inline]
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ProxyStream {
+    #[inline]
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    #[inline]
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    #[inline]
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// A Type-Length-Value field of a PROXY protocol v2 header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tlv {
+    kind: u8,
+    value: Bytes,
+}
+
+impl Tlv {
+    /// The type of this TLV, such as `0x01` for `PP2_TYPE_ALPN`.
+    #[inline]
+    pub fn kind(&self) -> u8 {
+        self.kind
+    }
+
+    /// The raw value of this TLV.
+    #[inline]
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+// ===== impl Handshake =====
+
+struct Handshake {
+    io: Option<AddrStream>,
+    buf: BytesMut,
+    strict: bool,
+    timeout: Option<Delay>,
+}
+
+impl Handshake {
+    fn new(io: AddrStream, strict: bool, timeout: Option<Duration>) -> Handshake {
+        Handshake {
+            io: Some(io),
+            buf: BytesMut::with_capacity(INIT_BUF_SIZE),
+            strict,
+            timeout: timeout.map(|dur| Delay::new(Instant::now() + dur)),
+        }
+    }
+}
+
+impl Future for Handshake {
+    type Output = io::Result<ProxyStream>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let me = &mut *self;
+        if let Some(ref mut timeout) = me.timeout {
+            if let Poll::Ready(()) = Pin::new(timeout).poll(cx) {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "PROXY protocol header timed out",
+                )));
+            }
+        }
+        loop {
+            match parse(&me.buf)? {
+                Parsed::Header(header, len) => {
+                    let io = me.io.take().expect("polled after complete");
+                    let mut buf = me.buf.take();
+                    let _ = buf.split_to(len);
+                    trace!("PROXY protocol header parsed ({} bytes)", len);
+                    return Poll::Ready(Ok(ProxyStream::new(io, Some(header), buf.freeze())));
+                },
+                Parsed::Missing => {
+                    if me.strict {
+                        return Poll::Ready(Err(invalid("missing PROXY protocol header")));
+                    }
+                    let io = me.io.take().expect("polled after complete");
+                    let buf = me.buf.take();
+                    return Poll::Ready(Ok(ProxyStream::new(io, None, buf.freeze())));
+                },
+                Parsed::Partial => (),
+            }
+
+            if !me.buf.has_remaining_mut() {
+                me.buf.reserve(INIT_BUF_SIZE);
+            }
+            let io = me.io.as_mut().expect("polled after complete");
+            let n = ready!(Pin::new(io).poll_read_buf(cx, &mut me.buf))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed before PROXY protocol header",
+                )));
+            }
+        }
+    }
+}
+
+// ===== parsing =====
+
+#[derive(Debug)]
+struct Header {
+    // (source, destination)
+    addrs: Option<(SocketAddr, SocketAddr)>,
+    tlvs: Vec<Tlv>,
+}
+
+#[derive(Debug)]
+enum Parsed {
+    /// A full header, and how many bytes it used.
+    Header(Header, usize),
+    /// Could still be a header, more bytes are needed.
+    Partial,
+    /// The connection doesn't start with a header.
+    Missing,
+}
+
+fn parse(buf: &[u8]) -> io::Result<Parsed> {
+    if starts_like(buf, V2_SIGNATURE) {
+        parse_v2(buf)
+    } else if starts_like(buf, V1_PREFIX) {
+        parse_v1(buf)
+    } else {
+        Ok(Parsed::Missing)
+    }
+}
+
+// Whether `buf` is a prefix of `sig`, or starts with all of it.
+fn starts_like(buf: &[u8], sig: &[u8]) -> bool {
+    let n = ::std::cmp::min(buf.len(), sig.len());
+    buf[..n] == sig[..n]
+}
+
+fn parse_v1(buf: &[u8]) -> io::Result<Parsed> {
+    let end = match buf.windows(2).position(|w| w == b"\r\n") {
+        Some(end) => end,
+        None if buf.len() >= V1_MAX_LEN => return Err(invalid("v1 header too long")),
+        None => return Ok(Parsed::Partial),
+    };
+    if end + 2 > V1_MAX_LEN {
+        return Err(invalid("v1 header too long"));
+    }
+
+    let line = str::from_utf8(&buf[V1_PREFIX.len()..end])
+        .map_err(|_| invalid("v1 header is not ASCII"))?;
+    let mut parts = line.split(' ');
+    let addrs = match parts.next() {
+        // The rest of the line must be ignored.
+        Some("UNKNOWN") => None,
+        Some(proto) if proto == "TCP4" || proto == "TCP6" => {
+            let addrs = v1_addrs(proto == "TCP4", &mut parts)
+                .ok_or_else(|| invalid("v1 header has invalid addresses"))?;
+            Some(addrs)
+        },
+        _ => return Err(invalid("v1 header has unknown protocol")),
+    };
+
+    Ok(Parsed::Header(Header {
+        addrs,
+        tlvs: Vec::new(),
+    }, end + 2))
+}
+
+fn v1_addrs<'a, I>(is_v4: bool, parts: &mut I) -> Option<(SocketAddr, SocketAddr)>
+where
+    I: Iterator<Item=&'a str>,
+{
+    let src = parts.next()?;
+    let dst = parts.next()?;
+    let src_port = parts.next()?.parse::<u16>().ok()?;
+    let dst_port = parts.next()?.parse::<u16>().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let (src, dst): (IpAddr, IpAddr) = if is_v4 {
+        (src.parse::<Ipv4Addr>().ok()?.into(), dst.parse::<Ipv4Addr>().ok()?.into())
+    } else {
+        (src.parse::<Ipv6Addr>().ok()?.into(), dst.parse::<Ipv6Addr>().ok()?.into())
+    };
+    Some((SocketAddr::new(src, src_port), SocketAddr::new(dst, dst_port)))
+}
+
+fn parse_v2(buf: &[u8]) -> io::Result<Parsed> {
+    if buf.len() < V2_FIXED_LEN {
+        return Ok(Parsed::Partial);
+    }
+
+    let version = buf[12] >> 4;
+    let command = buf[12] & 0x0F;
+    let family = buf[13] >> 4;
+    let len = ((buf[14] as usize) << 8) | buf[15] as usize;
+
+    if version != 2 {
+        return Err(invalid("unsupported v2 header version"));
+    }
+
+    let total = V2_FIXED_LEN + len;
+    if buf.len() < total {
+        return Ok(Parsed::Partial);
+    }
+    let payload = &buf[V2_FIXED_LEN..total];
+
+    let (addrs, rest) = match family {
+        // AF_INET
+        0x1 => {
+            if payload.len() < 12 {
+                return Err(invalid("v2 header too short for IPv4 addresses"));
+            }
+            let src = Ipv4Addr::new(payload[0], payload[1], payload[2], payload[3]);
+            let dst = Ipv4Addr::new(payload[4], payload[5], payload[6], payload[7]);
+            let addrs = (
+                SocketAddr::new(src.into(), be_u16(&payload[8..10])),
+                SocketAddr::new(dst.into(), be_u16(&payload[10..12])),
+            );
+            (Some(addrs), &payload[12..])
+        },
+        // AF_INET6
+        0x2 => {
+            if payload.len() < 36 {
+                return Err(invalid("v2 header too short for IPv6 addresses"));
+            }
+            let mut src = [0; 16];
+            let mut dst = [0; 16];
+            src.copy_from_slice(&payload[0..16]);
+            dst.copy_from_slice(&payload[16..32]);
+            let addrs = (
+                SocketAddr::new(Ipv6Addr::from(src).into(), be_u16(&payload[32..34])),
+                SocketAddr::new(Ipv6Addr::from(dst).into(), be_u16(&payload[34..36])),
+            );
+            (Some(addrs), &payload[36..])
+        },
+        // AF_UNIX, cannot be represented as a SocketAddr
+        0x3 => {
+            if payload.len() < 216 {
+                return Err(invalid("v2 header too short for unix addresses"));
+            }
+            (None, &payload[216..])
+        },
+        // AF_UNSPEC, the whole block must be ignored
+        _ => (None, &[][..]),
+    };
+
+    let addrs = match command {
+        // LOCAL: the proxy connected on its own behalf (health checks),
+        // addresses must be ignored.
+        0x0 => None,
+        // PROXY
+        0x1 => addrs,
+        _ => return Err(invalid("unsupported v2 header command")),
+    };
+
+    Ok(Parsed::Header(Header {
+        addrs,
+        tlvs: parse_tlvs(rest)?,
+    }, total))
+}
+
+fn parse_tlvs(mut buf: &[u8]) -> io::Result<Vec<Tlv>> {
+    let mut tlvs = Vec::new();
+    while !buf.is_empty() {
+        if buf.len() < 3 {
+            return Err(invalid("v2 header has truncated TLV"));
+        }
+        let len = be_u16(&buf[1..3]) as usize;
+        if buf.len() < 3 + len {
+            return Err(invalid("v2 header has truncated TLV"));
+        }
+        tlvs.push(Tlv {
+            kind: buf[0],
+            value: Bytes::from(&buf[3..3 + len]),
+        });
+        buf = &buf[3 + len..];
+    }
+    Ok(tlvs)
+}
+
+fn be_u16(buf: &[u8]) -> u16 {
+    ((buf[0] as u16) << 8) | buf[1] as u16
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(buf: &[u8]) -> (Header, usize) {
+        match parse(buf).expect("parse") {
+            Parsed::Header(header, len) => (header, len),
+            other => panic!("expected header, found {:?}", other),
+        }
+    }
+
+    fn addrs(src: &str, dst: &str) -> Option<(SocketAddr, SocketAddr)> {
+        Some((src.parse().unwrap(), dst.parse().unwrap()))
+    }
+
+    fn v2(command: u8, family: u8, payload: &[u8]) -> Vec<u8> {
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.push(0x20 | command);
+        buf.push(family);
+        buf.push((payload.len() >> 8) as u8);
+        buf.push(payload.len() as u8);
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    #[test]
+    fn test_parse_v1_tcp4() {
+        let buf = b"PROXY TCP4 192.168.0.1 10.0.0.1 56324 443\r\nGET / HTTP/1.1\r\n";
+        let (header, len) = header(buf);
+        assert_eq!(header.addrs, addrs("192.168.0.1:56324", "10.0.0.1:443"));
+        assert_eq!(&buf[len..], b"GET / HTTP/1.1\r\n");
+    }
+
+    #[test]
+    fn test_parse_v1_tcp6() {
+        let buf = b"PROXY TCP6 ::1 fe80::1 56324 443\r\n";
+        let (header, len) = header(buf);
+        assert_eq!(header.addrs, addrs("[::1]:56324", "[fe80::1]:443"));
+        assert_eq!(len, buf.len());
+    }
+
+    #[test]
+    fn test_parse_v1_unknown() {
+        let (header, _) = header(b"PROXY UNKNOWN ffff::1 ffff::2 1 2\r\n");
+        assert_eq!(header.addrs, None);
+    }
+
+    #[test]
+    fn test_parse_v1_partial() {
+        let bufs: &[&[u8]] = &[
+            b"",
+            b"P",
+            b"PROXY",
+            b"PROXY TCP4 192.168.0.1",
+            b"PROXY TCP4 1.1.1.1 2.2.2.2 1 2\r",
+        ];
+        for buf in bufs {
+            match parse(buf).unwrap() {
+                Parsed::Partial => (),
+                other => panic!("{:?} should be partial, found {:?}", buf, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_v1_invalid() {
+        parse(b"PROXY TCP4 ::1 ::1 1 2\r\n").unwrap_err();
+        parse(b"PROXY TCP6 1.1.1.1 2.2.2.2 1 2\r\n").unwrap_err();
+        parse(b"PROXY TCP4 1.1.1.1 2.2.2.2 1 99999\r\n").unwrap_err();
+        parse(b"PROXY TCP4 1.1.1.1 2.2.2.2 1 2 3\r\n").unwrap_err();
+        parse(b"PROXY UDP4 1.1.1.1 2.2.2.2 1 2\r\n").unwrap_err();
+        let mut long = b"PROXY ".to_vec();
+        long.extend_from_slice(&[b'A'; 120]);
+        parse(&long).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_missing() {
+        let bufs: &[&[u8]] = &[b"GET / HTTP/1.1\r\n", b"POST", b"PRI * HTTP/2.0\r\n"];
+        for buf in bufs {
+            match parse(buf).unwrap() {
+                Parsed::Missing => (),
+                other => panic!("{:?} should be missing, found {:?}", buf, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_v2_ipv4_tlvs() {
+        let mut payload = vec![127, 0, 0, 1, 10, 0, 0, 1, 0x1F, 0x90, 0x01, 0xBB];
+        // PP2_TYPE_ALPN "h2"
+        payload.extend_from_slice(&[0x01, 0x00, 0x02, b'h', b'2']);
+        // PP2_TYPE_NOOP, empty
+        payload.extend_from_slice(&[0x04, 0x00, 0x00]);
+        let mut buf = v2(0x1, 0x11, &payload);
+        let header_len = buf.len();
+        buf.extend_from_slice(b"GET");
+
+        let (header, len) = header(&buf);
+        assert_eq!(len, header_len);
+        assert_eq!(header.addrs, addrs("127.0.0.1:8080", "10.0.0.1:443"));
+        assert_eq!(header.tlvs.len(), 2);
+        assert_eq!(header.tlvs[0].kind(), 0x01);
+        assert_eq!(header.tlvs[0].value(), b"h2");
+        assert_eq!(header.tlvs[1].kind(), 0x04);
+        assert_eq!(header.tlvs[1].value(), b"");
+    }
+
+    #[test]
+    fn test_parse_v2_ipv6() {
+        let mut payload = vec![0; 36];
+        payload[15] = 1;
+        payload[31] = 2;
+        payload[33] = 80;
+        payload[35] = 81;
+        let (header, _) = header(&v2(0x1, 0x21, &payload));
+        assert_eq!(header.addrs, addrs("[::1]:80", "[::2]:81"));
+    }
+
+    #[test]
+    fn test_parse_v2_local() {
+        let (local, len) = header(&v2(0x0, 0x00, &[]));
+        assert_eq!(local.addrs, None);
+        assert_eq!(len, V2_FIXED_LEN);
+
+        // LOCAL ignores any addresses sent
+        let payload = [127, 0, 0, 1, 10, 0, 0, 1, 0x1F, 0x90, 0x01, 0xBB];
+        let (local, _) = header(&v2(0x0, 0x11, &payload));
+        assert_eq!(local.addrs, None);
+    }
+
+    #[test]
+    fn test_parse_v2_partial() {
+        let buf = v2(0x1, 0x11, &[127, 0, 0, 1, 10, 0, 0, 1, 0x1F, 0x90, 0x01, 0xBB]);
+        for i in 0..buf.len() {
+            match parse(&buf[..i]).unwrap() {
+                Parsed::Partial => (),
+                other => panic!("{} bytes should be partial, found {:?}", i, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_v2_invalid() {
+        // bad version
+        let mut buf = v2(0x1, 0x11, &[0; 12]);
+        buf[12] = 0x11;
+        parse(&buf).unwrap_err();
+        // bad command
+        parse(&v2(0x2, 0x11, &[0; 12])).unwrap_err();
+        // too short for the family
+        parse(&v2(0x1, 0x11, &[0; 8])).unwrap_err();
+        // truncated TLV
+        let mut payload = vec![0; 12];
+        payload.extend_from_slice(&[0x01, 0x00, 0x05, b'h']);
+        parse(&v2(0x1, 0x11, &payload)).unwrap_err();
+    }
+}
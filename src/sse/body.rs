@@ -0,0 +1,219 @@
+use std::error::Error as StdError;
+use std::fmt;
+#[cfg(feature = "runtime")]
+use std::time::{Duration, Instant};
+
+use futures_core::TryStream;
+use http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use http::{HeaderValue, Response};
+use pin_utils::{unsafe_pinned, unsafe_unpinned};
+#[cfg(feature = "runtime")]
+use tokio_timer::Delay;
+
+use crate::body::{Chunk, Payload};
+#[cfg(feature = "runtime")]
+use crate::common::Future;
+use crate::common::{Pin, Poll, Unpin, task};
+use super::Event;
+
+/// How long an `Sse` waits for an event before sending a heartbeat, by
+/// default.
+#[cfg(feature = "runtime")]
+const DEFAULT_HEARTBEAT: Duration = Duration::from_secs(15);
+
+/// A `text/event-stream` body, sending a stream of [`Event`](Event)s.
+///
+/// With the `runtime` feature, a heartbeat comment is sent whenever no
+/// event has been sent for 15 seconds, which can be changed with
+/// [`heartbeat`](Sse::heartbeat).
+///
+/// The body ends when the stream of events does. Errors of the stream are
+/// boxed, and end the response.
+#[must_use = "streams do nothing unless polled"]
+pub struct Sse<S> {
+    events: S,
+    heartbeat: Heartbeat,
+    is_end_stream: bool,
+}
+
+struct Heartbeat {
+    #[cfg(feature = "runtime")]
+    interval: Option<Duration>,
+    #[cfg(feature = "runtime")]
+    last_sent: Instant,
+    #[cfg(feature = "runtime")]
+    delay: Option<Delay>,
+}
+
+impl<S> Sse<S>
+where
+    S: TryStream<Ok = Event> + Send + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    unsafe_pinned!(events: S);
+    unsafe_unpinned!(is_end_stream: bool);
+
+    fn heartbeat_mut(self: Pin<&mut Self>) -> &mut Heartbeat {
+        unsafe { &mut self.get_unchecked_mut().heartbeat }
+    }
+
+    /// Creates a body sending each event of a stream.
+    pub fn new(events: S) -> Sse<S> {
+        Sse {
+            events,
+            heartbeat: Heartbeat::new(),
+            is_end_stream: false,
+        }
+    }
+
+    /// Sets how long to wait for an event before sending a heartbeat
+    /// comment.
+    ///
+    /// Pass `None` to disable heartbeats.
+    ///
+    /// Default is 15 seconds.
+    #[cfg(feature = "runtime")]
+    pub fn heartbeat(mut self, interval: Option<Duration>) -> Sse<S> {
+        self.heartbeat.interval = interval;
+        self
+    }
+
+    /// Creates a `200 OK` response with this body, and the `Content-Type`
+    /// and `Cache-Control` headers of an event stream.
+    pub fn into_response(self) -> Response<Sse<S>> {
+        let mut res = Response::new(self);
+        res.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+        res.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        res
+    }
+}
+
+impl<S: Unpin> Unpin for Sse<S> {}
+
+impl<S> Payload for Sse<S>
+where
+    S: TryStream<Ok = Event> + Send + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    type Data = Chunk;
+    type Error = Box<dyn StdError + Send + Sync>;
+
+    fn poll_data(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        if self.is_end_stream {
+            return Poll::Ready(None);
+        }
+        match self.as_mut().events().try_poll_next(cx) {
+            Poll::Ready(Some(Ok(event))) => {
+                self.as_mut().heartbeat_mut().reset();
+                Poll::Ready(Some(Ok(event.encode())))
+            },
+            Poll::Ready(Some(Err(err))) => {
+                *self.as_mut().is_end_stream() = true;
+                Poll::Ready(Some(Err(err.into())))
+            },
+            Poll::Ready(None) => {
+                *self.as_mut().is_end_stream() = true;
+                Poll::Ready(None)
+            },
+            Poll::Pending => {
+                ready!(self.as_mut().heartbeat_mut().poll_due(cx));
+                trace!("sending event stream heartbeat");
+                self.as_mut().heartbeat_mut().reset();
+                Poll::Ready(Some(Ok(Chunk::from(":\n\n"))))
+            },
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.is_end_stream
+    }
+}
+
+impl<S> fmt::Debug for Sse<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sse")
+            .field("is_end_stream", &self.is_end_stream)
+            .finish()
+    }
+}
+
+impl Heartbeat {
+    #[cfg(feature = "runtime")]
+    fn new() -> Heartbeat {
+        Heartbeat {
+            interval: Some(DEFAULT_HEARTBEAT),
+            last_sent: Instant::now(),
+            delay: None,
+        }
+    }
+
+    #[cfg(feature = "runtime")]
+    fn reset(&mut self) {
+        self.last_sent = Instant::now();
+    }
+
+    #[cfg(feature = "runtime")]
+    fn poll_due(&mut self, cx: &mut task::Context<'_>) -> Poll<()> {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return Poll::Pending,
+        };
+        let deadline = self.last_sent + interval;
+        match self.delay {
+            Some(ref mut delay) if delay.deadline() == deadline => (),
+            _ => self.delay = Some(Delay::new(deadline)),
+        }
+        Pin::new(self.delay.as_mut().expect("delay just set")).poll(cx)
+    }
+
+    // Heartbeats need the runtime's timer.
+    #[cfg(not(feature = "runtime"))]
+    fn new() -> Heartbeat {
+        Heartbeat {}
+    }
+
+    #[cfg(not(feature = "runtime"))]
+    fn reset(&mut self) {}
+
+    #[cfg(not(feature = "runtime"))]
+    fn poll_due(&mut self, _cx: &mut task::Context<'_>) -> Poll<()> {
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_channel::mpsc;
+    use futures_util::future::poll_fn;
+    use futures_util::StreamExt;
+    use http::header::CONTENT_TYPE;
+
+    use crate::body::Payload;
+    use crate::common::Pin;
+    use super::super::Event;
+    use super::Sse;
+
+    #[tokio::test]
+    async fn sse_sends_events_and_heartbeats() {
+        let (mut tx, rx) = mpsc::channel(1);
+        let res = Sse::new(rx.map(Ok::<_, ::std::io::Error>))
+            .heartbeat(Some(Duration::from_millis(20)))
+            .into_response();
+        assert_eq!(res.headers()[CONTENT_TYPE], "text/event-stream");
+        let mut body = res.into_body();
+
+        tx.try_send(Event::new().data("one")).expect("send");
+        let chunk = poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await.expect("event").expect("event");
+        assert_eq!(chunk.into_bytes(), "data: one\n\n");
+
+        // nothing sent for a while
+        let chunk = poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await.expect("heartbeat").expect("heartbeat");
+        assert_eq!(chunk.into_bytes(), ":\n\n");
+
+        drop(tx);
+        assert!(poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await.is_none());
+        assert!(body.is_end_stream());
+    }
+}
@@ -0,0 +1,247 @@
+//! Server-Sent Events
+//!
+//! A `text/event-stream` response keeps sending events to a client for as
+//! long as it is open. This module can both send and receive such streams:
+//!
+//! - An [`Sse`](sse::Sse) is a `Payload` made from a stream of
+//!   [`Event`](sse::Event)s, which formats each event, and sends heartbeat
+//!   comments while there are none, so that proxies don't close the idle
+//!   connection. A client that reconnects sends the id of the last event
+//!   it received, which [`last_event_id`](sse::last_event_id) returns.
+//! - An [`EventStream`](sse::EventStream) parses a received `Body` into a
+//!   stream of [`Message`](sse::Message)s, keeping the last event id and
+//!   the reconnection time the server asked for.
+//!
+//! # Example
+//!
+//! ```
+//! # fn run(req: hyper::Request<hyper::Body>) {
+//! use futures_util::stream;
+//! use hyper::sse::{self, Event, Sse};
+//!
+//! // continue after the last event this client saw
+//! let start = sse::last_event_id(&req)
+//!     .and_then(|id| id.parse::<u64>().ok())
+//!     .map(|id| id + 1)
+//!     .unwrap_or(0);
+//!
+//! let events = stream::iter((start..start + 3).map(|i| {
+//!     Ok::<_, std::io::Error>(Event::new().id(i.to_string()).data(format!("tick {}", i)))
+//! }));
+//! let res = Sse::new(events).into_response();
+//! # drop(res);
+//! # }
+//! ```
+use std::fmt;
+use std::time::Duration;
+
+use bytes::{BufMut, BytesMut};
+use http::Request;
+
+use crate::body::Chunk;
+
+pub use self::body::Sse;
+pub use self::parse::{EventStream, Message};
+
+mod body;
+mod parse;
+
+/// An event to send in an [`Sse`](Sse) body.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use hyper::sse::Event;
+///
+/// let event = Event::new()
+///     .event("update")
+///     .id("42")
+///     .retry(Duration::from_secs(5))
+///     .data("first line\nsecond line");
+/// # drop(event);
+/// ```
+#[derive(Clone, Default)]
+pub struct Event {
+    comment: Option<String>,
+    event: Option<String>,
+    id: Option<String>,
+    retry: Option<Duration>,
+    data: Option<String>,
+}
+
+impl Event {
+    /// Creates an empty `Event`.
+    pub fn new() -> Event {
+        Event::default()
+    }
+
+    /// Sets the data of this event.
+    ///
+    /// Data with several lines is sent as several `data` fields, which the
+    /// client joins back together.
+    pub fn data<D: Into<String>>(mut self, data: D) -> Event {
+        self.data = Some(data.into());
+        self
+    }
+
+    /// Sets the type of this event.
+    ///
+    /// Clients dispatch events without a type as `message`. Line breaks are
+    /// removed, so that the type can't add fields to the event.
+    pub fn event<E: Into<String>>(mut self, event: E) -> Event {
+        self.event = Some(single_line(event.into()));
+        self
+    }
+
+    /// Sets the id of this event.
+    ///
+    /// A client that reconnects sends the last id it received in the
+    /// `Last-Event-ID` header. Line breaks and NUL characters, which
+    /// clients reject in an id, are removed.
+    pub fn id<I: Into<String>>(mut self, id: I) -> Event {
+        self.id = Some(single_line(id.into()).replace('\0', ""));
+        self
+    }
+
+    /// Sets how long the client should wait before reconnecting, if the
+    /// connection is lost.
+    pub fn retry(mut self, retry: Duration) -> Event {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Sets a comment to send with this event, which clients ignore.
+    pub fn comment<C: Into<String>>(mut self, comment: C) -> Event {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    fn encode(&self) -> Chunk {
+        let mut buf = BytesMut::with_capacity(self.data.as_ref().map(|data| data.len()).unwrap_or(0) + 32);
+        if let Some(ref comment) = self.comment {
+            put_lines(&mut buf, ": ", comment);
+        }
+        if let Some(ref event) = self.event {
+            put_field(&mut buf, "event: ", event);
+        }
+        if let Some(ref id) = self.id {
+            put_field(&mut buf, "id: ", id);
+        }
+        if let Some(retry) = self.retry {
+            let millis = retry.as_secs() * 1000 + u64::from(retry.subsec_millis());
+            put_field(&mut buf, "retry: ", &millis.to_string());
+        }
+        if let Some(ref data) = self.data {
+            put_lines(&mut buf, "data: ", data);
+        }
+        buf.reserve(1);
+        buf.put_u8(b'\n');
+        Chunk::from(buf.freeze())
+    }
+}
+
+impl fmt::Debug for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Event")
+            .field("comment", &self.comment)
+            .field("event", &self.event)
+            .field("id", &self.id)
+            .field("retry", &self.retry)
+            .field("data", &self.data)
+            .finish()
+    }
+}
+
+/// Returns the `Last-Event-ID` that a reconnecting client sent.
+///
+/// It is the id of the last event the client received, so the events
+/// after it can be sent again.
+pub fn last_event_id<B>(req: &Request<B>) -> Option<&str> {
+    req.headers()
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Removes line breaks, which would end a field early.
+fn single_line(value: String) -> String {
+    if value.contains(|c: char| c == '\r' || c == '\n') {
+        value.replace(|c: char| c == '\r' || c == '\n', "")
+    } else {
+        value
+    }
+}
+
+/// Puts a line, where `prefix` is the name of the field and `: `, or just
+/// `: ` for a comment.
+fn put_field(buf: &mut BytesMut, prefix: &str, value: &str) {
+    buf.reserve(prefix.len() + value.len() + 1);
+    buf.put(prefix);
+    buf.put(value);
+    buf.put("\n");
+}
+
+/// Puts a field for each line of `value`, which may end with `\r\n`, `\n`
+/// or `\r`.
+fn put_lines(buf: &mut BytesMut, prefix: &str, value: &str) {
+    let mut rest = value;
+    loop {
+        match rest.find(|c: char| c == '\r' || c == '\n') {
+            Some(pos) => {
+                put_field(buf, prefix, &rest[..pos]);
+                let skip = if rest[pos..].starts_with("\r\n") { 2 } else { 1 };
+                rest = &rest[pos + skip..];
+            },
+            None => {
+                put_field(buf, prefix, rest);
+                return;
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use http::Request;
+
+    use super::{last_event_id, Event};
+
+    #[test]
+    fn event_encodes_fields_and_lines() {
+        let event = Event::new()
+            .comment("hi")
+            .event("up\ndate")
+            .id("7")
+            .retry(Duration::from_millis(1500))
+            .data("a\r\nb\nc\rd\n");
+        assert_eq!(
+            event.encode().into_bytes(),
+            ": hi\nevent: update\nid: 7\nretry: 1500\ndata: a\ndata: b\ndata: c\ndata: d\ndata: \n\n"
+        );
+        assert_eq!(Event::new().data("").encode().into_bytes(), "data: \n\n");
+    }
+
+    #[test]
+    fn event_and_id_cannot_add_fields() {
+        let event = Event::new()
+            .event("tick\r\ndata: injected")
+            .id("1\n\nid: 2\0")
+            .data("ok");
+        assert_eq!(
+            event.encode().into_bytes(),
+            "event: tickdata: injected\nid: 1id: 2\ndata: ok\n\n"
+        );
+    }
+
+    #[test]
+    fn last_event_id_reads_header() {
+        let req = Request::get("/")
+            .header("Last-Event-ID", "42")
+            .body(())
+            .unwrap();
+        assert_eq!(last_event_id(&req), Some("42"));
+        assert_eq!(last_event_id(&Request::new(())), None);
+    }
+}
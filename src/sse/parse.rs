@@ -0,0 +1,343 @@
+use std::fmt;
+use std::mem;
+use std::time::Duration;
+
+use bytes::BytesMut;
+use futures_core::Stream;
+
+use crate::body::{Body, Payload};
+use crate::common::{Pin, Poll, task};
+
+/// The default for the most bytes a single line may have.
+const DEFAULT_MAX_LINE_SIZE: usize = 64 * 1024;
+
+/// The default for the most bytes of `data` and `event` a single event
+/// may have.
+const DEFAULT_MAX_EVENT_SIZE: usize = 1024 * 1024;
+
+/// An event received in an [`EventStream`](EventStream).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    event: String,
+    data: String,
+    id: Option<String>,
+}
+
+impl Message {
+    /// Returns the type of this event, which is `message` if the server
+    /// didn't set one.
+    pub fn event(&self) -> &str {
+        &self.event
+    }
+
+    /// Returns the data of this event.
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+
+    /// Returns the last event id, when this event was received.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_ref().map(|id| &**id)
+    }
+}
+
+/// A `Stream` of the [`Message`](Message)s in a `text/event-stream` body.
+///
+/// When the connection is lost, a client should wait for
+/// [`retry`](EventStream::retry), and reconnect with the
+/// [`last_event_id`](EventStream::last_event_id) in a `Last-Event-ID`
+/// header.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(async_await)]
+/// # fn main() {}
+/// # async fn run(res: hyper::Response<hyper::Body>) -> hyper::Result<()> {
+/// use futures_util::TryStreamExt;
+/// use hyper::sse::EventStream;
+///
+/// let mut events = EventStream::new(res.into_body());
+/// while let Some(message) = events.try_next().await? {
+///     println!("{}: {}", message.event(), message.data());
+/// }
+/// println!("reconnect after {:?}, from {:?}", events.retry(), events.last_event_id());
+/// # Ok(())
+/// # }
+/// ```
+#[must_use = "streams do nothing unless polled"]
+pub struct EventStream {
+    body: Body,
+    buf: BytesMut,
+    /// The previous line ended with `\r`, so a `\n` that starts the next
+    /// belongs to it.
+    skip_lf: bool,
+    started: bool,
+    is_eof: bool,
+    event: String,
+    data: String,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+    max_line_size: usize,
+    max_event_size: usize,
+}
+
+impl EventStream {
+    /// Parses the events of a body.
+    pub fn new(body: Body) -> EventStream {
+        EventStream {
+            body,
+            buf: BytesMut::new(),
+            skip_lf: false,
+            started: false,
+            is_eof: false,
+            event: String::new(),
+            data: String::new(),
+            last_event_id: None,
+            retry: None,
+            max_line_size: DEFAULT_MAX_LINE_SIZE,
+            max_event_size: DEFAULT_MAX_EVENT_SIZE,
+        }
+    }
+
+    /// Sets the most bytes a single line may have.
+    ///
+    /// Reading a longer line fails with an error where
+    /// `is_body_too_large` is true, and ends the stream.
+    ///
+    /// Default is 64kb.
+    pub fn max_line_size(mut self, max: usize) -> EventStream {
+        self.max_line_size = max;
+        self
+    }
+
+    /// Sets the most bytes the `data` and `event` of a single event may
+    /// have together.
+    ///
+    /// Reading a larger event fails with an error where
+    /// `is_body_too_large` is true, and ends the stream.
+    ///
+    /// Default is 1mb.
+    pub fn max_event_size(mut self, max: usize) -> EventStream {
+        self.max_event_size = max;
+        self
+    }
+
+    /// Returns the id of the last event received, if any had one.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_ref().map(|id| &**id)
+    }
+
+    /// Returns how long the server asked to wait before reconnecting, if
+    /// it did.
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    /// Parses the buffered lines, until an event is complete.
+    fn parse(&mut self) -> crate::Result<Option<Message>> {
+        loop {
+            if self.skip_lf && !self.buf.is_empty() {
+                if self.buf[0] == b'\n' {
+                    let _ = self.buf.split_to(1);
+                }
+                self.skip_lf = false;
+            }
+            if !self.started {
+                // a leading byte order mark is ignored
+                if self.buf.len() < 3 && b"\xEF\xBB\xBF".starts_with(&self.buf) && !self.is_eof {
+                    return Ok(None);
+                }
+                if self.buf.starts_with(b"\xEF\xBB\xBF") {
+                    let _ = self.buf.split_to(3);
+                }
+                self.started = true;
+            }
+
+            let end = match self.buf.iter().position(|&b| b == b'\r' || b == b'\n') {
+                Some(end) if end > self.max_line_size => return Err(self.too_large("line")),
+                Some(end) => end,
+                None if self.buf.len() > self.max_line_size => return Err(self.too_large("line")),
+                None => return Ok(None),
+            };
+            self.skip_lf = self.buf[end] == b'\r';
+            let line = self.buf.split_to(end + 1);
+            let line = String::from_utf8_lossy(&line[..end]);
+            if line.is_empty() {
+                if let Some(message) = self.dispatch() {
+                    return Ok(Some(message));
+                }
+            } else {
+                self.field(&line);
+                if self.data.len() + self.event.len() > self.max_event_size {
+                    return Err(self.too_large("event"));
+                }
+            }
+        }
+    }
+
+    /// Ends the stream, after a line or event went over its limit.
+    fn too_large(&mut self, what: &str) -> crate::Error {
+        debug!("event stream {} is larger than the limit", what);
+        self.buf.clear();
+        self.event.clear();
+        self.data.clear();
+        self.is_eof = true;
+        crate::Error::new_body_too_large()
+    }
+
+    fn field(&mut self, line: &str) {
+        let (name, value) = match line.find(':') {
+            Some(0) => return,
+            Some(pos) => {
+                let value = &line[pos + 1..];
+                (&line[..pos], if value.starts_with(' ') { &value[1..] } else { value })
+            },
+            None => (line, ""),
+        };
+        match name {
+            "event" => self.event = value.to_owned(),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            },
+            "id" if !value.contains('\0') => self.last_event_id = Some(value.to_owned()),
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(millis) = value.parse() {
+                    self.retry = Some(Duration::from_millis(millis));
+                }
+            },
+            _ => trace!("ignoring event stream field {:?}", name),
+        }
+    }
+
+    fn dispatch(&mut self) -> Option<Message> {
+        let event = mem::replace(&mut self.event, String::new());
+        if self.data.is_empty() {
+            return None;
+        }
+        let mut data = mem::replace(&mut self.data, String::new());
+        data.pop();
+        Some(Message {
+            event: if event.is_empty() { "message".to_owned() } else { event },
+            data,
+            id: self.last_event_id.clone(),
+        })
+    }
+}
+
+impl Stream for EventStream {
+    type Item = crate::Result<Message>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.parse() {
+                Ok(Some(message)) => return Poll::Ready(Some(Ok(message))),
+                Ok(None) => (),
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
+            if self.is_eof {
+                // an event without the empty line after it is discarded
+                return Poll::Ready(None);
+            }
+            match ready!(Pin::new(&mut self.body).poll_data(cx)) {
+                Some(Ok(chunk)) => self.buf.extend_from_slice(&chunk),
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => self.is_eof = true,
+            }
+        }
+    }
+}
+
+impl fmt::Debug for EventStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventStream")
+            .field("last_event_id", &self.last_event_id)
+            .field("retry", &self.retry)
+            .field("max_line_size", &self.max_line_size)
+            .field("max_event_size", &self.max_event_size)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::TryStreamExt;
+
+    use crate::Body;
+    use super::super::{Event, Sse};
+    use super::EventStream;
+
+    fn body_in_chunks(s: &'static str, size: usize) -> Body {
+        let chunks = s.as_bytes()
+            .chunks(size)
+            .map(|chunk| Ok::<_, ::std::io::Error>(chunk))
+            .collect::<Vec<_>>();
+        Body::wrap_stream(futures_util::stream::iter(chunks))
+    }
+
+    #[tokio::test]
+    async fn event_stream_parses_fields_across_chunks() {
+        let stream = "\u{FEFF}: comment\r\n\
+                      retry: 3000\r\n\
+                      data: first\r\n\r\n\
+                      event: update\rid: 1\rdata:second\rdata\r\r\
+                      id\n\
+                      data: third\n\n\
+                      data: incomplete\n";
+        for &size in &[1, 2, 5, 1024] {
+            let mut events = EventStream::new(body_in_chunks(stream, size));
+
+            let first = events.try_next().await.expect("first").expect("first");
+            assert_eq!((first.event(), first.data(), first.id()), ("message", "first", None));
+            assert_eq!(events.retry(), Some(Duration::from_secs(3)));
+
+            let second = events.try_next().await.expect("second").expect("second");
+            assert_eq!((second.event(), second.data(), second.id()), ("update", "second\n", Some("1")));
+
+            let third = events.try_next().await.expect("third").expect("third");
+            assert_eq!((third.event(), third.data(), third.id()), ("message", "third", Some("")));
+
+            assert!(events.try_next().await.expect("end").is_none());
+            assert_eq!(events.last_event_id(), Some(""));
+        }
+    }
+
+    #[tokio::test]
+    async fn event_stream_limits_line_and_event_size() {
+        let mut events = EventStream::new(body_in_chunks("data: ok\n\ndata: too long\n\n", 3))
+            .max_line_size(10);
+        let first = events.try_next().await.expect("first").expect("first");
+        assert_eq!(first.data(), "ok");
+        let err = events.try_next().await.expect_err("line too long");
+        assert!(err.is_body_too_large(), "{:?}", err);
+        assert!(events.try_next().await.expect("end").is_none());
+
+        let mut events = EventStream::new(body_in_chunks("data: 1234\ndata: 5678\n\n", 3))
+            .max_event_size(8);
+        let err = events.try_next().await.expect_err("event too large");
+        assert!(err.is_body_too_large(), "{:?}", err);
+        assert!(events.try_next().await.expect("end").is_none());
+    }
+
+    #[tokio::test]
+    async fn event_stream_reads_sse_body() {
+        let sse = Sse::new(futures_util::stream::iter(vec![
+            Ok::<_, ::std::io::Error>(Event::new().id("1").data("a\nb")),
+            Ok(Event::new().comment("ignored").event("ping").data("")),
+        ]));
+        let bytes = crate::body::to_bytes(sse, 1024).await.expect("to_bytes");
+
+        let events = EventStream::new(Body::from(bytes))
+            .map_ok(|message| (message.event().to_owned(), message.data().to_owned()))
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("events");
+        assert_eq!(events, vec![
+            ("message".to_owned(), "a\nb".to_owned()),
+            ("ping".to_owned(), "".to_owned()),
+        ]);
+    }
+}
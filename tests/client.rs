@@ -1207,6 +1207,184 @@ mod dispatch_impl {
         drop(client);
     }
 
+    #[test]
+    fn client_keep_alive_max_retires_connection() {
+        let _ = pretty_env_logger::try_init();
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut rt = Runtime::new().unwrap();
+        let connector = DebugConnector::new();
+        let connects = connector.connects.clone();
+
+        let client = Client::builder()
+            .build(connector);
+
+        let (tx1, rx1) = oneshot::channel();
+        let (tx2, rx2) = oneshot::channel();
+        let (tx3, rx3) = oneshot::channel();
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut buf = [0; 4096];
+            sock.read(&mut buf).expect("read 1");
+            // only one more request is accepted on this connection
+            sock.write_all(b"HTTP/1.1 200 OK\r\nKeep-Alive: max=1\r\nContent-Length: 0\r\n\r\n").expect("write 1");
+            let _ = tx1.send(());
+
+            let n2 = sock.read(&mut buf).expect("read 2");
+            assert_ne!(n2, 0);
+            sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").expect("write 2");
+            let _ = tx2.send(());
+
+            // the first connection is still open, but used up
+            let mut sock2 = server.accept().unwrap().0;
+            sock2.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock2.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+            let n3 = sock2.read(&mut buf).expect("read 3");
+            let third_get = "GET /c HTTP/1.1\r\n";
+            assert_eq!(s(&buf[..third_get.len().min(n3)]), third_get);
+            sock2.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").expect("write 3");
+            let _ = tx3.send(());
+            drop(sock);
+        });
+
+        for (path, rx) in vec![("a", rx1), ("b", rx2), ("c", rx3)] {
+            let rx = rx.expect("thread panicked");
+            let req = Request::builder()
+                .uri(&*format!("http://{}/{}", addr, path))
+                .body(Body::empty())
+                .unwrap();
+            let res = client.request(req);
+            rt.block_on(future::join(res, rx).map(|r| r.0)).unwrap();
+
+            // sleep real quick to let the threadpool put connection in ready
+            // state and back into client pool
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        assert_eq!(connects.load(Ordering::SeqCst), 2, "third request should use a new connection");
+    }
+
+    #[test]
+    fn client_retries_replayable_body_when_reused_conn_closes() {
+        let _ = pretty_env_logger::try_init();
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut rt = Runtime::new().unwrap();
+        let connector = DebugConnector::new();
+        let connects = connector.connects.clone();
+
+        let client = Client::builder()
+            .build(connector);
+
+        let (tx1, rx1) = oneshot::channel();
+        let (tx2, rx2) = oneshot::channel();
+        thread::spawn(move || {
+            fn read_until(sock: &mut ::std::net::TcpStream, needle: &str) -> String {
+                let mut buf = [0; 4096];
+                let mut req = String::new();
+                while !req.contains(needle) {
+                    let n = sock.read(&mut buf).expect("read");
+                    assert_ne!(n, 0, "eof before {:?}", needle);
+                    req.push_str(s(&buf[..n]));
+                }
+                req
+            }
+
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+            read_until(&mut sock, "\r\n\r\n");
+            sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").expect("write 1");
+            let _ = tx1.send(());
+
+            // read the whole second request, then close without a response
+            read_until(&mut sock, "hello");
+            drop(sock);
+
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+            let req = read_until(&mut sock, "hello");
+            assert!(req.starts_with("PUT /b HTTP/1.1\r\n"), "{:?}", req);
+            // the replayed request keeps its extensions
+            assert!(req.contains("\r\nX-Custom: 1\r\n"), "{:?}", req);
+            sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").expect("write 2");
+            let _ = tx2.send(());
+        });
+
+        let rx = rx1.expect("thread panicked");
+        let req = Request::builder()
+            .uri(&*format!("http://{}/a", addr))
+            .body(Body::empty())
+            .unwrap();
+        let res = client.request(req);
+        rt.block_on(future::join(res, rx).map(|r| r.0)).unwrap();
+
+        // sleep real quick to let the threadpool put connection in ready
+        // state and back into client pool
+        thread::sleep(Duration::from_millis(50));
+
+        let rx = rx2.expect("thread panicked");
+        let mut req = Request::builder()
+            .method("PUT")
+            .uri(&*format!("http://{}/b", addr))
+            .header("x-custom", "1")
+            .body(Body::replayable(Body::from("hello"), 1024))
+            .unwrap();
+        let mut case = hyper::ext::HeaderCaseMap::new();
+        case.append(b"X-Custom").unwrap();
+        req.extensions_mut().insert(case);
+        let res = client.request(req);
+        let res = rt.block_on(future::join(res, rx).map(|r| r.0)).unwrap();
+        assert_eq!(res.status(), 200);
+
+        assert_eq!(connects.load(Ordering::SeqCst), 2, "retry should use a new connection");
+    }
+
+    #[test]
+    fn client_on_progress_reports_response_body_until_stalled() {
+        use std::sync::{Arc, Mutex};
+        use hyper::ext::OnProgress;
+
+        let _ = pretty_env_logger::try_init();
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut rt = Runtime::new().unwrap();
+
+        let client = Client::new();
+
+        let (done_tx, done_rx) = ::std::sync::mpsc::channel::<()>();
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut buf = [0; 4096];
+            sock.read(&mut buf).expect("read 1");
+            sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nhello").expect("write 1");
+            // never send the rest, until the client is done
+            let _ = done_rx.recv_timeout(Duration::from_secs(5));
+        });
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen2 = seen.clone();
+        let mut req = Request::builder()
+            .uri(&*format!("http://{}/a", addr))
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut().insert(OnProgress::new(move |transfer| {
+            seen2.lock().unwrap().push((transfer.transferred(), transfer.total()));
+        }).stall_timeout(Duration::from_millis(100)));
+
+        let res = rt.block_on(client.request(req)).unwrap();
+        assert_eq!(res.status(), 200);
+        let err = rt.block_on(res.into_body().try_concat()).expect_err("body should stall");
+        assert!(err.is_body_stalled(), "{:?}", err);
+        assert_eq!(*seen.lock().unwrap(), vec![(5, Some(10))]);
+        drop(done_tx);
+    }
+
     #[test]
     fn client_keep_alive_extra_body() {
         let _ = pretty_env_logger::try_init();
@@ -1684,6 +1862,265 @@ mod conn {
         rt.block_on(future::join(res, rx).map(|r| r.0)).unwrap();
     }
 
+    #[test]
+    fn on_informational_receives_interim_responses() {
+        use std::sync::{Arc, Mutex};
+        use hyper::ext::OnInformational;
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut rt = Runtime::new().unwrap();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut buf = [0; 4096];
+            sock.read(&mut buf).expect("read 1");
+            sock.write_all(b"\
+                HTTP/1.1 103 Early Hints\r\n\
+                Link: </style.css>; rel=preload\r\n\
+                \r\n\
+                HTTP/1.1 102 Processing\r\n\
+                \r\n\
+                HTTP/1.1 200 OK\r\n\
+                Content-Length: 0\r\n\
+                \r\n\
+            ").unwrap();
+        });
+
+        let tcp = rt.block_on(tcp_connect(&addr)).unwrap();
+
+        let (mut client, conn) = rt.block_on(conn::handshake(tcp)).unwrap();
+
+        rt.spawn(conn.map_err(|e| panic!("conn error: {}", e)).map(|_| ()));
+
+        let interim = Arc::new(Mutex::new(Vec::new()));
+        let interim2 = interim.clone();
+        let mut req = Request::builder()
+            .uri("/a")
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut().insert(OnInformational::new(move |res| {
+            let link = res.headers()
+                .get("link")
+                .map(|link| link.to_str().unwrap().to_owned());
+            interim2.lock().unwrap().push((res.status().as_u16(), link));
+        }));
+
+        let res = rt.block_on(client.send_request(req)).unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+        assert_eq!(*interim.lock().unwrap(), vec![
+            (103, Some("</style.css>; rel=preload".to_owned())),
+            (102, None),
+        ]);
+    }
+
+    #[test]
+    fn expect_continue_waits_for_100() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut rt = Runtime::new().unwrap();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut buf = [0; 4096];
+            let n = sock.read(&mut buf).expect("read 1");
+            let head = s(&buf[..n]);
+            assert!(head.contains("expect: 100-continue\r\n"), "{:?}", head);
+            assert!(head.ends_with("\r\n\r\n"), "body sent before 100 Continue: {:?}", head);
+
+            sock.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").unwrap();
+            let n = sock.read(&mut buf).expect("read 2");
+            assert_eq!(s(&buf[..n]), "hello");
+
+            sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+        });
+
+        let tcp = rt.block_on(tcp_connect(&addr)).unwrap();
+
+        let (mut client, conn) = rt.block_on(conn::handshake(tcp)).unwrap();
+
+        rt.spawn(conn.map_err(|e| panic!("conn error: {}", e)).map(|_| ()));
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/a")
+            .header("expect", "100-continue")
+            .body(Body::from("hello"))
+            .unwrap();
+
+        let res = rt.block_on(client.send_request(req)).unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+    }
+
+    #[test]
+    fn expect_continue_final_response_skips_body() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut rt = Runtime::new().unwrap();
+
+        let (tx1, rx1) = oneshot::channel();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut buf = [0; 4096];
+            sock.read(&mut buf).expect("read 1");
+            sock.write_all(b"\
+                HTTP/1.1 417 Expectation Failed\r\n\
+                Content-Length: 0\r\n\
+                \r\n\
+            ").unwrap();
+
+            // the body is never sent, and the connection can't be reused,
+            // since the server still expects the content-length bytes
+            let n = sock.read(&mut buf).expect("read 2");
+            assert_eq!(n, 0, "unexpected body: {:?}", s(&buf[..n]));
+            let _ = tx1.send(());
+        });
+
+        let tcp = rt.block_on(tcp_connect(&addr)).unwrap();
+
+        let (mut client, conn) = rt.block_on(conn::handshake(tcp)).unwrap();
+
+        rt.spawn(conn.map_err(|e| panic!("conn error: {}", e)).map(|_| ()));
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/a")
+            .header("expect", "100-continue")
+            .body(Body::from("hello"))
+            .unwrap();
+
+        let res = rt.block_on(client.send_request(req)).unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::EXPECTATION_FAILED);
+
+        rt.block_on(rx1.expect("thread panicked"));
+    }
+
+    #[test]
+    fn pipelined_requests() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut rt = Runtime::new().unwrap();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut buf = [0; 4096];
+            let mut reqs = String::new();
+            // both requests are sent before any response
+            while reqs.matches("\r\n\r\n").count() < 2 {
+                let n = sock.read(&mut buf).expect("read");
+                assert_ne!(n, 0, "eof before second request: {:?}", reqs);
+                reqs.push_str(s(&buf[..n]));
+            }
+            let a = reqs.find("GET /a ").expect("first request");
+            let b = reqs.find("GET /b ").expect("second request");
+            assert!(a < b, "requests out of order: {:?}", reqs);
+
+            sock.write_all(b"\
+                HTTP/1.1 200 OK\r\n\
+                Content-Length: 3\r\n\
+                \r\n\
+                one\
+                HTTP/1.1 200 OK\r\n\
+                Content-Length: 3\r\n\
+                \r\n\
+                two\
+            ").unwrap();
+        });
+
+        let tcp = rt.block_on(tcp_connect(&addr)).unwrap();
+
+        let (mut client, conn) = rt.block_on(conn::Builder::new()
+            .http1_pipeline_depth(2)
+            .handshake(tcp)).unwrap();
+
+        rt.spawn(conn.map_err(|e| panic!("conn error: {}", e)).map(|_| ()));
+
+        let req = Request::builder()
+            .uri("/a")
+            .body(Body::empty())
+            .unwrap();
+        let res1 = client.send_request(req);
+
+        rt.block_on(poll_fn(|cx| client.poll_ready(cx))).unwrap();
+        let req = Request::builder()
+            .uri("/b")
+            .body(Body::empty())
+            .unwrap();
+        let res2 = client.send_request(req);
+
+        let (res1, res2) = rt.block_on(future::join(res1, res2));
+        let body1 = rt.block_on(res1.unwrap().into_body().try_concat()).unwrap();
+        let body2 = rt.block_on(res2.unwrap().into_body().try_concat()).unwrap();
+        assert_eq!(body1.as_ref(), b"one");
+        assert_eq!(body2.as_ref(), b"two");
+    }
+
+    #[test]
+    fn pipelined_requests_canceled_when_connection_closes() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut rt = Runtime::new().unwrap();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut buf = [0; 4096];
+            let mut reqs = String::new();
+            while reqs.matches("\r\n\r\n").count() < 2 {
+                let n = sock.read(&mut buf).expect("read");
+                assert_ne!(n, 0, "eof before second request: {:?}", reqs);
+                reqs.push_str(s(&buf[..n]));
+            }
+
+            // only answer the first request
+            sock.write_all(b"\
+                HTTP/1.1 200 OK\r\n\
+                Content-Length: 3\r\n\
+                Connection: close\r\n\
+                \r\n\
+                one\
+            ").unwrap();
+        });
+
+        let tcp = rt.block_on(tcp_connect(&addr)).unwrap();
+
+        let (mut client, conn) = rt.block_on(conn::Builder::new()
+            .http1_pipeline_depth(2)
+            .handshake(tcp)).unwrap();
+
+        rt.spawn(conn.map_err(|e| panic!("conn error: {}", e)).map(|_| ()));
+
+        let req = Request::builder()
+            .uri("/a")
+            .body(Body::empty())
+            .unwrap();
+        let res1 = client.send_request(req);
+
+        rt.block_on(poll_fn(|cx| client.poll_ready(cx))).unwrap();
+        let req = Request::builder()
+            .uri("/b")
+            .body(Body::empty())
+            .unwrap();
+        let res2 = client.send_request(req);
+
+        let res1 = rt.block_on(res1).unwrap();
+        let body1 = rt.block_on(res1.into_body().try_concat()).unwrap();
+        assert_eq!(body1.as_ref(), b"one");
+
+        let err = rt.block_on(res2).unwrap_err();
+        assert!(err.is_canceled(), "{:?}", err);
+    }
+
     #[test]
     fn incoming_content_length() {
         use hyper::body::Payload;
@@ -2145,6 +2582,51 @@ mod conn {
         rt.block_on(future::poll_fn(|ctx| client.poll_ready(ctx))).expect_err("client should be closed");
     }
 
+    #[test]
+    fn http2_on_informational_is_user_error() {
+        use futures_util::future;
+        use hyper::{Response, Server};
+        use hyper::ext::OnInformational;
+        use hyper::service::{make_service_fn, service_fn};
+
+        let _ = pretty_env_logger::try_init();
+
+        let mut rt = Runtime::new().unwrap();
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into())
+            .http2_only(true)
+            .serve(make_service_fn(|_| async move {
+                Ok::<_, hyper::Error>(service_fn(|_req| future::ok::<_, hyper::Error>(Response::new(Body::empty()))))
+            }));
+        let addr = server.local_addr();
+        rt.spawn(server.map(|_| ()));
+
+        let io = rt.block_on(tcp_connect(&addr)).expect("tcp connect");
+        let (mut client, conn) = rt.block_on(
+            conn::Builder::new().http2_only(true).handshake::<_, Body>(io)
+        ).expect("http handshake");
+
+        rt.spawn(conn
+            .map_err(|e| panic!("client conn error: {:?}", e))
+            .map(|_| ()));
+
+        let mut req = Request::builder()
+            .uri(format!("http://{}/", addr))
+            .body(Body::empty())
+            .expect("request builder");
+        req.extensions_mut().insert(OnInformational::new(|_| ()));
+
+        let err = rt.block_on(client.send_request(req)).expect_err("req1 send");
+        assert!(err.is_user(), "{:?}", err);
+
+        // the connection is still usable
+        let req = Request::builder()
+            .uri(format!("http://{}/", addr))
+            .body(Body::empty())
+            .expect("request builder");
+        rt.block_on(client.send_request(req)).expect("req2 send");
+    }
+
     struct DebugStream {
         tcp: TcpStream,
         shutdown_called: bool,
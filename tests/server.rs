@@ -734,6 +734,188 @@ fn expect_continue_sends_100() {
     assert_eq!(body, msg);
 }
 
+#[test]
+fn expect_continue_rejected_with_417_without_reading_body() {
+    let _ = pretty_env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(b"\
+            POST /upload HTTP/1.1\r\n\
+            Host: example.domain\r\n\
+            Expect: 100-continue\r\n\
+            Content-Length: 5\r\n\
+            \r\n\
+        ").expect("write");
+        let mut buf = [0; 256];
+        let n = tcp.read(&mut buf).expect("read");
+        let expected = "HTTP/1.1 417 Expectation Failed\r\n";
+        assert_eq!(s(&buf[..expected.len().min(n)]), expected);
+    });
+
+    let mut incoming = listener.incoming();
+    let fut = incoming.next()
+        .map(Option::unwrap)
+        .map_err(|_| unreachable!())
+        .and_then(|socket| {
+            Http::new().serve_connection(socket, service_fn(|req: Request<Body>| {
+                assert_eq!(req.headers()["expect"], "100-continue");
+                let res = Response::builder()
+                    .status(StatusCode::EXPECTATION_FAILED)
+                    .body(Body::empty())
+                    .unwrap();
+                future::ok::<_, hyper::Error>(res)
+            }))
+        });
+
+    rt.block_on(fut).unwrap();
+}
+
+#[test]
+fn informational_responses_before_final_response() {
+    use hyper::ext::Informational;
+
+    let _ = pretty_env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(b"\
+            POST / HTTP/1.1\r\n\
+            Host: example.domain\r\n\
+            Expect: 100-continue\r\n\
+            Content-Length: 5\r\n\
+            Connection: close\r\n\
+            \r\n\
+        ").expect("write 1");
+
+        let msg = b"HTTP/1.1 100 Continue\r\n\r\n";
+        let mut buf = vec![0; msg.len()];
+        tcp.read_exact(&mut buf).expect("read 1");
+        assert_eq!(buf, msg);
+
+        tcp.write_all(b"hello").expect("write 2");
+        let mut response = String::new();
+        tcp.read_to_string(&mut response).expect("read 2");
+
+        // the manual 100 Continue replaced the automatic one
+        assert!(!response.contains("100 Continue"), "{:?}", response);
+        assert!(response.starts_with("HTTP/1.1 103 "), "{:?}", response);
+        let hints = "\r\nlink: </style.css>; rel=preload; as=style\r\n\r\nHTTP/1.1 200 OK\r\n";
+        assert!(response.contains(hints), "{:?}", response);
+        assert!(response.ends_with("\r\n\r\nhello"), "{:?}", response);
+    });
+
+    let mut incoming = listener.incoming();
+    let fut = incoming.next()
+        .map(Option::unwrap)
+        .map_err(|_| unreachable!())
+        .and_then(|socket| {
+            Http::new().serve_connection(socket, service_fn(|req: Request<Body>| {
+                let info = req
+                    .extensions()
+                    .get::<Informational>()
+                    .expect("Informational")
+                    .clone();
+                info.send(Response::builder().status(100).body(()).unwrap())
+                    .expect("send 100");
+                info.send(Response::builder().status(200).body(()).unwrap())
+                    .expect_err("200 isn't informational");
+
+                req.into_body()
+                    .try_concat()
+                    .map_ok(move |body| {
+                        let hints = Response::builder()
+                            .status(103)
+                            .header("link", "</style.css>; rel=preload; as=style")
+                            .body(())
+                            .unwrap();
+                        info.send(hints).expect("send 103");
+                        Response::new(Body::from(body))
+                    })
+            }))
+        });
+
+    rt.block_on(fut).unwrap();
+}
+
+#[test]
+fn informational_response_to_http10_is_error() {
+    use hyper::ext::Informational;
+
+    let _ = pretty_env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(b"\
+            GET / HTTP/1.0\r\n\
+            \r\n\
+        ").expect("write 1");
+
+        let mut response = String::new();
+        tcp.read_to_string(&mut response).expect("read 1");
+        assert!(response.starts_with("HTTP/1.0 200 OK\r\n"), "{:?}", response);
+    });
+
+    let mut incoming = listener.incoming();
+    let fut = incoming.next()
+        .map(Option::unwrap)
+        .map_err(|_| unreachable!())
+        .and_then(|socket| {
+            Http::new().serve_connection(socket, service_fn(|req: Request<Body>| {
+                let err = req
+                    .extensions()
+                    .get::<Informational>()
+                    .expect("Informational")
+                    .send(Response::builder().status(103).body(()).unwrap())
+                    .expect_err("HTTP/1.0 can't have informational responses");
+                assert!(err.is_user(), "{:?}", err);
+                future::ok::<_, hyper::Error>(Response::new(Body::empty()))
+            }))
+        });
+
+    rt.block_on(fut).unwrap();
+}
+
+#[test]
+fn informational_response_over_http2_is_error() {
+    use hyper::ext::Informational;
+
+    let _ = pretty_env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+
+    let server = Server::bind(&([127, 0, 0, 1], 0).into())
+        .http2_only(true)
+        .serve(make_service_fn(|_| {
+            future::ok::<_, BoxError>(service_fn(|req: Request<Body>| {
+                let err = req
+                    .extensions()
+                    .get::<Informational>()
+                    .expect("Informational")
+                    .send(Response::builder().status(103).body(()).unwrap())
+                    .expect_err("HTTP/2 informational responses aren't supported");
+                assert!(err.is_user(), "{:?}", err);
+                future::ok::<_, BoxError>(Response::new(Body::empty()))
+            }))
+        }));
+    let uri = format!("http://{}", server.local_addr()).parse().expect("server addr should parse");
+    rt.spawn(server.map(|_| ()));
+
+    let client = Client::builder()
+        .http2_only(true)
+        .build_http::<hyper::Body>();
+    let res = rt.block_on(client.get(uri)).expect("client get");
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
 #[test]
 fn pipeline_disabled() {
     let server = serve();
@@ -1430,34 +1612,43 @@ fn illegal_request_length_returns_400_response() {
 }
 
 #[test]
-#[should_panic]
-fn max_buf_size_panic_too_small() {
-    const MAX: usize = 8191;
-    Http::new().max_buf_size(MAX);
-}
-#[test]
-fn max_buf_size_no_panic() {
-    const MAX: usize = 8193;
-    Http::new().max_buf_size(MAX);
+fn unknown_transfer_encoding_returns_501_response() {
+    let mut rt = Runtime::new().unwrap();
+    let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(b"POST / HTTP/1.1\r\nTransfer-Encoding: foo, chunked\r\n\r\n").unwrap();
+        let mut buf = [0; 256];
+        tcp.read(&mut buf).unwrap();
+
+        let expected = "HTTP/1.1 501 ";
+        assert_eq!(s(&buf[..expected.len()]), expected);
+    });
+
+    let mut incoming = listener.incoming();
+    let fut = incoming.next()
+        .map(Option::unwrap)
+        .map_err(|_| unreachable!())
+        .and_then(|socket| Http::new().serve_connection(socket, HelloWorld));
+
+    rt.block_on(fut).expect_err("unknown Transfer-Encoding should error");
 }
 
 #[test]
-fn max_buf_size() {
-    let _ = pretty_env_logger::try_init();
+fn unsupported_version_returns_505_response() {
     let mut rt = Runtime::new().unwrap();
     let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
     let addr = listener.local_addr().unwrap();
 
-    const MAX: usize = 16_000;
-
     thread::spawn(move || {
         let mut tcp = connect(&addr);
-        tcp.write_all(b"POST /").expect("write 1");
-        tcp.write_all(&vec![b'a'; MAX]).expect("write 2");
+        tcp.write_all(b"GET / HTTP/1.2\r\n\r\n").unwrap();
         let mut buf = [0; 256];
-        tcp.read(&mut buf).expect("read 1");
+        tcp.read(&mut buf).unwrap();
 
-        let expected = "HTTP/1.1 431 ";
+        let expected = "HTTP/1.1 505 ";
         assert_eq!(s(&buf[..expected.len()]), expected);
     });
 
@@ -1465,105 +1656,498 @@ fn max_buf_size() {
     let fut = incoming.next()
         .map(Option::unwrap)
         .map_err(|_| unreachable!())
-        .and_then(|socket| {
-            Http::new()
-                .max_buf_size(MAX)
-                .serve_connection(socket, HelloWorld)
-        });
+        .and_then(|socket| Http::new().serve_connection(socket, HelloWorld));
 
-    rt.block_on(fut).expect_err("should TooLarge error");
+    rt.block_on(fut).expect_err("unsupported version should error");
 }
 
 #[test]
-fn streaming_body() {
+fn strict_framing_rejects_smuggling_corpus() {
     let _ = pretty_env_logger::try_init();
 
-    // disable keep-alive so we can use read_to_end
-    let server = serve_opts()
-        .keep_alive(false)
-        .serve();
-
-    static S: &'static [&'static [u8]] = &[&[b'x'; 1_000] as &[u8]; 1_00] as _;
-    let b = ::futures_util::stream::iter(S.into_iter())
-        .map(|&s| Ok::<_, hyper::Error>(s));
-    let b = hyper::Body::wrap_stream(b);
-    server
-        .reply()
-        .body_stream(b);
+    // Each payload is followed by a request that would be smuggled if the
+    // framing of the first were misread.
+    let corpus: Vec<(&str, Vec<u8>)> = vec![
+        ("CL.TE", b"\
+            POST / HTTP/1.1\r\n\
+            Host: a\r\n\
+            Content-Length: 6\r\n\
+            Transfer-Encoding: chunked\r\n\
+            \r\n\
+            0\r\n\
+            \r\n\
+            G".to_vec()),
+        ("TE.CL", b"\
+            POST / HTTP/1.1\r\n\
+            Host: a\r\n\
+            Transfer-Encoding: chunked\r\n\
+            Content-Length: 3\r\n\
+            \r\n\
+            8\r\n\
+            SMUGGLED\r\n\
+            0\r\n\
+            \r\n".to_vec()),
+        ("TE non-final chunked", b"\
+            POST / HTTP/1.1\r\n\
+            Host: a\r\n\
+            Transfer-Encoding: chunked, identity\r\n\
+            \r\n\
+            0\r\n\
+            \r\n".to_vec()),
+        ("TE repeated chunked", b"\
+            POST / HTTP/1.1\r\n\
+            Host: a\r\n\
+            Transfer-Encoding: chunked\r\n\
+            Transfer-Encoding: chunked\r\n\
+            \r\n\
+            0\r\n\
+            \r\n".to_vec()),
+        ("CL differing duplicates", b"\
+            POST / HTTP/1.1\r\n\
+            Host: a\r\n\
+            Content-Length: 0\r\n\
+            Content-Length: 44\r\n\
+            \r\n\
+            GET /smuggled HTTP/1.1\r\n\
+            Host: a\r\n\
+            \r\n".to_vec()),
+        ("CL with sign", b"\
+            POST / HTTP/1.1\r\n\
+            Host: a\r\n\
+            Content-Length: +4\r\n\
+            \r\n\
+            abcd".to_vec()),
+        ("chunk size whitespace", b"\
+            POST / HTTP/1.1\r\n\
+            Host: a\r\n\
+            Transfer-Encoding: chunked\r\n\
+            \r\n\
+            1 \r\n\
+            a\r\n\
+            0\r\n\
+            \r\n".to_vec()),
+        ("chunk extensions oversized", format!("\
+            POST / HTTP/1.1\r\n\
+            Host: a\r\n\
+            Transfer-Encoding: chunked\r\n\
+            \r\n\
+            1;{}\r\n\
+            a\r\n\
+            0\r\n\
+            \r\n\
+        ", "a".repeat(2048)).into_bytes()),
+    ];
 
-    let mut tcp = connect(server.addr());
-    tcp.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
-    let mut buf = Vec::new();
-    tcp.read_to_end(&mut buf).expect("read 1");
+    let mut rt = Runtime::new().unwrap();
+    for (name, payload) in corpus {
+        let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let mut tcp = connect(&addr);
+            tcp.write_all(&payload).unwrap();
+            let mut response = Vec::new();
+            let _ = tcp.read_to_end(&mut response);
+            s(&response).to_owned()
+        });
 
-    assert!(buf.starts_with(b"HTTP/1.1 200 OK\r\n"), "response is 200 OK");
-    assert_eq!(buf.len(), 100_789, "full streamed body read");
+        let mut incoming = listener.incoming();
+        let fut = incoming.next()
+            .map(Option::unwrap)
+            .map_err(|_| unreachable!())
+            .and_then(|socket| {
+                Http::new()
+                    .http1_strict_framing(true)
+                    .serve_connection(socket, service_fn(|req: Request<Body>| {
+                        req.into_body()
+                            .try_concat()
+                            .map_ok(|body| Response::new(Body::from(body)))
+                    }))
+            });
+        rt.block_on(fut).expect_err(name);
+
+        let response = client.join().unwrap();
+        assert!(!response.contains(" 200 OK"), "{}: {:?}", name, response);
+    }
 }
 
 #[test]
-fn http1_response_with_http2_version() {
-    let server = serve();
-    let addr_str = format!("http://{}", server.addr());
+fn http1_keep_alive_timeout_advertised_and_enforced() {
+    let _ = pretty_env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
 
-    let mut rt = Runtime::new().expect("runtime new");
+    let client = thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        tcp.write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").expect("write");
+        let mut buf = [0; 1024];
+        let n = tcp.read(&mut buf).expect("read 1");
+        let response = s(&buf[..n]).to_owned();
+        assert!(response.contains("\r\nkeep-alive: timeout=1\r\n"), "{:?}", response);
+
+        // the idle connection is closed once the timeout elapses
+        let idle_at = Instant::now();
+        let n = tcp.read(&mut buf).expect("read 2");
+        assert_eq!(n, 0);
+        assert!(idle_at.elapsed() >= Duration::from_millis(500), "closed too soon");
+    });
 
-    server
-        .reply()
-        .version(hyper::Version::HTTP_2);
+    let mut incoming = listener.incoming();
+    let fut = incoming.next()
+        .map(Option::unwrap)
+        .map_err(|_| unreachable!())
+        .and_then(|socket| {
+            Http::new()
+                .http1_keep_alive_timeout(Duration::from_secs(1))
+                .http1_keep_alive_header(true)
+                .serve_connection(socket, service_fn(|_| {
+                    future::ok::<_, hyper::Error>(Response::new(Body::empty()))
+                }))
+        });
+    rt.block_on(fut).expect("serve_connection");
 
-    rt.block_on({
-        let client = Client::new();
-        let uri = addr_str.parse().expect("server addr should parse");
-        client.get(uri)
-    }).unwrap();
+    client.join().unwrap();
 }
 
 #[test]
-fn try_h2() {
-    let server = serve();
-    let addr_str = format!("http://{}", server.addr());
-
-    let mut rt = Runtime::new().expect("runtime new");
+fn max_request_body_size_rejects_content_length() {
+    let _ = pretty_env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
 
-    rt.block_on({
-        let client = Client::builder()
-            .http2_only(true)
-            .build_http::<hyper::Body>();
-        let uri = addr_str.parse().expect("server addr should parse");
+    let client = thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 100\r\n\r\n").expect("write");
+        let mut buf = vec![];
+        tcp.read_to_end(&mut buf).expect("read");
+        let response = s(&buf);
+        assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"), "{:?}", response);
+    });
 
-        client
-            .get(uri)
-            .map_ok(|_| { () })
-            .map_err(|_e| { () })
-    }).unwrap();
+    let called = Arc::new(AtomicBool::new(false));
+    let called2 = called.clone();
+    let mut incoming = listener.incoming();
+    let fut = incoming.next()
+        .map(Option::unwrap)
+        .map_err(|_| unreachable!())
+        .and_then(|socket| {
+            Http::new()
+                .max_request_body_size(10)
+                .serve_connection(socket, service_fn(move |_| {
+                    called2.store(true, Ordering::SeqCst);
+                    future::ok::<_, hyper::Error>(Response::new(Body::empty()))
+                }))
+        });
+    let err = rt.block_on(fut).expect_err("serve_connection");
+    assert!(err.is_body_too_large(), "{:?}", err);
+    assert!(!called.load(Ordering::SeqCst), "service called");
 
-    assert_eq!(server.body(), b"");
+    client.join().unwrap();
 }
 
 #[test]
-fn http1_only() {
-    let server = serve_opts()
-        .http1_only()
-        .serve();
-    let addr_str = format!("http://{}", server.addr());
+fn max_request_body_size_errors_chunked_body() {
+    let _ = pretty_env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
 
-    let mut rt = Runtime::new().expect("runtime new");
+    let client = thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(b"\
+            POST / HTTP/1.1\r\n\
+            Host: a\r\n\
+            Transfer-Encoding: chunked\r\n\
+            \r\n\
+            5\r\nhello\r\n\
+            6\r\n world\r\n\
+            0\r\n\r\n\
+        ").expect("write");
+        let mut buf = vec![];
+        tcp.read_to_end(&mut buf).expect("read");
+        let response = s(&buf);
+        assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"), "{:?}", response);
+    });
 
-    rt.block_on({
-        let client = Client::builder()
-            .http2_only(true)
-            .build_http::<hyper::Body>();
-        let uri = addr_str.parse().expect("server addr should parse");
-        client.get(uri)
-    }).unwrap_err();
+    let mut incoming = listener.incoming();
+    let fut = incoming.next()
+        .map(Option::unwrap)
+        .map_err(|_| unreachable!())
+        .and_then(|socket| {
+            Http::new()
+                .max_request_body_size(10)
+                .serve_connection(socket, service_fn(|req: Request<Body>| async move {
+                    let err = hyper::body::to_bytes(req.into_body(), 1024)
+                        .await
+                        .expect_err("body over limit");
+                    assert!(err.is_body_too_large(), "{:?}", err);
+                    let mut res = Response::new(Body::empty());
+                    *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+                    Ok::<_, hyper::Error>(res)
+                }))
+        });
+    rt.block_on(fut).expect("serve_connection");
+
+    client.join().unwrap();
 }
 
 #[test]
-fn http2_service_error_sends_reset_reason() {
-    use std::error::Error;
-
-    let server = serve();
-    let addr_str = format!("http://{}", server.addr());
+fn write_rate_limit_paces_responses() {
+    use hyper::rt::RateLimit;
+
+    let _ = pretty_env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let client = thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        let start = Instant::now();
+        tcp.write_all(b"GET / HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n").expect("write");
+        let mut buf = vec![];
+        tcp.read_to_end(&mut buf).expect("read");
+        assert!(s(&buf).ends_with(&"x".repeat(3_000)));
+        // 1,000 bytes at once, then the other ~2,000 at 20,000 per second
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(80), "{:?}", elapsed);
+    });
+
+    let mut incoming = listener.incoming();
+    let fut = incoming.next()
+        .map(Option::unwrap)
+        .map_err(|_| unreachable!())
+        .and_then(|socket| {
+            Http::new()
+                .write_rate_limit(RateLimit::with_burst(20_000, 1_000))
+                .serve_connection(socket, service_fn(|_| {
+                    future::ok::<_, hyper::Error>(Response::new(Body::from(vec![b'x'; 3_000])))
+                }))
+        });
+    rt.block_on(fut).expect("serve_connection");
+
+    client.join().unwrap();
+}
+
+#[test]
+fn error_response_hook_builds_parse_error_response() {
+    use bytes::Bytes;
+
+    let _ = pretty_env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    const MAX: usize = 16_000;
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(b"GET /").expect("write 1");
+        tcp.write_all(&vec![b'a'; MAX]).expect("write 2");
+        let mut buf = [0; 1024];
+        let n = tcp.read(&mut buf).expect("read");
+        let response = s(&buf[..n]);
+
+        let expected = "HTTP/1.1 414 URI Too Long\r\n";
+        assert!(response.starts_with(expected), "{:?}", response);
+        assert!(response.contains("\r\nx-error: URI too long\r\n"), "{:?}", response);
+        assert!(response.contains("\r\ncontent-length: 13\r\n"), "{:?}", response);
+        assert!(response.ends_with("\r\n\r\nuri too long!"), "{:?}", response);
+    });
+
+    let mut incoming = listener.incoming();
+    let fut = incoming.next()
+        .map(Option::unwrap)
+        .map_err(|_| unreachable!())
+        .and_then(|socket| {
+            Http::new()
+                .max_buf_size(MAX)
+                .error_response(|err, status| {
+                    Response::builder()
+                        .status(status)
+                        .header("x-error", err.to_string())
+                        .body(Bytes::from_static(b"uri too long!"))
+                        .unwrap()
+                })
+                .serve_connection(socket, HelloWorld)
+        });
+
+    rt.block_on(fut).expect_err("should UriTooLong error");
+}
+
+#[test]
+#[should_panic]
+fn max_buf_size_panic_too_small() {
+    const MAX: usize = 8191;
+    Http::new().max_buf_size(MAX);
+}
+#[test]
+fn max_buf_size_no_panic() {
+    const MAX: usize = 8193;
+    Http::new().max_buf_size(MAX);
+}
+
+#[test]
+fn max_buf_size() {
+    let _ = pretty_env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    const MAX: usize = 16_000;
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(b"POST /").expect("write 1");
+        tcp.write_all(&vec![b'a'; MAX]).expect("write 2");
+        let mut buf = [0; 256];
+        tcp.read(&mut buf).expect("read 1");
+
+        // not even the request line fit in the buffer
+        let expected = "HTTP/1.1 414 ";
+        assert_eq!(s(&buf[..expected.len()]), expected);
+    });
+
+    let mut incoming = listener.incoming();
+    let fut = incoming.next()
+        .map(Option::unwrap)
+        .map_err(|_| unreachable!())
+        .and_then(|socket| {
+            Http::new()
+                .max_buf_size(MAX)
+                .serve_connection(socket, HelloWorld)
+        });
+
+    let err = rt.block_on(fut).expect_err("should UriTooLong error");
+    assert!(err.is_uri_too_long(), "{:?}", err);
+}
+
+#[test]
+fn max_buf_size_headers() {
+    let _ = pretty_env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    const MAX: usize = 16_000;
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(b"POST / HTTP/1.1\r\nx-long: ").expect("write 1");
+        tcp.write_all(&vec![b'a'; MAX]).expect("write 2");
+        let mut buf = [0; 256];
+        tcp.read(&mut buf).expect("read 1");
+
+        let expected = "HTTP/1.1 431 ";
+        assert_eq!(s(&buf[..expected.len()]), expected);
+    });
+
+    let mut incoming = listener.incoming();
+    let fut = incoming.next()
+        .map(Option::unwrap)
+        .map_err(|_| unreachable!())
+        .and_then(|socket| {
+            Http::new()
+                .max_buf_size(MAX)
+                .serve_connection(socket, HelloWorld)
+        });
+
+    let err = rt.block_on(fut).expect_err("should TooLarge error");
+    assert!(err.is_head_too_large(), "{:?}", err);
+}
+
+#[test]
+fn streaming_body() {
+    let _ = pretty_env_logger::try_init();
+
+    // disable keep-alive so we can use read_to_end
+    let server = serve_opts()
+        .keep_alive(false)
+        .serve();
+
+    static S: &'static [&'static [u8]] = &[&[b'x'; 1_000] as &[u8]; 1_00] as _;
+    let b = ::futures_util::stream::iter(S.into_iter())
+        .map(|&s| Ok::<_, hyper::Error>(s));
+    let b = hyper::Body::wrap_stream(b);
+    server
+        .reply()
+        .body_stream(b);
+
+    let mut tcp = connect(server.addr());
+    tcp.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut buf = Vec::new();
+    tcp.read_to_end(&mut buf).expect("read 1");
+
+    assert!(buf.starts_with(b"HTTP/1.1 200 OK\r\n"), "response is 200 OK");
+    assert_eq!(buf.len(), 100_789, "full streamed body read");
+}
+
+#[test]
+fn http1_response_with_http2_version() {
+    let server = serve();
+    let addr_str = format!("http://{}", server.addr());
+
+    let mut rt = Runtime::new().expect("runtime new");
+
+    server
+        .reply()
+        .version(hyper::Version::HTTP_2);
+
+    rt.block_on({
+        let client = Client::new();
+        let uri = addr_str.parse().expect("server addr should parse");
+        client.get(uri)
+    }).unwrap();
+}
+
+#[test]
+fn try_h2() {
+    let server = serve();
+    let addr_str = format!("http://{}", server.addr());
+
+    let mut rt = Runtime::new().expect("runtime new");
+
+    rt.block_on({
+        let client = Client::builder()
+            .http2_only(true)
+            .build_http::<hyper::Body>();
+        let uri = addr_str.parse().expect("server addr should parse");
+
+        client
+            .get(uri)
+            .map_ok(|_| { () })
+            .map_err(|_e| { () })
+    }).unwrap();
+
+    assert_eq!(server.body(), b"");
+}
+
+#[test]
+fn http1_only() {
+    let server = serve_opts()
+        .http1_only()
+        .serve();
+    let addr_str = format!("http://{}", server.addr());
+
+    let mut rt = Runtime::new().expect("runtime new");
+
+    rt.block_on({
+        let client = Client::builder()
+            .http2_only(true)
+            .build_http::<hyper::Body>();
+        let uri = addr_str.parse().expect("server addr should parse");
+        client.get(uri)
+    }).unwrap_err();
+}
+
+#[test]
+fn http2_service_error_sends_reset_reason() {
+    use std::error::Error;
+
+    let server = serve();
+    let addr_str = format!("http://{}", server.addr());
 
     server
         .reply()
@@ -1626,6 +2210,75 @@ fn http2_body_user_error_sends_reset_reason() {
     assert_eq!(h2_err.reason(), Some(h2::Reason::INADEQUATE_SECURITY));
 }
 
+#[test]
+fn http2_body_channel_sends_trailers() {
+    use hyper::body::Payload;
+
+    let server = serve();
+    let addr_str = format!("http://{}", server.addr());
+
+    let (mut tx, body) = Body::channel();
+    tx.send_data("hello".into()).expect("send_data");
+    let mut trailers = hyper::HeaderMap::new();
+    trailers.insert("grpc-status", HeaderValue::from_static("0"));
+    tx.send_trailers(trailers).expect("send_trailers");
+    tx.send_trailers(hyper::HeaderMap::new()).expect_err("trailers already sent");
+    drop(tx);
+
+    server
+        .reply()
+        .body_stream(body);
+
+    let mut rt = Runtime::new().expect("runtime new");
+
+    let (data, trailers) = rt.block_on(async {
+        let client = Client::builder()
+            .http2_only(true)
+            .build_http::<hyper::Body>();
+        let uri = addr_str.parse().expect("server addr should parse");
+
+        let mut body = client.get(uri).await?.into_body();
+        let mut data = Vec::new();
+        while let Some(chunk) = body.next().await {
+            data.extend_from_slice(&chunk?);
+        }
+        let trailers = future::poll_fn(|cx| Pin::new(&mut body).poll_trailers(cx)).await;
+        Ok::<_, hyper::Error>((data, trailers))
+    }).expect("response");
+
+    assert_eq!(data, b"hello");
+    let trailers = trailers.expect("trailers").expect("trailers ok");
+    assert_eq!(trailers["grpc-status"], "0");
+}
+
+#[test]
+fn http1_body_channel_sends_chunked_trailers() {
+    let server = serve();
+
+    let (mut tx, body) = Body::channel();
+    tx.send_data("hello".into()).expect("send_data");
+    let mut trailers = hyper::HeaderMap::new();
+    trailers.insert("grpc-status", HeaderValue::from_static("0"));
+    tx.send_trailers(trailers).expect("send_trailers");
+    drop(tx);
+
+    server
+        .reply()
+        .body_stream(body);
+
+    let mut req = connect(server.addr());
+    req.write_all(b"\
+        GET / HTTP/1.1\r\n\
+        Host: example.domain\r\n\
+        Connection: close\r\n\
+        \r\n\
+    ").expect("writing");
+
+    read_until(&mut req, |buf| {
+        buf.ends_with(b"\r\n5\r\nhello\r\n0\r\ngrpc-status: 0\r\n\r\n")
+    }).expect("reading");
+}
+
 struct Svc;
 
 impl tower_service::Service<Request<Body>> for Svc {
@@ -1680,6 +2333,160 @@ fn http2_service_poll_ready_error_sends_goaway() {
     assert_eq!(h2_err.reason(), Some(h2::Reason::INADEQUATE_SECURITY));
 }
 
+#[test]
+fn http2_error_response_hook_for_content_length_over_limit() {
+    use bytes::Bytes;
+
+    let _ = pretty_env_logger::try_init();
+
+    let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into())
+        .http2_only(true)
+        .max_request_body_size(10)
+        .error_response(|err, status| {
+            Response::builder()
+                .status(status)
+                .header("x-error", err.to_string())
+                .body(Bytes::from_static(b"too big!"))
+                .unwrap()
+        })
+        .serve(make_service_fn(|_| async move {
+            Ok::<_, BoxError>(service_fn(|_req: Request<Body>| -> future::Ready<Result<Response<Body>, hyper::Error>> {
+                unreachable!("service shouldn't be called")
+            }))
+        }));
+
+    let addr_str = format!("http://{}", server.local_addr());
+
+    let mut rt = Runtime::new().expect("runtime new");
+
+    rt.spawn(server
+        .map_err(|e| unreachable!("server shouldn't error: {:?}", e))
+        .map(|_| ()));
+
+    let res = rt.block_on({
+        let client = Client::builder()
+            .http2_only(true)
+            .build_http::<hyper::Body>();
+        let req = Request::post(addr_str)
+            .body(Body::from(vec![b'a'; 100]))
+            .expect("request builder");
+        client.request(req)
+    }).expect("client request");
+
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(res.headers().contains_key("x-error"), "{:?}", res.headers());
+    let body = rt.block_on(res.into_body().try_concat()).expect("body");
+    assert_eq!(body.as_ref(), b"too big!");
+}
+
+#[test]
+fn http2_max_request_body_size_responds_413() {
+    let _ = pretty_env_logger::try_init();
+
+    let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into())
+        .http2_only(true)
+        .max_request_body_size(10)
+        .serve(make_service_fn(|_| async move {
+            Ok::<_, BoxError>(service_fn(|req: Request<Body>| async move {
+                let err = hyper::body::to_bytes(req.into_body(), 1024)
+                    .await
+                    .expect_err("body over limit");
+                assert!(err.is_body_too_large(), "{:?}", err);
+                // never sent, the response hadn't started yet
+                Ok::<_, hyper::Error>(Response::new(Body::from("unreachable")))
+            }))
+        }));
+
+    let addr_str = format!("http://{}", server.local_addr());
+
+    let mut rt = Runtime::new().expect("runtime new");
+
+    rt.spawn(server
+        .map_err(|e| unreachable!("server shouldn't error: {:?}", e))
+        .map(|_| ()));
+
+    // no content-length, so the server only finds out while reading
+    let b = ::futures_util::stream::iter(vec![
+        Ok::<_, BoxError>("hello"),
+        Ok(" world"),
+    ]);
+    let body = hyper::Body::wrap_stream(b);
+
+    let res = rt.block_on({
+        let client = Client::builder()
+            .http2_only(true)
+            .build_http::<hyper::Body>();
+        let req = Request::post(addr_str)
+            .body(body)
+            .expect("request builder");
+        client.request(req)
+    }).expect("client request");
+
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let body = rt.block_on(res.into_body().try_concat()).expect("body");
+    assert!(body.is_empty(), "{:?}", body);
+}
+
+#[test]
+fn http2_max_request_body_size_resets_started_response() {
+    use std::error::Error;
+
+    let _ = pretty_env_logger::try_init();
+
+    let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into())
+        .http2_only(true)
+        .max_request_body_size(10)
+        .serve(make_service_fn(|_| async move {
+            Ok::<_, BoxError>(service_fn(|req: Request<Body>| async move {
+                // respond first, then read the body
+                let (tx, body) = Body::channel();
+                tokio::spawn(async move {
+                    let err = hyper::body::to_bytes(req.into_body(), 1024)
+                        .await
+                        .expect_err("body over limit");
+                    assert!(err.is_body_too_large(), "{:?}", err);
+                    drop(tx);
+                });
+                Ok::<_, hyper::Error>(Response::new(body))
+            }))
+        }));
+
+    let addr_str = format!("http://{}", server.local_addr());
+
+    let mut rt = Runtime::new().expect("runtime new");
+
+    rt.spawn(server
+        .map_err(|e| unreachable!("server shouldn't error: {:?}", e))
+        .map(|_| ()));
+
+    let b = ::futures_util::stream::iter(vec![
+        Ok::<_, BoxError>("hello"),
+        Ok(" world"),
+    ]);
+    let body = hyper::Body::wrap_stream(b);
+
+    let res = rt.block_on({
+        let client = Client::builder()
+            .http2_only(true)
+            .build_http::<hyper::Body>();
+        let req = Request::post(addr_str)
+            .body(body)
+            .expect("request builder");
+        client.request(req)
+    }).expect("client request");
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let err = rt.block_on(res.into_body().try_concat()).unwrap_err();
+
+    let h2_err = err
+        .source()
+        .expect("source")
+        .downcast_ref::<h2::Error>()
+        .expect("downcast");
+
+    assert_eq!(h2_err.reason(), Some(h2::Reason::ENHANCE_YOUR_CALM));
+}
+
 #[test]
 fn skips_content_length_for_304_responses() {
     let server = serve();
@@ -1725,6 +2532,401 @@ fn skips_content_length_and_body_for_304_responses() {
     assert_eq!(lines.next(), Some(""));
     assert_eq!(lines.next(), None);
 }
+
+#[test]
+fn proxy_protocol_overrides_remote_addr() {
+    use hyper::server::conn::{AddrIncoming, ProxyStream};
+
+    let _ = pretty_env_logger::try_init();
+    let (addr_tx, addr_rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut incoming = AddrIncoming::bind(&([127, 0, 0, 1], 0).into())
+            .expect("bind")
+            .proxy_protocol();
+        incoming.set_strict(true);
+        addr_tx.send(incoming.local_addr()).expect("server addr tx");
+
+        let server = Server::builder(incoming)
+            .serve(make_service_fn(|conn: &ProxyStream| {
+                let remote_addr = conn.remote_addr();
+                future::ok::<_, BoxError>(service_fn(move |_req| {
+                    future::ok::<_, BoxError>(Response::new(Body::from(remote_addr.to_string())))
+                }))
+            }));
+
+        let mut rt = Runtime::new().expect("rt new");
+        rt.block_on(server).unwrap();
+    });
+
+    let addr = addr_rx.recv().expect("server addr rx");
+
+    // no header in strict mode closes the connection
+    let mut req = connect(&addr);
+    req.write_all(b"\
+        GET / HTTP/1.1\r\n\
+        Host: example.domain\r\n\
+        Connection: close\r\n\
+        \r\n\
+    ").unwrap();
+    let mut response = String::new();
+    req.read_to_string(&mut response).unwrap();
+    assert_eq!(response, "");
+
+    let mut req = connect(&addr);
+    req.write_all(b"\
+        PROXY TCP4 192.168.0.1 10.0.0.1 56324 443\r\n\
+        GET / HTTP/1.1\r\n\
+        Host: example.domain\r\n\
+        Connection: close\r\n\
+        \r\n\
+    ").unwrap();
+    let mut response = String::new();
+    req.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{:?}", response);
+    assert!(response.ends_with("192.168.0.1:56324"), "{:?}", response);
+}
+
+#[test]
+fn proxy_protocol_handshake_timeout_closes_connection() {
+    use hyper::server::conn::{AddrIncoming, ProxyStream};
+
+    let _ = pretty_env_logger::try_init();
+    let (addr_tx, addr_rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut incoming = AddrIncoming::bind(&([127, 0, 0, 1], 0).into())
+            .expect("bind")
+            .proxy_protocol();
+        incoming.set_handshake_timeout(Some(Duration::from_millis(100)));
+        addr_tx.send(incoming.local_addr()).expect("server addr tx");
+
+        let server = Server::builder(incoming)
+            .serve(make_service_fn(|_: &ProxyStream| {
+                future::ok::<_, BoxError>(service_fn(|_req| {
+                    future::ok::<_, BoxError>(Response::new(Body::empty()))
+                }))
+            }));
+
+        let mut rt = Runtime::new().expect("rt new");
+        rt.block_on(server).unwrap();
+    });
+
+    let addr = addr_rx.recv().expect("server addr rx");
+
+    // a connection that never sends its header is closed, well before
+    // the read timeout
+    let mut req = connect(&addr);
+    let mut buf = [0; 256];
+    let n = req.read(&mut buf).unwrap();
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn connection_info_in_request_extensions() {
+    use hyper::server::conn::{AddrStream, ConnectionInfo};
+
+    let _ = pretty_env_logger::try_init();
+    let (addr_tx, addr_rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let server = Server::bind(&([127, 0, 0, 1], 0).into())
+            .serve(make_service_fn(|_: &AddrStream| {
+                future::ok::<_, BoxError>(service_fn(|req: Request<Body>| {
+                    let info = req.extensions().get::<ConnectionInfo>().expect("ConnectionInfo");
+                    let body = format!(
+                        "{} {:?} {} {}\n",
+                        info.request_count(),
+                        info.version(),
+                        info.local_addr().expect("local_addr"),
+                        info.remote_addr().expect("remote_addr"),
+                    );
+                    future::ok::<_, BoxError>(Response::new(Body::from(body)))
+                }))
+            }));
+        addr_tx.send(server.local_addr()).expect("server addr tx");
+
+        let mut rt = Runtime::new().expect("rt new");
+        rt.block_on(server).unwrap();
+    });
+
+    let addr = addr_rx.recv().expect("server addr rx");
+    let mut req = connect(&addr);
+    let client_addr = req.local_addr().unwrap();
+    req.write_all(b"\
+        GET / HTTP/1.1\r\n\
+        Host: example.domain\r\n\
+        \r\n\
+        GET / HTTP/1.0\r\n\
+        Host: example.domain\r\n\
+        \r\n\
+    ").unwrap();
+    let mut response = String::new();
+    req.read_to_string(&mut response).unwrap();
+
+    let first = format!("1 HTTP/1.1 {} {}\n", addr, client_addr);
+    let second = format!("2 HTTP/1.0 {} {}\n", addr, client_addr);
+    assert!(response.contains(&first), "{:?}", response);
+    assert!(response.contains(&second), "{:?}", response);
+}
+
+#[test]
+fn connection_info_from_serve_connection_with_info() {
+    use hyper::server::conn::{AddrIncoming, ConnectionInfo};
+
+    let _ = pretty_env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let mut incoming = AddrIncoming::bind(&([127, 0, 0, 1], 0).into()).expect("bind");
+    let addr = incoming.local_addr();
+
+    let client = thread::spawn(move || {
+        let mut req = connect(&addr);
+        req.write_all(b"\
+            GET / HTTP/1.1\r\n\
+            Host: example.domain\r\n\
+            Connection: close\r\n\
+            \r\n\
+        ").unwrap();
+        let mut response = String::new();
+        req.read_to_string(&mut response).unwrap();
+        (req.local_addr().unwrap(), response)
+    });
+
+    let socket = rt.block_on(incoming.next()).expect("accept").expect("accept");
+    let svc = service_fn(|req: Request<Body>| {
+        let info = req.extensions().get::<ConnectionInfo>().expect("ConnectionInfo");
+        let body = format!("{:?} {:?}", info.local_addr(), info.remote_addr());
+        future::ok::<_, BoxError>(Response::new(Body::from(body)))
+    });
+    let info = ConnectionInfo::from_transport(&socket);
+    rt.block_on(Http::new().serve_connection_with_info(socket, svc, info)).expect("serve_connection");
+
+    let (client_addr, response) = client.join().unwrap();
+    let expected = format!("Some({}) Some({})", addr, client_addr);
+    assert!(response.ends_with(&expected), "{:?}", response);
+}
+
+#[test]
+fn file_body_sends_range_then_next_response() {
+    use hyper::body::FileBody;
+    use hyper::server::conn::AddrStream;
+
+    let _ = pretty_env_logger::try_init();
+    let path = std::env::temp_dir().join(format!("hyper-test-file-body-{}", std::process::id()));
+    let contents = (0..300_000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+    std::fs::write(&path, &contents).expect("write temp file");
+    let expected = contents[1_000..201_000].to_vec();
+
+    let (addr_tx, addr_rx) = mpsc::channel();
+    let server_path = path.clone();
+    thread::spawn(move || {
+        let server = Server::bind(&([127, 0, 0, 1], 0).into())
+            .serve(make_service_fn(move |_: &AddrStream| {
+                let path = server_path.clone();
+                future::ok::<_, BoxError>(service_fn(move |_req: Request<Body>| {
+                    let body = FileBody::open(&path)
+                        .and_then(|body| body.range(1_000..201_000))
+                        .expect("file body");
+                    future::ok::<_, BoxError>(Response::new(body))
+                }))
+            }));
+        addr_tx.send(server.local_addr()).expect("server addr tx");
+
+        let mut rt = Runtime::new().expect("rt new");
+        rt.block_on(server).unwrap();
+    });
+
+    let addr = addr_rx.recv().expect("server addr rx");
+    let mut req = connect(&addr);
+    req.write_all(b"\
+        GET / HTTP/1.1\r\n\
+        Host: example.domain\r\n\
+        \r\n\
+        GET / HTTP/1.1\r\n\
+        Host: example.domain\r\n\
+        Connection: close\r\n\
+        \r\n\
+    ").unwrap();
+    let mut response = Vec::new();
+    req.read_to_end(&mut response).unwrap();
+    std::fs::remove_file(&path).expect("remove temp file");
+
+    // both responses should be the same range, in order
+    let mut rest = &response[..];
+    for _ in 0..2 {
+        let head_end = rest.windows(4).position(|w| w == b"\r\n\r\n").expect("end of head") + 4;
+        let head = s(&rest[..head_end]);
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{:?}", head);
+        assert!(head.contains("content-length: 200000\r\n"), "{:?}", head);
+        let body_end = head_end + expected.len();
+        assert!(rest.len() >= body_end, "response too short: {}", rest.len());
+        assert!(rest[head_end..body_end] == expected[..], "body mismatch");
+        rest = &rest[body_end..];
+    }
+    assert!(rest.is_empty(), "trailing bytes: {}", rest.len());
+}
+
+#[test]
+fn file_body_waits_for_full_socket() {
+    use hyper::body::FileBody;
+    use hyper::server::conn::AddrStream;
+
+    let _ = pretty_env_logger::try_init();
+    let path = std::env::temp_dir().join(format!("hyper-test-file-body-full-{}", std::process::id()));
+    // much more than fits in the socket buffers
+    let contents = (0..16 * 1024 * 1024).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+    std::fs::write(&path, &contents).expect("write temp file");
+
+    let (addr_tx, addr_rx) = mpsc::channel();
+    let server_path = path.clone();
+    thread::spawn(move || {
+        let builder = Server::bind(&([127, 0, 0, 1], 0).into());
+        #[cfg(target_os = "linux")]
+        let builder = builder.http1_sendfile(true);
+        let server = builder
+            .serve(make_service_fn(move |_: &AddrStream| {
+                let path = server_path.clone();
+                future::ok::<_, BoxError>(service_fn(move |_req: Request<Body>| {
+                    let body = FileBody::open(&path).expect("file body");
+                    future::ok::<_, BoxError>(Response::new(body))
+                }))
+            }));
+        addr_tx.send(server.local_addr()).expect("server addr tx");
+
+        let mut rt = Runtime::new().expect("rt new");
+        rt.block_on(server).unwrap();
+    });
+
+    let addr = addr_rx.recv().expect("server addr rx");
+    let mut req = connect(&addr);
+    req.write_all(b"\
+        GET / HTTP/1.1\r\n\
+        Host: example.domain\r\n\
+        Connection: close\r\n\
+        \r\n\
+    ").unwrap();
+    // let the server fill the socket before reading anything
+    thread::sleep(Duration::from_millis(200));
+    let mut response = Vec::new();
+    req.read_to_end(&mut response).unwrap();
+    std::fs::remove_file(&path).expect("remove temp file");
+
+    let head_end = response.windows(4).position(|w| w == b"\r\n\r\n").expect("end of head") + 4;
+    assert_eq!(response.len() - head_end, contents.len());
+    assert!(response[head_end..] == contents[..], "body mismatch");
+}
+
+#[test]
+fn preserve_header_case_and_order() {
+    use hyper::ext::HeaderCaseMap;
+    use hyper::server::conn::AddrStream;
+
+    let _ = pretty_env_logger::try_init();
+    let (addr_tx, addr_rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let server = Server::bind(&([127, 0, 0, 1], 0).into())
+            .http1_preserve_header_case(true)
+            .serve(make_service_fn(|_: &AddrStream| {
+                future::ok::<_, BoxError>(service_fn(|req: Request<Body>| {
+                    let received = req
+                        .extensions()
+                        .get::<HeaderCaseMap>()
+                        .expect("HeaderCaseMap")
+                        .iter()
+                        .map(|(_, orig)| String::from_utf8_lossy(orig).into_owned())
+                        .collect::<Vec<_>>()
+                        .join(",");
+
+                    let mut map = HeaderCaseMap::new();
+                    map.append(b"X-Upper").unwrap();
+                    map.append(b"x-LOWER").unwrap();
+                    map.append(b"X-Upper").unwrap();
+                    let mut res = Response::builder()
+                        .header("x-lower", "a")
+                        .header("x-upper", "b")
+                        .header("x-upper", "c")
+                        .body(Body::from(received))
+                        .unwrap();
+                    res.extensions_mut().insert(map);
+                    future::ok::<_, BoxError>(res)
+                }))
+            }));
+        addr_tx.send(server.local_addr()).expect("server addr tx");
+
+        let mut rt = Runtime::new().expect("rt new");
+        rt.block_on(server).unwrap();
+    });
+
+    let addr = addr_rx.recv().expect("server addr rx");
+    let mut req = connect(&addr);
+    req.write_all(b"\
+        GET / HTTP/1.1\r\n\
+        HOST: example.domain\r\n\
+        x-Custom: 1\r\n\
+        Connection: close\r\n\
+        X-CUSTOM: 2\r\n\
+        \r\n\
+    ").unwrap();
+    let mut response = String::new();
+    req.read_to_string(&mut response).unwrap();
+
+    assert!(response.contains("\r\nX-Upper: b\r\nx-LOWER: a\r\nX-Upper: c\r\n"), "{:?}", response);
+    assert!(response.ends_with("\r\n\r\nHOST,x-Custom,Connection,X-CUSTOM"), "{:?}", response);
+}
+
+#[test]
+fn bind_reuseport_shares_addr_and_shutdown() {
+    let _ = pretty_env_logger::try_init();
+    let (addr_tx, addr_rx) = mpsc::channel();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+    let thread = thread::spawn(move || {
+        let servers = Server::bind_reuseport(&([127, 0, 0, 1], 0).into(), 3)
+            .expect("bind_reuseport")
+            .into_iter()
+            .map(|builder| {
+                builder.serve(make_service_fn(|_| {
+                    future::ok::<_, BoxError>(service_fn(|_req| {
+                        future::ok::<_, BoxError>(Response::new(Body::from("reuseport")))
+                    }))
+                }))
+            })
+            .collect::<Vec<_>>();
+
+        let addr = servers[0].local_addr();
+        for server in &servers {
+            assert_eq!(server.local_addr(), addr);
+        }
+        addr_tx.send(addr).expect("server addr tx");
+
+        let graceful = Server::with_shared_graceful_shutdown(servers, async {
+            shutdown_rx.await.ok();
+        });
+        let mut rt = Runtime::new().expect("rt new");
+        for res in rt.block_on(future::join_all(graceful)) {
+            res.expect("server");
+        }
+    });
+
+    let addr = addr_rx.recv().expect("server addr rx");
+    for _ in 0..6 {
+        let mut req = connect(&addr);
+        req.write_all(b"\
+            GET / HTTP/1.1\r\n\
+            Host: example.domain\r\n\
+            Connection: close\r\n\
+            \r\n\
+        ").unwrap();
+        let mut response = String::new();
+        req.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("reuseport"), "{:?}", response);
+    }
+
+    shutdown_tx.send(()).unwrap();
+    thread.join().expect("all servers shut down");
+}
+
 // -------------------------------------------------
 // the Server that is used to run all the tests with
 // -------------------------------------------------